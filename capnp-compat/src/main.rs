@@ -0,0 +1,80 @@
+extern crate parser;
+extern crate serde_json;
+
+use std::env;
+use std::process;
+
+//
+// Compares two versions of a schema and lists what changed between them. Each version is a
+// serialized code generator request, e.g. `capnp compile -o- schema.capnp > new.bin`.
+//
+// Exits with 1 if any change breaks wire compatibility, and with 2 if the requests can't be read.
+//
+
+const USAGE: &str = "Usage: capnp-compat [--packed] [--json] <old request> <new request>";
+
+struct Args {
+    packed: bool,
+    json: bool,
+    old: String,
+    new: String
+}
+
+fn parse_args(args: Vec<String>) -> Option<Args> {
+    let mut packed = false;
+    let mut json = false;
+    let mut paths = vec!();
+    for arg in args {
+        match arg.as_str() {
+            "--packed" => packed = true,
+            "--json" => json = true,
+            _ if arg.starts_with("--") => return None,
+            _ => paths.push(arg)
+        }
+    }
+
+    if paths.len() != 2 {
+        return None;
+    }
+    let new = paths.pop().unwrap();
+    let old = paths.pop().unwrap();
+    Some(Args { packed: packed, json: json, old: old, new: new })
+}
+
+fn run(args: &Args) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut options = parser::ReadOptions::new();
+    options.packed(args.packed);
+
+    let old = parser::read_message_from_file(&args.old, &options)?;
+    let new = parser::read_message_from_file(&args.new, &options)?;
+    let report = parser::check_compatibility(&old, &new);
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for change in report.changes() {
+            println!("{}", change);
+        }
+    }
+
+    Ok(report.is_breaking())
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1).collect()) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(false) => (),
+        Ok(true) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+}
@@ -40,6 +40,7 @@ pub enum CppType {
     Float,
     Double,
     String,
+    Bytes,
     Vector(Box<CppType>),
     RefId(Id)
 }
@@ -49,14 +50,47 @@ pub enum CppType {
 pub struct EnumClass {
     id: Id,
     name: Name,
-    enumerants: Vec<Name>
+    enumerants: Vec<Enumerant>,
+    doc_comment: Option<String>
+}
+
+#[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq, Eq)]
+#[get = "pub"]
+pub struct Enumerant {
+    name: Name,
+    doc_comment: Option<String>
 }
 
 #[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq, Eq)]
 #[get = "pub"]
 pub struct Field {
     name: Name,
-    cpp_type: CppType
+    cpp_type: CppType,
+    doc_comment: Option<String>
+}
+
+///
+/// The value of a constant, resolved against the schema but not yet against the generated types.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConstValue {
+    /// Used as is, e.g. `1.5`, `true` or `"text"`.
+    Literal(String),
+    Enumerant(Id, Name),
+
+    /// The fields that were given, by name. The others get their default value.
+    Object(Id, Vec<(Name, ConstValue)>),
+    List(Vec<ConstValue>)
+}
+
+#[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq, Eq)]
+#[get = "pub"]
+pub struct Constant {
+    id: Id,
+    name: Name,
+    cpp_type: CppType,
+    value: ConstValue,
+    doc_comment: Option<String>
 }
 
 #[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq, Eq)]
@@ -66,7 +100,13 @@ pub struct Class {
     name: Name,
     inner_types: Vec<ComplexTypeDef>,
     union: Option<UnnamedUnion>,
-    fields: Vec<Field>
+    fields: Vec<Field>,
+    constants: Vec<Constant>,
+
+    /// Groups are held by value in their parent and share its capnp struct.
+    is_group: bool,
+
+    doc_comment: Option<String>
 }
 
 #[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq, Eq)]
@@ -76,10 +116,39 @@ pub struct UnnamedUnion {
     fields: Vec<Field>
 }
 
+#[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq, Eq)]
+#[get = "pub"]
+pub struct Method {
+    name: Name,
+
+    /// Implicit parameter and result structs are inner types of the interface.
+    params: Id,
+    results: Id,
+
+    doc_comment: Option<String>
+}
+
+///
+/// An abstract class for implementations of a capnp interface, whose methods return promises of
+/// their results. Fields of the interface's type hold its `Client`, which calls a capnp server.
+///
+#[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq, Eq)]
+#[get = "pub"]
+pub struct Interface {
+    id: Id,
+    name: Name,
+    inner_types: Vec<ComplexTypeDef>,
+    superclasses: Vec<Id>,
+    methods: Vec<Method>,
+    constants: Vec<Constant>,
+    doc_comment: Option<String>
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ComplexTypeDef {
     EnumClass(EnumClass),
-    Class(Class)
+    Class(Class),
+    Interface(Interface)
 }
 
 #[derive(Constructor, Clone, Getters, CopyGetters, MutGetters, Setters, Debug, PartialEq)]
@@ -87,6 +156,7 @@ pub enum ComplexTypeDef {
 #[get_mut = "pub"]
 pub struct Namespace {
     defs: Vec<ComplexTypeDef>,
+    constants: Vec<Constant>,
     namespaces: HashMap<Name, Namespace>
 }
 
@@ -310,7 +380,7 @@ impl ToString for FullyQualifiedName {
 #[allow(dead_code)]
 impl Namespace {
     pub fn empty() -> Namespace {
-        Namespace { defs: vec!(), namespaces: HashMap::new() }
+        Namespace { defs: vec!(), constants: vec!(), namespaces: HashMap::new() }
     }
 
     fn create_empty_namespace(&mut self, name: &Name) -> &mut Namespace {
@@ -385,14 +455,16 @@ impl ComplexTypeDef {
     pub fn id(&self) -> Id {
         match self {
             ComplexTypeDef::EnumClass(e) => *e.id(),
-            ComplexTypeDef::Class(c) => *c.id()
+            ComplexTypeDef::Class(c) => *c.id(),
+            ComplexTypeDef::Interface(i) => *i.id()
         }
     }
 
     pub fn name(&self) -> &Name {
         match self {
             ComplexTypeDef::EnumClass(e) => e.name(),
-            ComplexTypeDef::Class(c) => c.name()
+            ComplexTypeDef::Class(c) => c.name(),
+            ComplexTypeDef::Interface(i) => i.name()
         }
     }
 }
@@ -3,7 +3,7 @@ use super::*;
 
 fn codegen_enum_class(enum_class: &ast::EnumClass) -> String {
     indoc!("
-        enum class #NAME {
+        #DOC_COMMENTenum class #NAME {
             #ENUMERANTS
         };
     ")
@@ -12,10 +12,11 @@ fn codegen_enum_class(enum_class: &ast::EnumClass) -> String {
         "#ENUMERANTS",
         &enum_class.enumerants()
             .iter()
-            .map(|e| e.to_upper_camel_case(&[]))
+            .map(|e| format!("{}{}", codegen_doc_comment(e.doc_comment()), e.name().to_upper_camel_case(&[])).replace("\n", "\n    "))
             .collect::<Vec<String>>()
             .join(",\n    ")
     )
+    .replace("#DOC_COMMENT", &codegen_doc_comment(enum_class.doc_comment()))
 }
 
 fn codegen_field(ctx: &Context, f: &ast::Field) -> String {
@@ -24,11 +25,12 @@ fn codegen_field(ctx: &Context, f: &ast::Field) -> String {
 
 fn codegen_field_getter_prototype(ctx: &Context, f: &ast::Field) -> String {
     indoc!("
-        const #TYPE #GETTER() const;
+        #DOC_COMMENTconst #TYPE #GETTER() const;
         #TYPE #GETTER();
     ")
     .replace("#TYPE", &codegen_type_as_ref_if_complex(ctx, f.cpp_type()))
     .replace("#GETTER", &f.name().to_lower_camel_case(&[]))
+    .replace("#DOC_COMMENT", &codegen_doc_comment(f.doc_comment()))
 }
 
 fn codegen_field_setter_prototype(ctx: &Context, class_name: &ast::Name, f: &ast::Field) -> String {
@@ -45,11 +47,12 @@ fn codegen_union_getter_prototypes(ctx: &Context, u_option: &Option<ast::Unnamed
                 .iter()
                 .map(|f| {
                     indoc!("
-                        const #TYPE& #GETTER() const;
-                        #TYPE& #GETTER();
+                        #DOC_COMMENTconst #TYPE #GETTER() const;
+                        #TYPE #GETTER();
                     ")
-                    .replace("#TYPE", &codegen_cpp_type(ctx, f.cpp_type()))
+                    .replace("#TYPE", &codegen_type_as_ref_if_complex(ctx, f.cpp_type()))
                     .replace("#GETTER", &f.name().with_prepended("as").to_lower_camel_case(&[]))
+                    .replace("#DOC_COMMENT", &codegen_doc_comment(f.doc_comment()))
                 })
                 .collect()
         }
@@ -91,6 +94,183 @@ fn codegen_union_field(ctx: &Context, u: &ast::UnnamedUnion) -> String {
     )
 }
 
+fn is_constexpr_cpp_type(ctx: &Context, t: &ast::CppType) -> bool {
+    !is_complex_cpp_type(t) || is_enum_class(ctx, t)
+}
+
+///
+/// Whether values of the type are made of generated classes. Those are move-only, and may not
+/// be complete yet where a constant is declared.
+///
+fn contains_class(ctx: &Context, t: &ast::CppType) -> bool {
+    match t {
+        ast::CppType::RefId(_) => !is_enum_class(ctx, t),
+        ast::CppType::Vector(element_type) => contains_class(ctx, element_type),
+        _ => false
+    }
+}
+
+fn codegen_name_relative_to_current_namespace(ctx: &Context, fqn: &ast::FullyQualifiedName) -> String {
+    ast::FullyQualifiedName::new(fqn.names()[ctx.current_namespace().names().len()..].to_vec()).to_string()
+}
+
+fn codegen_default_value(ctx: &Context, t: &ast::CppType) -> String {
+    match t {
+        ast::CppType::Void => String::from("std::monostate()"),
+        ast::CppType::Bool => String::from("false"),
+        ast::CppType::String | ast::CppType::Bytes | ast::CppType::Vector(_) => format!("{}()", codegen_cpp_type(ctx, t)),
+        ast::CppType::RefId(id) => match ctx.type_info().get(id).unwrap().cpp_type() {
+            ast::ComplexTypeDef::EnumClass(e) => match e.enumerants().first() {
+                Some(enumerant) => format!("{}::{}", ctx.resolve_full_name(*id), enumerant.name().to_upper_camel_case(&[])),
+                None => format!("static_cast<{}>(0)", ctx.resolve_full_name(*id))
+            },
+            ast::ComplexTypeDef::Class(c) => codegen_object_value(ctx, c, &vec!()),
+            ast::ComplexTypeDef::Interface(_) => format!("{}(nullptr)", codegen_cpp_type(ctx, t))
+        },
+        _ => String::from("0")
+    }
+}
+
+///
+/// Calls the constructor of a class with the given field values. For a union the constructor
+/// of the member that was given is used, or of the first member.
+///
+fn codegen_object_value(ctx: &Context, c: &ast::Class, values: &Vec<(ast::Name, ast::ConstValue)>) -> String {
+    let value_of = |f: &ast::Field| match values.iter().find(|(name, _)| name == f.name()) {
+        Some((_, value)) => codegen_const_value(ctx, f.cpp_type(), value),
+        None => codegen_default_value(ctx, f.cpp_type())
+    };
+
+    let mut args = c.fields()
+        .iter()
+        .filter(|f| match c.union() { Some(_) => f.name().to_string() != "which", None => true })
+        .map(value_of)
+        .collect::<Vec<String>>();
+
+    if let Some(u) = c.union() {
+        let member = u.fields()
+            .iter()
+            .find(|f| values.iter().any(|(name, _)| name == f.name()))
+            .unwrap_or(&u.fields()[0]);
+        args.push(format!("{}::Which::{}", ctx.resolve_full_name(*c.id()), member.name().to_upper_camel_case(&[])));
+
+        // Members of the same type share a constructor, so literals must match its type exactly.
+        match values.iter().find(|(name, _)| name == member.name()) {
+            Some((_, ast::ConstValue::Literal(literal))) if *member.cpp_type() != ast::CppType::Void =>
+                args.push(format!("static_cast<{}>({})", codegen_cpp_type(ctx, member.cpp_type()), literal)),
+            _ => args.push(value_of(member))
+        }
+    }
+
+    format!("{}({})", ctx.resolve_full_name(*c.id()), args.join(", "))
+}
+
+fn codegen_const_value(ctx: &Context, t: &ast::CppType, value: &ast::ConstValue) -> String {
+    match (t, value) {
+        // An enumerant the schema doesn't know by name.
+        (ast::CppType::RefId(_), ast::ConstValue::Literal(literal)) if is_enum_class(ctx, t) => {
+            format!("static_cast<{}>({})", codegen_cpp_type(ctx, t), literal)
+        },
+        (_, ast::ConstValue::Literal(literal)) => literal.clone(),
+        (_, ast::ConstValue::Enumerant(id, name)) => format!("{}::{}", ctx.resolve_full_name(*id), name.to_upper_camel_case(&[])),
+        (ast::CppType::Bytes, ast::ConstValue::List(items)) => {
+            format!(
+                "std::vector<uint8_t>{{{}}}",
+                items.iter().map(|item| codegen_const_value(ctx, &ast::CppType::UChar, item)).collect::<Vec<String>>().join(", ")
+            )
+        },
+        // Initializer lists copy their elements, which classes don't allow.
+        (ast::CppType::Vector(element_type), ast::ConstValue::List(items)) if contains_class(ctx, element_type) => {
+            indoc!("
+                [] {
+                    #TYPE list;
+                    #ELEMENTS
+                    return list;
+                }()")
+            .trim_start()
+            .replace("#TYPE", &codegen_cpp_type(ctx, t))
+            .replace(
+                "#ELEMENTS",
+                &items.iter()
+                    .map(|item| format!("list.push_back({});", codegen_const_value(ctx, element_type, item)))
+                    .collect::<Vec<String>>()
+                    .join("\n    ")
+            )
+        },
+        (ast::CppType::Vector(element_type), ast::ConstValue::List(items)) => {
+            format!(
+                "{}{{{}}}",
+                codegen_cpp_type(ctx, t),
+                items.iter().map(|item| codegen_const_value(ctx, element_type, item)).collect::<Vec<String>>().join(", ")
+            )
+        },
+        // The translator only gives struct values to struct types, and reports the rest as invalid.
+        (ast::CppType::RefId(id), ast::ConstValue::Object(_, values)) => match ctx.type_info().get(id).unwrap().cpp_type() {
+            ast::ComplexTypeDef::Class(c) => codegen_object_value(ctx, c, values),
+            ast::ComplexTypeDef::EnumClass(_) => unreachable!("Struct value for enum: {}", id),
+            ast::ComplexTypeDef::Interface(_) => unreachable!("Struct value for interface: {}", id)
+        },
+        (t, value) => unreachable!("Constant value {:?} for type {:?}", value, t)
+    }
+}
+
+fn codegen_constant(ctx: &Context, k: &ast::Constant) -> String {
+    indoc!("#DOC_COMMENT#SPECIFIERS #TYPE #NAME = #VALUE;")
+    .replace("#SPECIFIERS", if is_constexpr_cpp_type(ctx, k.cpp_type()) { "inline constexpr" } else { "inline const" })
+    .replace("#TYPE", &codegen_cpp_type(ctx, k.cpp_type()))
+    .replace("#NAME", &k.name().to_string())
+    .replace("#VALUE", &codegen_const_value(ctx, k.cpp_type(), k.value()))
+    .replace("#DOC_COMMENT", &codegen_doc_comment(k.doc_comment()))
+}
+
+///
+/// Constants nested in a class become static members. Those made of classes are only declared
+/// here, see `codegen_class_constant_definitions`.
+///
+fn codegen_class_constant_prototype(ctx: &Context, k: &ast::Constant) -> String {
+    let prototype =
+        if contains_class(ctx, k.cpp_type()) {
+            indoc!("static const #TYPE #NAME;")
+        } else if is_constexpr_cpp_type(ctx, k.cpp_type()) {
+            indoc!("static constexpr #TYPE #NAME = #VALUE;")
+        } else {
+            indoc!("static inline const #TYPE #NAME = #VALUE;")
+        };
+
+    format!("{}{}", codegen_doc_comment(k.doc_comment()), prototype)
+    .replace("#TYPE", &codegen_cpp_type(ctx, k.cpp_type()))
+    .replace("#NAME", &k.name().to_string())
+    .replace("#VALUE", &codegen_const_value(ctx, k.cpp_type(), k.value()))
+}
+
+///
+/// Defines the class and interface constants that could only be declared in the class, once the
+/// outermost class is complete.
+///
+fn codegen_class_constant_definitions(ctx: &Context, def: &ast::ComplexTypeDef) -> Vec<String> {
+    let (constants, inner_types) = match def {
+        ast::ComplexTypeDef::Class(c) => (c.constants(), c.inner_types()),
+        ast::ComplexTypeDef::Interface(i) => (i.constants(), i.inner_types()),
+        ast::ComplexTypeDef::EnumClass(_) => return vec!()
+    };
+    let class_name = codegen_name_relative_to_current_namespace(ctx, ctx.type_info().get(&def.id()).unwrap().fqn());
+
+    let mut ret = vec!();
+    for k in constants.iter().filter(|k| contains_class(ctx, k.cpp_type())) {
+        ret.push(
+            indoc!("inline const #TYPE #CLASS::#NAME = #VALUE;")
+            .replace("#TYPE", &codegen_cpp_type(ctx, k.cpp_type()))
+            .replace("#CLASS", &class_name)
+            .replace("#NAME", &k.name().to_string())
+            .replace("#VALUE", &codegen_const_value(ctx, k.cpp_type(), k.value()))
+        );
+    }
+    for inner_type in inner_types {
+        ret.extend(codegen_class_constant_definitions(ctx, inner_type));
+    }
+    ret
+}
+
 fn codegen_constructor_prototype_fields(ctx: &Context, class_name: &ast::Name, fields: &Vec<ast::Field>) -> String {
     indoc!("
         #NAME(
@@ -112,9 +292,9 @@ fn codegen_constructor_prototypes(ctx: &Context, c: &ast::Class) -> Vec<String>
 
     match c.union() {
         Some(u) => {
-            for field in u.fields() {
+            for field in union_fields_with_distinct_types(u) {
                 let mut fields = c.fields().clone();
-                fields.push(field.clone());
+                fields.push(ast::Field::new(ast::Name::from("whichData"), field.cpp_type().clone(), None));
                 ret.push(codegen_constructor_prototype_fields(ctx, c.name(), &fields))
             }
         }
@@ -146,6 +326,12 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> String {
         .filter(|s| s.len() != 0)
         .collect();
 
+    // Constants
+    let class_constants: Vec<String> = c.constants()
+        .iter()
+        .map(|k| codegen_class_constant_prototype(ctx, k))
+        .collect();
+
     // Fields
     let mut class_fields: Vec<String> = vec!();
     class_fields.push(
@@ -203,6 +389,18 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> String {
             )
         )
     }
+    if class_constants.len() > 0 {
+        class_sections.push(
+            indoc!("
+                public:
+                    #CLASS_CONSTANTS
+            ")
+            .replace(
+                "#CLASS_CONSTANTS",
+                &class_constants.join("\n").replace("\n", "\n    ")
+            )
+        )
+    }
     if class_fields.len() > 0 {
         class_sections.push(
             indoc!("
@@ -232,7 +430,7 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> String {
     }
 
     indoc!("
-        class #NAME {
+        #DOC_COMMENTclass #NAME {
         #SECTIONS
         };
     ")
@@ -241,12 +439,161 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> String {
         "#SECTIONS",
         &class_sections.join("\n")
     )
+    .replace("#DOC_COMMENT", &codegen_doc_comment(c.doc_comment()))
+}
+
+///
+/// Implementations may finish a call asynchronously, the same way the client does.
+///
+fn codegen_method_signature(ctx: &Context, m: &ast::Method) -> String {
+    format!(
+        "kj::Promise<{}> {}({}&& params)",
+        ctx.resolve_full_name(*m.results()),
+        m.name().to_lower_camel_case(&[]),
+        ctx.resolve_full_name(*m.params())
+    )
+}
+
+///
+/// Dispatches capnp calls, including those of superclasses, to an implementation.
+///
+fn codegen_interface_server(ctx: &Context, i: &ast::Interface) -> String {
+    indoc!("
+        class Server final : public #CAPNP_INTERFACE::Server {
+        public:
+            explicit Server(std::unique_ptr<#INTERFACE> impl);
+
+        protected:
+            #METHODS
+
+        private:
+            std::unique_ptr<#INTERFACE> _impl;
+        };
+    ")
+    .replace(
+        "#METHODS",
+        &interface_methods_with_inherited(ctx, i)
+            .iter()
+            .map(|m| {
+                format!(
+                    "kj::Promise<void> {}({}Context context) override;",
+                    m.name().to_lower_camel_case(&[]),
+                    m.name().to_upper_camel_case(&[])
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n    ")
+    )
+    .replace("#CAPNP_INTERFACE", &ctx.capnp_names().get(i.id()).unwrap().to_string())
+    .replace("#INTERFACE", &ctx.resolve_full_name(*i.id()))
+}
+
+///
+/// Turns calls into capnp requests. Unlike the interface, it can be stored in fields.
+///
+fn codegen_interface_client(ctx: &Context, i: &ast::Interface) -> String {
+    indoc!("
+        class Client {
+        public:
+            explicit Client(#CAPNP_INTERFACE::Client client);
+            Client clone() const;
+            #CAPNP_INTERFACE::Client capnpClient() const;
+            #METHODS
+
+        private:
+            // capnp clients are only copied from mutable references, though that just adds one.
+            mutable #CAPNP_INTERFACE::Client _client;
+        };
+    ")
+    .replace(
+        "#METHODS",
+        &interface_methods_with_inherited(ctx, i)
+            .iter()
+            .map(|m| {
+                format!(
+                    "{}kj::Promise<{}> {}({}&& params);",
+                    codegen_doc_comment(m.doc_comment()),
+                    ctx.resolve_full_name(*m.results()),
+                    m.name().to_lower_camel_case(&[]),
+                    ctx.resolve_full_name(*m.params())
+                )
+                .replace("\n", "\n    ")
+            })
+            .collect::<Vec<String>>()
+            .join("\n    ")
+    )
+    .replace("#CAPNP_INTERFACE", &ctx.capnp_names().get(i.id()).unwrap().to_string())
+}
+
+fn codegen_interface(ctx: &Context, i: &ast::Interface) -> String {
+    // The param and result classes may hold a Client, even of this interface, so it's defined
+    // first. It only needs their names.
+    let mut inner_types: Vec<String> = i.inner_types()
+        .iter()
+        .map(codegen_type_definition_prototype)
+        .collect();
+    inner_types.push(codegen_interface_server(ctx, i));
+    inner_types.push(codegen_interface_client(ctx, i));
+    inner_types.extend(
+        i.inner_types()
+            .iter()
+            .map(|t| codegen_complex_type_definition(ctx, t))
+    );
+
+    let mut methods = vec!(format!("virtual ~{}() = default;", i.name().to_string()));
+    methods.extend(
+        i.methods()
+            .iter()
+            .map(|m| format!("{}virtual {} = 0;", codegen_doc_comment(m.doc_comment()), codegen_method_signature(ctx, m)))
+    );
+
+    let constants: Vec<String> = i.constants()
+        .iter()
+        .map(|k| codegen_class_constant_prototype(ctx, k))
+        .collect();
+
+    let superclasses =
+        if i.superclasses().is_empty() {
+            String::new()
+        } else {
+            format!(
+                " : {}",
+                i.superclasses()
+                    .iter()
+                    .map(|id| format!("public virtual {}", ctx.resolve_full_name(*id)))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            )
+        };
+
+    indoc!("
+        #DOC_COMMENTclass #NAME#SUPERCLASSES {
+        public:
+            #INNER_TYPES
+        #CONSTANTSpublic:
+            #METHODS
+        };
+    ")
+    .replace("#NAME", &i.name().to_string())
+    .replace("#SUPERCLASSES", &superclasses)
+    .replace("#INNER_TYPES", &inner_types.join("\n").replace("\n", "\n    "))
+    .replace(
+        "#CONSTANTS",
+        &if constants.is_empty() {
+            String::new()
+        } else {
+            format!("public:\n    {}\n", constants.join("\n").replace("\n", "\n    "))
+        }
+    )
+    .replace("#METHODS", &methods.join("\n").replace("\n", "\n    "))
+    .replace("#DOC_COMMENT", &codegen_doc_comment(i.doc_comment()))
 }
 
 fn codegen_complex_type_definition(ctx: &Context, def: &ast::ComplexTypeDef) -> String {
     match def {
         ast::ComplexTypeDef::Class(c) => codegen_class(ctx, c),
-        ast::ComplexTypeDef::EnumClass(e) => codegen_enum_class(e)
+        ast::ComplexTypeDef::EnumClass(e) => codegen_enum_class(e),
+        ast::ComplexTypeDef::Interface(i) => codegen_interface(ctx, i)
     }
 }
 
@@ -261,10 +608,11 @@ fn generate_all_types_used_by_cpp_type(ctx: &Context, cpp_type: &ast::CppType) -
     return deps;
 }
 
-fn generate_all_types_used_by_type(ctx: &Context, def: &ast::ComplexTypeDef) -> Vec<ast::FullyQualifiedName> {
-    let id = def.id();
-    let def_info = ctx.type_info().get(&id).unwrap();
-
+///
+/// The types that a type's definition refers to, not counting the type itself unless it's
+/// recursive.
+///
+fn generate_types_referenced_by_type(ctx: &Context, def: &ast::ComplexTypeDef) -> Vec<ast::FullyQualifiedName> {
     let mut deps = vec!();
     match def {
         ast::ComplexTypeDef::Class(c) => {
@@ -274,6 +622,15 @@ fn generate_all_types_used_by_type(ctx: &Context, def: &ast::ComplexTypeDef) ->
             for inner_type in c.inner_types() {
                 deps.extend(generate_all_types_used_by_type(ctx, inner_type).into_iter());
             }
+            // A constant of the class's own type is only declared inside it, which C++ allows.
+            let own_fqn = ctx.type_info().get(c.id()).unwrap().fqn();
+            for constant in c.constants() {
+                deps.extend(
+                    generate_all_types_used_by_cpp_type(ctx, constant.cpp_type())
+                        .into_iter()
+                        .filter(|fqn| fqn != own_fqn)
+                )
+            }
             if let Some(u) = c.union() {
                 for field in u.fields() {
                     if let ast::CppType::RefId(id) = field.cpp_type() {
@@ -282,11 +639,35 @@ fn generate_all_types_used_by_type(ctx: &Context, def: &ast::ComplexTypeDef) ->
                 }
             }
         },
-        ast::ComplexTypeDef::EnumClass(_) => {}
+        ast::ComplexTypeDef::EnumClass(_) => {},
+        ast::ComplexTypeDef::Interface(i) => {
+            // The interface's Client is defined before its inner types, so they may refer to it.
+            let own_fqn = ctx.type_info().get(i.id()).unwrap().fqn();
+            for inner_type in i.inner_types() {
+                deps.extend(
+                    generate_all_types_used_by_type(ctx, inner_type)
+                        .into_iter()
+                        .filter(|fqn| fqn != own_fqn)
+                );
+            }
+            for constant in i.constants() {
+                deps.extend(generate_all_types_used_by_cpp_type(ctx, constant.cpp_type()))
+            }
+            for superclass in i.superclasses() {
+                deps.push(ctx.type_info().get(superclass).unwrap().fqn().clone())
+            }
+            for method in i.methods() {
+                deps.push(ctx.type_info().get(method.params()).unwrap().fqn().clone());
+                deps.push(ctx.type_info().get(method.results()).unwrap().fqn().clone());
+            }
+        }
     }
+    return deps;
+}
 
-    deps.push(def_info.fqn().clone());
-
+fn generate_all_types_used_by_type(ctx: &Context, def: &ast::ComplexTypeDef) -> Vec<ast::FullyQualifiedName> {
+    let mut deps = generate_types_referenced_by_type(ctx, def);
+    deps.push(ctx.type_info().get(&def.id()).unwrap().fqn().clone());
     return deps;
 }
 
@@ -295,15 +676,7 @@ fn generate_dependency_list_for_type(ctx: &Context, def: &ast::ComplexTypeDef) -
     let def_info = ctx.type_info().get(&id).unwrap();
     let def_path = def_info.fqn().parent();
 
-    println!("  td {} => {:?}",
-        def_info.fqn().to_string(),
-        generate_all_types_used_by_type(ctx, def)
-            .iter()
-            .map(|fqn| fqn.to_string())
-            .collect::<Vec<String>>()
-    );
-
-    return generate_all_types_used_by_type(ctx, def)
+    return generate_types_referenced_by_type(ctx, def)
         .iter()
         .filter(|fqn| fqn.is_prefixed_by(&def_path))
         .filter(|fqn| fqn.names().len() == def_info.fqn().names().len())
@@ -324,12 +697,17 @@ fn generate_all_type_dependencies_recursive(
         deps.extend(generate_all_types_used_by_type(ctx, def))
     }
 
+    for constant in namespace.constants() {
+        deps.extend(generate_all_types_used_by_cpp_type(ctx, constant.cpp_type()))
+    }
+
     return deps;
 }
 
 fn generate_dependency_list_for_namespaces(
     ctx: &Context,
     fqn: &ast::FullyQualifiedName,
+    name: &ast::Name,
     namespace: &ast::Namespace
 ) -> Vec<ast::Name> {
 
@@ -341,7 +719,8 @@ fn generate_dependency_list_for_namespaces(
             fqn.names().len() > 0
         {
             let depname = type_dependency.names().get(fqn.names().len()).unwrap().clone();
-            if !deps.contains(&depname) {
+            // Types in the namespace itself don't order it.
+            if depname != *name && !deps.contains(&depname) {
                 deps.push(depname)
             }
         }
@@ -350,41 +729,29 @@ fn generate_dependency_list_for_namespaces(
     return deps;
 }
 
-fn insert_names_sorted_by_dependencies<'a>(
-    dst: &mut Vec<&'a ast::Name>,
-    name: &'a ast::Name,
-    deps: &'a HashMap<&'a ast::Name, Vec<ast::Name>>,
-    queue: Vec<&'a ast::Name>
-) {
-    println!("    -> Call: {} dst.len={} deps.len={} q.len={}", name.to_string(), dst.len(), deps.len(), queue.len());
-
-    if dst.contains(&name) {
-        println!("    -> In dst {}", name.to_string());
-        return;
-    }
-
-    if queue.contains(&name) {
-        println!("    -> In queue {}", name.to_string());
-        return;
-    }
-
-    match deps.get(name) {
-        Some(dep_list) =>
-            for dep in dep_list {
-                let mut new_queue = queue.clone();
-                new_queue.push(name);
-                insert_names_sorted_by_dependencies(dst, dep, deps, new_queue)
-            },
-        None => ()
+///
+/// Orders names so that each comes after the ones it depends on. Recursive types would need
+/// indirection, which isn't generated yet, so they're only reported.
+///
+fn sort_names_by_dependencies<'a>(names: Vec<&'a ast::Name>, deps: &'a HashMap<&'a ast::Name, Vec<ast::Name>>) -> Vec<&'a ast::Name> {
+    let components = parser::sort_by_dependencies(&names, |name| {
+        deps.get(name).map(|dep_list| dep_list.iter().collect()).unwrap_or_else(Vec::new)
+    });
+
+    let mut sorted = vec!();
+    for component in components {
+        if component.is_cyclic() {
+            eprintln!(
+                "WARN: Recursive types are not supported yet: {:?}",
+                component.members().iter().map(|name| name.to_string()).collect::<Vec<String>>()
+            );
+        }
+        sorted.extend(component.members().iter().cloned());
     }
-
-    println!("    -> ins {}", name.to_string());
-    dst.push(name);
+    sorted
 }
 
 fn codegen_namespace_contents(ctx: &Context, namespace: &ast::Namespace) -> String {
-    println!("Current Namespace: {}", ctx.current_namespace().to_string());
-
     //
     // TODO: In the future, it would be better to identify all types that must be generated,
     //       sort those by dependency, group the sorted list by namespace and then generate.
@@ -393,19 +760,13 @@ fn codegen_namespace_contents(ctx: &Context, namespace: &ast::Namespace) -> Stri
     // Sort namespaces so that every type is fully defined when it's needed.
     let mut namespace_dependencies : HashMap<&ast::Name, Vec<ast::Name>> = HashMap::new();
     for (name, child_namespace) in namespace.namespaces() {
-        namespace_dependencies.insert(name, generate_dependency_list_for_namespaces(ctx, ctx.current_namespace(), &child_namespace));
+        namespace_dependencies.insert(name, generate_dependency_list_for_namespaces(ctx, ctx.current_namespace(), name, &child_namespace));
     }
 
-    for (n, d) in &namespace_dependencies {
-        println!("  nd: {} => {:?}", n.to_string(), d.iter().map(ast::Name::to_string).collect::<Vec<String>>());
-    }
-
-    let mut sorted_child_namespaces = vec!();
-    for (name, _) in namespace.namespaces() {
-        insert_names_sorted_by_dependencies(&mut sorted_child_namespaces, name, &namespace_dependencies, vec!());
-    }
-
-    println!("  Namespace Order: {:?}", sorted_child_namespaces.iter().map(|it| it.to_string()).collect::<Vec<String>>());
+    // The namespaces are kept in a map; sort them first so the output is stable.
+    let mut child_namespace_names = namespace.namespaces().keys().collect::<Vec<&ast::Name>>();
+    child_namespace_names.sort_by_key(|name| name.to_string());
+    let sorted_child_namespaces = sort_names_by_dependencies(child_namespace_names, &namespace_dependencies);
 
     let mut namespace_defs : Vec<String> = vec!();
     namespace_defs.push(
@@ -422,10 +783,7 @@ fn codegen_namespace_contents(ctx: &Context, namespace: &ast::Namespace) -> Stri
         type_dependencies.insert(def.name(), generate_dependency_list_for_type(ctx, def));
     }
 
-    let mut sorted_type_dependencies = vec!();
-    for def in namespace.defs() {
-        insert_names_sorted_by_dependencies(&mut sorted_type_dependencies, def.name(), &type_dependencies, vec!())
-    }
+    let sorted_type_dependencies = sort_names_by_dependencies(namespace.defs().iter().map(|def| def.name()).collect(), &type_dependencies);
 
     let mut sorted_types = vec!();
     for name in sorted_type_dependencies {
@@ -444,6 +802,18 @@ fn codegen_namespace_contents(ctx: &Context, namespace: &ast::Namespace) -> Stri
             .join("\n")
     );
 
+    // Constants come after the types, which they may be made of.
+    let mut constants = namespace.constants()
+        .iter()
+        .map(|k| codegen_constant(ctx, k))
+        .collect::<Vec<String>>();
+    for def in &sorted_types {
+        constants.extend(codegen_class_constant_definitions(ctx, def));
+    }
+    if constants.len() > 0 {
+        namespace_defs.push(constants.join("\n") + "\n");
+    }
+
     indoc!(
         "#DEFINITIONS"
     )
@@ -472,6 +842,9 @@ pub fn codegen_type_definition_prototype(def: &ast::ComplexTypeDef) -> String {
         ast::ComplexTypeDef::Class(c) => {
             format!("class {};", c.name().to_upper_camel_case(&[]))
         }
+        ast::ComplexTypeDef::Interface(i) => {
+            format!("class {};", i.name().to_upper_camel_case(&[]))
+        }
     }
 }
 
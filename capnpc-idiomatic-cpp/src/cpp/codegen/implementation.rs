@@ -6,7 +6,46 @@ fn codegen_constructor_arg(ctx: &Context, f: &ast::Field) -> String {
     format!("{} {}", codegen_type_as_rvalue_ref_if_complex(ctx, f.cpp_type()), f.name().to_string())
 }
 
-fn codegen_constructor_initializer(f: &ast::Field) -> String {
+///
+/// Initializes the variant of a union at the alternative that `which` selects. Only fields of
+/// the constructor's type are candidates, and the index is needed since they may share a type.
+///
+fn codegen_which_data_initializer(ctx: &Context, c: &ast::Class, u: &ast::UnnamedUnion, t: &ast::CppType) -> String {
+    let which_data = if is_complex_cpp_type(t) { "std::move(whichData)" } else { "whichData" };
+    let alternatives = u.fields()
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| f.cpp_type() == t)
+        .collect::<Vec<(usize, &ast::Field)>>();
+
+    let (first, rest) = alternatives.split_first().unwrap();
+    if rest.is_empty() {
+        return format!("_whichData(std::in_place_index<{}>, {})", first.0, which_data);
+    }
+
+    let initializer = rest.iter().fold(
+        format!("decltype(_whichData)(std::in_place_index<{}>, {})", first.0, which_data),
+        |otherwise, (index, f)| {
+            format!(
+                "which == {}::Which::{} ? decltype(_whichData)(std::in_place_index<{}>, {}) : {}",
+                ctx.current_namespace().with_appended(c.name()).to_string(),
+                f.name().to_upper_camel_case(&[]),
+                index,
+                which_data,
+                otherwise
+            )
+        }
+    );
+    format!("_whichData({})", initializer)
+}
+
+fn codegen_constructor_initializer(ctx: &Context, c: &ast::Class, f: &ast::Field) -> String {
+    if let Some(u) = c.union() {
+        if f.name().to_string() == "whichData" {
+            return codegen_which_data_initializer(ctx, c, u, f.cpp_type());
+        }
+    }
+
     if is_complex_cpp_type(&f.cpp_type()) {
         format!("_#NAME(std::move(#NAME))").replace("#NAME", &f.name().to_string())
     } else {
@@ -33,6 +72,7 @@ fn codegen_move_constructor_assign(f: &ast::Field) -> String {
 fn codegen_clone_field(ctx: &Context, f: &ast::Field) -> String {
     match f.cpp_type() {
         ast::CppType::String => format!("std::string(_#NAME)"),
+        ast::CppType::Bytes => format!("std::vector<uint8_t>(_#NAME)"),
         ast::CppType::Vector(_) => format!("std::move(#NAME)"),
         ast::CppType::RefId(id) =>
             if is_enum_class(ctx, f.cpp_type()) {
@@ -114,7 +154,7 @@ fn codegen_constructor(ctx: &Context, c: &ast::Class, fields: &Vec<ast::Field>)
     )
     .replace(
         "#FIELDS",
-        &fields.iter().map(|f| codegen_constructor_initializer(f)).collect::<Vec<String>>().join(",\n    ")
+        &fields.iter().map(|f| codegen_constructor_initializer(ctx, c, f)).collect::<Vec<String>>().join(",\n    ")
     )
 }
 
@@ -124,7 +164,7 @@ fn codegen_destructor(ctx: &Context, c: &ast::Class) -> String {
 
 fn codegen_clone_vector_field(ctx: &Context, f: &ast::Field, element_type: &ast::CppType, field_ref: &String) -> String {
     let clone_element =
-        if is_complex_cpp_type(&element_type) && !is_enum_class(ctx, &element_type) {
+        if is_complex_cpp_type(&element_type) && !is_enum_class(ctx, &element_type) && *element_type != ast::CppType::Bytes {
             format!("i->clone()")
         } else {
             format!("*i")
@@ -147,7 +187,8 @@ fn codegen_clone_union_case(ctx: &Context, c: &ast::Class, f: &ast::Field) -> St
 
     let conversion =
         match f.cpp_type() {
-            ast::CppType::String => format!("this->#AS_CONVERSION().clone()"),
+            ast::CppType::String => format!("std::string(this->#AS_CONVERSION())"),
+            ast::CppType::Bytes => format!("std::vector<uint8_t>(this->#AS_CONVERSION())"),
             // NOTE: In this case the vector is cloned earlier with the variable name the same as the field name.
             ast::CppType::Vector(_) => format!("std::move({})", f.name().to_lower_camel_case(&[])),
             ast::CppType::RefId(_) =>
@@ -279,9 +320,9 @@ fn codegen_constructors(ctx: &Context, c: &ast::Class) -> Vec<String> {
 
     match c.union() {
         Some(u) => {
-            for field in u.fields() {
+            for field in union_fields_with_distinct_types(u) {
                 let mut fields = c.fields().clone();
-                fields.push(ast::Field::new(ast::Name::from("whichData"), field.cpp_type().clone()));
+                fields.push(ast::Field::new(ast::Name::from("whichData"), field.cpp_type().clone(), None));
                 ret.push(codegen_constructor(ctx, c, &fields));
             }
         }
@@ -413,10 +454,59 @@ fn codegen_enum(_ctx: &Context, _c: &ast::EnumClass) -> Vec<String> {
     vec!()
 }
 
+///
+/// The server and client methods that make RPC calls are generated with the serde code.
+///
+fn codegen_interface(ctx: &Context, i: &ast::Interface) -> Vec<String> {
+    let mut defs = vec!();
+    for inner_type in i.inner_types() {
+        defs.extend(codegen_complex_type_def(&ctx.with_child_namespace(i.name()), inner_type));
+    }
+
+    let interface = ctx.current_namespace().with_appended(i.name()).to_string();
+    let capnp_interface = ctx.capnp_names().get(i.id()).unwrap().to_string();
+    defs.push(
+        indoc!(
+            "#INTERFACE::Server::Server(std::unique_ptr<#INTERFACE> impl)
+                : _impl(std::move(impl)) {
+            }"
+        )
+        .replace("#INTERFACE", &interface)
+    );
+    defs.push(
+        indoc!(
+            "#INTERFACE::Client::Client(#CAPNP_INTERFACE::Client client)
+                : _client(std::move(client)) {
+            }"
+        )
+        .replace("#INTERFACE", &interface)
+        .replace("#CAPNP_INTERFACE", &capnp_interface)
+    );
+    defs.push(
+        indoc!(
+            "#INTERFACE::Client #INTERFACE::Client::clone() const {
+                return #INTERFACE::Client(_client);
+            }"
+        )
+        .replace("#INTERFACE", &interface)
+    );
+    defs.push(
+        indoc!(
+            "#CAPNP_INTERFACE::Client #INTERFACE::Client::capnpClient() const {
+                return _client;
+            }"
+        )
+        .replace("#INTERFACE", &interface)
+        .replace("#CAPNP_INTERFACE", &capnp_interface)
+    );
+    return defs;
+}
+
 fn codegen_complex_type_def(ctx: &Context, def: &ast::ComplexTypeDef) -> Vec<String> {
     match def {
         ast::ComplexTypeDef::EnumClass(c) => codegen_enum(ctx, c),
-        ast::ComplexTypeDef::Class(c) => codegen_class(ctx, c)
+        ast::ComplexTypeDef::Class(c) => codegen_class(ctx, c),
+        ast::ComplexTypeDef::Interface(i) => codegen_interface(ctx, i)
     }
 }
 
@@ -60,6 +60,10 @@ impl Context {
             ast::ComplexTypeDef::Class(c) => {
                 self.type_info.insert(*c.id(), TypeInfo::new(c.name().clone(), fqn.with_appended(&c.name()), t.clone()));
                 c.inner_types().iter().for_each(|t| self.set_type_info_from_complex_type_def(&fqn.with_appended(c.name()), t))
+            },
+            ast::ComplexTypeDef::Interface(i) => {
+                self.type_info.insert(*i.id(), TypeInfo::new(i.name().clone(), fqn.with_appended(&i.name()), t.clone()));
+                i.inner_types().iter().for_each(|t| self.set_type_info_from_complex_type_def(&fqn.with_appended(i.name()), t))
             }
         }
     }
@@ -83,7 +87,7 @@ impl Context {
         match self.type_info.get(&id) {
             Some(info) => info.fqn().to_string(),
             None => {
-                println!("WARNING: Unable to resolve reference for id: {}", id);
+                eprintln!("WARN: Unable to resolve reference for id: {}", id);
                 format!("ref<{}>", id)
             }
         }
@@ -102,18 +106,71 @@ fn is_enum_class(ctx: &Context, t: &ast::CppType) -> bool {
     }
 }
 
+fn is_interface(ctx: &Context, t: &ast::CppType) -> bool {
+    match t {
+        ast::CppType::RefId(id) => {
+            match ctx.type_info().get(id).unwrap().cpp_type() {
+                ast::ComplexTypeDef::Interface(_) => true,
+                _ => false
+            }
+        },
+        _ => false
+    }
+}
+
 fn is_complex_cpp_type(t: &ast::CppType) -> bool {
     match t {
         ast::CppType::String => true,
+        ast::CppType::Bytes => true,
         ast::CppType::Vector(_) => true,
         ast::CppType::RefId(_) => true,
         _ => false
     }
 }
 
+///
+/// The union fields that get a constructor of their own. Fields of the same type share one,
+/// which picks the variant alternative from `which`.
+///
+fn union_fields_with_distinct_types(u: &ast::UnnamedUnion) -> Vec<&ast::Field> {
+    let mut fields: Vec<&ast::Field> = vec!();
+    for field in u.fields() {
+        if !fields.iter().any(|f| f.cpp_type() == field.cpp_type()) {
+            fields.push(field);
+        }
+    }
+    fields
+}
+
+///
+/// The methods of an interface followed by those it inherits, which its server has to dispatch.
+/// Interfaces reached through more than one superclass are only visited once.
+///
+fn interface_methods_with_inherited<'a>(ctx: &'a Context, i: &'a ast::Interface) -> Vec<&'a ast::Method> {
+    let mut visited = vec!(*i.id());
+    let mut pending = vec!(i);
+    let mut methods = vec!();
+    while !pending.is_empty() {
+        let interface = pending.remove(0);
+        methods.extend(interface.methods().iter());
+        for superclass in interface.superclasses() {
+            if visited.contains(superclass) {
+                continue;
+            }
+            visited.push(*superclass);
+            match ctx.type_info().get(superclass).map(|info| info.cpp_type()) {
+                Some(ast::ComplexTypeDef::Interface(s)) => pending.push(s),
+                _ => eprintln!("WARN: Unable to resolve superclass {} of interface {}", superclass, i.name().to_string())
+            }
+        }
+    }
+    methods
+}
+
 fn codegen_cpp_type(ctx: &Context, t: &ast::CppType) -> String {
     match t {
-        ast::CppType::Void => String::from("void"),
+        // Void fields and union members hold no data, but still need a type that can be stored.
+        ast::CppType::Void => String::from("std::monostate"),
         ast::CppType::Bool => String::from("bool"),
         ast::CppType::Char => String::from("char"),
         ast::CppType::Short => String::from("short"),
@@ -126,7 +183,10 @@ fn codegen_cpp_type(ctx: &Context, t: &ast::CppType) -> String {
         ast::CppType::Float => String::from("float"),
         ast::CppType::Double => String::from("double"),
         ast::CppType::String => String::from("std::string"),
+        ast::CppType::Bytes => String::from("std::vector<uint8_t>"),
         ast::CppType::Vector(t) => format!("std::vector<{}>", codegen_cpp_type(ctx, &*t)),
+        // Fields of an interface's type hold a client for it.
+        ast::CppType::RefId(id) if is_interface(ctx, t) => format!("{}::Client", ctx.resolve_full_name(*id)),
         ast::CppType::RefId(id) => format!("{}", ctx.resolve_full_name(*id).to_string())
     }
 }
@@ -149,6 +209,16 @@ fn codegen_type_as_rvalue_ref_if_complex(ctx: &Context, t: &ast::CppType) -> Str
     }
 }
 
+///
+/// Renders a schema doc comment as a Doxygen comment to be placed directly above a declaration.
+///
+fn codegen_doc_comment(doc_comment: &Option<String>) -> String {
+    match doc_comment {
+        Some(doc) => parser::ast::doc_comment_lines(doc, "///").iter().map(|line| line.clone() + "\n").collect(),
+        None => String::new()
+    }
+}
+
 fn codegen_import(import: &ast::Import) -> String {
     format!("#include \"{}\"", import.text())
 }
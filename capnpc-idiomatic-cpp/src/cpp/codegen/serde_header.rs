@@ -10,7 +10,8 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> Vec<String> {
         let child_defs =
             match def {
                 ast::ComplexTypeDef::EnumClass(child) => codegen_enum(&ctx.with_child_namespace(c.name()), child),
-                ast::ComplexTypeDef::Class(child) => codegen_class(&ctx.with_child_namespace(c.name()), child)
+                ast::ComplexTypeDef::Class(child) => codegen_class(&ctx.with_child_namespace(c.name()), child),
+                ast::ComplexTypeDef::Interface(child) => codegen_interface(&ctx.with_child_namespace(c.name()), child)
             };
 
         defs.extend(child_defs);
@@ -29,6 +30,24 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> Vec<String> {
     return defs;
 }
 
+///
+/// Clients are passed as capabilities, so only the param and result structs need conversions.
+///
+fn codegen_interface(ctx: &Context, i: &ast::Interface) -> Vec<String> {
+    let mut defs = vec!();
+    for def in i.inner_types() {
+        let child_defs =
+            match def {
+                ast::ComplexTypeDef::EnumClass(child) => codegen_enum(&ctx.with_child_namespace(i.name()), child),
+                ast::ComplexTypeDef::Class(child) => codegen_class(&ctx.with_child_namespace(i.name()), child),
+                ast::ComplexTypeDef::Interface(child) => codegen_interface(&ctx.with_child_namespace(i.name()), child)
+            };
+
+        defs.extend(child_defs);
+    }
+    return defs;
+}
+
 fn codegen_enum(ctx: &Context, e: &ast::EnumClass) -> Vec<String> {
     if e.name().to_string() == "Which" {
         return vec!();
@@ -66,7 +85,8 @@ fn codegen_namespace_contents(ctx: &Context, namespace: &ast::Namespace) -> Vec<
         let child_defs =
             match def {
                 ast::ComplexTypeDef::EnumClass(c) => codegen_enum(ctx, c),
-                ast::ComplexTypeDef::Class(c) => codegen_class(ctx, c)
+                ast::ComplexTypeDef::Class(c) => codegen_class(ctx, c),
+                ast::ComplexTypeDef::Interface(i) => codegen_interface(ctx, i)
             };
 
         defs.extend(child_defs);
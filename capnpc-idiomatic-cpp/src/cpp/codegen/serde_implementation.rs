@@ -7,6 +7,7 @@ use super::*;
  *   #GET_FIELD_METHOD
  *   #SET_FIELD_METHOD
  *   #INIT_FIELD_METHOD
+ *   #CAPNP_GET_FIELD_METHOD
  */
 fn generic_field_setting_code(ctx: &Context, f: &ast::Field) -> String {
     match f.cpp_type() {
@@ -27,30 +28,59 @@ fn generic_field_setting_code(ctx: &Context, f: &ast::Field) -> String {
                             element_list.set(i, serialize(src.#GET_FIELD_METHOD()[i]));
                         }
                     }")
+                } else if let ast::ComplexTypeDef::Interface(_) = ctx.type_info().get(&id).unwrap().cpp_type() {
+                    indoc!("{
+                        auto element_list = builder.#INIT_FIELD_METHOD(src.#GET_FIELD_METHOD().size());
+                        for (unsigned int i = 0; i < src.#GET_FIELD_METHOD().size(); i++) {
+                            element_list.set(i, src.#GET_FIELD_METHOD()[i].capnpClient());
+                        }
+                    }")
                 } else {
                     complex_object_serialization_code
                 }
+            } else if let ast::CppType::Bytes = **t {
+                indoc!("{
+                    auto element_list = builder.#INIT_FIELD_METHOD(src.#GET_FIELD_METHOD().size());
+                    for (unsigned int i = 0; i < src.#GET_FIELD_METHOD().size(); i++) {
+                        const auto& element = src.#GET_FIELD_METHOD()[i];
+                        element_list.set(i, kj::arrayPtr(element.data(), element.size()));
+                    }
+                }")
             } else {
                 complex_object_serialization_code
             }
         },
+        // The bytes are copied into the message, so a view over the vector is enough.
+        ast::CppType::Bytes => indoc!("{
+            const auto& bytes = src.#GET_FIELD_METHOD();
+            builder.#SET_FIELD_METHOD(kj::arrayPtr(bytes.data(), bytes.size()));
+        }"),
         ast::CppType::RefId(id) => {
             let type_info = ctx.type_info().get(id).unwrap();
             match type_info.cpp_type() {
                 ast::ComplexTypeDef::EnumClass(_) => indoc!("builder.#SET_FIELD_METHOD(serialize(src.#GET_FIELD_METHOD()));"),
-                ast::ComplexTypeDef::Class(_) => indoc!("serialize(builder.#INIT_FIELD_METHOD(), src.#GET_FIELD_METHOD());")
+                // A group lives in its parent's struct, so there's nothing to initialize, unless
+                // it's a union member (see codegen_union_field_setter).
+                ast::ComplexTypeDef::Class(c) if *c.is_group() => indoc!("serialize(builder.#CAPNP_GET_FIELD_METHOD(), src.#GET_FIELD_METHOD());"),
+                ast::ComplexTypeDef::Class(_) => indoc!("serialize(builder.#INIT_FIELD_METHOD(), src.#GET_FIELD_METHOD());"),
+                // The capability is shared with the message rather than serialized.
+                ast::ComplexTypeDef::Interface(_) => indoc!("builder.#SET_FIELD_METHOD(src.#GET_FIELD_METHOD().capnpClient());")
             }
         },
+        ast::CppType::Void => indoc!("builder.#SET_FIELD_METHOD();"),
         _ => indoc!("builder.#SET_FIELD_METHOD(src.#GET_FIELD_METHOD());")
     }.to_string()
 }
 
 fn codegen_union_field_setter(ctx: &Context, f: &ast::Field, idiomatic_class: &String) -> String {
+    // Only initX() sets the discriminant (and clears the previous member's data), so a group
+    // in a union is initialized rather than fetched like other groups are.
     let setting_code =
         generic_field_setting_code(ctx, f)
         .replace("#GET_FIELD_METHOD", &f.name().with_prepended("as").to_lower_camel_case(&[]))
         .replace("#SET_FIELD_METHOD", &f.name().with_prepended("set").to_lower_camel_case(&[]))
-        .replace("#INIT_FIELD_METHOD", &f.name().with_prepended("init").to_lower_camel_case(&[]));
+        .replace("#INIT_FIELD_METHOD", &f.name().with_prepended("init").to_lower_camel_case(&[]))
+        .replace("#CAPNP_GET_FIELD_METHOD", &f.name().with_prepended("init").to_lower_camel_case(&[]));
 
     indoc!(
         "case #CASE: {
@@ -76,11 +106,11 @@ fn codegen_union_field_constructor(ctx: &Context, c: &ast::Class, f: &ast::Field
         &mut c.fields()
             .iter()
             .filter(|f| f.name().to_string() != "which")
-            .map(|f| codegen_field_getter(f))
+            .map(|f| codegen_field_getter(ctx, f))
             .collect::<Vec<String>>()
     );
     constructor_args.push(format!("{}::Which::{}", &idiomatic_class, &f.name().to_upper_camel_case(&[]))); // #IDIOMATIC_ENUMERANT (i.e. "which")
-    constructor_args.push(codegen_field_getter(f));
+    constructor_args.push(codegen_field_getter(ctx, f));
 
     stages.push(
         indoc!(
@@ -152,14 +182,18 @@ fn codegen_field_setter(ctx: &Context, f: &ast::Field) -> String {
     .replace("#GET_FIELD_METHOD", &f.name().to_lower_camel_case(&[]))
     .replace("#SET_FIELD_METHOD", &f.name().with_prepended("set").to_lower_camel_case(&[]))
     .replace("#INIT_FIELD_METHOD", &f.name().with_prepended("init").to_lower_camel_case(&[]))
+    .replace("#CAPNP_GET_FIELD_METHOD", &f.name().with_prepended("get").to_lower_camel_case(&[]))
 }
 
-fn codegen_vector_field_element_deserialization(f: &ast::Field, element_type: &ast::CppType) -> String {
+fn codegen_vector_field_element_deserialization(ctx: &Context, f: &ast::Field, element_type: &ast::CppType) -> String {
     match element_type {
         ast::CppType::Vector(_) => panic!("Unsupported: vector of vectors."),
+        ast::CppType::RefId(_) if is_interface(ctx, element_type) => indoc!("#TYPE(*i)"),
         ast::CppType::RefId(_) => indoc!("deserialize(*i)"),
+        ast::CppType::Bytes => indoc!("std::vector<uint8_t>((*i).begin(), (*i).end())"),
         _ => indoc!("*i")
     }
+    .replace("#TYPE", &codegen_cpp_type(ctx, element_type))
     .replace("#FIELD_NAME", &f.name().to_string())
     .replace("#GET_FIELD_METHOD", &f.name().with_prepended("get").to_lower_camel_case(&[]))
 }
@@ -174,15 +208,19 @@ fn codegen_vector_field_deserialization(ctx: &Context, f: &ast::Field, element_t
     .replace("#NAME", &f.name().to_string())
     .replace("#TYPE", &codegen_cpp_type(ctx, element_type))
     .replace("#GET_FIELD_METHOD", &f.name().with_prepended("get").to_lower_camel_case(&[]))
-    .replace("#DESERIALIZE_INNER_TYPE", &codegen_vector_field_element_deserialization(f, element_type))
+    .replace("#DESERIALIZE_INNER_TYPE", &codegen_vector_field_element_deserialization(ctx, f, element_type))
 }
 
-fn codegen_field_getter(f: &ast::Field) -> String {
+fn codegen_field_getter(ctx: &Context, f: &ast::Field) -> String {
     match f.cpp_type() {
         ast::CppType::Vector(_) => indoc!("std::move(#FIELD_NAME)"),
+        ast::CppType::RefId(_) if is_interface(ctx, f.cpp_type()) => indoc!("#TYPE(src.#GET_FIELD_METHOD())"),
         ast::CppType::RefId(_) => indoc!("deserialize(src.#GET_FIELD_METHOD())"),
+        ast::CppType::Void => indoc!("std::monostate()"),
+        ast::CppType::Bytes => indoc!("std::vector<uint8_t>(src.#GET_FIELD_METHOD().begin(), src.#GET_FIELD_METHOD().end())"),
         _ => indoc!("src.#GET_FIELD_METHOD()")
     }
+    .replace("#TYPE", &codegen_cpp_type(ctx, f.cpp_type()))
     .replace("#FIELD_NAME", &f.name().to_string())
     .replace("#GET_FIELD_METHOD", &f.name().with_prepended("get").to_lower_camel_case(&[]))
 }
@@ -239,7 +277,7 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> Vec<String> {
                     "#FIELDS",
                     &c.fields()
                         .iter()
-                        .map(|f| codegen_field_getter(f))
+                        .map(|f| codegen_field_getter(ctx, f))
                         .collect::<Vec<String>>()
                         .join(",\n")
                         .replace("\n", "\n    ")
@@ -252,7 +290,8 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> Vec<String> {
         let child_defs =
             match def {
                 ast::ComplexTypeDef::EnumClass(child) => codegen_enum(&ctx.with_child_namespace(c.name()), child),
-                ast::ComplexTypeDef::Class(child) => codegen_class(&ctx.with_child_namespace(c.name()), child)
+                ast::ComplexTypeDef::Class(child) => codegen_class(&ctx.with_child_namespace(c.name()), child),
+                ast::ComplexTypeDef::Interface(child) => codegen_interface(&ctx.with_child_namespace(c.name()), child)
             };
 
         defs.extend(child_defs);
@@ -285,6 +324,100 @@ fn codegen_class(ctx: &Context, c: &ast::Class) -> Vec<String> {
     defs
 }
 
+fn codegen_interface(ctx: &Context, i: &ast::Interface) -> Vec<String> {
+    let mut defs = vec!();
+    for def in i.inner_types() {
+        let child_defs =
+            match def {
+                ast::ComplexTypeDef::EnumClass(child) => codegen_enum(&ctx.with_child_namespace(i.name()), child),
+                ast::ComplexTypeDef::Class(child) => codegen_class(&ctx.with_child_namespace(i.name()), child),
+                ast::ComplexTypeDef::Interface(child) => codegen_interface(&ctx.with_child_namespace(i.name()), child)
+            };
+
+        defs.extend(child_defs);
+    }
+    defs
+}
+
+fn codegen_server_method(ctx: &Context, interface: &String, m: &ast::Method) -> String {
+    indoc!("
+    kj::Promise<void> #INTERFACE::Server::#METHOD(#CONTEXT context) {
+        return _impl->#METHOD(Serde::deserialize(context.getParams())).then([context](#RESULTS results) mutable {
+            Serde::serialize(context.getResults(), results);
+        });
+    }")
+    .replace("#INTERFACE", interface)
+    .replace("#RESULTS", &ctx.resolve_full_name(*m.results()))
+    .replace("#METHOD", &m.name().to_lower_camel_case(&[]))
+    .replace("#CONTEXT", &format!("{}Context", m.name().to_upper_camel_case(&[])))
+}
+
+fn codegen_client_method(ctx: &Context, interface: &String, m: &ast::Method) -> String {
+    indoc!("
+    kj::Promise<#RESULTS> #INTERFACE::Client::#METHOD(#PARAMS&& params) {
+        auto request = _client.#REQUEST();
+        Serde::serialize(request, params);
+        return request.send().then([](capnp::Response<#CAPNP_RESULTS> response) {
+            return Serde::deserialize(response);
+        });
+    }")
+    .replace("#INTERFACE", interface)
+    .replace("#RESULTS", &ctx.resolve_full_name(*m.results()))
+    .replace("#PARAMS", &ctx.resolve_full_name(*m.params()))
+    .replace("#CAPNP_RESULTS", &ctx.capnp_names().get(m.results()).unwrap().to_string())
+    .replace("#METHOD", &m.name().to_lower_camel_case(&[]))
+    .replace("#REQUEST", &format!("{}Request", m.name().to_lower_camel_case(&[])))
+}
+
+///
+/// The methods of interface servers and clients, which convert between the idiomatic param and
+/// result classes and capnp messages. They're outside of `Serde` since they belong to the classes.
+///
+fn codegen_rpc_definitions(ctx: &Context, def: &ast::ComplexTypeDef) -> Vec<String> {
+    let mut defs = vec!();
+    match def {
+        ast::ComplexTypeDef::EnumClass(_) => {},
+        ast::ComplexTypeDef::Class(c) => {
+            for inner_type in c.inner_types() {
+                defs.extend(codegen_rpc_definitions(&ctx.with_child_namespace(c.name()), inner_type));
+            }
+        },
+        ast::ComplexTypeDef::Interface(i) => {
+            for inner_type in i.inner_types() {
+                defs.extend(codegen_rpc_definitions(&ctx.with_child_namespace(i.name()), inner_type));
+            }
+
+            let interface = ctx.current_namespace().with_appended(i.name()).to_string();
+            for m in interface_methods_with_inherited(ctx, i) {
+                defs.push(codegen_server_method(ctx, &interface, m));
+                defs.push(codegen_client_method(ctx, &interface, m));
+            }
+        }
+    }
+    defs
+}
+
+fn codegen_namespace_rpc_definitions(ctx: &Context, namespace: &ast::Namespace) -> Vec<String> {
+    let mut defs = vec!();
+
+    for (child_namespace_name, child_namespace) in namespace.namespaces() {
+        defs.extend(
+            codegen_namespace_rpc_definitions(
+                &ctx.with_child_namespace(child_namespace_name),
+                child_namespace
+            )
+        );
+    }
+
+    for def in namespace.defs() {
+        defs.extend(codegen_rpc_definitions(ctx, def));
+    }
+
+    defs.sort();
+
+    return defs;
+}
+
 fn codegen_enumerant_serialization(enumerant: &ast::Name, idiomatic_enum: &String, capnp_enum: &String) -> String {
     String::from("case #IDIOMATIC_CASE: return #CAPNP_CASE;")
         .replace("#IDIOMATIC_CASE", &format!("{}::{}", idiomatic_enum, enumerant.to_upper_camel_case(&[])))
@@ -321,7 +454,7 @@ fn codegen_enum(ctx: &Context, e: &ast::EnumClass) -> Vec<String> {
                 "#CASES",
                 &e.enumerants()
                     .iter()
-                    .map(|e| codegen_enumerant_serialization(e, &idiomatic_enum, &capnp_enum))
+                    .map(|e| codegen_enumerant_serialization(e.name(), &idiomatic_enum, &capnp_enum))
                     .collect::<Vec<String>>()
                     .join("\n")
                     .replace("\n", "\n        ")
@@ -337,7 +470,7 @@ fn codegen_enum(ctx: &Context, e: &ast::EnumClass) -> Vec<String> {
                 "#CASES",
                 &e.enumerants()
                     .iter()
-                    .map(|e| codegen_enumerant_deserialization(e, &idiomatic_enum, &capnp_enum))
+                    .map(|e| codegen_enumerant_deserialization(e.name(), &idiomatic_enum, &capnp_enum))
                     .collect::<Vec<String>>()
                     .join("\n")
                     .replace("\n", "\n        ")
@@ -361,7 +494,8 @@ fn codegen_namespace_contents(ctx: &Context, namespace: &ast::Namespace) -> Vec<
         let child_defs =
             match def {
                 ast::ComplexTypeDef::EnumClass(c) => codegen_enum(ctx, c),
-                ast::ComplexTypeDef::Class(c) => codegen_class(ctx, c)
+                ast::ComplexTypeDef::Class(c) => codegen_class(ctx, c),
+                ast::ComplexTypeDef::Interface(i) => codegen_interface(ctx, i)
             };
 
         defs.extend(child_defs);
@@ -384,7 +518,7 @@ pub fn codegen_serde_cpp_file(ctx: &Context, compilation_unit: &ast::Compilation
         
         namespace Serde {
         #DEFINITIONS
-        }"
+        }#RPC_DEFINITIONS"
     )
     .replace(
         "#IMPORTS",
@@ -398,6 +532,13 @@ pub fn codegen_serde_cpp_file(ctx: &Context, compilation_unit: &ast::Compilation
         "#DEFINITIONS",
         &codegen_namespace_contents(ctx, &compilation_unit.namespace()).join("\n\n")
     )
+    .replace(
+        "#RPC_DEFINITIONS",
+        &codegen_namespace_rpc_definitions(ctx, &compilation_unit.namespace())
+            .iter()
+            .map(|def| format!("\n\n{}", def))
+            .collect::<String>()
+    )
     .replace("    ", "\t");
 
     return (path, code);
@@ -4,18 +4,286 @@ mod codegen;
 
 use std::path::Path;
 
-pub fn code_gen(out_dir: &Path, cgr: &crate::parser::ast::CodeGeneratorRequest) -> codegen::Code {
-    // Use this to view the cgr for debugging.
-    println!("{:#?}", cgr);
-
+pub fn code_gen(out_dir: &Path, cgr: &crate::parser::ast::CodeGeneratorRequest) -> crate::parser::Result<codegen::Code> {
     let mut translation_ctx = translator::Context::new(&out_dir.to_path_buf());
-    translator::build_translation_context(&mut translation_ctx, &cgr);
-    let ast0 = translator::translate(&translation_ctx, cgr);
-    println!("{:#?}", ast0);
-    
+    translator::build_translation_context(&mut translation_ctx, &cgr)?;
+    let ast0 = translator::translate(&translation_ctx, cgr)?;
+
     let codegen_ctx = codegen::Context::new(out_dir.to_path_buf(), translation_ctx.capnp_names());
-    let code = codegen::codegen(&codegen_ctx, ast0);
-    println!("{:#?}", code);
+    return Ok(codegen::codegen(&codegen_ctx, ast0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{compile_source, CompileOptions};
+
+    const PREAMBLE: &str = r#"@0xe87e0317861d3aab;
+annotation namespace @0xb9c6f99ebf805f2c (file) :Text;
+annotation idiomaticCppNamespace @0xc8f4a5b2e3d1f001 (file) :Text;
+$namespace("app");
+$idiomaticCppNamespace("app::idiomatic");
+"#;
+
+    fn generate(schema: &str) -> codegen::Code {
+        let cgr = compile_source("test.capnp", &format!("{}{}", PREAMBLE, schema), &CompileOptions::new()).unwrap();
+        code_gen(Path::new("out"), &cgr).unwrap()
+    }
+
+    fn file<'a>(code: &'a codegen::Code, name: &str) -> &'a str {
+        code.files().get(&Path::new("out").join(name)).unwrap()
+    }
+
+    ///
+    /// Whether the line right after `doc` declares `declaration`, whatever their indentation.
+    ///
+    fn documents(header: &str, doc: &str, declaration: &str) -> bool {
+        let lines: Vec<&str> = header.lines().map(str::trim).collect();
+        lines.windows(2).any(|pair| pair[0] == doc && pair[1].starts_with(declaration))
+    }
+
+    #[test]
+    fn test_namespace_annotation_errors() {
+        let schema = "@0xe87e0317861d3aab;\nannotation idiomaticCppNamespace @0xc8f4a5b2e3d1f001 (file) :UInt32;\n$idiomaticCppNamespace(3);\n";
+        let cgr = compile_source("test.capnp", schema, &CompileOptions::new()).unwrap();
+        assert!(code_gen(Path::new("out"), &cgr).is_err());
+
+        // A schema can declare the annotation itself, with its own id.
+        let schema = "@0xe87e0317861d3aab;\nannotation namespace @0xb9c6f99ebf805f2c (file) :Text;\n$namespace(\"app\");\nannotation idiomaticCppNamespace @0xc8f4a5b2e3d1f002 (file) :Text;\n$idiomaticCppNamespace(\"app::idiomatic\");\nstruct Foo {}\n";
+        let cgr = compile_source("test.capnp", schema, &CompileOptions::new()).unwrap();
+        assert!(file(&code_gen(Path::new("out"), &cgr).unwrap(), "lib.hpp").contains("class Foo"));
+    }
+
+    #[test]
+    fn test_namespace_annotation_from_other_file() {
+        let dir = std::env::temp_dir().join(format!("capnpc-idiomatic-cpp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tags.capnp"), "@0xb2c3d4e5f6071829;\nannotation idiomaticCppNamespace @0xc8f4a5b2e3d1f002 (file) :Text;\n").unwrap();
+        let schema = "@0xe87e0317861d3aab;\nusing Tags = import \"tags.capnp\";\nannotation namespace @0xb9c6f99ebf805f2c (file) :Text;\n$namespace(\"app\");\n$Tags.idiomaticCppNamespace(\"app::idiomatic\");\nstruct Foo {}\n";
+        let cgr = compile_source(dir.join("test.capnp").to_str().unwrap(), schema, &CompileOptions::new());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Neither idiomatic.capnp's annotation nor one declared in the file, so it's an error
+        // rather than a file without classes.
+        let error = code_gen(Path::new("out"), &cgr.unwrap()).err().unwrap();
+        assert!(error.to_string().contains("idiomaticCppNamespace is declared in"));
+    }
+
+    #[test]
+    fn test_data_fields() {
+        let code = generate(r#"
+struct Blob {
+  payload @0 :Data;
+  chunks @1 :List(Data);
+}
+"#);
+        let header = file(&code, "lib.hpp");
+        assert!(header.contains("std::vector<uint8_t> _payload;"));
+        assert!(header.contains("std::vector<std::vector<uint8_t>> _chunks;"));
+        assert!(header.contains("const std::vector<uint8_t>& payload() const;"));
+        assert!(header.contains("Blob& payload(std::vector<uint8_t>&& val);"));
+
+        let serde = file(&code, "serde.cpp");
+        assert!(serde.contains("std::vector<uint8_t>(src.getPayload().begin(), src.getPayload().end())"));
+        assert!(serde.contains("chunks.push_back(std::vector<uint8_t>((*i).begin(), (*i).end()));"));
+        assert!(serde.contains("builder.setPayload(kj::arrayPtr(bytes.data(), bytes.size()));"));
+        assert!(serde.contains("element_list.set(i, kj::arrayPtr(element.data(), element.size()));"));
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let code = generate(r#"
+enum Color {  # A color.
+  red @0;  # Like a tomato.
+  green @1;
+}
+
+struct Shape {  # A shape.
+  size @0 :UInt32;  # In pixels.
+  color @1 :Color;
+}
+
+interface Canvas {
+  draw @0 (shape :Shape) -> ();  # Draws a shape.
+}
+"#);
+        let header = file(&code, "lib.hpp");
+        assert!(documents(header, "/// A color.", "enum class Color {"));
+        assert!(documents(header, "/// Like a tomato.", "Red"));
+        assert!(documents(header, "/// A shape.", "class Shape {"));
+        assert!(documents(header, "/// In pixels.", "const unsigned int size() const;"));
+        assert!(documents(header, "/// Draws a shape.", "virtual kj::Promise<"));
+        assert!(!header.contains("/// \n"));
+    }
+
+    #[test]
+    fn test_groups() {
+        let code = generate(r#"
+struct Shape {
+  extra :group {
+    visible @0 :Bool;
+  }
+  union {
+    circle @1 :Float64;
+    rect :group {
+      width @2 :Float64;
+      height @3 :Float64;
+    }
+  }
+}
+"#);
+        let header = file(&code, "lib.hpp");
+        assert!(header.contains("class Extra {"));
+        assert!(header.contains("class Rect {"));
+        assert!(header.contains("Extra _extra;"));
+
+        let serde = file(&code, "serde.cpp");
+        assert!(serde.contains("serialize(builder.getExtra(), src.extra());"));
+        assert!(serde.contains("deserialize(src.getExtra())"));
+        assert!(serde.contains("deserialize(src.getRect())"));
+    }
+
+    #[test]
+    fn test_group_in_union_sets_which() {
+        let code = generate(r#"
+struct Shape {
+  union {
+    circle @0 :Float64;
+    rect :group {
+      width @1 :Float64;
+    }
+  }
+}
+"#);
+        // Only initRect() switches which() to RECT; getRect() would leave it at CIRCLE.
+        let serde = file(&code, "serde.cpp");
+        assert!(serde.contains("case app::idiomatic::Shape::Which::Rect: {\n\t\t\tserialize(builder.initRect(), src.asRect());"));
+        assert!(!serde.contains("builder.getRect()"));
+    }
+
+    #[test]
+    fn test_named_unions() {
+        let code = generate(r#"
+struct Shape {
+  kind :union {
+    circle @0 :Float64;
+    label @1 :Text;
+  }
+  size :union {
+    small @2 :Void;
+    large @3 :UInt32;
+  }
+}
+"#);
+        let header = file(&code, "lib.hpp");
+        assert!(header.contains("class Kind {"));
+        assert!(header.contains("class Size {"));
+        assert!(header.contains("enum class Which {\n\t\t\tCircle,\n\t\t\tLabel\n\t\t};"));
+        assert!(header.contains("enum class Which {\n\t\t\tSmall,\n\t\t\tLarge\n\t\t};"));
+        assert!(header.contains("const std::string& asLabel() const;"));
+        assert!(header.contains("const app::idiomatic::Shape::Kind& kind() const;"));
+
+        let serde = file(&code, "serde.cpp");
+        assert!(serde.contains("case app::Shape::Kind::Which::CIRCLE: {"));
+        assert!(serde.contains("case app::idiomatic::Shape::Size::Which::Small: {\n\t\t\tbuilder.setSmall();"));
+        assert!(serde.contains("serialize(builder.getKind(), src.kind());"));
+    }
+
+    #[test]
+    fn test_constants() {
+        let code = generate(r#"
+enum Color { red @0; green @1; }
+enum Empty {}
+
+struct Shape {
+  color @0 :Color = green;
+  empty @1 :Empty;
+  size @2 :Int64;
+  const sides :UInt32 = 4;
+}
+
+const answer :Int32 = 42;
+const smallest :Int64 = -9223372036854775808;
+const largest :UInt64 = 18446744073709551615;
+const hello :Text = "hello";
+const favorite :Color = red;
+const bytes :Data = 0x"01 ff";
+const origin :Shape = (size = 3);
+"#);
+        let header = file(&code, "lib.hpp");
+        assert!(header.contains("static constexpr unsigned int sides = 4U;"));
+        assert!(header.contains("inline constexpr int answer = 42;"));
+        assert!(header.contains("inline constexpr long smallest = (-9223372036854775807LL - 1);"));
+        assert!(header.contains("inline constexpr unsigned long largest = 18446744073709551615ULL;"));
+        assert!(header.contains("inline const std::string hello = \"hello\";"));
+        assert!(header.contains("inline constexpr app::idiomatic::Color favorite = app::idiomatic::Color::Red;"));
+        assert!(header.contains("inline const std::vector<uint8_t> bytes = std::vector<uint8_t>{1, 255};"));
+        // An enum without enumerants still gets a value of its type.
+        assert!(header.contains(
+            "inline const app::idiomatic::Shape origin = app::idiomatic::Shape(app::idiomatic::Color::Green, static_cast<app::idiomatic::Empty>(0), 3LL);"
+        ));
+    }
+
+    #[test]
+    fn test_interfaces() {
+        let code = generate(r#"
+interface Resettable {
+  reset @0 () -> ();
+}
+
+interface Drawer extends(Resettable) {
+  draw @0 (scale :Float32) -> (done :Bool);
+}
+"#);
+        let header = file(&code, "lib.hpp");
+        assert!(header.contains("class Drawer : public virtual app::idiomatic::Resettable {"));
+        assert!(header.contains(
+            "virtual kj::Promise<app::idiomatic::Drawer::DrawResults> draw(app::idiomatic::Drawer::DrawParams&& params) = 0;"
+        ));
+        assert!(header.contains("class Server final : public app::Drawer::Server {"));
+        assert!(header.contains("kj::Promise<void> reset(ResetContext context) override;"));
+        assert!(header.contains("kj::Promise<app::idiomatic::Drawer::DrawResults> draw(app::idiomatic::Drawer::DrawParams&& params);"));
+
+        let serde = file(&code, "serde.cpp");
+        assert!(serde.contains(
+            "return _impl->draw(Serde::deserialize(context.getParams())).then([context](app::idiomatic::Drawer::DrawResults results) mutable {"
+        ));
+        assert!(serde.contains("return request.send().then([](capnp::Response<app::Drawer::DrawResults> response) {"));
+    }
+
+    #[test]
+    fn test_interface_constants() {
+        let code = generate(r#"
+struct Shape {
+  size @0 :Int64;
+}
+
+interface Drawer {
+  const scale :Float32 = 2;
+  const unit :Shape = (size = 1);
+  draw @0 () -> ();
+}
+"#);
+        let header = file(&code, "lib.hpp");
+        assert!(header.contains("static constexpr float scale = 2.0f;"));
+        assert!(header.contains("static const app::idiomatic::Shape unit;"));
+        assert!(header.contains("inline const app::idiomatic::Shape Drawer::unit = app::idiomatic::Shape(1LL);"));
+    }
 
-    return code;
-}
\ No newline at end of file
+    #[test]
+    fn test_self_referencing_interface() {
+        let code = generate(r#"
+interface Foo {
+  get @0 () -> (foo :Foo);
+}
+"#);
+        // GetResults holds a Foo::Client, so the Client has to be defined before it.
+        let header = file(&code, "lib.hpp");
+        let get_results_declaration = header.find("class GetResults;").unwrap();
+        let client_definition = header.find("class Client {").unwrap();
+        let get_results_definition = header.find("class GetResults {").unwrap();
+        assert!(get_results_declaration < client_definition);
+        assert!(client_definition < get_results_definition);
+        assert!(header.contains("app::idiomatic::Foo::Client _foo;"));
+    }
+}
@@ -1,35 +1,30 @@
 use crate::getset::{Getters, CopyGetters, MutGetters, Setters};
 use std::collections::HashMap;
 use std::path::PathBuf;
-use multimap::MultiMap;
 
 use crate::cpp::ast::*;
 use parser::ast::CodeGeneratorRequest;
 
+/// `$idiomaticCppNamespace` from idiomatic.capnp, which ships with this crate.
+pub const IDIOMATIC_CPP_NAMESPACE_ANNOTATION_ID: Id = 0xc8f4a5b2e3d1f001;
+
+pub const IDIOMATIC_CPP_NAMESPACE_ANNOTATION_NAME: &str = "idiomaticCppNamespace";
+
 #[derive(Clone, CopyGetters, Getters, MutGetters, Setters)]
 pub struct Context {
     out_dir: PathBuf,
 
-    #[getset(get_copy)]
-    namespace_annotation_id: u64,
-
-    #[getset(get_copy)]
-    name_annotation_id: u64,
-
-    #[getset(get_copy)]
-    idiomatic_namespace_annotation_id: u64,
+    #[getset(get)]
+    annotations: parser::AnnotationRegistry,
 
     #[getset(get, set)]
     namespace: FullyQualifiedName,
 
-    #[getset(get, get_mut)]
-    names: HashMap<Id, Name>,
-
-    #[getset(get, get_mut)]
-    children: MultiMap<Id, Id>,
+    #[getset(get)]
+    schema: parser::SchemaGraph,
 
-    #[getset(get, get_mut)]
-    nodes: HashMap<Id, crate::parser::ast::Node>,
+    /// See `method_struct_of`.
+    method_structs: HashMap<Id, (Id, String)>,
 
     #[get = "pub"]
     #[get_mut]
@@ -40,13 +35,10 @@ impl Context {
     pub fn new(out_dir: &PathBuf) -> Self {
         Context {
             out_dir: out_dir.clone(),
-            namespace_annotation_id: 0,
-            name_annotation_id: 0,
-            idiomatic_namespace_annotation_id: 0,
+            annotations: parser::AnnotationRegistry::default(),
             namespace: FullyQualifiedName::empty(),
-            names: HashMap::new(),
-            children: MultiMap::new(),
-            nodes: HashMap::new(),
+            schema: parser::SchemaGraph::default(),
+            method_structs: HashMap::new(),
             capnp_names: HashMap::new()
         }
     }
@@ -57,110 +49,161 @@ impl Context {
         return ctx;
     }
 
-    fn set_annotation_ids_from_file(&mut self, file: &parser::ast::Node) {
-        file.nested_nodes()
-            .iter()
-            .for_each(|n| {
-                if n.name() == &"namespace" {
-                    self.namespace_annotation_id = n.id()
-                }
-                if n.name() == &"name" {
-                    self.name_annotation_id = n.id()
-                }
-                if n.name() == &"idiomaticCppNamespace" {
-                    self.idiomatic_namespace_annotation_id = n.id()
-                }
-            });
+    fn set_annotations_from(&mut self, cgr: &CodeGeneratorRequest) {
+        self.annotations = parser::AnnotationRegistry::new(cgr);
     }
 
-    fn set_annotation_ids_from(&mut self, cgr: &CodeGeneratorRequest) {
-        cgr.nodes()
-            .iter()
-            .filter(|n| n.which() == &parser::ast::node::Which::File)
-            .for_each(|n| self.set_annotation_ids_from_file(n));
+    fn set_schema_from(&mut self, cgr: &CodeGeneratorRequest) {
+        self.schema = parser::SchemaGraph::new(cgr);
+    }
+
+    fn set_method_structs_from(&mut self, cgr: &CodeGeneratorRequest) {
+        let is_implicit = |id: Id| match self.schema.node(id) {
+            Some(node) => node.scope_id() == 0 && node.which() != &parser::ast::node::Which::File,
+            None => false
+        };
 
-        if self.namespace_annotation_id == 0 {
-            panic!("Unable to determine namespace annotation id from c++.capnp.");
+        let mut method_structs = HashMap::new();
+        for node in cgr.nodes() {
+            if let parser::ast::node::Which::Interface { methods, .. } = node.which() {
+                for method in methods {
+                    if is_implicit(method.param_struct_type()) {
+                        method_structs.entry(method.param_struct_type())
+                            .or_insert((node.id(), format!("{}Params", title_case(method.name()))));
+                    }
+                    if is_implicit(method.result_struct_type()) {
+                        method_structs.entry(method.result_struct_type())
+                            .or_insert((node.id(), format!("{}Results", title_case(method.name()))));
+                    }
+                }
+            }
         }
-        if self.name_annotation_id == 0 {
-            panic!("Unable to determine name annotation id from c++.capnp.");
+        self.method_structs = method_structs;
+    }
+
+    fn name_of(&self, id: Id) -> Name {
+        // Groups are named after their field, so their class is capitalized to keep it apart from the
+        // getter, the same way capnp does it.
+        self.capnp_name_of(id)
+    }
+
+    ///
+    /// The name capnp's C++ generator uses for a node. Groups get their field name with the
+    /// first letter capitalized, e.g. `Shape::Extra` for `extra :group`.
+    ///
+    fn capnp_name_of(&self, id: Id) -> Name {
+        if let Some((_, method_struct_name)) = self.method_struct_of(id) {
+            return Name::from(&method_struct_name);
         }
-        if self.idiomatic_namespace_annotation_id == 0 {
-            panic!("Unable to determine namespace annotation id for idiomatic c++ classes.");
+
+        let name = self.schema().name(id).expect(&format!("Unable to find name for node: {}", id));
+        if self.is_group(id) {
+            Name::from(&title_case(name))
+        } else {
+            Name::from(name)
         }
     }
 
-    fn set_names_from(&mut self, cgr: &CodeGeneratorRequest) {
-        for node in cgr.nodes() {
-            if node.which() == &crate::parser::ast::node::Which::File {
-                let name = String::from(&node.display_name()[0..node.display_name_prefix_length()-1]);
-                self.names_mut().insert(
-                    node.id(),
-                    Name::from(&name)
-                );
-            }
-
-            for nested_node in node.nested_nodes() {
-                self.names_mut().insert(nested_node.id(), Name::from(nested_node.name()));
-            }
+    ///
+    /// For the implicit param and result structs of a method, which aren't in any scope: the
+    /// interface that declares the method, and the name that capnp's C++ generator gives the
+    /// struct in it, e.g. `DrawParams`.
+    ///
+    fn method_struct_of(&self, id: Id) -> Option<(Id, String)> {
+        self.method_structs.get(&id).cloned()
+    }
 
-            self.children_mut().insert(node.scope_id(), node.id());
-            self.nodes_mut().insert(node.id(), node.clone());
+    fn capnp_fqn_of(&self, namespace: &FullyQualifiedName, id: Id) -> FullyQualifiedName {
+        match self.method_struct_of(id) {
+            Some((interface_id, name)) => self.capnp_fqn_of(namespace, interface_id).with_appended(&Name::from(&name)),
+            None => self.schema.ancestry(id)
+                .iter()
+                .filter(|ancestor| ancestor.which() != &parser::ast::node::Which::File)
+                .fold(namespace.clone(), |fqn, ancestor| fqn.with_appended(&self.capnp_name_of(ancestor.id())))
         }
     }
 
-    fn set_capnp_names_for_child_nodes(&mut self, parent_fqn: &FullyQualifiedName, node: &parser::ast::Node) {
-        let fqn =
-            if node.which() == &parser::ast::node::Which::File {
-                parent_fqn.clone()
-            } else {
-                let name = self.names().get(&node.id()).expect(&format!("Unable to find name for node: {}", node.id())).clone();
-                parent_fqn.with_appended(&name)
-            };
+    ///
+    /// The id of the `idiomaticCppNamespace` annotation for a file. Schemas written before
+    /// idiomatic.capnp existed declare the annotation themselves, so a declaration in the file
+    /// wins, then the one in idiomatic.capnp, and the id from idiomatic.capnp when neither is in
+    /// the request.
+    ///
+    fn idiomatic_namespace_annotation_id(&self, file_node: &parser::ast::Node) -> Id {
+        self.annotations().find(file_node.display_name(), IDIOMATIC_CPP_NAMESPACE_ANNOTATION_NAME)
+            .or_else(|| self.annotations().find("idiomatic.capnp", IDIOMATIC_CPP_NAMESPACE_ANNOTATION_NAME))
+            .map_or(IDIOMATIC_CPP_NAMESPACE_ANNOTATION_ID, |declaration| declaration.id())
+    }
+
+    ///
+    /// The idiomatic namespace of a file, if it has one. An annotation with the right name but
+    /// some other declaration is an error rather than a file that silently gets no classes.
+    ///
+    fn idiomatic_namespace_of(&self, file_node: &parser::ast::Node) -> parser::Result<Option<String>> {
+        let id = self.idiomatic_namespace_annotation_id(file_node);
+        let unresolved = self.annotations()
+            .applied(file_node.annotations())
+            .into_iter()
+            .find(|applied| applied.declaration().name() == IDIOMATIC_CPP_NAMESPACE_ANNOTATION_NAME && applied.declaration().id() != id);
+
+        if let Some(applied) = unresolved {
+            return Err(parser::Error::Schema(format!(
+                "{}: ${} is declared in {} with id {:#x}. Declare it in this file or import idiomatic.capnp.",
+                file_node.display_name(), IDIOMATIC_CPP_NAMESPACE_ANNOTATION_NAME, applied.declaration().file(), applied.declaration().id()
+            )));
+        }
 
-        self.capnp_names.insert(node.id(), fqn.clone());
+        self.annotations().value::<String>(file_node.annotations(), id)
+    }
 
-        println!("Capnp Name: {} {}", node.id(), self.capnp_names().get(&node.id()).unwrap().to_string());
+    fn is_group(&self, id: Id) -> bool {
+        match self.schema().node(id).map(|node| node.which()) {
+            Some(parser::ast::node::Which::Struct { is_group, .. }) => *is_group,
+            _ => false
+        }
+    }
 
-        let child_ids = self.children.get_vec(&node.id())
-            .unwrap_or(&vec!())
-            .iter()
-            .map(|it| *it)
-            .collect::<Vec<u64>>();
-
-        child_ids.iter()
-            .for_each(|child_id| {
-                let child_node = self.nodes().get(child_id).expect(&format!("Unable to find child node with id: {}", child_id)).clone();
-                self.set_capnp_names_for_child_nodes(&fqn, &child_node);
-            });
+    fn set_capnp_names_for_file(&mut self, namespace: &FullyQualifiedName, file_node: &parser::ast::Node) {
+        for node in self.schema.nodes() {
+            let scope_id = self.method_struct_of(node.id()).map_or(node.id(), |(interface_id, _)| interface_id);
+            if self.schema.file_of(scope_id).map(|file| file.id()) != Some(file_node.id()) {
+                continue;
+            }
+
+            let fqn = self.capnp_fqn_of(namespace, node.id());
+            self.capnp_names.insert(node.id(), fqn);
+        }
     }
 
-    fn set_capnp_names_from(&mut self, cgr: &CodeGeneratorRequest) {
+    fn set_capnp_names_from(&mut self, cgr: &CodeGeneratorRequest) -> parser::Result<()> {
         let files = cgr.nodes()
             .iter()
             .filter(|node| node.which() == &crate::parser::ast::node::Which::File)
             .collect::<Vec<&parser::ast::Node>>();
 
-        files.iter().for_each(|file_node| {
-            let ns_name = file_node.annotations()
-                .iter()
-                .find(|a| a.id() == self.namespace_annotation_id());
+        for file_node in files {
+            let ns_name = self.annotations().value::<String>(file_node.annotations(), parser::CPP_NAMESPACE_ANNOTATION_ID)?;
 
-            if let None = ns_name {
-                println!("WARN: Unable to find capnp namespace annotation for file: {}", file_node.display_name());
-                return;
-            }
-            
-            if let parser::ast::Value::Text(t) = ns_name.unwrap().value() {
-                let fqn = FullyQualifiedName::from(t.split("::").collect::<Vec<&str>>());
-                self.set_capnp_names_for_child_nodes(&fqn, &file_node);
-            } else {
-                panic!("Value for capnp namespace annotation was not a string.")
-            }
-        });
+            let ns_name = match ns_name {
+                Some(ns_name) => ns_name,
+                None => {
+                    eprintln!("WARN: Unable to find capnp namespace annotation for file: {}", file_node.display_name());
+                    continue;
+                }
+            };
+
+            let fqn = FullyQualifiedName::from(ns_name.split("::").collect::<Vec<&str>>());
+            self.set_capnp_names_for_file(&fqn, &file_node);
+        }
+        Ok(())
     }
 }
 
+fn title_case(name: &str) -> String {
+    let mut chars = name.chars();
+    chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
 fn translate_parser_type_to_cpp_type(pt: &parser::ast::Type) -> CppType {
     match pt {
         parser::ast::Type::Void => CppType::Void,
@@ -176,162 +219,352 @@ fn translate_parser_type_to_cpp_type(pt: &parser::ast::Type) -> CppType {
         parser::ast::Type::Float32 => CppType::Float,
         parser::ast::Type::Float64 => CppType::Double,
         parser::ast::Type::Text => CppType::String,
-        parser::ast::Type::Data => panic!("Unsupported type 'Data'"),
+        parser::ast::Type::Data => CppType::Bytes,
         parser::ast::Type::List(t) => CppType::Vector(Box::new(translate_parser_type_to_cpp_type(&*t))),
-        parser::ast::Type::Enum { type_id } => CppType::RefId(*type_id),
-        parser::ast::Type::Struct { type_id } => CppType::RefId(*type_id),
-        parser::ast::Type::Interface { .. } => panic!("Unsupported type 'Interface'"),
-        parser::ast::Type::AnyPointer => panic!("Unsupported type 'AnyPointer'")
+        parser::ast::Type::Enum { type_id, .. } => CppType::RefId(*type_id),
+        parser::ast::Type::Struct { type_id, .. } => CppType::RefId(*type_id),
+        parser::ast::Type::Interface { type_id, .. } => CppType::RefId(*type_id),
+        parser::ast::Type::AnyPointer(_) => panic!("Unsupported type 'AnyPointer'")
     }
 }
 
-fn translate_parser_field_to_cpp_field(f: &parser::ast::Field) -> Field {
+fn translate_parser_field_to_cpp_field(f: &parser::ast::Field, doc_comment: Option<String>) -> Field {
     match f.which() {
-        crate::parser::ast::field::Which::Group(_) => { panic!("Groups are not supported."); }
-        crate::parser::ast::field::Which::Slot(t) => {
-            return Field::new(Name::from(f.name()), translate_parser_type_to_cpp_type(t));
+        // The group itself is generated as a nested class, from the node that the field refers to.
+        crate::parser::ast::field::Which::Group(id) => {
+            return Field::new(Name::from(f.name()), CppType::RefId(*id), doc_comment);
+        }
+        crate::parser::ast::field::Which::Slot { type_: t, .. } => {
+            return Field::new(Name::from(f.name()), translate_parser_type_to_cpp_type(t), doc_comment);
         }
     }
 }
 
 fn translate_parser_field_to_enumerant(f: &parser::ast::Field) -> Name {
-    match f.which() {
-        crate::parser::ast::field::Which::Group(_) => { panic!("Groups are not supported."); }
-        crate::parser::ast::field::Which::Slot(_) => {
-            return Name::from(f.name());
+    Name::from(f.name())
+}
+
+///
+/// A C++ string literal. Control characters are written as octal escapes, which unlike hex
+/// escapes can't run into the characters that follow.
+///
+fn translate_text_to_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            c if c.is_control() && (c as u32) < 0x80 => literal.push_str(&format!("\\{:03o}", c as u32)),
+            c => literal.push(c)
         }
     }
+    literal.push('"');
+    literal
+}
+
+fn translate_float_to_literal(value: f64, cpp_type: &str, suffix: &str) -> String {
+    if value.is_nan() {
+        format!("std::numeric_limits<{}>::quiet_NaN()", cpp_type)
+    } else if value.is_infinite() {
+        format!("{}std::numeric_limits<{}>::infinity()", if value < 0.0 { "-" } else { "" }, cpp_type)
+    } else {
+        format!("{:?}{}", value, suffix)
+    }
+}
+
+///
+/// Why the value of a constant can't be translated.
+///
+#[derive(Debug, PartialEq)]
+enum ConstValueError {
+    /// The value can't be generated, e.g. a capability. The constant is skipped.
+    Unsupported(String),
+
+    /// The value doesn't match the nodes of the request.
+    Invalid(String)
+}
+
+///
+/// The value of a constant, or why it can't be generated.
+///
+fn translate_parser_value_to_const_value(ctx: &Context, t: &parser::ast::Type, v: &parser::ast::Value) -> Result<ConstValue, ConstValueError> {
+    use parser::ast::Value;
+
+    Ok(match (t, v) {
+        (_, Value::Void) => ConstValue::Literal(String::from("std::monostate()")),
+        (_, Value::Bool(b)) => ConstValue::Literal(b.to_string()),
+        (_, Value::Int8(i)) => ConstValue::Literal(i.to_string()),
+        (_, Value::Int16(i)) => ConstValue::Literal(i.to_string()),
+        (_, Value::Int32(i)) => ConstValue::Literal(i.to_string()),
+        // The magnitude of the minimum doesn't fit in a long long, so it can't simply be negated.
+        (_, Value::Int64(i)) if *i == i64::MIN => ConstValue::Literal(format!("({}LL - 1)", i + 1)),
+        // `long` is only 32 bits wide on some platforms.
+        (_, Value::Int64(i)) => ConstValue::Literal(format!("{}LL", i)),
+        (_, Value::Uint8(i)) => ConstValue::Literal(i.to_string()),
+        (_, Value::Uint16(i)) => ConstValue::Literal(i.to_string()),
+        (_, Value::Uint32(i)) => ConstValue::Literal(format!("{}U", i)),
+        (_, Value::Uint64(i)) => ConstValue::Literal(format!("{}ULL", i)),
+        (_, Value::Float32(f)) => ConstValue::Literal(translate_float_to_literal(*f as f64, "float", "f")),
+        (_, Value::Float64(f)) => ConstValue::Literal(translate_float_to_literal(*f, "double", "")),
+        (_, Value::Text(text)) => ConstValue::Literal(translate_text_to_literal(text)),
+        (_, Value::Data(bytes)) => ConstValue::List(bytes.iter().map(|b| ConstValue::Literal(b.to_string())).collect()),
+        (parser::ast::Type::List(element_type), Value::List(values)) => {
+            ConstValue::List(
+                values.iter()
+                    .map(|value| translate_parser_value_to_const_value(ctx, element_type, value))
+                    .collect::<Result<Vec<ConstValue>, ConstValueError>>()?
+            )
+        },
+        (parser::ast::Type::Enum { type_id, .. }, Value::Enum(ordinal)) => {
+            match ctx.schema().node(*type_id).map(|node| node.which()) {
+                Some(parser::ast::node::Which::Enum(enumerants)) => match enumerants.get(*ordinal as usize) {
+                    Some(enumerant) => ConstValue::Enumerant(*type_id, Name::from(enumerant.name())),
+                    // From a newer version of the schema; the enum class can still hold it.
+                    None => ConstValue::Literal(ordinal.to_string())
+                },
+                _ => return Err(ConstValueError::Invalid(format!("Unable to find enum: {}", type_id)))
+            }
+        },
+        (parser::ast::Type::Struct { type_id, .. }, Value::Struct(fields)) => translate_struct_value_to_const_value(ctx, *type_id, fields)?,
+        (t, v) => return Err(ConstValueError::Unsupported(format!("unsupported value {:?} of type {:?}", v, t)))
+    })
+}
+
+fn translate_struct_value_to_const_value(ctx: &Context, id: Id, values: &Vec<parser::ast::value::StructField>) -> Result<ConstValue, ConstValueError> {
+    let fields = match ctx.schema().node(id).map(|node| node.which()) {
+        Some(parser::ast::node::Which::Struct { fields, .. }) => fields,
+        _ => return Err(ConstValueError::Invalid(format!("Unable to find struct: {}", id)))
+    };
+
+    let mut field_values = vec!();
+    for value in values {
+        let field = match fields.iter().find(|f| f.name() == value.name()) {
+            Some(field) => field,
+            None => return Err(ConstValueError::Invalid(format!("Unable to find field '{}' of struct: {}", value.name(), id)))
+        };
+
+        let field_value = match (field.which(), value.value()) {
+            (parser::ast::field::Which::Slot { type_, .. }, value) => translate_parser_value_to_const_value(ctx, type_, value)?,
+            (parser::ast::field::Which::Group(group_id), parser::ast::Value::Struct(group_values)) => {
+                translate_struct_value_to_const_value(ctx, *group_id, group_values)?
+            },
+            (_, value) => return Err(ConstValueError::Invalid(format!("Unexpected value for group '{}': {:?}", field.name(), value)))
+        };
+        field_values.push((Name::from(field.name()), field_value));
+    }
+
+    Ok(ConstValue::Object(id, field_values))
+}
+
+///
+/// Constants with values that can't be generated, such as capabilities, are skipped with a warning.
+///
+fn generate_constant_for_node(ctx: &Context, cgr: &CodeGeneratorRequest, node: &parser::ast::Node) -> parser::Result<Option<Constant>> {
+    match node.which() {
+        parser::ast::node::Which::Const { type_, value } => match translate_parser_value_to_const_value(ctx, type_, value) {
+            Ok(const_value) => Ok(Some(Constant::new(
+                node.id(),
+                ctx.name_of(node.id()),
+                translate_parser_type_to_cpp_type(type_),
+                const_value,
+                cgr.doc_comment(node.id()).cloned()
+            ))),
+            Err(ConstValueError::Unsupported(reason)) => {
+                eprintln!("WARN: Skipping constant {}: {}", node.display_name(), reason);
+                Ok(None)
+            },
+            Err(ConstValueError::Invalid(reason)) => {
+                Err(parser::Error::Invalid(format!("Constant {}: {}", node.display_name(), reason)))
+            }
+        },
+        _ => Err(parser::Error::Invalid(format!("Generating constant for non-constant node: {}", node.id())))
+    }
+}
+
+fn is_constant(node: &parser::ast::Node) -> bool {
+    match node.which() {
+        parser::ast::node::Which::Const { .. } => true,
+        _ => false
+    }
 }
 
 fn generate_refid_for_union_which(id: Id) -> Id {
     id + 1
 }
 
-fn generate_base_ast_type_for_node(ctx: &Context, cgr: &CodeGeneratorRequest, node: &parser::ast::Node) -> ComplexTypeDef
+fn generate_base_ast_type_for_node(ctx: &Context, cgr: &CodeGeneratorRequest, node: &parser::ast::Node) -> parser::Result<ComplexTypeDef>
 {
     use parser::ast::node::Which;
 
-    println!("Processing: {}", node.id());
-
-    let name = ctx.names.get(&node.id()).expect(&format!("Unable to determine name for node with id: {}", node.id())).clone();
-    let mut inner_types = ctx.children()
-        .get_vec(&node.id())
-        .unwrap_or(&vec!())
+    let name = ctx.name_of(node.id());
+    let mut inner_types = ctx.schema()
+        .children(node.id())
         .iter()
-        .map(|n|
-            generate_base_ast_type_for_node(ctx, cgr, ctx.nodes().get(n).unwrap())
-        ).collect::<Vec<ComplexTypeDef>>();
+        .filter(|n| !is_constant(n))
+        .map(|n| generate_base_ast_type_for_node(ctx, cgr, n))
+        .collect::<parser::Result<Vec<ComplexTypeDef>>>()?;
+    let constants = ctx.schema()
+        .children(node.id())
+        .iter()
+        .filter(|n| is_constant(n))
+        .map(|n| generate_constant_for_node(ctx, cgr, n))
+        .collect::<parser::Result<Vec<Option<Constant>>>>()?
+        .into_iter()
+        .flatten()
+        .collect::<Vec<Constant>>();
+
+    let doc_comment = cgr.doc_comment(node.id()).cloned();
+    let member_doc_comment = |index: usize| cgr.member_doc_comment(node.id(), index).cloned();
 
     match node.which() {
         Which::File => panic!("Generating ast for file in incorrect area of the code."),
-        Which::Struct { discriminant_count, fields, .. } => {
+        Which::Struct { discriminant_count, fields, is_group, .. } => {
             if *discriminant_count as usize > 0 {
 
                 let mut class_fields = vec!();
-                for f in fields {
+                for (index, f) in fields.iter().enumerate() {
                     if f.discriminant_value() == crate::parser::ast::field::NO_DISCRIMINANT {
-                        class_fields.push(translate_parser_field_to_cpp_field(f));
+                        class_fields.push(translate_parser_field_to_cpp_field(f, member_doc_comment(index)));
                     }
                 }
 
                 class_fields.push(Field::new(
                     Name::from(&String::from("which")),
-                    CppType::RefId(generate_refid_for_union_which(node.id()))
+                    CppType::RefId(generate_refid_for_union_which(node.id())),
+                    None
                 ));
 
                 let mut union_fields = vec!();
-                for f in fields {
+                for (index, f) in fields.iter().enumerate() {
                     if f.discriminant_value() != crate::parser::ast::field::NO_DISCRIMINANT {
-                        union_fields.push(translate_parser_field_to_cpp_field(f));
+                        union_fields.push(translate_parser_field_to_cpp_field(f, member_doc_comment(index)));
                     }
                 }
 
                 let which = EnumClass::new(
                     generate_refid_for_union_which(node.id()),
                     Name::from("Which"),
-                    union_fields.iter().map(|f| f.name().clone()).collect()
+                    union_fields.iter().map(|f| Enumerant::new(f.name().clone(), f.doc_comment().clone())).collect(),
+                    None
                 );
                 let union = UnnamedUnion::new(node.id(), union_fields);
                 inner_types.push(ComplexTypeDef::EnumClass(which));
                 
-                return ComplexTypeDef::Class(Class::new(
+                return Ok(ComplexTypeDef::Class(Class::new(
                     node.id(),
                     name.clone(),
                     inner_types,
                     Some(union),
-                    class_fields
-                ));
+                    class_fields,
+                    constants,
+                    *is_group,
+                    doc_comment
+                )));
 
             } else {
-                return ComplexTypeDef::Class(Class::new(
+                return Ok(ComplexTypeDef::Class(Class::new(
                     node.id(),
                     name.clone(),
                     inner_types,
                     None,
-                    fields.iter().map(translate_parser_field_to_cpp_field).collect()
-                ));
+                    fields.iter()
+                        .enumerate()
+                        .map(|(index, f)| translate_parser_field_to_cpp_field(f, member_doc_comment(index)))
+                        .collect(),
+                    constants,
+                    *is_group,
+                    doc_comment
+                )));
             }
         },
         Which::Enum(enumerants) => {
-            return ComplexTypeDef::EnumClass(EnumClass::new(
+            return Ok(ComplexTypeDef::EnumClass(EnumClass::new(
                 node.id(),
                 name.clone(),
                 enumerants.iter()
-                    .map(parser::ast::Enumerant::name)
-                    .map(|enumerant| Name::from(enumerant))
-                    .collect()
-            ))
+                    .enumerate()
+                    .map(|(index, enumerant)| Enumerant::new(Name::from(enumerant.name()), member_doc_comment(index)))
+                    .collect(),
+                doc_comment
+            )))
         },
-        Which::Interface => panic!("Interfaces are not supported."),
-        Which::Const => panic!("Constants are not supported."),
-        Which::Annotation => panic!("Generating ast for annotation in incorrect area of the code.")
-    }
-}
-
-fn generate_base_ast_for_file_node(ctx: &Context, cgr: &CodeGeneratorRequest, node: &parser::ast::Node, root: &mut Namespace) {
-    let idiomatic_namespace_annotation_option = node.annotations()
-        .iter()
-        .filter(|a| a.id() == ctx.idiomatic_namespace_annotation_id())
-        .last();
+        Which::Interface { methods, superclasses } => {
+            // Implicit param and result structs aren't nested anywhere, so they're added here.
+            for method in methods {
+                for id in vec!(method.param_struct_type(), method.result_struct_type()) {
+                    if let Some(method_struct) = ctx.method_struct_of(id).and_then(|_| ctx.schema().node(id)) {
+                        inner_types.push(generate_base_ast_type_for_node(ctx, cgr, method_struct)?);
+                    }
+                }
+            }
 
-    if let None = idiomatic_namespace_annotation_option {
-        println!("INFO: Skipping generation for file '{}'. Missing idiomatic namespace annotation.", node.display_name());
-        return;
+            return Ok(ComplexTypeDef::Interface(Interface::new(
+                node.id(),
+                name.clone(),
+                inner_types,
+                superclasses.iter().map(|superclass| superclass.id()).collect(),
+                methods.iter()
+                    .enumerate()
+                    .map(|(index, method)| Method::new(
+                        Name::from(method.name()),
+                        method.param_struct_type(),
+                        method.result_struct_type(),
+                        member_doc_comment(index)
+                    ))
+                    .collect(),
+                constants,
+                doc_comment
+            )))
+        },
+        Which::Const { .. } => panic!("Generating ast for constant in incorrect area of the code."),
+        Which::Annotation { .. } => panic!("Generating ast for annotation in incorrect area of the code.")
     }
+}
 
-    let idiomatic_namespace_name =
-        if let parser::ast::Value::Text(t) = idiomatic_namespace_annotation_option.unwrap().value() {
-            t
-        } else {
-            panic!(format!("Namespace annotation for {} was not a string.", node.display_name()));
-        };
+fn generate_base_ast_for_file_node(ctx: &Context, cgr: &CodeGeneratorRequest, node: &parser::ast::Node, root: &mut Namespace) -> parser::Result<()> {
+    let idiomatic_namespace_name = match ctx.idiomatic_namespace_of(node)? {
+        Some(name) => name,
+        None => {
+            eprintln!("INFO: Skipping generation for file '{}'. Missing idiomatic namespace annotation.", node.display_name());
+            return Ok(());
+        }
+    };
 
     let idiomatic_namespace_path = FullyQualifiedName::new(idiomatic_namespace_name.split("::").map(Name::from).collect());
     let namespace = root.get_or_create_namespace_mut(&idiomatic_namespace_path);
 
-    cgr.nodes()
+    let children = cgr.nodes()
         .iter()
         .filter(|potential_child| potential_child.scope_id() == node.id())
-        .filter(|potential_child| potential_child.which() != &parser::ast::node::Which::Annotation)
-        .for_each(
-            |child| 
+        .filter(|potential_child| match potential_child.which() { parser::ast::node::Which::Annotation { .. } => false, _ => true });
+
+    for child in children {
+        if is_constant(child) {
+            namespace.constants_mut().extend(
+                generate_constant_for_node(&ctx.with_namespace(&idiomatic_namespace_path), cgr, child)?
+            )
+        } else {
             namespace.defs_mut().push(
                 generate_base_ast_type_for_node(
                 &ctx.with_namespace(&idiomatic_namespace_path),
                 cgr,
                 child
-            ))
-        );
+            )?)
+        }
+    }
+    Ok(())
 }
 
-fn generate_base_ast(ctx: &Context, cgr: &CodeGeneratorRequest) -> Namespace {
+fn generate_base_ast(ctx: &Context, cgr: &CodeGeneratorRequest) -> parser::Result<Namespace> {
     let mut root = Namespace::empty();
 
-    cgr.nodes().iter()
-        .filter(|node| node.which() == &parser::ast::node::Which::File)
-        .for_each(|node| generate_base_ast_for_file_node(ctx, cgr, node, &mut root));
+    for node in cgr.nodes().iter().filter(|node| node.which() == &parser::ast::node::Which::File) {
+        generate_base_ast_for_file_node(ctx, cgr, node, &mut root)?;
+    }
 
-    return root;
+    return Ok(root);
 }
 
 fn generate_imports(cgr: &CodeGeneratorRequest) -> Vec<Import> {
@@ -340,9 +573,18 @@ fn generate_imports(cgr: &CodeGeneratorRequest) -> Vec<Import> {
         .map(|filename| format!("{}{}", filename, ".h"))
         .map(|filename| Import::new(filename))
         .collect();
+    imports.push(Import::new(String::from("cstdint")));
+    imports.push(Import::new(String::from("limits")));
     imports.push(Import::new(String::from("string")));
     imports.push(Import::new(String::from("variant")));
     imports.push(Import::new(String::from("vector")));
+
+    // Interfaces are served and called through capnp RPC.
+    if cgr.nodes().iter().any(|node| match node.which() { parser::ast::node::Which::Interface { .. } => true, _ => false }) {
+        imports.push(Import::new(String::from("capnp/capability.h")));
+        imports.push(Import::new(String::from("kj/async.h")));
+        imports.push(Import::new(String::from("memory")));
+    }
     return imports;
 }
 
@@ -371,17 +613,82 @@ fn generate_serde(cgr: &CodeGeneratorRequest, ast: &Namespace) -> CompilationUni
     )
 }
 
-pub fn build_translation_context(ctx: &mut Context, cgr: &CodeGeneratorRequest) {
-    ctx.set_annotation_ids_from(&cgr);
-    ctx.set_names_from(&cgr);
-    ctx.set_capnp_names_from(&cgr);
+pub fn build_translation_context(ctx: &mut Context, cgr: &CodeGeneratorRequest) -> parser::Result<()> {
+    ctx.set_annotations_from(&cgr);
+    ctx.set_schema_from(&cgr);
+    ctx.set_method_structs_from(&cgr);
+    ctx.set_capnp_names_from(&cgr)
 }
 
-pub fn translate(ctx: &Context, cgr: &CodeGeneratorRequest) -> CppAst {
-    let ast = generate_base_ast(&ctx, cgr);
+pub fn translate(ctx: &Context, cgr: &CodeGeneratorRequest) -> parser::Result<CppAst> {
+    let ast = generate_base_ast(&ctx, cgr)?;
 
-    return CppAst::new(vec!(
+    return Ok(CppAst::new(vec!(
         generate_poco(cgr, &ast),
         generate_serde(cgr, &ast),
-    ));
-}
\ No newline at end of file
+    )));
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::ast::{Brand, Type, Value};
+    use parser::ast::type_::{AnyPointer, Unconstrained};
+
+    fn const_value(ctx: &Context, t: Type, v: Value) -> Result<ConstValue, ConstValueError> {
+        translate_parser_value_to_const_value(ctx, &t, &v)
+    }
+
+    #[test]
+    fn test_int64_literals() {
+        let ctx = Context::new(&PathBuf::from("out"));
+
+        assert_eq!(const_value(&ctx, Type::Int64, Value::Int64(-5)), Ok(ConstValue::Literal(String::from("-5LL"))));
+        assert_eq!(
+            const_value(&ctx, Type::Int64, Value::Int64(i64::MIN)),
+            Ok(ConstValue::Literal(String::from("(-9223372036854775807LL - 1)")))
+        );
+        assert_eq!(
+            const_value(&ctx, Type::Uint64, Value::Uint64(u64::MAX)),
+            Ok(ConstValue::Literal(String::from("18446744073709551615ULL")))
+        );
+    }
+
+    #[test]
+    fn test_unknown_enumerant() {
+        let cgr = parser::compile_source("test.capnp", "@0xe87e0317861d3aab;\nenum Color { red @0; }\n", &parser::CompileOptions::new()).unwrap();
+        let mut ctx = Context::new(&PathBuf::from("out"));
+        build_translation_context(&mut ctx, &cgr).unwrap();
+        let color = cgr.nodes().iter().find(|node| node.display_name() == "test.capnp:Color").unwrap().id();
+        let color_type = || Type::Enum { type_id: color, brand: Brand::default() };
+
+        assert_eq!(const_value(&ctx, color_type(), Value::Enum(0)), Ok(ConstValue::Enumerant(color, Name::from("red"))));
+        assert_eq!(const_value(&ctx, color_type(), Value::Enum(7)), Ok(ConstValue::Literal(String::from("7"))));
+    }
+
+    #[test]
+    fn test_unsupported_values() {
+        let ctx = Context::new(&PathBuf::from("out"));
+        let is_unsupported = |result: Result<ConstValue, ConstValueError>| matches!(result, Err(ConstValueError::Unsupported(_)));
+
+        assert!(is_unsupported(const_value(&ctx, Type::AnyPointer(AnyPointer::Unconstrained(Unconstrained::AnyKind)), Value::AnyPointer)));
+        assert!(is_unsupported(const_value(&ctx, Type::List(Box::new(Type::Text)), Value::Unknown)));
+        assert!(is_unsupported(const_value(&ctx, Type::List(Box::new(Type::List(Box::new(Type::Int8)))), Value::List(vec!(Value::Unknown)))));
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let cgr = parser::compile_source("test.capnp", "@0xe87e0317861d3aab;\nstruct Shape { size @0 :Int64; }\n", &parser::CompileOptions::new()).unwrap();
+        let mut ctx = Context::new(&PathBuf::from("out"));
+        build_translation_context(&mut ctx, &cgr).unwrap();
+        let shape = cgr.nodes().iter().find(|node| node.display_name() == "test.capnp:Shape").unwrap().id();
+        let is_invalid = |result: Result<ConstValue, ConstValueError>| matches!(result, Err(ConstValueError::Invalid(_)));
+
+        assert!(is_invalid(const_value(&ctx, Type::Enum { type_id: shape, brand: Brand::default() }, Value::Enum(0))));
+        assert!(is_invalid(const_value(&ctx, Type::Struct { type_id: 7, brand: Brand::default() }, Value::Struct(vec!()))));
+        assert!(is_invalid(const_value(
+            &ctx,
+            Type::Struct { type_id: shape, brand: Brand::default() },
+            Value::Struct(vec!(parser::ast::value::StructField::new(String::from("color"), Value::Int64(1))))
+        )));
+    }
+}
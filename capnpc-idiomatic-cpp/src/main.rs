@@ -1,7 +1,6 @@
 extern crate capnp;
 #[macro_use] extern crate derive_more;
 extern crate getset;
-extern crate multimap;
 extern crate parser;
 extern crate indoc;
 
@@ -9,7 +8,7 @@ mod cpp;
 
 use std::env;
 use std::fs::File;
-use std::io::{Write, Error};
+use std::io::Write;
 use std::path::PathBuf;
 
 fn get_output_dir() -> PathBuf {
@@ -19,9 +18,12 @@ fn get_output_dir() -> PathBuf {
     }
 }
 
-fn main() -> Result<(), Error> {
-    let capnp_ast = parser::read_message(&mut std::io::stdin());
-    let code = cpp::code_gen(&get_output_dir(), &capnp_ast);
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let capnp_ast = parser::RequestArgs::parse(env::args().skip(1))?.read_request()?;
+    if let Some(path) = parser::json_dump_path() {
+        parser::write_json_to_file(path, &capnp_ast)?;
+    }
+    let code = cpp::code_gen(&get_output_dir(), &capnp_ast)?;
 
     for (path, code) in code.files() {
         println!("Writing file: {:#?}", path);
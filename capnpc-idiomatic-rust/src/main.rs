@@ -25,10 +25,13 @@ fn get_output_file() -> Result<File, Error> {
     File::create(out_dir)
 }
 
-fn main() -> Result<(), Error> {
-    let capnp_ast = parser::read_message(&mut std::io::stdin());
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let capnp_ast = parser::RequestArgs::parse(env::args().skip(1))?.read_request()?;
+    if let Some(path) = parser::json_dump_path() {
+        parser::write_json_to_file(path, &capnp_ast)?;
+    }
     let mut output = get_output_file()?;
-    let code = rust::code_gen(&get_output_dir(), &capnp_ast);
+    let code = rust::code_gen(&get_output_dir(), &capnp_ast)?;
     write!(output, "{}", code)?;
 
     Ok(())
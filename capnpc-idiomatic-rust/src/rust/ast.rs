@@ -65,14 +65,18 @@ pub struct Enum {
     enum_origin: EnumOrigin,
 
     #[get]
-    enumerants: Vec<Enumerant>
+    enumerants: Vec<Enumerant>,
+
+    #[get]
+    doc_comment: Option<String>
 }
 
 #[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq)]
 #[get]
 pub struct Enumerant {
     name: Name,
-    rust_type: Type
+    rust_type: Type,
+    doc_comment: Option<String>
 }
 
 #[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq)]
@@ -93,14 +97,18 @@ pub struct Struct {
     capnp_type_name: FullyQualifiedName,
 
     #[get]
-    fields: Vec<Field>
+    fields: Vec<Field>,
+
+    #[get]
+    doc_comment: Option<String>
 }
 
 #[derive(Constructor, Clone, Getters, CopyGetters, Setters, Debug, PartialEq)]
 #[get]
 pub struct Field {
     name: Name,
-    rust_type: Type
+    rust_type: Type,
+    doc_comment: Option<String>
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -253,6 +261,20 @@ impl FullyQualifiedName {
     }
 }
 
+impl Enumerant {
+    fn with_doc_comment(mut self, doc_comment: Option<String>) -> Enumerant {
+        self.doc_comment = doc_comment;
+        self
+    }
+}
+
+impl Field {
+    fn with_doc_comment(mut self, doc_comment: Option<String>) -> Field {
+        self.doc_comment = doc_comment;
+        self
+    }
+}
+
 impl TypeDef {
     fn is_simple_enum(&self) -> bool {
         match self {
@@ -281,19 +303,27 @@ pub struct TranslationContext {
 
     #[get]
     #[get_mut]
-    names: HashMap<Id, Name>,
+    schema: crate::parser::SchemaGraph,
 
     #[get]
     #[get_mut]
-    children: MultiMap<Id, Id>,
+    source_info: HashMap<Id, crate::parser::ast::node::SourceInfo>
+}
 
-    #[get]
-    #[get_mut]
-    nodes: HashMap<Id, crate::parser::ast::Node>
+///
+/// Why a request could not be translated.
+///
+#[derive(Debug, Display)]
+pub enum TranslationError {
+    /// The request refers to a node that it doesn't contain.
+    #[display(fmt = "Unable to find node: {}", _0)]
+    MissingNode(Id)
 }
 
-pub trait Translator<AST> {
-    fn translate(ctx: &TranslationContext, n: &AST) -> Self;
+impl std::error::Error for TranslationError {}
+
+pub trait Translator<AST> : Sized {
+    fn translate(ctx: &TranslationContext, n: &AST) -> Result<Self, TranslationError>;
 }
 
 impl TranslationContext {
@@ -302,9 +332,8 @@ impl TranslationContext {
             existing_modules_in_out_dir: existing_modules_in_out_dir,
             filename: String::new(),
             module_path: vec!(),
-            names: HashMap::new(),
-            children: MultiMap::new(),
-            nodes: HashMap::new()
+            schema: crate::parser::SchemaGraph::default(),
+            source_info: HashMap::new()
         };
     }
 
@@ -320,6 +349,28 @@ impl TranslationContext {
         return c;
     }
 
+    ///
+    /// Files are named without their extension, so that the name can be used for their module.
+    ///
+    fn name_of(&self, id: Id) -> Result<Name, TranslationError> {
+        let name = self.schema.name(id).ok_or(TranslationError::MissingNode(id))?;
+        match self.schema.node(id) {
+            Some(node) if node.which() == &crate::parser::ast::node::Which::File =>
+                Ok(Name::from(&String::from(name.split('.').next().unwrap_or(name)))),
+            _ => Ok(Name::from(name))
+        }
+    }
+
+    fn doc_comment(&self, id: Id) -> Option<String> {
+        self.source_info.get(&id).and_then(|info| info.doc_comment().clone())
+    }
+
+    fn member_doc_comment(&self, id: Id, index: usize) -> Option<String> {
+        self.source_info.get(&id)
+            .and_then(|info| info.members().get(index))
+            .and_then(|member| member.doc_comment().clone())
+    }
+
     fn generate_capnp_mod_from_filename(filename: &String) -> Name {
         return Name::from(&filename.to_lowercase().replace(".", "_"));
     }
@@ -351,7 +402,7 @@ impl TranslationContext {
 }
 
 impl Translator<crate::parser::ast::CodeGeneratorRequest> for RustAst  {
-    fn translate(ctx: &TranslationContext, cgr: &crate::parser::ast::CodeGeneratorRequest) -> Self {
+    fn translate(ctx: &TranslationContext, cgr: &crate::parser::ast::CodeGeneratorRequest) -> Result<Self, TranslationError> {
         let mut ctx = ctx.clone();
         ctx = build_translation_context_from_cgr(&mut ctx, cgr);
 
@@ -360,7 +411,7 @@ impl Translator<crate::parser::ast::CodeGeneratorRequest> for RustAst  {
         for node in cgr.nodes().iter().filter(|x| x.which() == &crate::parser::ast::node::Which::File) {
             let filename = get_filename_from_cgr(cgr, node.id());
             external_mod_decls.push(TranslationContext::generate_capnp_mod_from_filename(&filename).to_snake_case(RESERVED));
-            defs.push(Module::translate(&ctx.clone_with_filename(filename), node));
+            defs.push(Module::translate(&ctx.clone_with_filename(filename), node)?);
         }
 
         println!("{:?}", ctx.existing_modules_in_out_dir());
@@ -371,26 +422,26 @@ impl Translator<crate::parser::ast::CodeGeneratorRequest> for RustAst  {
             .map(|s| s.clone())
             .collect::<Vec<_>>();
 
-        return RustAst {
+        return Ok(RustAst {
             external_crate_decls: vec!(
                 "#[macro_use] extern crate derive_more;".to_string(),
                 "extern crate getset;".to_string()
             ),
             external_mod_decls: external_mod_decls,
             defs: defs
-        };
+        });
     }
 }
 
 impl Translator<crate::parser::ast::Type> for Type {
-    fn translate(ctx: &TranslationContext, t: &crate::parser::ast::Type) -> Self {
+    fn translate(ctx: &TranslationContext, t: &crate::parser::ast::Type) -> Result<Self, TranslationError> {
         use crate::parser::ast::Type as ParserType;
 
-        match t {
-            ParserType::AnyPointer => { panic!("Unsupported type: AnyPointer") },
+        Ok(match t {
+            ParserType::AnyPointer(_) => { panic!("Unsupported type: AnyPointer") },
             ParserType::Bool => { Type::Bool },
             ParserType::Data => { panic!("Unsupported type: Data") },
-            ParserType::Enum { type_id } => { Type::RefId(*type_id) },
+            ParserType::Enum { type_id, .. } => { Type::RefId(*type_id) },
             ParserType::Float32 => { Type::Float32 },
             ParserType::Float64 => { Type::Float64 },
             ParserType::Int16 => { Type::Int16 },
@@ -398,200 +449,198 @@ impl Translator<crate::parser::ast::Type> for Type {
             ParserType::Int64 => { Type::Int64  },
             ParserType::Int8 => { Type::Int8  },
             ParserType::Interface { .. } => { panic!("Unsupported type: Interface") },
-            ParserType::List( boxed_type ) => { Type::List(Box::new(Type::translate(ctx, &*boxed_type))) },
-            ParserType::Struct { type_id } => { Type::RefId(*type_id) },
+            ParserType::List( boxed_type ) => { Type::List(Box::new(Type::translate(ctx, &*boxed_type)?)) },
+            ParserType::Struct { type_id, .. } => { Type::RefId(*type_id) },
             ParserType::Text => { Type::String },
             ParserType::Uint16 => { Type::Uint16 },
             ParserType::Uint32 => { Type::Uint32 },
             ParserType::Uint64 => { Type::Uint64 },
             ParserType::Uint8 => { Type::Uint8 },
             ParserType::Void => { Type::Unit }
-        }
+        })
     }
 }
 
 impl Translator<crate::parser::ast::Field> for Field {
-    fn translate(ctx: &TranslationContext, f: &crate::parser::ast::Field) -> Self {
+    fn translate(ctx: &TranslationContext, f: &crate::parser::ast::Field) -> Result<Self, TranslationError> {
         match f.which() {
             crate::parser::ast::field::Which::Group(_) => { panic!("Groups are not supported."); }
-            crate::parser::ast::field::Which::Slot(t) => {
-                return Field::new(Name::from(f.name()), Type::translate(ctx, t));
+            crate::parser::ast::field::Which::Slot { type_: t, .. } => {
+                return Ok(Field::new(Name::from(f.name()), Type::translate(ctx, t)?, None));
             }
         }
     }
 }
 
 impl Translator<crate::parser::ast::Field> for Enumerant {
-    fn translate(ctx: &TranslationContext, f: &crate::parser::ast::Field) -> Self {
+    fn translate(ctx: &TranslationContext, f: &crate::parser::ast::Field) -> Result<Self, TranslationError> {
         match f.which() {
             crate::parser::ast::field::Which::Group(_) => { panic!("Groups are not supported."); }
-            crate::parser::ast::field::Which::Slot(t) => {
-                return Enumerant::new(Name::from(f.name()), Type::translate(ctx, t));
+            crate::parser::ast::field::Which::Slot { type_: t, .. } => {
+                return Ok(Enumerant::new(Name::from(f.name()), Type::translate(ctx, t)?, None));
             }
         }
     }
 }
 
 impl Translator<crate::parser::ast::Enumerant> for Enumerant {
-    fn translate(_: &TranslationContext, e: &crate::parser::ast::Enumerant) -> Self {
-        return Enumerant::new(Name::from(e.name()), Type::Unit);
+    fn translate(_: &TranslationContext, e: &crate::parser::ast::Enumerant) -> Result<Self, TranslationError> {
+        return Ok(Enumerant::new(Name::from(e.name()), Type::Unit, None));
     }
 }
 
 impl Translator<crate::parser::ast::Node> for TypeDef  {
-    fn translate(ctx: &TranslationContext, n: &crate::parser::ast::Node) -> Self {
+    fn translate(ctx: &TranslationContext, n: &crate::parser::ast::Node) -> Result<Self, TranslationError> {
         match &n.which() {
-            &crate::parser::ast::node::Which::Annotation => { panic!() },
-            &crate::parser::ast::node::Which::Const => { panic!() },
+            &crate::parser::ast::node::Which::Annotation { .. } => { panic!() },
+            &crate::parser::ast::node::Which::Const { .. } => { panic!() },
             &crate::parser::ast::node::Which::Enum(enumerants) => {
-                let name = ctx.names().get(&n.id()).unwrap().clone();
+                let name = ctx.name_of(n.id())?;
                 let mut new_enumerants = vec!();
-                for e in enumerants {
-                    new_enumerants.push(Enumerant::translate(&ctx, e))
+                for (i, e) in enumerants.iter().enumerate() {
+                    new_enumerants.push(Enumerant::translate(&ctx, e)?.with_doc_comment(ctx.member_doc_comment(n.id(), i)))
                 }
-                return TypeDef::Enum(
+                return Ok(TypeDef::Enum(
                     Enum::new(
                         n.id(),
                         name.clone(),
                         ctx.generate_fully_qualified_type_name(&name),
                         ctx.generate_capnp_type_name(&name),
                         EnumOrigin::Enum,
-                        new_enumerants
+                        new_enumerants,
+                        ctx.doc_comment(n.id())
                     )
-                );
+                ));
             },
             &crate::parser::ast::node::Which::File => { panic!() },
-            &crate::parser::ast::node::Which::Interface => { panic!() },
+            &crate::parser::ast::node::Which::Interface { .. } => { panic!() },
             &crate::parser::ast::node::Which::Struct { discriminant_count, fields, .. } => {
-                let name = ctx.names().get(&n.id()).unwrap().clone();
+                let name = ctx.name_of(n.id())?;
 
                 if fields.len() == 0 {
-                    return TypeDef::Struct(Struct::new(
+                    return Ok(TypeDef::Struct(Struct::new(
                         n.id(),
                         name.clone(),
                         ctx.generate_fully_qualified_type_name(&name),
                         ctx.generate_capnp_type_name(&name),
-                        vec![]
-                    ))
+                        vec![],
+                        ctx.doc_comment(n.id())
+                    )))
                 }
 
                 // Use a Rust enum here.
                 if *discriminant_count as usize == fields.len() {
-                    return TypeDef::Enum(Enum::new(
+                    let mut enumerants = vec!();
+                    for (i, f) in fields.iter().enumerate() {
+                        enumerants.push(Enumerant::translate(ctx, f)?.with_doc_comment(ctx.member_doc_comment(n.id(), i)));
+                    }
+                    return Ok(TypeDef::Enum(Enum::new(
                         n.id(),
                         name.clone(),
                         ctx.generate_fully_qualified_type_name(&name),
                         ctx.generate_capnp_type_name(&name),
                         EnumOrigin::Struct,
-                        fields.iter().map(|f| Enumerant::translate(ctx, f)).collect()
-                    ));
+                        enumerants,
+                        ctx.doc_comment(n.id())
+                    )));
                 }
 
                 // Part, but not all, of this is in a union.
                 if *discriminant_count > 0 && (*discriminant_count as usize) < fields.len() {
 
                     let mut new_fields = vec!();
-                    for f in fields {
+                    for (i, f) in fields.iter().enumerate() {
                         if f.discriminant_value() == crate::parser::ast::field::NO_DISCRIMINANT {
-                            new_fields.push(Field::translate(ctx, f));
+                            new_fields.push(Field::translate(ctx, f)?.with_doc_comment(ctx.member_doc_comment(n.id(), i)));
                         }
                     }
 
                     new_fields.push(Field::new(
                         Name::from(&String::from("which")),
-                        Type::RefId(generate_id_for_which_enum(n.id()))
+                        Type::RefId(generate_id_for_which_enum(n.id())),
+                        None
                     ));
 
-                    return TypeDef::Struct(Struct::new(
+                    return Ok(TypeDef::Struct(Struct::new(
                         n.id(),
                         name.clone(),
                         ctx.generate_fully_qualified_type_name(&name),
                         ctx.generate_capnp_type_name(&name),
-                        new_fields
-                    ));
+                        new_fields,
+                        ctx.doc_comment(n.id())
+                    )));
                 }
 
-                return TypeDef::Struct(Struct::new(
+                let mut new_fields = vec!();
+                for (i, f) in fields.iter().enumerate() {
+                    new_fields.push(Field::translate(ctx, f)?.with_doc_comment(ctx.member_doc_comment(n.id(), i)));
+                }
+                return Ok(TypeDef::Struct(Struct::new(
                     n.id(),
                     name.clone(),
                     ctx.generate_fully_qualified_type_name(&name),
                     ctx.generate_capnp_type_name(&name),
-                    fields.iter().map(|f| Field::translate(ctx, f)).collect()
-                ));
+                    new_fields,
+                    ctx.doc_comment(n.id())
+                )));
             }
         }
     }
 }
 
 impl Translator<crate::parser::ast::Node> for Module  {
-    fn translate(ctx: &TranslationContext, n: &crate::parser::ast::Node) -> Self {
+    fn translate(ctx: &TranslationContext, n: &crate::parser::ast::Node) -> Result<Self, TranslationError> {
         let mut defs = vec!();
-        let module_name = ctx.names().get(&n.id()).unwrap().clone();
+        let module_name = ctx.name_of(n.id())?;
         let subctx = ctx.clone_with_submodule(&module_name);
 
         defs.push(ModuleElement::UseDecl("crate::getset::{Getters, CopyGetters, MutGetters, Setters}".to_string()));
 
         for nested_node in n.nested_nodes() {
-            let node_option = ctx.nodes.get(&nested_node.id());
-            if let None = node_option {
-                println!("WARNING: Unable to find node \"{}\" from \"{}\"", nested_node.name(), n.display_name());
-                continue;
-            }
-
-            let node = node_option.unwrap();
+            let node = ctx.schema().node(nested_node.id()).ok_or(TranslationError::MissingNode(nested_node.id()))?;
 
             if let
                 crate::parser::ast::node::Which::Enum(_) |
                 crate::parser::ast::node::Which::Struct { .. } = node.which()
             {
-                defs.push(ModuleElement::TypeDef(TypeDef::translate(&subctx, &node)));
+                defs.push(ModuleElement::TypeDef(TypeDef::translate(&subctx, &node)?));
             }
 
-            defs.push(ModuleElement::Module(Module::translate(&subctx, &node)));
+            defs.push(ModuleElement::Module(Module::translate(&subctx, &node)?));
         }
 
         // If part (but not all) of this node is a union generate a "Which" enum.
         if let crate::parser::ast::node::Which::Struct { discriminant_count, fields, .. } = n.which() {
             if *discriminant_count > 0 && (*discriminant_count as usize) < fields.len() {
                 let name = Name::from(&String::from("Which"));
+                let mut enumerants = vec!();
+                for (i, f) in fields.iter().enumerate() {
+                    if f.discriminant_value() != crate::parser::ast::field::NO_DISCRIMINANT {
+                        enumerants.push(Enumerant::translate(&subctx, f)?.with_doc_comment(ctx.member_doc_comment(n.id(), i)));
+                    }
+                }
                 let e = Enum::new(
                     generate_id_for_which_enum(n.id()),
                     name.clone(),
                     subctx.generate_fully_qualified_type_name(&name),
                     ctx.generate_capnp_type_name(&module_name),
                     EnumOrigin::WhichForPartialUnion,
-                    fields.iter()
-                        .filter(|f| f.discriminant_value() != crate::parser::ast::field::NO_DISCRIMINANT)
-                        .map(|f| Enumerant::translate(&subctx, f))
-                        .collect()
+                    enumerants,
+                    None
                 );
                 defs.push(ModuleElement::TypeDef(TypeDef::Enum(e)));
             }
         }
 
-        return Module::new(module_name.clone(), defs);
+        return Ok(Module::new(module_name.clone(), defs));
     }
 }
 
 fn build_translation_context_from_cgr(ctx: &TranslationContext, cgr: &crate::parser::ast::CodeGeneratorRequest) -> TranslationContext {
     let mut ctx = ctx.clone();
 
-    for node in cgr.nodes() {
-        if node.which() == &crate::parser::ast::node::Which::File {
-            let name = String::from(&node.display_name()[0..node.display_name_prefix_length()-1]);
-            ctx.names_mut().insert(
-                node.id(),
-                Name::from(&name)
-            );
-        }
-
-        for nested_node in node.nested_nodes() {
-            ctx.names_mut().insert(nested_node.id(), Name::from(nested_node.name()));
-        }
-
-        ctx.children_mut().insert(node.scope_id(), node.id());
-        ctx.nodes_mut().insert(node.id(), node.clone());
-    }
+    *ctx.schema_mut() = crate::parser::SchemaGraph::new(cgr);
+    *ctx.source_info_mut() = cgr.source_info().clone();
 
     return ctx;
 }
@@ -672,14 +721,14 @@ impl Resolver for Type {
 impl Resolver for Enumerant {
     fn build_context(_: &mut ResolutionContext, _: &Self) {}
     fn resolve(ctx: &ResolutionContext, n: &Self) -> Self {
-        return Enumerant::new(n.name().clone(), Type::resolve(ctx, n.rust_type()));
+        return Enumerant::new(n.name().clone(), Type::resolve(ctx, n.rust_type()), n.doc_comment().clone());
     }
 }
 
 impl Resolver for Field {
     fn build_context(_: &mut ResolutionContext, _: &Self) {}
     fn resolve(ctx: &ResolutionContext, n: &Self) -> Self {
-        return Field::new(n.name().clone(), Type::resolve(ctx, n.rust_type()));
+        return Field::new(n.name().clone(), Type::resolve(ctx, n.rust_type()), n.doc_comment().clone());
     }
 }
 
@@ -695,7 +744,8 @@ impl Resolver for Enum {
             n.fully_qualified_type_name().clone(),
             n.capnp_type_name().clone(),
             n.enum_origin(),
-            n.enumerants().iter().map(|x| Enumerant::resolve(ctx, x)).collect()
+            n.enumerants().iter().map(|x| Enumerant::resolve(ctx, x)).collect(),
+            n.doc_comment().clone()
         )
     }
 }
@@ -711,7 +761,8 @@ impl Resolver for Struct {
             n.name().clone(),
             n.fully_qualified_type_name().clone(),
             n.capnp_type_name().clone(),
-            n.fields().iter().map(|x| Field::resolve(ctx, x)).collect()
+            n.fields().iter().map(|x| Field::resolve(ctx, x)).collect(),
+            n.doc_comment().clone()
         );
     }
 }
@@ -915,27 +966,36 @@ impl ToCode for Type {
     }
 }
 
+fn doc_comment_to_code(doc_comment: &Option<String>) -> String {
+    match doc_comment {
+        Some(doc) => crate::parser::ast::doc_comment_lines(doc, "///").iter().map(|line| line.clone() + "\n").collect(),
+        None => String::new()
+    }
+}
+
 impl ToCode for Enumerant {
     fn to_code(&self) -> String {
         let mut ret = self.name.to_camel_case(RESERVED);
         if self.rust_type != Type::Unit {
             ret = format!("{}({})", ret, self.rust_type.to_code())
         }
-        return ret;
+        return format!("{}{}", doc_comment_to_code(&self.doc_comment), ret);
     }
 }
 
 impl ToCode for Enum {
     fn to_code(&self) -> String {
         return format!(
-            "#[derive(Clone, Debug, PartialEq)]\n\
+            "{}#[derive(Clone, Debug, PartialEq)]\n\
             pub enum {} {{\n\t{}\n}}",
+            doc_comment_to_code(self.doc_comment()),
             self.name().to_camel_case(RESERVED),
             self.enumerants()
                 .iter()
                 .map(|x| { x.to_code() })
                 .collect::<Vec<String>>()
-                .join(",\n\t")
+                .join(",\n")
+                .replace("\n", "\n\t")
         );
     }
 }
@@ -943,7 +1003,8 @@ impl ToCode for Enum {
 impl ToCode for Field {
     fn to_code(&self) -> String {
         format!(
-            "#[getset({} = \"pub\", set = \"pub\"{})]\n{}: {}",
+            "{}#[getset({} = \"pub\", set = \"pub\"{})]\n{}: {}",
+            doc_comment_to_code(self.doc_comment()),
             if self.rust_type().is_primitive() { "get_copy" } else { "get" },
             if self.rust_type().is_primitive() { "" } else { ", get_mut = \"pub\"" },
             self.name().to_snake_case(RESERVED),
@@ -955,8 +1016,9 @@ impl ToCode for Field {
 impl ToCode for Struct {
     fn to_code(&self) -> String {
         return format!(
-            "#[derive(Clone, Constructor, Getters, CopyGetters, MutGetters, Setters, Debug, PartialEq)]\n\
+            "{}#[derive(Clone, Constructor, Getters, CopyGetters, MutGetters, Setters, Debug, PartialEq)]\n\
             pub struct {} {{\n\t{}\n}}",
+            doc_comment_to_code(self.doc_comment()),
             self.name().to_camel_case(RESERVED),
             self.fields()
                 .iter()
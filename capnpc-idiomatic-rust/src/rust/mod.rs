@@ -29,11 +29,11 @@ fn get_list_of_existing_modules(out_dir: &Path) -> Vec<String> {
     return modules;
 }
 
-fn translate(out_dir: &Path, cgr: &crate::parser::ast::CodeGeneratorRequest) -> ast::RustAst {
+fn translate(out_dir: &Path, cgr: &crate::parser::ast::CodeGeneratorRequest) -> Result<ast::RustAst, ast::TranslationError> {
     let translated = ast::RustAst::translate(
         &ast::TranslationContext::new(get_list_of_existing_modules(out_dir)),
         &cgr
-    );
+    )?;
 
     let mut resolution_context = ast::ResolutionContext::new();
     ast::RustAst::build_context(&mut resolution_context, &translated);
@@ -42,20 +42,61 @@ fn translate(out_dir: &Path, cgr: &crate::parser::ast::CodeGeneratorRequest) ->
         &translated
     );
 
-    return resolved;
+    return Ok(resolved);
 }
 
 fn to_code(ast: &ast::RustAst) -> String {
     return ast.to_code();
 }
 
-pub fn code_gen(out_dir: &Path, cgr: &crate::parser::ast::CodeGeneratorRequest) -> String {
-    // Use this to view the cgr for debugging.
-    //println!("{:#?}", cgr);
-    let ast0 = translate(out_dir, &cgr);
+pub fn code_gen(out_dir: &Path, cgr: &crate::parser::ast::CodeGeneratorRequest) -> Result<String, ast::TranslationError> {
+    let ast0 = translate(out_dir, &cgr)?;
     let ast1 = ast::RustAst::generate_serde(
         &ast::SerdeGenerationContext::new(),
         &ast0
     );
-    return to_code(&ast1);
-}
\ No newline at end of file
+    return Ok(to_code(&ast1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{compile_source, CompileOptions};
+
+    const SCHEMA: &str = r#"@0xd1a5e1c2b3a4f509;
+struct Shape {
+  # A shape.
+  #
+  # Drawn on screen.
+  sides @0 :UInt32;
+  # How many sides.
+}
+
+enum Color {
+  # A color.
+  red @0;
+  # The color red.
+  green @1;
+}
+"#;
+
+    #[test]
+    fn test_doc_comments() {
+        let cgr = compile_source("test.capnp", SCHEMA, &CompileOptions::new()).unwrap();
+        let code = code_gen(Path::new("out"), &cgr).unwrap();
+
+        assert!(code.contains("\t/// A shape.\n\t///\n\t/// Drawn on screen.\n\t#[derive("));
+        assert!(code.contains("\t\t/// How many sides.\n\t\t#[getset(get_copy = \"pub\", set = \"pub\")]\n\t\tsides: u32"));
+        assert!(code.contains("\t/// A color.\n\t#[derive(Clone, Debug, PartialEq)]\n\tpub enum Color {"));
+        assert!(code.contains("\t\t/// The color red.\n\t\tRed,\n\t\tGreen\n"));
+    }
+
+    #[test]
+    fn test_module_named_after_file() {
+        let cgr = compile_source("test.capnp", SCHEMA, &CompileOptions::new()).unwrap();
+        let code = code_gen(Path::new("out"), &cgr).unwrap();
+
+        assert!(code.contains("pub mod test {"));
+        assert!(code.contains("for crate::test::Shape {"));
+    }
+}
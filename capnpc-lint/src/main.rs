@@ -0,0 +1,101 @@
+extern crate parser;
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+//
+// Checks schemas against style rules, either as a plugin (`capnp compile -olint schema.capnp`)
+// or by compiling the schema files given as arguments. The rules are set in a JSON config file,
+// given with --config or in CAPNPC_LINT_CONFIG since plugins get no arguments.
+//
+// Lines are worked out by reading the schema files. As a plugin, their names are relative to the
+// directory `capnp compile` ran in, less any --src-prefix, which plugins aren't told.
+// Exits with 1 if any diagnostic is an error, and with 2 if the schemas or config can't be read.
+//
+
+const USAGE: &str = "Usage: capnpc-lint [--config <file>] [--json] [-I <dir>] [--src-prefix=<dir>] [--traversal-limit=<words>] [<schema>...]";
+
+struct Args {
+    config: Option<PathBuf>,
+    json: bool,
+    request: parser::RequestArgs
+}
+
+fn parse_args(args: Vec<String>) -> Option<Args> {
+    let mut parsed = Args {
+        config: env::var_os("CAPNPC_LINT_CONFIG").map(PathBuf::from),
+        json: false,
+        request: parser::RequestArgs::from_env().ok()?
+    };
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            parsed.config = Some(PathBuf::from(args.next()?));
+        } else if arg == "--json" {
+            parsed.json = true;
+        } else if !parsed.request.parse_arg(&arg, &mut args).ok()? {
+            return None;
+        }
+    }
+    Some(parsed)
+}
+
+///
+/// The text of a file named in the request, looked up the way its display name was made.
+///
+fn read_source(display_name: &str, src_prefixes: &[PathBuf]) -> Option<String> {
+    std::iter::once(Path::new(display_name).to_path_buf())
+        .chain(src_prefixes.iter().map(|prefix| prefix.join(display_name)))
+        .find_map(|path| std::fs::read_to_string(path).ok())
+}
+
+fn run(args: &Args) -> Result<bool, Box<dyn std::error::Error>> {
+    let config = match &args.config {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => parser::LintConfig::new()
+    };
+    let cgr = args.request.read_request()?;
+
+    let mut diagnostics = parser::lint(&cgr, &config);
+    let mut sources: HashMap<String, Option<String>> = HashMap::new();
+    for diagnostic in &mut diagnostics {
+        let source = sources.entry(diagnostic.file().clone())
+            .or_insert_with(|| read_source(diagnostic.file(), args.request.options().src_prefixes()));
+        if let Some(source) = source {
+            diagnostic.locate(source);
+        }
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+    } else {
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+    }
+
+    Ok(diagnostics.iter().any(|diagnostic| diagnostic.level() == parser::Level::Error))
+}
+
+fn main() {
+    let args = match parse_args(env::args().skip(1).collect()) {
+        Some(args) => args,
+        None => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(false) => (),
+        Ok(true) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+}
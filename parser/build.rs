@@ -1,11 +1,27 @@
 extern crate capnpc;
 
+///
+/// The parser reads the source positions of nodes and their members, which older versions of
+/// schema.capnp don't declare. Generating from one of those would silently drop the accessors.
+///
+fn check_source_positions(schema_path: &str) {
+    let schema = std::fs::read_to_string(schema_path).expect("read schema.capnp");
+    if !schema.contains("startByte") || !schema.contains("endByte") {
+        panic!(
+            "{} doesn't declare SourceInfo.startByte and endByte. Point CAPNP_ROOT_DIR at a Cap'n Proto release that does.",
+            schema_path
+        );
+    }
+}
+
 fn main() {
     match std::env::var("CAPNP_ROOT_DIR") {
         Ok(capnp_dir) => {
+            let schema_path = format!("{}/capnp/{}", &capnp_dir, "schema.capnp");
+            check_source_positions(&schema_path);
             capnpc::CompilerCommand::new()
                 .src_prefix(format!("{}/capnp", &capnp_dir))
-                .file(format!("{}/capnp/{}", &capnp_dir, "schema.capnp"))
+                .file(&schema_path)
                 .import_path(&capnp_dir)
                 .output_path("src")
                 .run().expect("schema compiler command");
@@ -14,4 +30,4 @@ fn main() {
             println!("cargo:warning=CAPNP_ROOT_DIR is not defined. Skipping code generation.");
         }
     }
-}
\ No newline at end of file
+}
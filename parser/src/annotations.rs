@@ -0,0 +1,293 @@
+use getset::{Getters, CopyGetters};
+use std::collections::HashMap;
+
+use crate::ast;
+use crate::ast::{CodeGeneratorRequest, Id};
+use crate::error::{Error, Result};
+use crate::schema_graph::SchemaGraph;
+
+/// `$Cxx.namespace` from `capnp/c++.capnp`.
+pub const CPP_NAMESPACE_ANNOTATION_ID: Id = 0xb9c6f99ebf805f2c;
+
+/// `$Cxx.name` from `capnp/c++.capnp`.
+pub const CPP_NAME_ANNOTATION_ID: Id = 0xf264a779fef191ce;
+
+#[derive(Clone, Getters, CopyGetters, Debug, PartialEq)]
+pub struct AnnotationDeclaration {
+    #[get_copy = "pub"]
+    id: Id,
+
+    /// The display name of the file that declares the annotation.
+    #[get = "pub"]
+    file: String,
+
+    /// The name within the file, e.g. `namespace` or `Outer.tag`.
+    #[get = "pub"]
+    name: String,
+
+    #[get = "pub"]
+    type_: ast::Type,
+
+    #[get = "pub"]
+    targets: Vec<ast::node::AnnotationTarget>
+}
+
+///
+/// Values that an annotation's value can be decoded into.
+///
+pub trait FromValue: Sized {
+    fn from_value(value: &ast::Value) -> Option<Self>;
+}
+
+macro_rules! from_value {
+    ($type_:ty, $variant:ident) => {
+        impl FromValue for $type_ {
+            fn from_value(value: &ast::Value) -> Option<$type_> {
+                match value {
+                    ast::Value::$variant(v) => Some(v.clone()),
+                    _ => None
+                }
+            }
+        }
+    };
+}
+
+from_value!(bool, Bool);
+from_value!(i8, Int8);
+from_value!(i16, Int16);
+from_value!(i32, Int32);
+from_value!(i64, Int64);
+from_value!(u8, Uint8);
+from_value!(u16, Uint16);
+from_value!(u32, Uint32);
+from_value!(u64, Uint64);
+from_value!(f32, Float32);
+from_value!(f64, Float64);
+from_value!(String, Text);
+
+impl FromValue for () {
+    fn from_value(value: &ast::Value) -> Option<()> {
+        match value {
+            ast::Value::Void => Some(()),
+            _ => None
+        }
+    }
+}
+
+impl FromValue for ast::Value {
+    fn from_value(value: &ast::Value) -> Option<ast::Value> {
+        Some(value.clone())
+    }
+}
+
+///
+/// Data is decoded as bytes, and lists of anything else element by element.
+///
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &ast::Value) -> Option<Vec<T>> {
+        match value {
+            ast::Value::Data(bytes) => bytes.iter().map(|b| T::from_value(&ast::Value::Uint8(*b))).collect(),
+            ast::Value::List(values) => values.iter().map(T::from_value).collect(),
+            _ => None
+        }
+    }
+}
+
+///
+/// An annotation applied to a declaration, with the declaration of the annotation itself.
+///
+#[derive(Clone, Getters, CopyGetters, Debug, PartialEq)]
+pub struct Applied<'r> {
+    #[get_copy = "pub"]
+    declaration: &'r AnnotationDeclaration,
+
+    #[get_copy = "pub"]
+    value: &'r ast::Value
+}
+
+impl<'r> Applied<'r> {
+    pub fn get<T: FromValue>(&self) -> Result<T> {
+        T::from_value(self.value).ok_or_else(|| {
+            Error::Schema(format!(
+                "Unexpected value for annotation {}:{}: {:?}",
+                self.declaration.file, self.declaration.name, self.value
+            ))
+        })
+    }
+}
+
+///
+/// The annotations declared anywhere in a request, looked up by id or by file and name, with
+/// typed access to the values applied to nodes, fields, enumerants and methods.
+///
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct AnnotationRegistry {
+    declarations: HashMap<Id, AnnotationDeclaration>
+}
+
+impl AnnotationRegistry {
+    pub fn new(cgr: &CodeGeneratorRequest) -> AnnotationRegistry {
+        let graph = SchemaGraph::new(cgr);
+        let mut declarations = HashMap::new();
+        for node in graph.nodes() {
+            if let ast::node::Which::Annotation { type_, targets } = node.which() {
+                let file = graph.file_of(node.id()).map_or_else(String::new, |file| file.display_name().clone());
+                let name = graph.qualified_name(node.id()).unwrap_or_default().join(".");
+                declarations.insert(node.id(), AnnotationDeclaration {
+                    id: node.id(),
+                    file: file,
+                    name: name,
+                    type_: type_.clone(),
+                    targets: targets.clone()
+                });
+            }
+        }
+        AnnotationRegistry { declarations: declarations }
+    }
+
+    pub fn declaration(&self, id: Id) -> Option<&AnnotationDeclaration> {
+        self.declarations.get(&id)
+    }
+
+    ///
+    /// The annotation with the given name in the given file. The file matches the display
+    /// name or its trailing path components, so `c++.capnp` finds `capnp/c++.capnp`. A file
+    /// with that exact display name wins; otherwise, more than one match finds nothing.
+    ///
+    pub fn find(&self, file: &str, name: &str) -> Option<&AnnotationDeclaration> {
+        let suffix = format!("/{}", file);
+        let matches: Vec<&AnnotationDeclaration> = self.declarations.values()
+            .filter(|declaration| declaration.name == name && (declaration.file == file || declaration.file.ends_with(&suffix)))
+            .collect();
+        match matches.iter().find(|declaration| declaration.file == file) {
+            Some(exact) => Some(exact),
+            None if matches.len() == 1 => Some(matches[0]),
+            None => None
+        }
+    }
+
+    ///
+    /// The annotations in a list that are declared in the request, in the order they were
+    /// applied. Pass the annotations of a node, field, enumerant or method.
+    ///
+    pub fn applied<'r>(&'r self, annotations: &'r [ast::Annotation]) -> Vec<Applied<'r>> {
+        annotations.iter()
+            .filter_map(|annotation| {
+                self.declaration(annotation.id()).map(|declaration| Applied { declaration: declaration, value: annotation.value() })
+            })
+            .collect()
+    }
+
+    ///
+    /// The value of the annotation with the given id, if it was applied. When it was applied
+    /// more than once the last one wins.
+    ///
+    pub fn value<T: FromValue>(&self, annotations: &[ast::Annotation], id: Id) -> Result<Option<T>> {
+        self.applied(annotations)
+            .into_iter()
+            .rev()
+            .find(|applied| applied.declaration.id == id)
+            .map(|applied| applied.get())
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{compile_files, CompileOptions};
+
+    const CPP: &str = r#"@0xbdf87d7bb8304e81;
+annotation namespace @0xb9c6f99ebf805f2c (file) :Text;
+annotation name @0xf264a779fef191ce (file) :Text;
+"#;
+
+    const IDIOMATIC: &str = r#"@0xc8f4a5b2e3d1f000;
+annotation idiomaticCppNamespace @0xc8f4a5b2e3d1f001 (file) :Text;
+annotation versions @0xc8f4a5b2e3d1f002 (file) :List(UInt16);
+"#;
+
+    const APP: &str = r#"@0xe87e0317861d3aab;
+using Cxx = import "/capnp/c++.capnp";
+using Idiomatic = import "/idiomatic.capnp";
+$Cxx.namespace("app");
+$Idiomatic.idiomaticCppNamespace("app::idiomatic");
+$Idiomatic.versions([1, 2]);
+"#;
+
+    ///
+    /// Compiles the first of `files` after writing them all to a directory of their own, which
+    /// is also the import path and the source prefix.
+    ///
+    fn compile(dir_name: &str, files: &[(&str, &str)]) -> CodeGeneratorRequest {
+        let dir = std::env::temp_dir().join(format!("capnp-annotations-{}-{}", dir_name, std::process::id()));
+        for (name, source) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        let mut options = CompileOptions::new();
+        options.import_path(&dir).src_prefix(&dir);
+        let cgr = compile_files(&[dir.join(files[0].0)], &options);
+        std::fs::remove_dir_all(&dir).unwrap();
+        cgr.unwrap()
+    }
+
+    fn request(dir_name: &str) -> CodeGeneratorRequest {
+        compile(dir_name, &[("app.capnp", APP), ("capnp/c++.capnp", CPP), ("idiomatic.capnp", IDIOMATIC)])
+    }
+
+    #[test]
+    fn test_find() {
+        let registry = AnnotationRegistry::new(&request("find"));
+        assert_eq!(registry.find("c++.capnp", "namespace").map(|d| d.id()), Some(CPP_NAMESPACE_ANNOTATION_ID));
+        assert_eq!(registry.find("capnp/c++.capnp", "name").map(|d| d.id()), Some(CPP_NAME_ANNOTATION_ID));
+        assert_eq!(registry.find("idiomatic.capnp", "versions").map(|d| d.type_().clone()), Some(ast::Type::List(Box::new(ast::Type::Uint16))));
+        assert!(registry.find("++.capnp", "name").is_none());
+        assert!(registry.find("idiomatic.capnp", "namespace").is_none());
+    }
+
+    #[test]
+    fn test_values() {
+        let cgr = request("values");
+        let registry = AnnotationRegistry::new(&cgr);
+        let graph = SchemaGraph::new(&cgr);
+        let annotations = graph.node(0xe87e0317861d3aab).unwrap().annotations();
+
+        let applied: Vec<&str> = registry.applied(annotations).iter().map(|a| a.declaration().name().as_str()).collect();
+        assert_eq!(applied, vec!("namespace", "idiomaticCppNamespace", "versions"));
+
+        assert_eq!(registry.value::<String>(annotations, CPP_NAMESPACE_ANNOTATION_ID).unwrap(), Some(String::from("app")));
+        assert_eq!(registry.value::<String>(annotations, CPP_NAME_ANNOTATION_ID).unwrap(), None);
+        assert_eq!(registry.value::<String>(annotations, 0xc8f4a5b2e3d1f001).unwrap(), Some(String::from("app::idiomatic")));
+        assert_eq!(registry.value::<Vec<u16>>(annotations, 0xc8f4a5b2e3d1f002).unwrap(), Some(vec!(1, 2)));
+        assert!(registry.value::<bool>(annotations, CPP_NAMESPACE_ANNOTATION_ID).is_err());
+    }
+
+    #[test]
+    fn test_find_ambiguous() {
+        let tags = |id: &str| format!("@{};\nannotation tag (file) :Text;\n", id);
+        let (a, b, top) = (tags("0xa1b2c3d4e5f60001"), tags("0xa1b2c3d4e5f60002"), tags("0xa1b2c3d4e5f60003"));
+        let main = "@0xe87e0317861d3aab;\nusing A = import \"a/tags.capnp\";\nusing B = import \"b/tags.capnp\";\n";
+
+        let with_exact = format!("{}using T = import \"tags.capnp\";\n", main);
+        let registry = AnnotationRegistry::new(&compile(
+            "ambiguous",
+            &[("main.capnp", &with_exact), ("a/tags.capnp", &a), ("b/tags.capnp", &b), ("tags.capnp", &top)]
+        ));
+        assert_eq!(registry.find("tags.capnp", "tag").map(|d| d.file().as_str()), Some("tags.capnp"));
+        assert_eq!(registry.find("a/tags.capnp", "tag").map(|d| d.file().as_str()), Some("a/tags.capnp"));
+
+        let without_exact = compile("ambiguous-without-exact", &[("main.capnp", main), ("a/tags.capnp", &a), ("b/tags.capnp", &b)]);
+        assert!(AnnotationRegistry::new(&without_exact).find("tags.capnp", "tag").is_none());
+    }
+
+    #[test]
+    fn test_missing_declarations() {
+        // Without c++.capnp in the request there's nothing to find, rather than a panic.
+        let registry = AnnotationRegistry::new(&compile("missing", &[("app.capnp", "@0xe87e0317861d3aab;\n")]));
+        let annotations = vec!(ast::Annotation::new(CPP_NAMESPACE_ANNOTATION_ID, ast::Value::Text(String::from("app"))));
+        assert!(registry.find("c++.capnp", "namespace").is_none());
+        assert_eq!(registry.value::<String>(&annotations, CPP_NAMESPACE_ANNOTATION_ID).unwrap(), None);
+    }
+}
@@ -1,18 +1,56 @@
 use getset::{Getters, CopyGetters, Setters};
+use std::collections::{BTreeMap, HashMap};
 
-type Id = u64;
+pub type Id = u64;
 
-#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq)]
+#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
 #[get = "pub"]
 pub struct CodeGeneratorRequest {
     nodes: Vec<Node>,
-    requested_files: Vec<code_generator_request::RequestedFile>
+    requested_files: Vec<code_generator_request::RequestedFile>,
+
+    ///
+    /// Doc comments and source positions for each node (and its members) that has any, keyed by node id.
+    ///
+    #[serde(serialize_with = "serialize_sorted_by_id")]
+    source_info: HashMap<Id, node::SourceInfo>
+}
+
+// Sorted, so that dumps of the same request are identical.
+fn serialize_sorted_by_id<S: serde::Serializer, T: serde::Serialize>(map: &HashMap<Id, T>, serializer: S) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(&map.iter().collect::<BTreeMap<&Id, &T>>(), serializer)
+}
+
+impl CodeGeneratorRequest {
+    pub fn doc_comment(&self, id: Id) -> Option<&String> {
+        self.source_info.get(&id).and_then(|info| info.doc_comment().as_ref())
+    }
+
+    ///
+    /// The doc comment of a field, enumerant or method, given its index in the node's list.
+    ///
+    pub fn member_doc_comment(&self, id: Id, index: usize) -> Option<&String> {
+        self.source_info.get(&id)
+            .and_then(|info| info.members().get(index))
+            .and_then(|member| member.doc_comment().as_ref())
+    }
+}
+
+///
+/// The lines of a doc comment, each behind a comment marker such as `///` or `#`, for backends
+/// and printers to place above a declaration. Blank lines get no trailing whitespace.
+///
+pub fn doc_comment_lines(doc_comment: &str, marker: &str) -> Vec<String> {
+    doc_comment.trim_end()
+        .lines()
+        .map(|line| format!("{} {}", marker, line).trim_end().to_string())
+        .collect()
 }
 
 pub mod code_generator_request {
     use super::*;
 
-    #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Default, Debug, PartialEq)]
+    #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Default, Debug, PartialEq, Serialize)]
     pub struct RequestedFile {
         #[get_copy = "pub"]
         id: Id,
@@ -27,7 +65,7 @@ pub mod code_generator_request {
     pub mod requested_file {
         use super::*;
 
-        #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Default, Debug, PartialEq)]
+        #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Default, Debug, PartialEq, Serialize)]
         pub struct Import {
             #[get_copy = "pub"]
             id: Id,
@@ -38,7 +76,7 @@ pub mod code_generator_request {
     }
 }
 
-#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq)]
+#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
 pub struct Annotation {
     #[get_copy = "pub"]
     id: Id,
@@ -47,7 +85,7 @@ pub struct Annotation {
     value: Value
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum Type {
     Void,
     Bool,
@@ -64,19 +102,114 @@ pub enum Type {
     Text,
     Data,
     List(Box<Type>),
-    Enum { type_id: Id },
-    Struct { type_id: Id },
-    Interface { type_id: Id },
-    AnyPointer
+    Enum { type_id: Id, brand: Brand },
+    Struct { type_id: Id, brand: Brand },
+    Interface { type_id: Id, brand: Brand },
+    AnyPointer(type_::AnyPointer)
+}
+
+pub mod type_ {
+    use super::Id;
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    pub enum AnyPointer {
+        Unconstrained(Unconstrained),
+
+        /// A type parameter of the node `scope_id`.
+        Parameter { scope_id: Id, parameter_index: u16 },
+
+        /// A type parameter of the method being declared.
+        ImplicitMethodParameter { parameter_index: u16 }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+    pub enum Unconstrained {
+        AnyKind,
+        Struct,
+        List,
+        Capability
+    }
+}
+
+///
+/// The bindings for the generic parameters of a type and of each of its enclosing scopes.
+///
+#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Default, Debug, PartialEq, Serialize)]
+pub struct Brand {
+    #[get = "pub"]
+    scopes: Vec<brand::Scope>
+}
+
+pub mod brand {
+    use getset::{Getters, CopyGetters, Setters};
+    use super::Id;
+
+    #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
+    pub struct Scope {
+        #[get_copy = "pub"]
+        scope_id: Id,
+
+        #[get = "pub"]
+        which: scope::Which
+    }
+
+    pub mod scope {
+        #[derive(Clone, Debug, PartialEq, Serialize)]
+        pub enum Which {
+            Bind(Vec<super::Binding>),
+
+            /// The scope's parameters are bound to the same types as in the enclosing context.
+            Inherit
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
+    pub enum Binding {
+        Unbound,
+        Type(super::Type)
+    }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum Value {
+    Void,
+    Bool(bool),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+    Uint64(u64),
+    Float32(f32),
+    Float64(f64),
     Text(String),
+    Data(Vec<u8>),
+    List(Vec<Value>),
+    Enum(u16),
+    Struct(Vec<value::StructField>),
+    Interface,
+    AnyPointer,
+
+    /// A list or struct value whose schema type is not available in the request.
     Unknown
 }
 
-#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq)]
+pub mod value {
+    use getset::{Getters, CopyGetters, Setters};
+
+    #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
+    pub struct StructField {
+        #[get = "pub"]
+        name: String,
+
+        #[get = "pub"]
+        value: super::Value
+    }
+}
+
+#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
 pub struct Node {
     #[get_copy = "pub"]
     id: Id,
@@ -96,6 +229,12 @@ pub struct Node {
     #[get = "pub"]
     annotations: Vec<Annotation>,
 
+    #[get = "pub"]
+    parameters: Vec<node::Parameter>,
+
+    #[get_copy = "pub"]
+    is_generic: bool,
+
     #[get = "pub"]
     which: node::Which
 }
@@ -104,7 +243,7 @@ pub mod node {
     use getset::{Getters, CopyGetters, Setters};
     use crate::ast;
 
-    #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq)]
+    #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
     pub struct NestedNode {
         #[get_copy = "pub"]
         id: super::Id,
@@ -113,30 +252,131 @@ pub mod node {
         name: String
     }
 
-    #[derive(Clone, Debug, PartialEq)]
+    #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
+    pub struct Parameter {
+        #[get = "pub"]
+        name: String
+    }
+
+    ///
+    /// The encoding a list of this struct prefers, when the struct is small enough to fit in one.
+    ///
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+    pub enum ElementSize {
+        Empty,
+        Bit,
+        Byte,
+        TwoBytes,
+        FourBytes,
+        EightBytes,
+        Pointer,
+        InlineComposite
+    }
+
+    #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
+    pub struct SourceInfo {
+        #[get_copy = "pub"]
+        id: super::Id,
+
+        #[get = "pub"]
+        doc_comment: Option<String>,
+
+        /// One entry per field, enumerant or method, in the same order as the node lists them.
+        #[get = "pub"]
+        members: Vec<source_info::Member>,
+
+        ///
+        /// Byte offsets of the declaration in its file; the end is exclusive. Both are 0 when
+        /// the compiler didn't say, as older versions of `capnp compile` don't.
+        ///
+        #[get_copy = "pub"]
+        start_byte: u32,
+
+        #[get_copy = "pub"]
+        end_byte: u32
+    }
+
+    pub mod source_info {
+        use getset::{Getters, CopyGetters, Setters};
+
+        #[derive(Clone, Constructor, Getters, CopyGetters, Setters, Default, Debug, PartialEq, Serialize)]
+        pub struct Member {
+            #[get = "pub"]
+            doc_comment: Option<String>,
+
+            #[get_copy = "pub"]
+            start_byte: u32,
+
+            #[get_copy = "pub"]
+            end_byte: u32
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
     pub enum Which {
         File,
         Struct {
+            data_word_count: u16,
+            pointer_count: u16,
+            preferred_list_encoding: ElementSize,
             is_group: bool,
             discriminant_count: u16,
             discriminant_offset: u32,
             fields: Vec<ast::Field>
         },
         Enum(Vec<super::Enumerant>),
-        Interface,
+        Interface {
+            methods: Vec<ast::Method>,
+            superclasses: Vec<ast::Superclass>
+        },
+        Const {
+            type_: ast::Type,
+            value: ast::Value
+        },
+        Annotation {
+            type_: ast::Type,
+            targets: Vec<AnnotationTarget>
+        }
+    }
+
+    ///
+    /// The kinds of declaration an annotation may be applied to.
+    ///
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+    pub enum AnnotationTarget {
+        File,
         Const,
+        Enum,
+        Enumerant,
+        Struct,
+        Field,
+        Union,
+        Group,
+        Interface,
+        Method,
+        Param,
         Annotation
     }
 }
 
-#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq)]
+#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
 pub struct Field {
     #[get = "pub"]
     name: String,
 
+    /// The position of the field in the order it was declared in the schema file.
+    #[get_copy = "pub"]
+    code_order: u16,
+
     #[get_copy = "pub"]
     discriminant_value: u16,
 
+    #[get_copy = "pub"]
+    ordinal: field::Ordinal,
+
+    #[get = "pub"]
+    annotations: Vec<Annotation>,
+
     #[get = "pub"]
     which: field::Which
 }
@@ -144,16 +384,70 @@ pub struct Field {
 pub mod field {
     pub const NO_DISCRIMINANT : u16 = 0xFFFF;
 
-    #[derive(Clone, Debug, PartialEq)]
+    #[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+    pub enum Ordinal {
+        /// Groups and named unions have no ordinal of their own.
+        Implicit,
+        Explicit(u16)
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize)]
     pub enum Which {
-        Slot(super::Type),
+        Slot {
+            offset: u32,
+            type_: super::Type,
+            default_value: super::Value,
+            had_explicit_default: bool
+        },
         Group(u64)
     }
 }
 
     
-#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Default, Debug, PartialEq)]
+#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Default, Debug, PartialEq, Serialize)]
 pub struct Enumerant {
     #[get = "pub"]
-    name: String
-}
\ No newline at end of file
+    name: String,
+
+    #[get_copy = "pub"]
+    code_order: u16,
+
+    #[get = "pub"]
+    annotations: Vec<Annotation>
+}
+
+#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
+pub struct Method {
+    #[get = "pub"]
+    name: String,
+
+    #[get_copy = "pub"]
+    code_order: u16,
+
+    #[get_copy = "pub"]
+    param_struct_type: Id,
+
+    #[get_copy = "pub"]
+    result_struct_type: Id,
+
+    #[get = "pub"]
+    param_brand: Brand,
+
+    #[get = "pub"]
+    result_brand: Brand,
+
+    #[get = "pub"]
+    implicit_parameters: Vec<node::Parameter>,
+
+    #[get = "pub"]
+    annotations: Vec<Annotation>
+}
+
+#[derive(Clone, Constructor, Getters, CopyGetters, Setters, Debug, PartialEq, Serialize)]
+pub struct Superclass {
+    #[get_copy = "pub"]
+    id: Id,
+
+    #[get = "pub"]
+    brand: Brand
+}
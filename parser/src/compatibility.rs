@@ -0,0 +1,687 @@
+use getset::{Getters, CopyGetters};
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ast;
+use crate::ast::{CodeGeneratorRequest, Id, Node};
+use crate::schema_graph::SchemaGraph;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum Severity {
+    /// Code built against either schema can still exchange messages with the other.
+    Compatible,
+
+    /// Messages written with one schema would be misread with the other, or the change
+    /// removes something that code built against the old schema relies on.
+    Breaking
+}
+
+///
+/// One difference between an old and a new version of a schema.
+///
+#[derive(Clone, Getters, CopyGetters, Debug, PartialEq, Serialize)]
+pub struct Change {
+    #[get_copy = "pub"]
+    severity: Severity,
+
+    #[get_copy = "pub"]
+    node_id: Id,
+
+    /// The name of the node in the old schema, or in the new one if it was added.
+    #[get = "pub"]
+    node_name: String,
+
+    /// The field, enumerant or method the change is about, if any.
+    #[get = "pub"]
+    member: Option<String>,
+
+    #[get = "pub"]
+    description: String
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Compatible => "compatible",
+            Severity::Breaking => "breaking"
+        };
+        match &self.member {
+            Some(member) => write!(f, "{}: {}: {}: {}", severity, self.node_name, member, self.description),
+            None => write!(f, "{}: {}: {}", severity, self.node_name, self.description)
+        }
+    }
+}
+
+#[derive(Clone, Default, Getters, Debug, PartialEq, Serialize)]
+pub struct CompatibilityReport {
+    /// In the order of the old request, followed by the nodes that were added.
+    #[get = "pub"]
+    changes: Vec<Change>
+}
+
+impl CompatibilityReport {
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(|change| change.severity == Severity::Breaking)
+    }
+
+    pub fn breaking_changes(&self) -> Vec<&Change> {
+        self.changes.iter().filter(|change| change.severity == Severity::Breaking).collect()
+    }
+}
+
+///
+/// Compares two versions of a schema, matching nodes by id, fields by ordinal anywhere in
+/// their struct's groups and unions, and enumerants and methods by their number. Follows the
+/// rules for safe changes from the Cap'n Proto language reference: types, defaults and
+/// numbering must stay the same, new union members go at the end, fields may only move into
+/// new groups and unions, and nothing may be removed. Renames are reported as compatible.
+///
+pub fn check_compatibility(old: &CodeGeneratorRequest, new: &CodeGeneratorRequest) -> CompatibilityReport {
+    let old = SchemaGraph::new(old);
+    let new = SchemaGraph::new(new);
+    let mut checker = Checker { old: &old, new: &new, changes: vec!() };
+    checker.check();
+    CompatibilityReport { changes: checker.changes }
+}
+
+struct Checker<'a> {
+    old: &'a SchemaGraph,
+    new: &'a SchemaGraph,
+    changes: Vec<Change>
+}
+
+fn kind_of(which: &ast::node::Which) -> &'static str {
+    match which {
+        ast::node::Which::File => "file",
+        ast::node::Which::Struct { .. } => "struct",
+        ast::node::Which::Enum(_) => "enum",
+        ast::node::Which::Interface { .. } => "interface",
+        ast::node::Which::Const { .. } => "const",
+        ast::node::Which::Annotation { .. } => "annotation"
+    }
+}
+
+fn describe_type(graph: &SchemaGraph, type_: &ast::Type) -> String {
+    match type_ {
+        ast::Type::List(element_type) => format!("List({})", describe_type(graph, element_type)),
+        ast::Type::Enum { type_id, .. } | ast::Type::Struct { type_id, .. } | ast::Type::Interface { type_id, .. } => {
+            graph.qualified_name(*type_id).map(|names| names.join(".")).unwrap_or_else(|| format!("@{:#x}", type_id))
+        },
+        ast::Type::AnyPointer(_) => String::from("AnyPointer"),
+        primitive => format!("{:?}", primitive)
+    }
+}
+
+///
+/// The parameter and result structs the compiler generates for methods. They have no scope,
+/// and their ids change with the method's name, so they're compared through their methods.
+///
+fn implicit_method_structs(graph: &SchemaGraph) -> HashSet<Id> {
+    let mut ids = HashSet::new();
+    for node in graph.nodes() {
+        if let ast::node::Which::Interface { methods, .. } = node.which() {
+            for method in methods {
+                for id in vec!(method.param_struct_type(), method.result_struct_type()) {
+                    if graph.node(id).map_or(false, |n| n.scope_id() == 0) {
+                        ids.insert(id);
+                    }
+                }
+            }
+        }
+    }
+    ids
+}
+
+///
+/// What happened to the member with a given number (an enumerant or method), judging by the names.
+///
+enum Renumbering<'n> {
+    Unchanged,
+    Renamed(&'n String),
+    Moved(usize),
+    Removed
+}
+
+fn renumbering<'n>(old: &[&'n String], new: &[&'n String]) -> Vec<(usize, Renumbering<'n>)> {
+    old.iter()
+        .enumerate()
+        .map(|(number, name)| {
+            let change = match new.iter().position(|new_name| new_name == name) {
+                Some(new_number) if new_number == number => Renumbering::Unchanged,
+                Some(new_number) => Renumbering::Moved(new_number),
+                None => match new.get(number) {
+                    Some(new_name) if !old.contains(new_name) => Renumbering::Renamed(*new_name),
+                    _ => Renumbering::Removed
+                }
+            };
+            (number, change)
+        })
+        .collect()
+}
+
+///
+/// The new names that are neither in the old list nor a rename of an old member.
+///
+fn added<'n>(old: &[&'n String], new: &[&'n String]) -> Vec<&'n String> {
+    new.iter()
+        .enumerate()
+        .filter(|(_, name)| !old.contains(*name))
+        .filter(|(number, _)| match old.get(*number) {
+            Some(old_name) => new.contains(old_name),
+            None => true
+        })
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+fn is_group(node: &Node) -> bool {
+    match node.which() {
+        ast::node::Which::Struct { is_group, .. } => *is_group,
+        _ => false
+    }
+}
+
+///
+/// A field of a struct or of one of its groups and named unions. Fields can be moved into new
+/// groups and unions, so they're matched across the whole tree.
+///
+struct TreeField<'g> {
+    field: &'g ast::Field,
+
+    /// The struct or group that declares the field.
+    scope_id: Id,
+
+    /// The name qualified by the groups the field is in, e.g. `extra.visible`.
+    path: String,
+
+    /// The index of the group field that the field is in.
+    parent: Option<usize>
+}
+
+fn tree_fields(graph: &SchemaGraph, id: Id) -> Vec<TreeField> {
+    let mut fields = vec!();
+    add_tree_fields(graph, id, None, &mut fields);
+    fields
+}
+
+fn add_tree_fields<'g>(graph: &'g SchemaGraph, scope_id: Id, parent: Option<usize>, fields: &mut Vec<TreeField<'g>>) {
+    let scope_fields = match graph.node(scope_id).map(|node| node.which()) {
+        Some(ast::node::Which::Struct { fields: scope_fields, .. }) => scope_fields,
+        _ => return
+    };
+    for field in scope_fields {
+        let path = match parent {
+            Some(parent) => format!("{}.{}", fields[parent].path, field.name()),
+            None => field.name().clone()
+        };
+        fields.push(TreeField { field: field, scope_id: scope_id, path: path, parent: parent });
+        if let ast::field::Which::Group(group_id) = field.which() {
+            let index = fields.len() - 1;
+            add_tree_fields(graph, *group_id, Some(index), fields);
+        }
+    }
+}
+
+///
+/// Fields are matched by ordinal, and groups, which have none, by id. A group's id comes from
+/// its position in its parent, so it stays the same when the group is renamed.
+///
+fn find_field<'t, 'g>(fields: &'t [TreeField<'g>], field: &ast::Field) -> Option<&'t TreeField<'g>> {
+    fields.iter().find(|f| match (f.field.which(), field.which()) {
+        (ast::field::Which::Group(id), ast::field::Which::Group(other_id)) => id == other_id,
+        (ast::field::Which::Slot { .. }, ast::field::Which::Slot { .. }) => f.field.ordinal() == field.ordinal(),
+        _ => false
+    })
+}
+
+///
+/// The discriminant offset of the union of a struct or group, if it has one.
+///
+fn discriminant_offset(graph: &SchemaGraph, id: Id) -> Option<u32> {
+    match graph.node(id).map(|node| node.which()) {
+        Some(ast::node::Which::Struct { discriminant_count, discriminant_offset, .. }) if *discriminant_count > 0 => Some(*discriminant_offset),
+        _ => None
+    }
+}
+
+///
+/// Whether two values are encoded the same. Floats are compared by their bits, so an unchanged
+/// `nan` is the same and `0.0` and `-0.0`, which give different XOR masks on the wire, are not.
+///
+fn same_value(old: &ast::Value, new: &ast::Value) -> bool {
+    match (old, new) {
+        (ast::Value::Float32(old), ast::Value::Float32(new)) => old.to_bits() == new.to_bits(),
+        (ast::Value::Float64(old), ast::Value::Float64(new)) => old.to_bits() == new.to_bits(),
+        (ast::Value::List(old), ast::Value::List(new)) => {
+            old.len() == new.len() && old.iter().zip(new).all(|(old, new)| same_value(old, new))
+        },
+        (ast::Value::Struct(old), ast::Value::Struct(new)) => {
+            old.len() == new.len() && old.iter().zip(new).all(|(old, new)| old.name() == new.name() && same_value(old.value(), new.value()))
+        },
+        (old, new) => old == new
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn node_name(&self, id: Id) -> String {
+        self.old.fully_qualified_name(id)
+            .or_else(|| self.new.fully_qualified_name(id))
+            .or_else(|| self.old.node(id).or_else(|| self.new.node(id)).map(|node| node.display_name().clone()))
+            .unwrap_or_else(|| format!("@{:#x}", id))
+    }
+
+    fn report(&mut self, severity: Severity, node_id: Id, member: Option<&String>, description: String) {
+        let node_name = self.node_name(node_id);
+        self.changes.push(Change {
+            severity: severity,
+            node_id: node_id,
+            node_name: node_name,
+            member: member.cloned(),
+            description: description
+        });
+    }
+
+    fn check(&mut self) {
+        let old = self.old;
+        let new = self.new;
+        let old_method_structs = implicit_method_structs(old);
+        let new_method_structs = implicit_method_structs(new);
+
+        // Groups are compared with the structs they're in.
+        for old_node in old.nodes().iter().filter(|node| !old_method_structs.contains(&node.id()) && !is_group(node)) {
+            match new.node(old_node.id()) {
+                Some(new_node) => self.check_node(old_node, new_node),
+                None => self.report(Severity::Breaking, old_node.id(), None, format!("{} was removed", kind_of(old_node.which())))
+            }
+        }
+
+        for new_node in new.nodes().iter().filter(|node| !new_method_structs.contains(&node.id()) && !is_group(node)) {
+            if !old.contains(new_node.id()) {
+                self.report(Severity::Compatible, new_node.id(), None, format!("{} was added", kind_of(new_node.which())));
+            }
+        }
+    }
+
+    fn check_node(&mut self, old: &Node, new: &Node) {
+        let id = old.id();
+        if old.display_name() != new.display_name() {
+            self.report(Severity::Compatible, id, None, format!("renamed to {}", new.display_name()));
+        }
+
+        match (old.which(), new.which()) {
+            (ast::node::Which::File, ast::node::Which::File) => (),
+            (ast::node::Which::Struct { .. }, ast::node::Which::Struct { .. }) => self.check_struct(id, id),
+            (ast::node::Which::Enum(old_enumerants), ast::node::Which::Enum(new_enumerants)) => {
+                self.check_enum(id, old_enumerants, new_enumerants)
+            },
+            (
+                ast::node::Which::Interface { methods: old_methods, superclasses: old_superclasses },
+                ast::node::Which::Interface { methods: new_methods, superclasses: new_superclasses }
+            ) => {
+                self.check_methods(id, old_methods, new_methods);
+                self.check_superclasses(id, old_superclasses, new_superclasses)
+            },
+            (ast::node::Which::Const { type_: old_type, value: old_value }, ast::node::Which::Const { type_: new_type, value: new_value }) => {
+                if old_type != new_type {
+                    let description = format!("type changed from {} to {}", describe_type(self.old, old_type), describe_type(self.new, new_type));
+                    self.report(Severity::Breaking, id, None, description);
+                } else if !same_value(old_value, new_value) {
+                    // Constants aren't sent over the wire.
+                    self.report(Severity::Compatible, id, None, String::from("value changed"));
+                }
+            },
+            (ast::node::Which::Annotation { type_: old_type, .. }, ast::node::Which::Annotation { type_: new_type, .. }) => {
+                if old_type != new_type {
+                    let description = format!("type changed from {} to {}", describe_type(self.old, old_type), describe_type(self.new, new_type));
+                    self.report(Severity::Breaking, id, None, description);
+                }
+            },
+            (old_which, new_which) => {
+                let description = format!("changed from {} to {}", kind_of(old_which), kind_of(new_which));
+                self.report(Severity::Breaking, id, None, description)
+            }
+        }
+    }
+
+    fn check_struct(&mut self, old_id: Id, new_id: Id) {
+        let old = self.old;
+        let new = self.new;
+        let old_fields = tree_fields(old, old_id);
+        let new_fields = tree_fields(new, new_id);
+
+        if let (Some(old_offset), Some(new_offset)) = (discriminant_offset(old, old_id), discriminant_offset(new, new_id)) {
+            if old_offset != new_offset {
+                self.report(Severity::Breaking, old_id, None, String::from("union discriminant moved"));
+            }
+        }
+
+        for old_field in &old_fields {
+            match find_field(&new_fields, old_field.field) {
+                Some(new_field) => self.check_field(old_id, new_id, old_field, new_field, &old_fields, &new_fields),
+                // A removed group is reported without its fields.
+                None if old_field.parent.map_or(false, |parent| find_field(&new_fields, old_fields[parent].field).is_none()) => (),
+                None => self.report(Severity::Breaking, old_id, Some(&old_field.path), String::from("field was removed"))
+            }
+        }
+
+        for new_field in new_fields.iter().filter(|field| find_field(&old_fields, field.field).is_none()) {
+            // An added group is reported without its fields.
+            if new_field.parent.map_or(false, |parent| find_field(&old_fields, new_fields[parent].field).is_none()) {
+                continue;
+            }
+
+            let old_scope_id = if new_field.scope_id == new_id { old_id } else { new_field.scope_id };
+            let last_discriminant = old_fields.iter()
+                .filter(|field| field.scope_id == old_scope_id)
+                .map(|field| field.field.discriminant_value())
+                .filter(|discriminant| *discriminant != ast::field::NO_DISCRIMINANT)
+                .max();
+            let discriminant = new_field.field.discriminant_value();
+            match last_discriminant {
+                Some(last) if discriminant != ast::field::NO_DISCRIMINANT && discriminant <= last => {
+                    self.report(Severity::Breaking, old_id, Some(&new_field.path), String::from("union member was added before existing members"))
+                },
+                _ => self.report(Severity::Compatible, old_id, Some(&new_field.path), String::from("field was added"))
+            }
+        }
+    }
+
+    fn check_field(&mut self, old_id: Id, new_id: Id, old: &TreeField, new: &TreeField, old_fields: &[TreeField], new_fields: &[TreeField]) {
+        let member = Some(&old.path);
+        if old.field.name() != new.field.name() {
+            self.report(Severity::Compatible, old_id, member, format!("renamed to {}", new.field.name()));
+        }
+
+        if let ast::field::Which::Group(group_id) = old.field.which() {
+            if let (Some(old_offset), Some(new_offset)) = (discriminant_offset(self.old, *group_id), discriminant_offset(self.new, *group_id)) {
+                if old_offset != new_offset {
+                    self.report(Severity::Breaking, old_id, member, String::from("union discriminant moved"));
+                }
+            }
+        }
+
+        let same_scope = old.scope_id == new.scope_id || (old.scope_id == old_id && new.scope_id == new_id);
+        // A field can move into a union whose other members are all new.
+        let into_new_union = || new_fields.iter()
+            .filter(|f| f.scope_id == new.scope_id && !std::ptr::eq(f.field, new.field))
+            .filter(|f| f.field.discriminant_value() != ast::field::NO_DISCRIMINANT)
+            .all(|f| find_field(old_fields, f.field).is_none());
+
+        match (old.field.discriminant_value(), new.field.discriminant_value()) {
+            (old_discriminant, new_discriminant) if old_discriminant == new_discriminant && same_scope => (),
+            (ast::field::NO_DISCRIMINANT, ast::field::NO_DISCRIMINANT) => {
+                let description = match new.parent {
+                    Some(parent) => format!("moved into {}", new_fields[parent].path),
+                    None => String::from("moved out of its group")
+                };
+                self.report(Severity::Compatible, old_id, member, description)
+            },
+            (ast::field::NO_DISCRIMINANT, _) if into_new_union() => {
+                self.report(Severity::Compatible, old_id, member, String::from("moved into a new union"))
+            },
+            (ast::field::NO_DISCRIMINANT, _) => self.report(Severity::Breaking, old_id, member, String::from("moved into a union")),
+            (_, ast::field::NO_DISCRIMINANT) => self.report(Severity::Breaking, old_id, member, String::from("moved out of a union")),
+            (old_discriminant, new_discriminant) if same_scope => {
+                self.report(Severity::Breaking, old_id, member, format!("union discriminant changed from {} to {}", old_discriminant, new_discriminant))
+            },
+            (_, _) => self.report(Severity::Breaking, old_id, member, String::from("moved into a different union"))
+        }
+
+        // Fields are only matched with fields, and groups with groups.
+        if let (
+            ast::field::Which::Slot { offset: old_offset, type_: old_type, default_value: old_default, .. },
+            ast::field::Which::Slot { offset: new_offset, type_: new_type, default_value: new_default, .. }
+        ) = (old.field.which(), new.field.which()) {
+            if old_type != new_type {
+                let description = format!("type changed from {} to {}", describe_type(self.old, old_type), describe_type(self.new, new_type));
+                self.report(Severity::Breaking, old_id, member, description);
+            } else if old_offset != new_offset {
+                self.report(Severity::Breaking, old_id, member, format!("moved from offset {} to {}", old_offset, new_offset));
+            } else if !same_value(old_default, new_default) {
+                self.report(Severity::Breaking, old_id, member, String::from("default value changed"));
+            }
+        }
+    }
+
+    fn check_enum(&mut self, id: Id, old: &[ast::Enumerant], new: &[ast::Enumerant]) {
+        let old_names: Vec<&String> = old.iter().map(|e| e.name()).collect();
+        let new_names: Vec<&String> = new.iter().map(|e| e.name()).collect();
+        for (number, change) in renumbering(&old_names, &new_names) {
+            let member = Some(old_names[number]);
+            match change {
+                Renumbering::Unchanged => (),
+                Renumbering::Renamed(name) => self.report(Severity::Compatible, id, member, format!("renamed to {}", name)),
+                Renumbering::Moved(new_number) => self.report(Severity::Breaking, id, member, format!("renumbered from {} to {}", number, new_number)),
+                Renumbering::Removed => self.report(Severity::Breaking, id, member, String::from("enumerant was removed"))
+            }
+        }
+        for name in added(&old_names, &new_names) {
+            self.report(Severity::Compatible, id, Some(name), String::from("enumerant was added"));
+        }
+    }
+
+    fn check_method_struct(&mut self, old_id: Id, new_id: Id) {
+        let old = self.old;
+        let new = self.new;
+        match (old.node(old_id), new.node(new_id)) {
+            (Some(old_node), Some(_)) if old_node.scope_id() == 0 => self.check_struct(old_id, new_id),
+            _ => ()
+        }
+    }
+
+    fn check_methods(&mut self, id: Id, old: &[ast::Method], new: &[ast::Method]) {
+        let old_names: Vec<&String> = old.iter().map(|m| m.name()).collect();
+        let new_names: Vec<&String> = new.iter().map(|m| m.name()).collect();
+        for (number, change) in renumbering(&old_names, &new_names) {
+            let member = Some(old_names[number]);
+            match change {
+                Renumbering::Unchanged => (),
+                Renumbering::Renamed(name) => self.report(Severity::Compatible, id, member, format!("renamed to {}", name)),
+                Renumbering::Moved(new_number) => {
+                    self.report(Severity::Breaking, id, member, format!("renumbered from {} to {}", number, new_number));
+                    continue;
+                },
+                Renumbering::Removed => {
+                    self.report(Severity::Breaking, id, member, String::from("method was removed"));
+                    continue;
+                }
+            }
+            self.check_method_struct(old[number].param_struct_type(), new[number].param_struct_type());
+            self.check_method_struct(old[number].result_struct_type(), new[number].result_struct_type());
+        }
+        for name in added(&old_names, &new_names) {
+            self.report(Severity::Compatible, id, Some(name), String::from("method was added"));
+        }
+    }
+
+    fn check_superclasses(&mut self, id: Id, old: &[ast::Superclass], new: &[ast::Superclass]) {
+        for old_superclass in old.iter().filter(|s| !new.iter().any(|n| n.id() == s.id())) {
+            let description = format!("no longer extends {}", self.node_name(old_superclass.id()));
+            self.report(Severity::Breaking, id, None, description);
+        }
+        for new_superclass in new.iter().filter(|s| !old.iter().any(|o| o.id() == s.id())) {
+            let description = format!("now extends {}", self.node_name(new_superclass.id()));
+            self.report(Severity::Compatible, id, None, description);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::fixtures::{node, slot, struct_which};
+
+    fn int32(name: &str, ordinal: u16, offset: u32) -> ast::Field {
+        slot(name, ordinal, ast::field::NO_DISCRIMINANT, offset, ast::Type::Int32, ast::Value::Int32(0))
+    }
+
+    fn request(fields: Vec<ast::Field>, discriminant_count: u16, enumerants: Vec<&str>) -> CodeGeneratorRequest {
+        CodeGeneratorRequest::new(
+            vec!(
+                node(1, "test.capnp", 0, vec!(), ast::node::Which::File),
+                node(2, "test.capnp:Shape", 1, vec!(), struct_which(1, 0, false, discriminant_count, 3, fields)),
+                node(3, "test.capnp:Color", 1, vec!(), ast::node::Which::Enum(
+                    enumerants.into_iter().enumerate().map(|(i, name)| ast::Enumerant::new(String::from(name), i as u16, vec!())).collect()
+                ))
+            ),
+            vec!(),
+            HashMap::new()
+        )
+    }
+
+    fn descriptions(report: &CompatibilityReport) -> Vec<String> {
+        report.changes().iter().map(|change| change.to_string()).collect()
+    }
+
+    #[test]
+    fn test_identical_schemas() {
+        let schema = request(vec!(int32("a", 0, 0)), 0, vec!("red"));
+        assert_eq!(check_compatibility(&schema, &schema), CompatibilityReport::default());
+    }
+
+    #[test]
+    fn test_added_field_and_enumerant() {
+        let old = request(vec!(int32("a", 0, 0)), 0, vec!("red"));
+        let new = request(vec!(int32("a", 0, 0), int32("b", 1, 1)), 0, vec!("red", "green"));
+        let report = check_compatibility(&old, &new);
+        assert!(!report.is_breaking());
+        assert_eq!(descriptions(&report), vec!(
+            "compatible: test.capnp:Shape: b: field was added",
+            "compatible: test.capnp:Color: green: enumerant was added"
+        ));
+    }
+
+    #[test]
+    fn test_changed_field_type() {
+        let old = request(vec!(int32("a", 0, 0)), 0, vec!());
+        let new = request(vec!(slot("a", 0, ast::field::NO_DISCRIMINANT, 0, ast::Type::Text, ast::Value::Text(String::new()))), 0, vec!());
+        let report = check_compatibility(&old, &new);
+        assert!(report.is_breaking());
+        assert_eq!(descriptions(&report), vec!("breaking: test.capnp:Shape: a: type changed from Int32 to Text"));
+    }
+
+    #[test]
+    fn test_reused_ordinal() {
+        let old = request(vec!(int32("a", 0, 0), int32("b", 1, 1)), 0, vec!());
+        let new = request(vec!(int32("a", 0, 0), slot("c", 1, ast::field::NO_DISCRIMINANT, 1, ast::Type::Float32, ast::Value::Float32(0.0))), 0, vec!());
+        assert_eq!(descriptions(&check_compatibility(&old, &new)), vec!(
+            "compatible: test.capnp:Shape: b: renamed to c",
+            "breaking: test.capnp:Shape: b: type changed from Int32 to Float32"
+        ));
+    }
+
+    #[test]
+    fn test_removed_field_and_changed_default() {
+        let old = request(vec!(int32("a", 0, 0), int32("b", 1, 1)), 0, vec!());
+        let new = request(vec!(slot("a", 0, ast::field::NO_DISCRIMINANT, 0, ast::Type::Int32, ast::Value::Int32(5))), 0, vec!());
+        assert_eq!(descriptions(&check_compatibility(&old, &new)), vec!(
+            "breaking: test.capnp:Shape: a: default value changed",
+            "breaking: test.capnp:Shape: b: field was removed"
+        ));
+    }
+
+    #[test]
+    fn test_float_defaults() {
+        let float64 = |default_value: f64| slot("a", 0, ast::field::NO_DISCRIMINANT, 0, ast::Type::Float64, ast::Value::Float64(default_value));
+
+        let nan = request(vec!(float64(f64::NAN)), 0, vec!());
+        assert_eq!(check_compatibility(&nan, &nan), CompatibilityReport::default());
+
+        let positive_zero = request(vec!(float64(0.0)), 0, vec!());
+        let negative_zero = request(vec!(float64(-0.0)), 0, vec!());
+        assert_eq!(descriptions(&check_compatibility(&positive_zero, &negative_zero)), vec!("breaking: test.capnp:Shape: a: default value changed"));
+
+        let list = |values: Vec<f32>| slot("a", 0, ast::field::NO_DISCRIMINANT, 0, ast::Type::List(Box::new(ast::Type::Float32)),
+            ast::Value::List(values.into_iter().map(ast::Value::Float32).collect()));
+        let nan_list = request(vec!(list(vec!(1.0, f32::NAN))), 0, vec!());
+        assert_eq!(check_compatibility(&nan_list, &nan_list), CompatibilityReport::default());
+        assert!(check_compatibility(&request(vec!(list(vec!(0.0))), 0, vec!()), &request(vec!(list(vec!(-0.0))), 0, vec!())).is_breaking());
+    }
+
+    #[test]
+    fn test_union_members() {
+        let union_member = |name, ordinal, discriminant| slot(name, ordinal, discriminant, 0, ast::Type::Int32, ast::Value::Int32(0));
+        let old = request(vec!(union_member("circle", 0, 0), union_member("square", 2, 1)), 2, vec!());
+
+        let appended = request(vec!(union_member("circle", 0, 0), union_member("square", 2, 1), union_member("line", 3, 2)), 3, vec!());
+        assert!(!check_compatibility(&old, &appended).is_breaking());
+
+        let inserted = request(vec!(union_member("circle", 0, 0), union_member("dot", 1, 1), union_member("square", 2, 2)), 3, vec!());
+        assert_eq!(descriptions(&check_compatibility(&old, &inserted)), vec!(
+            "breaking: test.capnp:Shape: square: union discriminant changed from 1 to 2",
+            "breaking: test.capnp:Shape: dot: union member was added before existing members"
+        ));
+    }
+
+    fn compile(schema: &str) -> CodeGeneratorRequest {
+        crate::compile_source("test.capnp", &format!("@0xe87e0317861d3aab;\n{}", schema), &crate::CompileOptions::new()).unwrap()
+    }
+
+    #[test]
+    fn test_renamed_group() {
+        let old = compile("struct Shape {\n  extra :group {\n    visible @0 :Bool;\n  }\n}\n");
+        let new = compile("struct Shape {\n  more :group {\n    visible @0 :Bool;\n  }\n}\n");
+        assert_eq!(descriptions(&check_compatibility(&old, &new)), vec!("compatible: test.capnp:Shape: extra: renamed to more"));
+    }
+
+    #[test]
+    fn test_field_moved_into_new_group() {
+        let old = compile("struct Shape {\n  size @0 :Int32;\n}\n");
+        let new = compile("struct Shape {\n  extra :group {\n    size @0 :Int32;\n    visible @1 :Bool;\n  }\n}\n");
+        let report = check_compatibility(&old, &new);
+        assert!(!report.is_breaking());
+        assert_eq!(descriptions(&report), vec!(
+            "compatible: test.capnp:Shape: size: moved into extra",
+            "compatible: test.capnp:Shape: extra: field was added"
+        ));
+    }
+
+    #[test]
+    fn test_field_moved_into_new_union() {
+        let old = compile("struct Shape {\n  size @0 :Int32;\n}\n");
+        let new = compile("struct Shape {\n  union {\n    size @0 :Int32;\n    label @1 :Text;\n  }\n}\n");
+        let report = check_compatibility(&old, &new);
+        assert!(!report.is_breaking());
+        assert_eq!(descriptions(&report), vec!(
+            "compatible: test.capnp:Shape: size: moved into a new union",
+            "compatible: test.capnp:Shape: label: field was added"
+        ));
+
+        // Not when the union already had members.
+        let old = compile("struct Shape {\n  size @0 :Int32;\n  union {\n    circle @1 :Float64;\n    label @2 :Text;\n  }\n}\n");
+        let new = compile("struct Shape {\n  union {\n    size @0 :Int32;\n    circle @1 :Float64;\n    label @2 :Text;\n  }\n}\n");
+        assert!(check_compatibility(&old, &new).breaking_changes().iter().any(|change| change.description() == "moved into a union"));
+    }
+
+    #[test]
+    fn test_enumerants() {
+        let old = request(vec!(), 0, vec!("red", "green", "blue"));
+        let new = request(vec!(), 0, vec!("green", "crimson"));
+        assert_eq!(descriptions(&check_compatibility(&old, &new)), vec!(
+            "breaking: test.capnp:Color: red: enumerant was removed",
+            "breaking: test.capnp:Color: green: renumbered from 1 to 0",
+            "breaking: test.capnp:Color: blue: enumerant was removed",
+            "compatible: test.capnp:Color: crimson: enumerant was added"
+        ));
+
+        let renamed = request(vec!(), 0, vec!("red", "verdant", "blue"));
+        assert_eq!(descriptions(&check_compatibility(&old, &renamed)), vec!("compatible: test.capnp:Color: green: renamed to verdant"));
+    }
+
+    #[test]
+    fn test_removed_node_and_changed_kind() {
+        let old = request(vec!(), 0, vec!());
+        let mut nodes = old.nodes().clone();
+        nodes.retain(|node| node.id() != 3);
+        nodes[1] = node(2, "test.capnp:Shape", 1, vec!(), ast::node::Which::Enum(vec!()));
+        let new = CodeGeneratorRequest::new(nodes, vec!(), HashMap::new());
+
+        let report = check_compatibility(&old, &new);
+        assert_eq!(report.breaking_changes().len(), 2);
+        assert_eq!(descriptions(&report), vec!(
+            "breaking: test.capnp:Shape: changed from struct to enum",
+            "breaking: test.capnp:Color: enum was removed"
+        ));
+    }
+}
@@ -0,0 +1,677 @@
+use super::lexer::{Position, Spanned, Token};
+use super::SourceError;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub position: Position
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpressionKind {
+    Integer(u64),
+    NegativeInteger(u64),
+    Float(f64),
+    String(String),
+    Binary(Vec<u8>),
+
+    /// A name looked up through the enclosing scopes.
+    Name(String),
+
+    /// A name written `.Foo`, looked up at the top of the file.
+    AbsoluteName(String),
+    Import(String),
+    Member(Box<Expression>, String),
+
+    /// Generic arguments, e.g. `List(Text)`, or a struct value for an annotation, e.g. `$foo(a = 1)`.
+    Application(Box<Expression>, Vec<Argument>),
+    List(Vec<Expression>),
+    Tuple(Vec<Argument>)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Argument {
+    pub name: Option<String>,
+    pub value: Expression
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnnotationApplication {
+    pub name: Expression,
+    pub value: Option<Expression>,
+    pub position: Position
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MethodParams {
+    Fields(Vec<Declaration>),
+    Type(Expression)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeclarationKind {
+    File,
+    Using(Expression),
+    Const { type_: Expression, value: Expression },
+    Annotation { type_: Expression, targets: Vec<String> },
+    Struct,
+    Enum,
+    Interface { superclasses: Vec<Expression> },
+    Field { type_: Expression, default: Option<Expression> },
+    Group,
+    Union,
+    Enumerant,
+    Method { implicit_parameters: Vec<String>, params: MethodParams, results: Option<MethodParams> }
+}
+
+///
+/// A statement of a schema file. Members (fields, enumerants, methods) and nested
+/// declarations are both kept in `nested`, in the order they were written.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    pub position: Position,
+    pub id: Option<u64>,
+    pub ordinal: Option<u16>,
+    pub parameters: Vec<String>,
+    pub annotations: Vec<AnnotationApplication>,
+    pub doc_comment: Option<String>,
+    pub kind: DeclarationKind,
+    pub nested: Vec<Declaration>,
+
+    /// Just past the `;` or `}` that ends the declaration, or at the `,` or `)` after a
+    /// method parameter.
+    pub end: Position
+}
+
+impl Declaration {
+    fn new(name: String, position: Position, kind: DeclarationKind) -> Declaration {
+        Declaration {
+            name: name,
+            position: position,
+            id: None,
+            ordinal: None,
+            parameters: vec!(),
+            annotations: vec!(),
+            doc_comment: None,
+            kind: kind,
+            nested: vec!(),
+            end: position
+        }
+    }
+}
+
+///
+/// What kind of block a statement appears in, which decides how members are parsed.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Block {
+    File,
+    Struct,
+    Enum,
+    Interface
+}
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    index: usize,
+    end: Position
+}
+
+impl Parser {
+    fn skip_comments(&mut self) {
+        while let Some(Spanned { token: Token::Comment(_), .. }) = self.tokens.get(self.index) {
+            self.index += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.skip_comments();
+        self.tokens.get(self.index).map(|spanned| &spanned.token)
+    }
+
+    fn position(&mut self) -> Position {
+        self.skip_comments();
+        self.tokens.get(self.index).map_or(self.end, |spanned| spanned.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.skip_comments();
+        let token = self.tokens.get(self.index).map(|spanned| spanned.token.clone());
+        self.index += 1;
+        token
+    }
+
+    fn error<T>(&mut self, message: &str) -> Result<T, SourceError> {
+        Err(SourceError::new(self.position(), message))
+    }
+
+    fn is_symbol(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(s)) => *s == symbol,
+            _ => false
+        }
+    }
+
+    fn accept(&mut self, symbol: &str) -> bool {
+        let accepted = self.is_symbol(symbol);
+        if accepted {
+            self.index += 1;
+        }
+        accepted
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), SourceError> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            self.error(&format!("Expected '{}'.", symbol))
+        }
+    }
+
+    fn is_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Identifier(identifier)) => identifier == keyword,
+            _ => false
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, SourceError> {
+        match self.peek() {
+            Some(Token::Identifier(_)) => match self.next() {
+                Some(Token::Identifier(identifier)) => Ok(identifier),
+                _ => unreachable!()
+            },
+            _ => self.error("Expected a name.")
+        }
+    }
+
+    fn integer(&mut self) -> Result<u64, SourceError> {
+        match self.peek() {
+            Some(Token::Integer(i)) => {
+                let i = *i;
+                self.index += 1;
+                Ok(i)
+            },
+            _ => self.error("Expected an integer.")
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SourceError> {
+        match self.peek() {
+            Some(Token::String(_)) => match self.next() {
+                Some(Token::String(s)) => Ok(s),
+                _ => unreachable!()
+            },
+            _ => self.error("Expected a string.")
+        }
+    }
+
+    ///
+    /// The comments after the token just consumed, on the same line or on the lines right
+    /// below it. A blank line ends the doc comment.
+    ///
+    fn doc_comment(&mut self) -> Option<String> {
+        let mut line = match self.index.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some(spanned) => spanned.position.line,
+            None => return None
+        };
+        let mut lines = vec!();
+        while let Some(Spanned { token: Token::Comment(text), position }) = self.tokens.get(self.index) {
+            if position.line > line + 1 {
+                break;
+            }
+            line = position.line;
+            lines.push(format!("{}\n", text));
+            self.index += 1;
+        }
+        if lines.is_empty() {
+            None
+        } else {
+            Some(lines.concat())
+        }
+    }
+
+    fn comma_separated<T, F>(&mut self, close: &str, mut item: F) -> Result<Vec<T>, SourceError>
+        where F: FnMut(&mut Parser) -> Result<T, SourceError>
+    {
+        let mut items = vec!();
+        if self.accept(close) {
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.accept(close) {
+                return Ok(items);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn argument(&mut self) -> Result<Argument, SourceError> {
+        // `name = value`, or just a value.
+        if let (Some(Token::Identifier(name)), Some(Token::Symbol("="))) = (
+            self.peek().cloned(),
+            self.tokens.get(self.index + 1).map(|spanned| spanned.token.clone())
+        ) {
+            self.index += 2;
+            return Ok(Argument { name: Some(name), value: self.expression()? });
+        }
+        Ok(Argument { name: None, value: self.expression()? })
+    }
+
+    fn expression(&mut self) -> Result<Expression, SourceError> {
+        let position = self.position();
+        let kind = match self.next() {
+            Some(Token::Integer(i)) => ExpressionKind::Integer(i),
+            Some(Token::Float(f)) => ExpressionKind::Float(f),
+            Some(Token::String(s)) => ExpressionKind::String(s),
+            Some(Token::Binary(b)) => ExpressionKind::Binary(b),
+            Some(Token::Symbol("-")) => match self.next() {
+                Some(Token::Integer(i)) => ExpressionKind::NegativeInteger(i),
+                Some(Token::Float(f)) => ExpressionKind::Float(-f),
+                Some(Token::Identifier(ref name)) if name == "inf" => ExpressionKind::Float(f64::NEG_INFINITY),
+                _ => return Err(SourceError::new(position, "Expected a number after '-'."))
+            },
+            Some(Token::Symbol(".")) => ExpressionKind::AbsoluteName(self.identifier()?),
+            Some(Token::Symbol("[")) => ExpressionKind::List(self.comma_separated("]", Parser::expression)?),
+            Some(Token::Symbol("(")) => ExpressionKind::Tuple(self.comma_separated(")", Parser::argument)?),
+            Some(Token::Identifier(ref name)) if name == "import" => ExpressionKind::Import(self.string()?),
+            Some(Token::Identifier(name)) => ExpressionKind::Name(name),
+            _ => return Err(SourceError::new(position, "Expected an expression."))
+        };
+
+        let mut expression = Expression { kind: kind, position: position };
+        loop {
+            let position = self.position();
+            if self.accept(".") {
+                expression = Expression { kind: ExpressionKind::Member(Box::new(expression), self.identifier()?), position: position };
+            } else if self.accept("(") {
+                expression = Expression { kind: ExpressionKind::Application(Box::new(expression), self.comma_separated(")", Parser::argument)?), position: position };
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    ///
+    /// A name, possibly qualified, without generic arguments: the form annotations are applied with.
+    ///
+    fn name_expression(&mut self) -> Result<Expression, SourceError> {
+        let position = self.position();
+        let mut expression = if self.accept(".") {
+            Expression { kind: ExpressionKind::AbsoluteName(self.identifier()?), position: position }
+        } else if self.is_keyword("import") {
+            self.index += 1;
+            Expression { kind: ExpressionKind::Import(self.string()?), position: position }
+        } else {
+            Expression { kind: ExpressionKind::Name(self.identifier()?), position: position }
+        };
+        while self.accept(".") {
+            expression = Expression { kind: ExpressionKind::Member(Box::new(expression), self.identifier()?), position: position };
+        }
+        Ok(expression)
+    }
+
+    fn annotations(&mut self) -> Result<Vec<AnnotationApplication>, SourceError> {
+        let mut annotations = vec!();
+        loop {
+            let position = self.position();
+            if !self.accept("$") {
+                return Ok(annotations);
+            }
+            let name = self.name_expression()?;
+            let value = if self.is_symbol("(") {
+                let position = self.position();
+                self.index += 1;
+                let mut arguments = self.comma_separated(")", Parser::argument)?;
+                if arguments.len() == 1 && arguments[0].name.is_none() {
+                    Some(arguments.remove(0).value)
+                } else {
+                    Some(Expression { kind: ExpressionKind::Tuple(arguments), position: position })
+                }
+            } else {
+                None
+            };
+            annotations.push(AnnotationApplication { name: name, value: value, position: position });
+        }
+    }
+
+    fn id(&mut self) -> Result<Option<u64>, SourceError> {
+        if self.accept("@") {
+            Ok(Some(self.integer()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn ordinal(&mut self) -> Result<Option<u16>, SourceError> {
+        if !self.accept("@") {
+            return Ok(None);
+        }
+        let ordinal = self.integer()?;
+        if ordinal > 65534 {
+            return self.error("Ordinals must be less than 65535.");
+        }
+        Ok(Some(ordinal as u16))
+    }
+
+    fn parameters(&mut self, open: &str, close: &str) -> Result<Vec<String>, SourceError> {
+        if self.accept(open) {
+            self.comma_separated(close, Parser::identifier)
+        } else {
+            Ok(vec!())
+        }
+    }
+
+    ///
+    /// Ends a statement: either `;`, or a block of nested statements. The doc comment comes
+    /// after the `;` or `{`.
+    ///
+    fn end_statement(&mut self, declaration: &mut Declaration, block: Option<Block>) -> Result<(), SourceError> {
+        match block {
+            Some(block) => {
+                self.expect("{")?;
+                declaration.doc_comment = self.doc_comment();
+                while !self.is_symbol("}") {
+                    if self.peek().is_none() {
+                        return self.error("Expected '}'.");
+                    }
+                    self.statement(block, declaration)?;
+                }
+            },
+            None => {
+                if !self.is_symbol(";") {
+                    return self.error("Expected ';'.");
+                }
+            }
+        }
+        let end = self.position();
+        self.index += 1;
+        declaration.end = Position { line: end.line, column: end.column + 1 };
+        if block.is_none() {
+            declaration.doc_comment = self.doc_comment();
+        }
+        Ok(())
+    }
+
+    fn param(&mut self) -> Result<Declaration, SourceError> {
+        let position = self.position();
+        let name = self.identifier()?;
+        self.expect(":")?;
+        let type_ = self.expression()?;
+        let default = if self.accept("=") { Some(self.expression()?) } else { None };
+        let mut param = Declaration::new(name, position, DeclarationKind::Field { type_: type_, default: default });
+        param.annotations = self.annotations()?;
+        param.end = self.position();
+        Ok(param)
+    }
+
+    fn method_params(&mut self) -> Result<MethodParams, SourceError> {
+        if self.accept("(") {
+            Ok(MethodParams::Fields(self.comma_separated(")", Parser::param)?))
+        } else {
+            Ok(MethodParams::Type(self.expression()?))
+        }
+    }
+
+    fn member(&mut self, block: Block) -> Result<Declaration, SourceError> {
+        let position = self.position();
+        let name = self.identifier()?;
+        let ordinal = self.ordinal()?;
+
+        let mut member = match block {
+            Block::Enum => Declaration::new(name, position, DeclarationKind::Enumerant),
+            Block::Interface => {
+                let implicit_parameters = self.parameters("[", "]")?;
+                let params = self.method_params()?;
+                let results = if self.accept("->") { Some(self.method_params()?) } else { None };
+                Declaration::new(name, position, DeclarationKind::Method { implicit_parameters: implicit_parameters, params: params, results: results })
+            },
+            Block::Struct => {
+                self.expect(":")?;
+                if self.is_keyword("group") || self.is_keyword("union") {
+                    let kind = if self.is_keyword("group") { DeclarationKind::Group } else { DeclarationKind::Union };
+                    self.index += 1;
+                    let mut group = Declaration::new(name, position, kind);
+                    group.ordinal = ordinal;
+                    group.annotations = self.annotations()?;
+                    self.end_statement(&mut group, Some(Block::Struct))?;
+                    return Ok(group);
+                }
+                let type_ = self.expression()?;
+                let default = if self.accept("=") { Some(self.expression()?) } else { None };
+                Declaration::new(name, position, DeclarationKind::Field { type_: type_, default: default })
+            },
+            Block::File => return Err(SourceError::new(position, "Only declarations may appear at the top of a file."))
+        };
+        member.ordinal = ordinal;
+        member.annotations = self.annotations()?;
+        self.end_statement(&mut member, None)?;
+        Ok(member)
+    }
+
+    fn declaration(&mut self, keyword: &str) -> Result<Declaration, SourceError> {
+        let position = self.position();
+        self.index += 1;
+
+        if keyword == "union" {
+            // An unnamed union.
+            let mut union = Declaration::new(String::new(), position, DeclarationKind::Union);
+            union.ordinal = self.ordinal()?;
+            union.annotations = self.annotations()?;
+            self.end_statement(&mut union, Some(Block::Struct))?;
+            return Ok(union);
+        }
+
+        let name = self.identifier()?;
+        if keyword == "using" {
+            let target = if self.accept("=") { self.expression()? } else { Expression { kind: ExpressionKind::Name(name.clone()), position: position } };
+            let mut using = Declaration::new(name, position, DeclarationKind::Using(target));
+            self.end_statement(&mut using, None)?;
+            return Ok(using);
+        }
+
+        let parameters = if keyword == "struct" || keyword == "interface" { self.parameters("(", ")")? } else { vec!() };
+        let id = self.id()?;
+        let (kind, block) = match keyword {
+            "struct" => (DeclarationKind::Struct, Some(Block::Struct)),
+            "enum" => (DeclarationKind::Enum, Some(Block::Enum)),
+            "interface" => {
+                let superclasses = if self.is_keyword("extends") {
+                    self.index += 1;
+                    self.expect("(")?;
+                    self.comma_separated(")", Parser::expression)?
+                } else {
+                    vec!()
+                };
+                (DeclarationKind::Interface { superclasses: superclasses }, Some(Block::Interface))
+            },
+            "const" => {
+                self.expect(":")?;
+                let type_ = self.expression()?;
+                self.expect("=")?;
+                (DeclarationKind::Const { type_: type_, value: self.expression()? }, None)
+            },
+            "annotation" => {
+                self.expect("(")?;
+                let targets = self.comma_separated(")", |parser| {
+                    if parser.accept("*") { Ok(String::from("*")) } else { parser.identifier() }
+                })?;
+                self.expect(":")?;
+                (DeclarationKind::Annotation { targets: targets, type_: self.expression()? }, None)
+            },
+            _ => unreachable!()
+        };
+
+        let mut declaration = Declaration::new(name, position, kind);
+        declaration.id = id;
+        declaration.parameters = parameters;
+        declaration.annotations = self.annotations()?;
+        self.end_statement(&mut declaration, block)?;
+        Ok(declaration)
+    }
+
+    fn statement(&mut self, block: Block, parent: &mut Declaration) -> Result<(), SourceError> {
+        const KEYWORDS: [&str; 6] = ["using", "struct", "enum", "interface", "const", "annotation"];
+
+        if block == Block::File && self.is_symbol("@") {
+            self.index += 1;
+            parent.id = Some(self.integer()?);
+            return self.expect(";");
+        }
+        if block == Block::File && self.is_symbol("$") {
+            parent.annotations.extend(self.annotations()?);
+            return self.expect(";");
+        }
+
+        // Keywords are only keywords when followed by a name, so fields may still be called e.g. `enum`.
+        let keyword = match (self.peek().cloned(), self.tokens.get(self.index + 1).map(|spanned| &spanned.token)) {
+            (Some(Token::Identifier(keyword)), Some(Token::Identifier(_))) if KEYWORDS.contains(&keyword.as_str()) => Some(keyword),
+            (Some(Token::Identifier(ref keyword)), Some(Token::Symbol(symbol))) if keyword == "union" && ["{", "@", "$"].contains(symbol) && block == Block::Struct => Some(String::from("union")),
+            _ => None
+        };
+        let declaration = match keyword {
+            Some(keyword) => self.declaration(&keyword)?,
+            None => self.member(block)?
+        };
+
+        match (&declaration.kind, block) {
+            (DeclarationKind::Union, Block::Struct) | (DeclarationKind::Group, Block::Struct) => (),
+            (DeclarationKind::Union, _) | (DeclarationKind::Group, _) => {
+                return Err(SourceError::new(declaration.position, "Unions and groups may only appear in structs."));
+            },
+            _ => ()
+        }
+        parent.nested.push(declaration);
+        Ok(())
+    }
+}
+
+///
+/// Parses the tokens of a schema file into its declaration tree, rooted at a `File` declaration.
+///
+pub fn parse_file(tokens: Vec<Spanned>) -> Result<Declaration, SourceError> {
+    let end = tokens.last().map_or(Position { line: 1, column: 1 }, |spanned| spanned.position);
+    let mut parser = Parser { tokens: tokens, index: 0, end: end };
+    let mut file = Declaration::new(String::new(), Position { line: 1, column: 1 }, DeclarationKind::File);
+
+    // Comments at the top of the file document it.
+    let mut comments = vec!();
+    while let Some(Spanned { token: Token::Comment(text), .. }) = parser.tokens.get(parser.index) {
+        comments.push(format!("{}\n", text));
+        parser.index += 1;
+    }
+    if !comments.is_empty() {
+        file.doc_comment = Some(comments.concat());
+    }
+
+    while parser.peek().is_some() {
+        parser.statement(Block::File, &mut file)?;
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::lexer::tokenize;
+
+    fn parse(source: &str) -> Declaration {
+        parse_file(tokenize(source).unwrap()).unwrap()
+    }
+
+    fn name(name: &str) -> ExpressionKind {
+        ExpressionKind::Name(String::from(name))
+    }
+
+    #[test]
+    fn test_parse_struct() {
+        let file = parse(r#"
+            @0xe87e0317861d3aab;
+            $import "/capnp/c++.capnp".namespace("test");
+
+            struct Shape(T) @0xa1b2c3d4e5f60718 {
+              # A shape.
+              area @0 :Float64 = 1.5;  # The area.
+              union {
+                circle @1 :Void;
+                square @2 :List(T);
+              }
+              extra :group { visible @3 :Bool $hidden; }
+            }
+        "#);
+        assert_eq!(file.id, Some(0xe87e0317861d3aab));
+        assert_eq!(file.annotations.len(), 1);
+
+        let shape = &file.nested[0];
+        assert_eq!(shape.name, "Shape");
+        assert_eq!(shape.kind, DeclarationKind::Struct);
+        assert_eq!(shape.id, Some(0xa1b2c3d4e5f60718));
+        assert_eq!(shape.parameters, vec!(String::from("T")));
+        assert_eq!(shape.doc_comment, Some(String::from("A shape.\n")));
+
+        let area = &shape.nested[0];
+        assert_eq!(area.ordinal, Some(0));
+        assert_eq!(area.doc_comment, Some(String::from("The area.\n")));
+        match &area.kind {
+            DeclarationKind::Field { type_, default: Some(default) } => {
+                assert_eq!(type_.kind, name("Float64"));
+                assert_eq!(default.kind, ExpressionKind::Float(1.5));
+            },
+            kind => panic!("Unexpected {:?}", kind)
+        }
+
+        assert_eq!(shape.nested[1].kind, DeclarationKind::Union);
+        assert_eq!(shape.nested[1].name, "");
+        assert_eq!(shape.nested[1].nested.len(), 2);
+        assert_eq!(shape.nested[2].kind, DeclarationKind::Group);
+        assert_eq!(shape.nested[2].nested[0].annotations[0].name.kind, name("hidden"));
+    }
+
+    #[test]
+    fn test_parse_declarations() {
+        let file = parse(r#"
+            @0xe87e0317861d3aab;
+            using Other = import "other.capnp";
+            enum Color { red @0; green @1; }
+            const answer :Int32 = -42;
+            annotation tag @0xb9c6f99ebf805f2c (struct, field) :Text;
+            interface Drawer extends(Other.Base) {
+              draw @0 [U] (shape :U, scale :Float32 = 2) -> (done :Bool);
+              stop @1 () -> ();
+            }
+        "#);
+        let kinds: Vec<&str> = file.nested.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(kinds, vec!("Other", "Color", "answer", "tag", "Drawer"));
+        assert_eq!(file.nested[0].kind, DeclarationKind::Using(Expression {
+            kind: ExpressionKind::Import(String::from("other.capnp")),
+            position: Position { line: 3, column: 27 }
+        }));
+        assert_eq!(file.nested[1].nested[1].ordinal, Some(1));
+        match &file.nested[2].kind {
+            DeclarationKind::Const { value, .. } => assert_eq!(value.kind, ExpressionKind::NegativeInteger(42)),
+            kind => panic!("Unexpected {:?}", kind)
+        }
+        match &file.nested[3].kind {
+            DeclarationKind::Annotation { targets, .. } => assert_eq!(targets, &vec!(String::from("struct"), String::from("field"))),
+            kind => panic!("Unexpected {:?}", kind)
+        }
+        match &file.nested[4].nested[0].kind {
+            DeclarationKind::Method { implicit_parameters, params: MethodParams::Fields(params), results: Some(MethodParams::Fields(results)) } => {
+                assert_eq!(implicit_parameters, &vec!(String::from("U")));
+                assert_eq!(params.len(), 2);
+                assert_eq!(results[0].name, "done");
+            },
+            kind => panic!("Unexpected {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = parse_file(tokenize("@0xe87e0317861d3aab;\nstruct Foo {\n  bar @0 Int32;\n}").unwrap()).unwrap_err();
+        assert_eq!(error.position, Position { line: 3, column: 10 });
+        assert!(parse_file(tokenize("foo @0 :Int32;").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,68 @@
+use crate::ast::Id;
+
+//
+// Ids of declarations without an explicit `@0x...`, derived from their parent's id the same
+// way as the reference compiler: the first 8 bytes of an MD5 hash, big-endian, with the top
+// bit set.
+//
+
+fn finish(context: md5::Context) -> Id {
+    let digest = context.compute();
+    let mut id: Id = 0;
+    for byte in digest.iter().take(8) {
+        id = (id << 8) | *byte as Id;
+    }
+    id | (1 << 63)
+}
+
+fn hash_parent(parent_id: Id) -> md5::Context {
+    let mut context = md5::Context::new();
+    context.consume(parent_id.to_le_bytes());
+    context
+}
+
+pub fn child_id(parent_id: Id, name: &str) -> Id {
+    let mut context = hash_parent(parent_id);
+    context.consume(name.as_bytes());
+    finish(context)
+}
+
+///
+/// The id of a group or named union, given its index in the parent's field list.
+///
+pub fn group_id(parent_id: Id, index: u16) -> Id {
+    let mut context = hash_parent(parent_id);
+    context.consume(index.to_le_bytes());
+    finish(context)
+}
+
+pub fn method_params_id(interface_id: Id, method_ordinal: u16, is_results: bool) -> Id {
+    let mut context = hash_parent(interface_id);
+    context.consume(method_ordinal.to_le_bytes());
+    context.consume([is_results as u8]);
+    finish(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids() {
+        let parent = 0xe87e0317861d3aab;
+        assert!(child_id(parent, "Foo") & (1 << 63) != 0);
+        assert_eq!(child_id(parent, "Foo"), child_id(parent, "Foo"));
+        assert_ne!(child_id(parent, "Foo"), child_id(parent, "Bar"));
+        assert_ne!(child_id(parent, "Foo"), child_id(parent + 1, "Foo"));
+        assert_ne!(group_id(parent, 0), group_id(parent, 1));
+        assert_ne!(method_params_id(parent, 0, false), method_params_id(parent, 0, true));
+    }
+
+    #[test]
+    fn test_id_values() {
+        let parent = 0xe87e0317861d3aab;
+        assert_eq!(child_id(parent, "Foo"), 0x91f1e9026988cd46);
+        assert_eq!(group_id(parent, 1), 0xa31ba0c20922777f);
+        assert_eq!(method_params_id(parent, 2, true), 0xff9d0732cb81c507);
+    }
+}
@@ -0,0 +1,451 @@
+//
+// Struct layout, following the reference compiler's algorithm so that the offsets match what
+// `capnp compile` produces. Fields are added in ordinal order. Data is allocated in power-of-two
+// sizes (`lg_size` 0 is one bit, 6 is a word), reusing holes left by smaller fields. Members
+// of a union share space: each member is laid out in its own group, and the groups of a union
+// overlap in the locations the union allocated from its parent.
+//
+
+const WORD_LG_SIZE: u32 = 6;
+
+///
+/// The free space left by allocations, as at most one hole of each size below a word. An
+/// offset of 0 means there is no hole: holes always come after something.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct HoleSet {
+    holes: [u32; 6]
+}
+
+impl HoleSet {
+    fn try_allocate(&mut self, lg_size: u32) -> Option<u32> {
+        if lg_size >= WORD_LG_SIZE {
+            None
+        } else if self.holes[lg_size as usize] != 0 {
+            let result = self.holes[lg_size as usize];
+            self.holes[lg_size as usize] = 0;
+            Some(result)
+        } else {
+            let next = self.try_allocate(lg_size + 1)?;
+            let result = next * 2;
+            self.holes[lg_size as usize] = result + 1;
+            Some(result)
+        }
+    }
+
+    fn add_holes_at_end(&mut self, mut lg_size: u32, mut offset: u32, limit_lg_size: u32) {
+        while lg_size < limit_lg_size {
+            self.holes[lg_size as usize] = offset;
+            lg_size += 1;
+            offset = offset.div_ceil(2);
+        }
+    }
+
+    fn try_expand(&mut self, old_lg_size: u32, old_offset: u32, expansion_factor: u32) -> bool {
+        if expansion_factor == 0 {
+            return true;
+        }
+        if old_lg_size >= WORD_LG_SIZE || self.holes[old_lg_size as usize] != old_offset + 1 {
+            return false;
+        }
+        if self.try_expand(old_lg_size + 1, old_offset >> 1, expansion_factor - 1) {
+            self.holes[old_lg_size as usize] = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn smallest_at_least(&self, lg_size: u32) -> Option<u32> {
+        (lg_size..WORD_LG_SIZE).find(|i| self.holes[*i as usize] != 0)
+    }
+
+    ///
+    /// The size of the first word's used part, when the struct fits in one word. A hole at
+    /// offset 1 of some size means the upper half of the space that size covers is free.
+    ///
+    fn first_word_used(&self) -> u32 {
+        (0..WORD_LG_SIZE).rev().find(|i| self.holes[*i as usize] != 1).map_or(0, |i| i + 1)
+    }
+}
+
+///
+/// Where fields are allocated: the struct itself, or one member of a union.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scope {
+    Top,
+    Group(usize)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct DataLocation {
+    lg_size: u32,
+    offset: u32
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Union {
+    parent: Scope,
+    group_count: u32,
+    discriminant_offset: Option<u32>,
+    data_locations: Vec<DataLocation>,
+    pointer_locations: Vec<u32>
+}
+
+///
+/// How one union member uses one of the union's data locations.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+struct DataLocationUsage {
+    is_used: bool,
+    lg_size_used: u32,
+    holes: HoleSet
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Group {
+    parent: usize,
+    has_members: bool,
+    data_location_usage: Vec<DataLocationUsage>,
+    pointer_location_usage: usize
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StructLayout {
+    holes: HoleSet,
+    data_word_count: u32,
+    pointer_count: u32,
+    unions: Vec<Union>,
+    groups: Vec<Group>
+}
+
+impl StructLayout {
+    pub fn new() -> StructLayout {
+        StructLayout::default()
+    }
+
+    pub fn data_word_count(&self) -> u32 {
+        self.data_word_count
+    }
+
+    pub fn pointer_count(&self) -> u32 {
+        self.pointer_count
+    }
+
+    pub fn preferred_list_encoding(&self) -> crate::ast::node::ElementSize {
+        use crate::ast::node::ElementSize;
+
+        match (self.data_word_count, self.pointer_count) {
+            (0, 0) => ElementSize::Empty,
+            (1, 0) => match self.holes.first_word_used() {
+                0 => ElementSize::Bit,
+                1..=3 => ElementSize::Byte,
+                4 => ElementSize::TwoBytes,
+                5 => ElementSize::FourBytes,
+                _ => ElementSize::EightBytes
+            },
+            (0, 1) => ElementSize::Pointer,
+            _ => ElementSize::InlineComposite
+        }
+    }
+
+    pub fn new_union(&mut self, parent: Scope) -> usize {
+        self.unions.push(Union {
+            parent: parent,
+            group_count: 0,
+            discriminant_offset: None,
+            data_locations: vec!(),
+            pointer_locations: vec!()
+        });
+        self.unions.len() - 1
+    }
+
+    ///
+    /// A scope for one member of a union.
+    ///
+    pub fn new_group(&mut self, union: usize) -> Scope {
+        self.groups.push(Group { parent: union, has_members: false, data_location_usage: vec!(), pointer_location_usage: 0 });
+        Scope::Group(self.groups.len() - 1)
+    }
+
+    ///
+    /// The offset of the union's discriminant, in 16-bit units, allocating it if needed.
+    ///
+    pub fn discriminant_offset(&mut self, union: usize) -> u32 {
+        if let Some(offset) = self.unions[union].discriminant_offset {
+            return offset;
+        }
+        let offset = self.add_data(self.unions[union].parent, 4);
+        self.unions[union].discriminant_offset = Some(offset);
+        offset
+    }
+
+    ///
+    /// Allocates `2^lg_size` bits, returning the offset in units of that size.
+    ///
+    pub fn add_data(&mut self, scope: Scope, lg_size: u32) -> u32 {
+        match scope {
+            Scope::Top => match self.holes.try_allocate(lg_size) {
+                Some(hole) => hole,
+                None => {
+                    let offset = self.data_word_count << (WORD_LG_SIZE - lg_size);
+                    self.data_word_count += 1;
+                    self.holes.add_holes_at_end(lg_size, offset + 1, WORD_LG_SIZE);
+                    offset
+                }
+            },
+            Scope::Group(group) => self.add_group_data(group, lg_size)
+        }
+    }
+
+    pub fn add_pointer(&mut self, scope: Scope) -> u32 {
+        match scope {
+            Scope::Top => {
+                self.pointer_count += 1;
+                self.pointer_count - 1
+            },
+            Scope::Group(group) => {
+                self.add_member(group);
+                let union = self.groups[group].parent;
+                let usage = self.groups[group].pointer_location_usage;
+                self.groups[group].pointer_location_usage += 1;
+                if usage < self.unions[union].pointer_locations.len() {
+                    self.unions[union].pointer_locations[usage]
+                } else {
+                    let offset = self.add_pointer(self.unions[union].parent);
+                    self.unions[union].pointer_locations.push(offset);
+                    offset
+                }
+            }
+        }
+    }
+
+    pub fn add_void(&mut self, scope: Scope) {
+        if let Scope::Group(group) = scope {
+            self.add_member(group);
+        }
+    }
+
+    fn add_member(&mut self, group: usize) {
+        if !self.groups[group].has_members {
+            self.groups[group].has_members = true;
+            let union = self.groups[group].parent;
+            self.unions[union].group_count += 1;
+            if self.unions[union].group_count == 2 {
+                self.discriminant_offset(union);
+            }
+        }
+    }
+
+    fn try_expand_data(&mut self, scope: Scope, old_lg_size: u32, old_offset: u32, expansion_factor: u32) -> bool {
+        match scope {
+            Scope::Top => self.holes.try_expand(old_lg_size, old_offset, expansion_factor),
+            Scope::Group(group) => {
+                let must_fail = old_lg_size + expansion_factor > WORD_LG_SIZE || (old_offset & ((1 << expansion_factor) - 1)) != 0;
+                let union = self.groups[group].parent;
+                for i in 0..self.groups[group].data_location_usage.len() {
+                    let location = self.unions[union].data_locations[i];
+                    if location.lg_size >= old_lg_size && old_offset >> (location.lg_size - old_lg_size) == location.offset {
+                        let local_old_offset = old_offset - (location.offset << (location.lg_size - old_lg_size));
+                        return !must_fail && self.try_expand_usage_for(group, i, old_lg_size, local_old_offset, expansion_factor);
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    fn try_expand_location(&mut self, union: usize, i: usize, new_lg_size: u32) -> bool {
+        let location = self.unions[union].data_locations[i];
+        if new_lg_size <= location.lg_size {
+            return true;
+        }
+        let parent = self.unions[union].parent;
+        if self.try_expand_data(parent, location.lg_size, location.offset, new_lg_size - location.lg_size) {
+            let location = &mut self.unions[union].data_locations[i];
+            location.offset >>= new_lg_size - location.lg_size;
+            location.lg_size = new_lg_size;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn smallest_hole_at_least(usage: &DataLocationUsage, location: DataLocation, lg_size: u32) -> Option<u32> {
+        if !usage.is_used {
+            // The location is one big hole.
+            if lg_size <= location.lg_size { Some(location.lg_size) } else { None }
+        } else if lg_size >= usage.lg_size_used {
+            // Doesn't fit in a hole, but the usage could grow into the rest of the location.
+            if lg_size < location.lg_size { Some(lg_size) } else { None }
+        } else if let Some(hole) = usage.holes.smallest_at_least(lg_size) {
+            Some(hole)
+        } else if usage.lg_size_used < location.lg_size {
+            // Doubling the usage would leave a hole.
+            Some(usage.lg_size_used)
+        } else {
+            None
+        }
+    }
+
+    fn allocate_from_hole(&mut self, group: usize, i: usize, lg_size: u32) -> u32 {
+        let location = self.unions[self.groups[group].parent].data_locations[i];
+        let usage = &mut self.groups[group].data_location_usage[i];
+        let result = if !usage.is_used {
+            usage.is_used = true;
+            usage.lg_size_used = lg_size;
+            0
+        } else if lg_size >= usage.lg_size_used {
+            // Pad the current usage to the new size, and take the second half.
+            usage.holes.add_holes_at_end(usage.lg_size_used, 1, lg_size);
+            usage.lg_size_used = lg_size + 1;
+            1
+        } else if let Some(hole) = usage.holes.try_allocate(lg_size) {
+            hole
+        } else {
+            let result = 1 << (usage.lg_size_used - lg_size);
+            usage.holes.add_holes_at_end(lg_size, result + 1, usage.lg_size_used);
+            usage.lg_size_used += 1;
+            result
+        };
+        (location.offset << (location.lg_size - lg_size)) + result
+    }
+
+    fn try_expand_usage(&mut self, group: usize, i: usize, desired_usage: u32, new_holes: bool) -> bool {
+        let union = self.groups[group].parent;
+        if desired_usage > self.unions[union].data_locations[i].lg_size && !self.try_expand_location(union, i, desired_usage) {
+            return false;
+        }
+        let usage = &mut self.groups[group].data_location_usage[i];
+        if new_holes {
+            usage.holes.add_holes_at_end(usage.lg_size_used, 1, desired_usage);
+        }
+        usage.lg_size_used = desired_usage;
+        true
+    }
+
+    fn try_expand_usage_for(&mut self, group: usize, i: usize, old_lg_size: u32, old_offset: u32, expansion_factor: u32) -> bool {
+        let usage = &self.groups[group].data_location_usage[i];
+        if old_offset == 0 && usage.lg_size_used == old_lg_size {
+            // The location holds exactly the data being expanded.
+            self.try_expand_usage(group, i, old_lg_size + expansion_factor, false)
+        } else {
+            // The data was placed in a hole, which is now being expanded.
+            self.groups[group].data_location_usage[i].holes.try_expand(old_lg_size, old_offset, expansion_factor)
+        }
+    }
+
+    fn try_allocate_by_expanding(&mut self, group: usize, i: usize, lg_size: u32) -> Option<u32> {
+        let union = self.groups[group].parent;
+        let usage = self.groups[group].data_location_usage[i].clone();
+        if !usage.is_used {
+            if !self.try_expand_location(union, i, lg_size) {
+                return None;
+            }
+            let location = self.unions[union].data_locations[i];
+            let usage = &mut self.groups[group].data_location_usage[i];
+            usage.is_used = true;
+            usage.lg_size_used = lg_size;
+            Some(location.offset << (location.lg_size - lg_size))
+        } else {
+            let new_size = std::cmp::max(usage.lg_size_used, lg_size) + 1;
+            if !self.try_expand_usage(group, i, new_size, true) {
+                return None;
+            }
+            let hole = self.groups[group].data_location_usage[i].holes.try_allocate(lg_size)?;
+            let location = self.unions[union].data_locations[i];
+            Some((location.offset << (location.lg_size - lg_size)) + hole)
+        }
+    }
+
+    fn add_group_data(&mut self, group: usize, lg_size: u32) -> u32 {
+        self.add_member(group);
+        let union = self.groups[group].parent;
+
+        // The smallest hole that fits, to reduce fragmentation.
+        let mut best: Option<(u32, usize)> = None;
+        for i in 0..self.unions[union].data_locations.len() {
+            if self.groups[group].data_location_usage.len() == i {
+                self.groups[group].data_location_usage.push(DataLocationUsage::default());
+            }
+            let location = self.unions[union].data_locations[i];
+            if let Some(hole) = StructLayout::smallest_hole_at_least(&self.groups[group].data_location_usage[i], location, lg_size) {
+                if best.is_none_or(|(best_size, _)| hole < best_size) {
+                    best = Some((hole, i));
+                }
+            }
+        }
+        if let Some((_, i)) = best {
+            return self.allocate_from_hole(group, i, lg_size);
+        }
+
+        // No hole is big enough; try growing one of the locations.
+        for i in 0..self.unions[union].data_locations.len() {
+            if let Some(offset) = self.try_allocate_by_expanding(group, i, lg_size) {
+                return offset;
+            }
+        }
+
+        let parent = self.unions[union].parent;
+        let offset = self.add_data(parent, lg_size);
+        self.unions[union].data_locations.push(DataLocation { lg_size: lg_size, offset: offset });
+        self.groups[group].data_location_usage.push(DataLocationUsage { is_used: true, lg_size_used: lg_size, holes: HoleSet::default() });
+        offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::node::ElementSize;
+
+    #[test]
+    fn test_top_layout() {
+        let mut layout = StructLayout::new();
+        // a :Bool, b :UInt16, c :UInt64, d :UInt8, e :Text
+        assert_eq!(layout.add_data(Scope::Top, 0), 0);
+        assert_eq!(layout.add_data(Scope::Top, 4), 1);
+        assert_eq!(layout.add_data(Scope::Top, 6), 1);
+        assert_eq!(layout.add_data(Scope::Top, 3), 1);
+        assert_eq!(layout.add_pointer(Scope::Top), 0);
+        assert_eq!(layout.data_word_count(), 2);
+        assert_eq!(layout.pointer_count(), 1);
+        assert_eq!(layout.preferred_list_encoding(), ElementSize::InlineComposite);
+    }
+
+    #[test]
+    fn test_preferred_list_encoding() {
+        let mut layout = StructLayout::new();
+        assert_eq!(layout.preferred_list_encoding(), ElementSize::Empty);
+        layout.add_data(Scope::Top, 0);
+        assert_eq!(layout.preferred_list_encoding(), ElementSize::Bit);
+        layout.add_data(Scope::Top, 4);
+        assert_eq!(layout.preferred_list_encoding(), ElementSize::FourBytes);
+
+        let mut layout = StructLayout::new();
+        layout.add_pointer(Scope::Top);
+        assert_eq!(layout.preferred_list_encoding(), ElementSize::Pointer);
+    }
+
+    #[test]
+    fn test_union_layout() {
+        // struct { a @0 :UInt32; union { b @1 :UInt32; c @2 :UInt64; d @3 :Text; e @4 :Text; } }
+        let mut layout = StructLayout::new();
+        assert_eq!(layout.add_data(Scope::Top, 5), 0);
+
+        let union = layout.new_union(Scope::Top);
+        let b = layout.new_group(union);
+        let c = layout.new_group(union);
+        let d = layout.new_group(union);
+        let e = layout.new_group(union);
+        assert_eq!(layout.add_data(b, 5), 1);
+        // The second member allocates the discriminant; the shared location can't grow past it.
+        assert_eq!(layout.add_data(c, 6), 2);
+        assert_eq!(layout.discriminant_offset(union), 4);
+        assert_eq!(layout.add_pointer(d), 0);
+        assert_eq!(layout.add_pointer(e), 0);
+        assert_eq!(layout.data_word_count(), 3);
+        assert_eq!(layout.pointer_count(), 1);
+    }
+}
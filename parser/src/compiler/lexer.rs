@@ -0,0 +1,303 @@
+use super::SourceError;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Identifier(String),
+    Integer(u64),
+    Float(f64),
+    String(String),
+
+    /// A `0x"..."` literal.
+    Binary(Vec<u8>),
+
+    /// Punctuation, including the two-character `->`.
+    Symbol(&'static str),
+
+    /// The text of a `#` comment, without the `#` and the space after it.
+    Comment(String)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spanned {
+    pub token: Token,
+    pub position: Position
+}
+
+const SYMBOLS: [&str; 16] = ["->", "@", ":", ";", "=", "(", ")", "[", "]", "{", "}", ",", ".", "$", "-", "*"];
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    position: Position
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> String {
+        let mut taken = String::new();
+        while let Some(c) = self.peek() {
+            if !f(c) {
+                break;
+            }
+            taken.push(c);
+            self.next();
+        }
+        taken
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, SourceError> {
+        Err(SourceError::new(self.position, message))
+    }
+
+    fn number(&mut self, first: char) -> Result<Token, SourceError> {
+        if first == '0' && (self.peek() == Some('x') || self.peek() == Some('X')) {
+            self.next();
+            if self.peek() == Some('"') {
+                self.next();
+                return self.binary();
+            }
+            let digits = self.take_while(|c| c.is_ascii_hexdigit());
+            return match u64::from_str_radix(&digits, 16) {
+                Ok(i) => Ok(Token::Integer(i)),
+                Err(_) => self.error("Invalid hexadecimal integer.")
+            };
+        }
+
+        let mut literal = first.to_string();
+        literal.push_str(&self.take_while(|c| c.is_ascii_digit()));
+        let mut is_float = false;
+        if self.peek() == Some('.') {
+            // `1.5`, but not the `.` of a member access.
+            let mut lookahead = self.chars.clone();
+            lookahead.next();
+            if lookahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                is_float = true;
+                self.next();
+                literal.push('.');
+                literal.push_str(&self.take_while(|c| c.is_ascii_digit()));
+            }
+        }
+        if self.peek() == Some('e') || self.peek() == Some('E') {
+            is_float = true;
+            self.next();
+            literal.push('e');
+            if let Some(sign) = self.peek().filter(|c| *c == '+' || *c == '-') {
+                self.next();
+                literal.push(sign);
+            }
+            literal.push_str(&self.take_while(|c| c.is_ascii_digit()));
+        }
+
+        if is_float {
+            match literal.parse::<f64>() {
+                Ok(f) => Ok(Token::Float(f)),
+                Err(_) => self.error("Invalid floating-point number.")
+            }
+        } else if literal.len() > 1 && literal.starts_with('0') {
+            match u64::from_str_radix(&literal[1..], 8) {
+                Ok(i) => Ok(Token::Integer(i)),
+                Err(_) => self.error("Invalid octal integer.")
+            }
+        } else {
+            match literal.parse::<u64>() {
+                Ok(i) => Ok(Token::Integer(i)),
+                Err(_) => self.error("Integer is too big.")
+            }
+        }
+    }
+
+    fn binary(&mut self) -> Result<Token, SourceError> {
+        let mut digits = String::new();
+        loop {
+            match self.next() {
+                Some('"') => break,
+                Some(c) if c.is_ascii_hexdigit() => digits.push(c),
+                Some(c) if c.is_whitespace() => (),
+                _ => return self.error("Invalid character in binary literal.")
+            }
+        }
+        if digits.len() % 2 == 1 {
+            return self.error("Binary literal has an odd number of digits.");
+        }
+        let bytes = (0..digits.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect();
+        Ok(Token::Binary(bytes))
+    }
+
+    fn escape(&mut self) -> Result<char, SourceError> {
+        Ok(
+            match self.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('a') => '\x07',
+                Some('b') => '\x08',
+                Some('f') => '\x0c',
+                Some('v') => '\x0b',
+                Some('x') => {
+                    let mut digits = String::new();
+                    while digits.len() < 2 && self.peek().is_some_and(|c| c.is_ascii_hexdigit()) {
+                        digits.push(self.next().unwrap());
+                    }
+                    match u8::from_str_radix(&digits, 16) {
+                        Ok(b) => b as char,
+                        Err(_) => return self.error("Invalid hex escape.")
+                    }
+                },
+                Some(c) if c.is_digit(8) => {
+                    let mut digits = c.to_string();
+                    while digits.len() < 3 && self.peek().is_some_and(|c| c.is_digit(8)) {
+                        digits.push(self.next().unwrap());
+                    }
+                    match u8::from_str_radix(&digits, 8) {
+                        Ok(b) => b as char,
+                        Err(_) => return self.error("Invalid octal escape.")
+                    }
+                },
+                Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') | Some(c @ '?') => c,
+                _ => return self.error("Invalid escape sequence.")
+            }
+        )
+    }
+
+    fn string(&mut self) -> Result<Token, SourceError> {
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(Token::String(text)),
+                Some('\\') => text.push(self.escape()?),
+                Some('\n') | None => return self.error("Unterminated string literal."),
+                Some(c) => text.push(c)
+            }
+        }
+    }
+
+    fn symbol(&mut self, first: char, position: Position) -> Result<Token, SourceError> {
+        if first == '-' && self.peek() == Some('>') {
+            self.next();
+            return Ok(Token::Symbol("->"));
+        }
+        match SYMBOLS.iter().find(|symbol| symbol.len() == 1 && symbol.starts_with(first)) {
+            Some(symbol) => Ok(Token::Symbol(symbol)),
+            None => Err(SourceError::new(position, &format!("Unexpected character '{}'.", first)))
+        }
+    }
+}
+
+///
+/// Splits schema source into tokens. Comments are kept, since the ones following a declaration
+/// are its doc comment.
+///
+pub fn tokenize(source: &str) -> Result<Vec<Spanned>, SourceError> {
+    let mut lexer = Lexer { chars: source.chars().peekable(), position: Position { line: 1, column: 1 } };
+    let mut tokens = vec!();
+
+    loop {
+        lexer.take_while(char::is_whitespace);
+        let position = lexer.position;
+        let first = match lexer.next() {
+            Some(c) => c,
+            None => break
+        };
+
+        let token = match first {
+            '#' => {
+                let text = lexer.take_while(|c| c != '\n');
+                Token::Comment(String::from(text.strip_prefix(' ').unwrap_or(&text)))
+            },
+            '"' => lexer.string()?,
+            c if c.is_ascii_digit() => lexer.number(c)?,
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut identifier = c.to_string();
+                identifier.push_str(&lexer.take_while(|c| c.is_ascii_alphanumeric() || c == '_'));
+                Token::Identifier(identifier)
+            },
+            c => lexer.symbol(c, position)?
+        };
+        tokens.push(Spanned { token: token, position: position });
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        tokenize(source).unwrap().into_iter().map(|spanned| spanned.token).collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokens("foo @0 :List(Int32) = [1, -0x1f, 017, 1.5e3]; # Doc.\n"),
+            vec!(
+                Token::Identifier(String::from("foo")),
+                Token::Symbol("@"),
+                Token::Integer(0),
+                Token::Symbol(":"),
+                Token::Identifier(String::from("List")),
+                Token::Symbol("("),
+                Token::Identifier(String::from("Int32")),
+                Token::Symbol(")"),
+                Token::Symbol("="),
+                Token::Symbol("["),
+                Token::Integer(1),
+                Token::Symbol(","),
+                Token::Symbol("-"),
+                Token::Integer(0x1f),
+                Token::Symbol(","),
+                Token::Integer(0o17),
+                Token::Symbol(","),
+                Token::Float(1500.0),
+                Token::Symbol("]"),
+                Token::Symbol(";"),
+                Token::Comment(String::from("Doc."))
+            )
+        );
+    }
+
+    #[test]
+    fn test_tokenize_literals() {
+        assert_eq!(
+            tokens(r#""a\"b\n\x41" 0x"de ad" Foo.Bar -> x"#),
+            vec!(
+                Token::String(String::from("a\"b\nA")),
+                Token::Binary(vec!(0xde, 0xad)),
+                Token::Identifier(String::from("Foo")),
+                Token::Symbol("."),
+                Token::Identifier(String::from("Bar")),
+                Token::Symbol("->"),
+                Token::Identifier(String::from("x"))
+            )
+        );
+    }
+
+    #[test]
+    fn test_tokenize_positions() {
+        let spanned = tokenize("a\n  b").unwrap();
+        assert_eq!(spanned[1].position, Position { line: 2, column: 3 });
+        assert_eq!(tokenize("a ` b").unwrap_err().position, Position { line: 1, column: 3 });
+    }
+}
@@ -0,0 +1,461 @@
+//!
+//! Compiles `.capnp` schema files into a `CodeGeneratorRequest` without the `capnp` tool,
+//! so plugins can run on their own.
+//!
+
+mod grammar;
+mod ids;
+mod layout;
+mod lexer;
+mod translator;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::ast;
+use crate::{Error, Result};
+use grammar::{Declaration, DeclarationKind, Expression, ExpressionKind, MethodParams};
+use lexer::Position;
+
+///
+/// A syntax or semantic error at a position in a schema file.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceError {
+    position: Position,
+    message: String
+}
+
+impl SourceError {
+    pub fn new(position: Position, message: &str) -> SourceError {
+        SourceError { position: position, message: String::from(message) }
+    }
+
+    pub fn in_file(&self, display_name: &str) -> Error {
+        Error::Schema(format!("{}:{}:{}: {}", display_name, self.position.line, self.position.column, self.message))
+    }
+}
+
+///
+/// Where to find imported files, like the `-I` and `--src-prefix` flags of `capnp compile`.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompileOptions {
+    import_paths: Vec<PathBuf>,
+    src_prefixes: Vec<PathBuf>
+}
+
+impl CompileOptions {
+    pub fn new() -> CompileOptions {
+        CompileOptions::default()
+    }
+
+    ///
+    /// A directory searched for absolute imports such as `import "/capnp/c++.capnp"`.
+    ///
+    pub fn import_path<P: AsRef<Path>>(&mut self, path: P) -> &mut CompileOptions {
+        self.import_paths.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    ///
+    /// A prefix removed from the names of requested files, so generated code doesn't depend
+    /// on where the schemas are checked out.
+    ///
+    pub fn src_prefix<P: AsRef<Path>>(&mut self, prefix: P) -> &mut CompileOptions {
+        self.src_prefixes.push(prefix.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn src_prefixes(&self) -> &[PathBuf] {
+        &self.src_prefixes
+    }
+}
+
+///
+/// A parsed schema file and the files its imports resolved to.
+///
+pub struct SourceFile {
+    pub display_name: String,
+    pub root: Declaration,
+
+    /// Each import as written, with the index of the loaded file.
+    pub imports: Vec<(String, usize)>,
+
+    pub source: String
+}
+
+impl SourceFile {
+    ///
+    /// The byte offset of a position, for `SourceInfo`. Columns count characters, so the
+    /// line is walked to find it.
+    ///
+    pub fn byte_offset(&self, position: Position) -> u32 {
+        let line_start: usize = self.source.split_inclusive('\n').take(position.line - 1).map(str::len).sum();
+        let column = self.source[line_start..].char_indices()
+            .nth(position.column - 1)
+            .map_or(self.source.len() - line_start, |(i, _)| i);
+        (line_start + column) as u32
+    }
+}
+
+fn expression_imports(expression: &Expression, imports: &mut Vec<(String, Position)>) {
+    match &expression.kind {
+        ExpressionKind::Import(name) => imports.push((name.clone(), expression.position)),
+        ExpressionKind::Member(parent, _) => expression_imports(parent, imports),
+        ExpressionKind::Application(target, arguments) => {
+            expression_imports(target, imports);
+            for argument in arguments {
+                expression_imports(&argument.value, imports);
+            }
+        },
+        ExpressionKind::Tuple(arguments) => {
+            for argument in arguments {
+                expression_imports(&argument.value, imports);
+            }
+        },
+        ExpressionKind::List(items) => {
+            for item in items {
+                expression_imports(item, imports);
+            }
+        },
+        _ => ()
+    }
+}
+
+fn declaration_imports(decl: &Declaration, imports: &mut Vec<(String, Position)>) {
+    for annotation in &decl.annotations {
+        expression_imports(&annotation.name, imports);
+        if let Some(value) = &annotation.value {
+            expression_imports(value, imports);
+        }
+    }
+    let mut expressions = vec!();
+    match &decl.kind {
+        DeclarationKind::Using(target) => expressions.push(target),
+        DeclarationKind::Const { type_, value } => expressions.extend(vec!(type_, value)),
+        DeclarationKind::Annotation { type_, .. } => expressions.push(type_),
+        DeclarationKind::Interface { superclasses } => expressions.extend(superclasses),
+        DeclarationKind::Field { type_, default } => {
+            expressions.push(type_);
+            expressions.extend(default);
+        },
+        DeclarationKind::Method { params, results, .. } => {
+            for params in Some(params).into_iter().chain(results) {
+                match params {
+                    MethodParams::Fields(fields) => fields.iter().for_each(|field| declaration_imports(field, imports)),
+                    MethodParams::Type(type_) => expressions.push(type_)
+                }
+            }
+        },
+        _ => ()
+    }
+    for expression in expressions {
+        expression_imports(expression, imports);
+    }
+    for nested in &decl.nested {
+        declaration_imports(nested, imports);
+    }
+}
+
+struct Loader<'o> {
+    options: &'o CompileOptions,
+    files: Vec<SourceFile>,
+    by_path: HashMap<PathBuf, usize>
+}
+
+impl<'o> Loader<'o> {
+    fn display_name(&self, path: &Path) -> String {
+        let relative = self.options.src_prefixes.iter()
+            .find_map(|prefix| path.strip_prefix(prefix).ok())
+            .unwrap_or(path);
+        relative.to_string_lossy().replace('\\', "/")
+    }
+
+    ///
+    /// Finds an imported file, returning its path and display name. Absolute imports are
+    /// looked up in the import paths, others next to the importing file.
+    ///
+    fn find_import(&self, importer: &Path, importer_display_name: &str, name: &str) -> Option<(PathBuf, String)> {
+        if name.starts_with('/') {
+            let relative = name.trim_start_matches('/');
+            self.options.import_paths.iter()
+                .map(|dir| dir.join(relative))
+                .find(|candidate| candidate.is_file())
+                .map(|candidate| (candidate, String::from(relative)))
+        } else {
+            let candidate = importer.parent().unwrap_or_else(|| Path::new("")).join(name);
+            let display_name = match importer_display_name.rfind('/') {
+                Some(i) => format!("{}/{}", &importer_display_name[..i], name),
+                None => String::from(name)
+            };
+            if candidate.is_file() { Some((candidate, display_name)) } else { None }
+        }
+    }
+
+    fn load(&mut self, path: &Path, display_name: String, source: &str) -> Result<usize> {
+        let key = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if let Some(index) = self.by_path.get(&key) {
+            return Ok(*index);
+        }
+
+        let root = lexer::tokenize(source)
+            .and_then(grammar::parse_file)
+            .map_err(|e| e.in_file(&display_name))?;
+        let mut imports = vec!();
+        declaration_imports(&root, &mut imports);
+
+        let index = self.files.len();
+        self.by_path.insert(key, index);
+        self.files.push(SourceFile { display_name: display_name.clone(), root: root, imports: vec!(), source: String::from(source) });
+
+        for (name, position) in imports {
+            if self.files[index].imports.iter().any(|(import, _)| *import == name) {
+                continue;
+            }
+            let (import_path, import_display_name) = match self.find_import(path, &display_name, &name) {
+                Some(found) => found,
+                None => return Err(SourceError::new(position, &format!("Import failed: {}", name)).in_file(&display_name))
+            };
+            let import_source = std::fs::read_to_string(&import_path)?;
+            let imported = self.load(&import_path, import_display_name, &import_source)?;
+            self.files[index].imports.push((name, imported));
+        }
+        Ok(index)
+    }
+}
+
+///
+/// Compiles schema files the way `capnp compile` does before running a plugin, returning
+/// the request the plugin would have read.
+///
+pub fn compile_files<P: AsRef<Path>>(paths: &[P], options: &CompileOptions) -> Result<ast::CodeGeneratorRequest> {
+    let mut loader = Loader { options: options, files: vec!(), by_path: HashMap::new() };
+    let mut requested = vec!();
+    for path in paths {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        let display_name = loader.display_name(path);
+        requested.push(loader.load(path, display_name, &source)?);
+    }
+    translator::translate(&loader.files, &requested)
+}
+
+///
+/// Compiles a single schema given as text. Relative imports are resolved against the
+/// directory of `file_name`.
+///
+pub fn compile_source(file_name: &str, source: &str, options: &CompileOptions) -> Result<ast::CodeGeneratorRequest> {
+    let mut loader = Loader { options: options, files: vec!(), by_path: HashMap::new() };
+    let index = loader.load(Path::new(file_name), String::from(file_name), source)?;
+    translator::translate(&loader.files, &[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SchemaGraph;
+
+    const SCHEMA: &str = r#"
+        @0xe87e0317861d3aab;
+
+        struct Shape {
+          # A shape.
+          color @0 :Color = green;  # Its color.
+          union {
+            circle @1 :Float64;
+            square @2 :Float64;
+          }
+          extra :group {
+            visible @3 :Bool = true;
+            name @4 :Text;
+          }
+        }
+
+        enum Color { red @0; green @1; }
+
+        const origin :Shape = (color = red, square = 1.5);
+
+        interface Drawer {
+          draw @0 (shape :Shape, scale :Float32 = 2) -> (done :Bool);
+        }
+    "#;
+
+    fn compile(source: &str) -> ast::CodeGeneratorRequest {
+        compile_source("test.capnp", source, &CompileOptions::new()).unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        compile_source("test.capnp", source, &CompileOptions::new()).unwrap_err().to_string()
+    }
+
+    fn fields(node: &ast::Node) -> &Vec<ast::Field> {
+        match node.which() {
+            ast::node::Which::Struct { fields, .. } => fields,
+            which => panic!("Unexpected {:?}", which)
+        }
+    }
+
+    fn offset(field: &ast::Field) -> u32 {
+        match field.which() {
+            ast::field::Which::Slot { offset, .. } => *offset,
+            which => panic!("Unexpected {:?}", which)
+        }
+    }
+
+    #[test]
+    fn test_compile_struct() {
+        let cgr = compile(SCHEMA);
+        let graph = SchemaGraph::new(&cgr);
+        let shape_id = ids::child_id(0xe87e0317861d3aab, "Shape");
+        let shape = graph.node(shape_id).unwrap();
+        assert_eq!(shape.display_name(), "test.capnp:Shape");
+        assert_eq!(shape.display_name_prefix_length(), 11);
+        assert_eq!(cgr.doc_comment(shape_id), Some(&String::from("A shape.\n")));
+        assert_eq!(cgr.member_doc_comment(shape_id, 0), Some(&String::from("Its color.\n")));
+
+        match shape.which() {
+            ast::node::Which::Struct { data_word_count, pointer_count, discriminant_count, discriminant_offset, .. } => {
+                assert_eq!((*data_word_count, *pointer_count), (2, 1));
+                assert_eq!((*discriminant_count, *discriminant_offset), (2, 1));
+            },
+            which => panic!("Unexpected {:?}", which)
+        }
+
+        let fields = fields(shape);
+        let names: Vec<&str> = fields.iter().map(|field| field.name().as_str()).collect();
+        assert_eq!(names, vec!("color", "circle", "square", "extra"));
+        assert_eq!(fields.iter().take(3).map(offset).collect::<Vec<_>>(), vec!(0, 1, 1));
+        assert_eq!(fields[1].discriminant_value(), 0);
+        assert_eq!(fields[2].discriminant_value(), 1);
+        assert_eq!(fields[3].discriminant_value(), ast::field::NO_DISCRIMINANT);
+        match fields[0].which() {
+            ast::field::Which::Slot { default_value, had_explicit_default, .. } => {
+                assert_eq!(default_value, &ast::Value::Enum(1));
+                assert!(had_explicit_default);
+            },
+            which => panic!("Unexpected {:?}", which)
+        }
+
+        let extra_id = ids::group_id(shape_id, 3);
+        assert_eq!(fields[3].which(), &ast::field::Which::Group(extra_id));
+        let extra = graph.node(extra_id).unwrap();
+        assert_eq!(extra.display_name(), "test.capnp:Shape.extra");
+        assert_eq!(extra.scope_id(), shape_id);
+        assert_eq!(self::fields(extra).iter().map(offset).collect::<Vec<_>>(), vec!(32, 0));
+    }
+
+    #[test]
+    fn test_source_spans() {
+        let cgr = compile(SCHEMA);
+        let text = |start: u32, end: u32| &SCHEMA[start as usize..end as usize];
+
+        let shape = &cgr.source_info()[&ids::child_id(0xe87e0317861d3aab, "Shape")];
+        assert!(text(shape.start_byte(), shape.end_byte()).starts_with("struct Shape {"));
+        assert!(text(shape.start_byte(), shape.end_byte()).ends_with('}'));
+        let color = &shape.members()[0];
+        assert_eq!(text(color.start_byte(), color.end_byte()), "color @0 :Color = green;");
+
+        let file = &cgr.source_info()[&0xe87e0317861d3aab];
+        assert_eq!((file.start_byte(), file.end_byte()), (0, SCHEMA.len() as u32));
+    }
+
+    #[test]
+    fn test_compile_values() {
+        let cgr = compile(SCHEMA);
+        let graph = SchemaGraph::new(&cgr);
+        let origin = graph.node(ids::child_id(0xe87e0317861d3aab, "origin")).unwrap();
+        let value = |name: &str, value: ast::Value| ast::value::StructField::new(String::from(name), value);
+        match origin.which() {
+            ast::node::Which::Const { value: actual, .. } => assert_eq!(actual, &ast::Value::Struct(vec!(
+                value("color", ast::Value::Enum(0)),
+                value("square", ast::Value::Float64(1.5)),
+                value("extra", ast::Value::Struct(vec!(
+                    value("visible", ast::Value::Bool(true)),
+                    value("name", ast::Value::Text(String::new()))
+                )))
+            ))),
+            which => panic!("Unexpected {:?}", which)
+        }
+    }
+
+    #[test]
+    fn test_compile_interface() {
+        let cgr = compile(SCHEMA);
+        let graph = SchemaGraph::new(&cgr);
+        let drawer_id = ids::child_id(0xe87e0317861d3aab, "Drawer");
+        let method = match graph.node(drawer_id).unwrap().which() {
+            ast::node::Which::Interface { methods, .. } => methods[0].clone(),
+            which => panic!("Unexpected {:?}", which)
+        };
+        assert_eq!(method.param_struct_type(), ids::method_params_id(drawer_id, 0, false));
+        assert_eq!(method.result_struct_type(), ids::method_params_id(drawer_id, 0, true));
+
+        let params = graph.node(method.param_struct_type()).unwrap();
+        assert_eq!(params.display_name(), "test.capnp:Drawer.draw$Params");
+        assert_eq!(params.scope_id(), 0);
+        assert_eq!(self::fields(params).iter().map(offset).collect::<Vec<_>>(), vec!(0, 0));
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert_eq!(error("struct Foo {}"), "Invalid schema: test.capnp:1:1: The file has no id. Add one at the top, e.g. `@0x...;` with an id from `capnp id`.");
+        assert_eq!(error("@0xe87e0317861d3aab;\nstruct Foo {\n  bar @0 :Baz;\n}"), "Invalid schema: test.capnp:3:11: 'Baz' is not defined.");
+        assert_eq!(
+            error("@0xe87e0317861d3aab;\nstruct Foo {\n  a @0 :Int32;\n  b @2 :Int32;\n}"),
+            "Invalid schema: test.capnp:4:3: Skipped ordinal @1. Ordinals must be sequential with no holes."
+        );
+        assert!(error("@0xe87e0317861d3aab;\nstruct Foo {\n  union {\n    a @0 :Int32;\n  }\n}").contains("at least two members"));
+        assert_eq!(
+            error("@0xe87e0317861d3aab;\nusing A = B;\nusing B = A;\nstruct Foo {\n  a @0 :A;\n}"),
+            "Invalid schema: test.capnp:2:11: The alias depends on itself."
+        );
+    }
+
+    #[test]
+    fn test_print_round_trip() {
+        let cgr = compile(SCHEMA);
+        let printed = crate::print_file(&cgr, 0xe87e0317861d3aab).unwrap();
+        assert_eq!(compile(&printed).nodes(), cgr.nodes());
+    }
+
+    #[test]
+    fn test_print_round_trip_unnamed_union() {
+        let cgr = compile("@0xe87e0317861d3aab;\nstruct Value {\n  union {\n    void @0 :Void;\n    text @1 :Text;\n  }\n}\nstruct Holder {\n  kind :union {\n    a @0 :Void;\n    b @1 :Void;\n  }\n}\n");
+        let printed = crate::print_file(&cgr, 0xe87e0317861d3aab).unwrap();
+        assert_eq!(compile(&printed).nodes(), cgr.nodes());
+    }
+
+    #[test]
+    fn test_compile_imports() {
+        let dir = std::env::temp_dir().join(format!("capnp-compile-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("include")).unwrap();
+        std::fs::write(dir.join("include/base.capnp"), "@0xb2c3d4e5f6071829;\nstruct Base { id @0 :UInt64; }\n").unwrap();
+        std::fs::write(dir.join("main.capnp"), r#"
+            @0xe87e0317861d3aab;
+            using Base = import "/base.capnp".Base;
+            struct Derived { base @0 :Base; }
+        "#).unwrap();
+
+        let mut options = CompileOptions::new();
+        options.import_path(dir.join("include")).src_prefix(&dir);
+        let cgr = compile_files(&[dir.join("main.capnp")], &options).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(cgr.requested_files().len(), 1);
+        let requested = &cgr.requested_files()[0];
+        assert_eq!(requested.filename(), "main.capnp");
+        assert_eq!(requested.imports()[0].name(), "/base.capnp");
+        assert_eq!(requested.imports()[0].id(), 0xb2c3d4e5f6071829);
+
+        let graph = SchemaGraph::new(&cgr);
+        assert_eq!(graph.node(0xb2c3d4e5f6071829).unwrap().display_name(), "base.capnp");
+        let derived = graph.node(ids::child_id(0xe87e0317861d3aab, "Derived")).unwrap();
+        match fields(derived)[0].which() {
+            ast::field::Which::Slot { type_: ast::Type::Struct { type_id, .. }, .. } => {
+                assert_eq!(*type_id, ids::child_id(0xb2c3d4e5f6071829, "Base"));
+            },
+            which => panic!("Unexpected {:?}", which)
+        }
+    }
+}
@@ -0,0 +1,1107 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+use crate::ast;
+use crate::ast::Id;
+use crate::Result;
+use super::grammar::{AnnotationApplication, Argument, Declaration, DeclarationKind, Expression, ExpressionKind, MethodParams};
+use super::ids;
+use super::layout::{Scope, StructLayout};
+use super::lexer::Position;
+use super::{SourceError, SourceFile};
+
+const BUILTINS: [&str; 19] = [
+    "Void", "Bool", "Int8", "Int16", "Int32", "Int64", "UInt8", "UInt16", "UInt32", "UInt64",
+    "Float32", "Float64", "Text", "Data", "List", "AnyPointer", "AnyStruct", "AnyList", "Capability"
+];
+
+fn builtin_type(name: &str) -> Option<ast::Type> {
+    use ast::type_::{AnyPointer, Unconstrained};
+
+    Some(
+        match name {
+            "Void" => ast::Type::Void,
+            "Bool" => ast::Type::Bool,
+            "Int8" => ast::Type::Int8,
+            "Int16" => ast::Type::Int16,
+            "Int32" => ast::Type::Int32,
+            "Int64" => ast::Type::Int64,
+            "UInt8" => ast::Type::Uint8,
+            "UInt16" => ast::Type::Uint16,
+            "UInt32" => ast::Type::Uint32,
+            "UInt64" => ast::Type::Uint64,
+            "Float32" => ast::Type::Float32,
+            "Float64" => ast::Type::Float64,
+            "Text" => ast::Type::Text,
+            "Data" => ast::Type::Data,
+            "AnyPointer" => ast::Type::AnyPointer(AnyPointer::Unconstrained(Unconstrained::AnyKind)),
+            "AnyStruct" => ast::Type::AnyPointer(AnyPointer::Unconstrained(Unconstrained::Struct)),
+            "AnyList" => ast::Type::AnyPointer(AnyPointer::Unconstrained(Unconstrained::List)),
+            "Capability" => ast::Type::AnyPointer(AnyPointer::Unconstrained(Unconstrained::Capability)),
+            _ => return None
+        }
+    )
+}
+
+fn integer_value(value: i128, type_: &ast::Type) -> Option<ast::Value> {
+    Some(
+        match type_ {
+            ast::Type::Int8 => ast::Value::Int8(i8::try_from(value).ok()?),
+            ast::Type::Int16 => ast::Value::Int16(i16::try_from(value).ok()?),
+            ast::Type::Int32 => ast::Value::Int32(i32::try_from(value).ok()?),
+            ast::Type::Int64 => ast::Value::Int64(i64::try_from(value).ok()?),
+            ast::Type::Uint8 => ast::Value::Uint8(u8::try_from(value).ok()?),
+            ast::Type::Uint16 => ast::Value::Uint16(u16::try_from(value).ok()?),
+            ast::Type::Uint32 => ast::Value::Uint32(u32::try_from(value).ok()?),
+            ast::Type::Uint64 => ast::Value::Uint64(u64::try_from(value).ok()?),
+            ast::Type::Float32 => ast::Value::Float32(value as f32),
+            ast::Type::Float64 => ast::Value::Float64(value as f64),
+            _ => return None
+        }
+    )
+}
+
+const ANNOTATION_TARGETS: [&str; 12] = [
+    "file", "const", "enum", "enumerant", "struct", "field", "union", "group", "interface", "method", "param", "annotation"
+];
+
+fn annotation_target(name: &str) -> Option<ast::node::AnnotationTarget> {
+    use ast::node::AnnotationTarget;
+
+    Some(
+        match name {
+            "file" => AnnotationTarget::File,
+            "const" => AnnotationTarget::Const,
+            "enum" => AnnotationTarget::Enum,
+            "enumerant" => AnnotationTarget::Enumerant,
+            "struct" => AnnotationTarget::Struct,
+            "field" => AnnotationTarget::Field,
+            "union" => AnnotationTarget::Union,
+            "group" => AnnotationTarget::Group,
+            "interface" => AnnotationTarget::Interface,
+            "method" => AnnotationTarget::Method,
+            "param" => AnnotationTarget::Param,
+            "annotation" => AnnotationTarget::Annotation,
+            _ => return None
+        }
+    )
+}
+
+///
+/// Allocates space for a field of the given type, returning its offset in units of its size.
+///
+fn allocate(layout: &mut StructLayout, scope: Scope, type_: &ast::Type) -> u32 {
+    let lg_size = match type_ {
+        ast::Type::Void => {
+            layout.add_void(scope);
+            return 0;
+        },
+        ast::Type::Bool => 0,
+        ast::Type::Int8 | ast::Type::Uint8 => 3,
+        ast::Type::Int16 | ast::Type::Uint16 | ast::Type::Enum { .. } => 4,
+        ast::Type::Int32 | ast::Type::Uint32 | ast::Type::Float32 => 5,
+        ast::Type::Int64 | ast::Type::Uint64 | ast::Type::Float64 => 6,
+        _ => return layout.add_pointer(scope)
+    };
+    layout.add_data(scope, lg_size)
+}
+
+fn struct_which(layout: &mut StructLayout, is_group: bool, union: Option<usize>, discriminant_count: u16, fields: Vec<ast::Field>) -> ast::node::Which {
+    ast::node::Which::Struct {
+        data_word_count: layout.data_word_count() as u16,
+        pointer_count: layout.pointer_count() as u16,
+        preferred_list_encoding: layout.preferred_list_encoding(),
+        is_group: is_group,
+        discriminant_count: discriminant_count,
+        discriminant_offset: match union {
+            Some(union) if discriminant_count > 0 => layout.discriminant_offset(union),
+            _ => 0
+        },
+        fields: fields
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Member<'a> {
+    Node(Id),
+
+    /// A `using` alias, resolved in the scope that declares it.
+    Using(&'a Expression, Id)
+}
+
+struct Entry<'a> {
+    decl: &'a Declaration,
+    file: usize,
+    parent: Option<Id>,
+    display_name: String,
+    prefix_length: usize,
+    members: HashMap<String, Member<'a>>,
+    nested: Vec<ast::node::NestedNode>
+}
+
+///
+/// What a name refers to. Generic arguments given along the way are kept as bindings per scope.
+///
+#[derive(Clone, Debug)]
+enum Resolved {
+    Node(Id, Vec<(Id, Vec<ast::brand::Binding>)>),
+    Builtin(&'static str),
+    Type(ast::Type),
+    Parameter(Id, u16),
+    ImplicitParameter(u16)
+}
+
+///
+/// Where names in an expression are looked up: the innermost declaration, and the type
+/// parameters of the method being declared, if any.
+///
+#[derive(Clone, Copy)]
+struct Lexical<'b> {
+    file: usize,
+    scope: Id,
+    implicit_parameters: &'b [String]
+}
+
+///
+/// A struct, or one of its groups and named unions, while its fields are being laid out.
+///
+struct Container<'a> {
+    decl: &'a Declaration,
+    parent: Option<usize>,
+    code_order_in_parent: u16,
+
+    /// Whether this group is a member of its parent's union.
+    in_union: bool,
+
+    /// The layout scope of members that aren't in a union.
+    scope: Scope,
+
+    /// The layout union of members that are.
+    union: Option<usize>,
+
+    /// Named unions have only union members.
+    is_union: bool,
+
+    /// Assigned when the first field inside is laid out, in ordinal order.
+    id: Option<Id>,
+    display_name: String,
+    fields: Vec<ast::Field>,
+    docs: Vec<ast::node::source_info::Member>,
+    discriminant_count: u16,
+    code_order: u16
+}
+
+struct Leaf<'a> {
+    decl: &'a Declaration,
+    container: usize,
+    scope: Scope,
+    in_union: bool,
+    code_order: u16
+}
+
+///
+/// The members of a struct, gathered in declaration order before they are laid out.
+///
+struct Collected<'a> {
+    layout: StructLayout,
+    containers: Vec<Container<'a>>,
+    leaves: Vec<Leaf<'a>>
+}
+
+struct Translator<'a> {
+    files: &'a [SourceFile],
+    file_ids: Vec<Id>,
+    entries: HashMap<Id, Entry<'a>>,
+    order: Vec<Id>,
+
+    /// Every node built so far, including groups and method parameter structs.
+    translated: HashMap<Id, ast::Node>,
+
+    /// The nodes built for each declaration, in request order.
+    produced: HashMap<Id, Vec<Id>>,
+    in_progress: HashSet<Id>,
+
+    /// The `using` aliases being resolved, by scope and target position, to catch cycles.
+    aliases_in_progress: Vec<(Id, Position)>,
+    const_values: HashMap<Id, ast::Value>,
+    source_info: HashMap<Id, ast::node::SourceInfo>
+}
+
+impl<'a> Translator<'a> {
+    fn error<T>(&self, file: usize, position: Position, message: &str) -> Result<T> {
+        Err(SourceError::new(position, message).in_file(&self.files[file].display_name))
+    }
+
+    fn decl(&self, id: Id) -> &'a Declaration {
+        self.entries[&id].decl
+    }
+
+    fn span(&self, file: usize, decl: &Declaration) -> (u32, u32) {
+        let source = &self.files[file];
+        match decl.kind {
+            DeclarationKind::File => (0, source.source.len() as u32),
+            _ => (source.byte_offset(decl.position), source.byte_offset(decl.end))
+        }
+    }
+
+    ///
+    /// The doc comment and span of a field, enumerant or method.
+    ///
+    fn member_info(&self, file: usize, decl: &Declaration) -> ast::node::source_info::Member {
+        let (start_byte, end_byte) = self.span(file, decl);
+        ast::node::source_info::Member::new(decl.doc_comment.clone(), start_byte, end_byte)
+    }
+
+    fn check_id(&self, file: usize, decl: &Declaration) -> Result<()> {
+        match decl.id {
+            Some(id) if id & (1 << 63) == 0 => self.error(file, decl.position, &format!("Invalid id @0x{:x}: ids must have the highest bit set.", id)),
+            _ => Ok(())
+        }
+    }
+
+    fn declare(&mut self, decl: &'a Declaration, file: usize, parent: Option<Id>, id: Id, display_name: String, prefix_length: usize) -> Result<()> {
+        let mut members = HashMap::new();
+        let mut nested = vec!();
+        let mut children = vec!();
+        for child in &decl.nested {
+            let member = match &child.kind {
+                DeclarationKind::Using(target) => Member::Using(target, id),
+                DeclarationKind::Struct | DeclarationKind::Enum | DeclarationKind::Interface { .. } |
+                DeclarationKind::Const { .. } | DeclarationKind::Annotation { .. } => {
+                    self.check_id(file, child)?;
+                    let child_id = child.id.unwrap_or_else(|| ids::child_id(id, &child.name));
+                    nested.push(ast::node::NestedNode::new(child_id, child.name.clone()));
+                    children.push((child, child_id));
+                    Member::Node(child_id)
+                },
+                _ => continue
+            };
+            if members.insert(child.name.clone(), member).is_some() {
+                return self.error(file, child.position, &format!("'{}' is already defined.", child.name));
+            }
+        }
+
+        if self.entries.contains_key(&id) {
+            return self.error(file, decl.position, &format!("Duplicate id @0x{:016x}.", id));
+        }
+        let separator = if parent.is_none() { ':' } else { '.' };
+        self.entries.insert(id, Entry {
+            decl: decl,
+            file: file,
+            parent: parent,
+            display_name: display_name.clone(),
+            prefix_length: prefix_length,
+            members: members,
+            nested: nested
+        });
+        self.order.push(id);
+
+        for (child, child_id) in children {
+            self.declare(child, file, Some(id), child_id, format!("{}{}{}", display_name, separator, child.name), display_name.len() + 1)?;
+        }
+        Ok(())
+    }
+
+    fn ancestors(&self, id: Id) -> Vec<Id> {
+        let mut ancestors = vec!();
+        let mut current = Some(id);
+        while let Some(id) = current {
+            ancestors.push(id);
+            current = self.entries.get(&id).and_then(|entry| entry.parent);
+        }
+        ancestors
+    }
+
+    fn is_generic(&self, id: Id) -> bool {
+        self.ancestors(id).iter().any(|ancestor| !self.decl(*ancestor).parameters.is_empty())
+    }
+
+    fn resolve_member(&mut self, member: Member<'a>) -> Result<Resolved> {
+        match member {
+            Member::Node(id) => Ok(Resolved::Node(id, vec!())),
+            Member::Using(target, scope) => {
+                let file = self.entries[&scope].file;
+                let alias = (scope, target.position);
+                if self.aliases_in_progress.contains(&alias) {
+                    return self.error(file, target.position, "The alias depends on itself.");
+                }
+                self.aliases_in_progress.push(alias);
+                let resolved = self.resolve(target, Lexical { file: file, scope: scope, implicit_parameters: &[] });
+                self.aliases_in_progress.pop();
+                resolved
+            }
+        }
+    }
+
+    fn resolve(&mut self, expression: &Expression, ctx: Lexical) -> Result<Resolved> {
+        match &expression.kind {
+            ExpressionKind::Name(name) => {
+                if let Some(index) = ctx.implicit_parameters.iter().position(|parameter| parameter == name) {
+                    return Ok(Resolved::ImplicitParameter(index as u16));
+                }
+                let mut scope = Some(ctx.scope);
+                while let Some(id) = scope {
+                    let entry = &self.entries[&id];
+                    if let Some(index) = entry.decl.parameters.iter().position(|parameter| parameter == name) {
+                        return Ok(Resolved::Parameter(id, index as u16));
+                    }
+                    if let Some(member) = entry.members.get(name).cloned() {
+                        return self.resolve_member(member);
+                    }
+                    scope = entry.parent;
+                }
+                match BUILTINS.iter().find(|builtin| *builtin == name) {
+                    Some(builtin) => Ok(Resolved::Builtin(builtin)),
+                    None => self.error(ctx.file, expression.position, &format!("'{}' is not defined.", name))
+                }
+            },
+            ExpressionKind::AbsoluteName(name) => {
+                match self.entries[&self.file_ids[ctx.file]].members.get(name).cloned() {
+                    Some(member) => self.resolve_member(member),
+                    None => self.error(ctx.file, expression.position, &format!("'{}' is not defined.", name))
+                }
+            },
+            ExpressionKind::Import(name) => {
+                match self.files[ctx.file].imports.iter().find(|(import, _)| import == name) {
+                    Some((_, file)) => Ok(Resolved::Node(self.file_ids[*file], vec!())),
+                    None => self.error(ctx.file, expression.position, &format!("Import failed: {}", name))
+                }
+            },
+            ExpressionKind::Member(parent, name) => {
+                match self.resolve(parent, ctx)? {
+                    Resolved::Node(id, bindings) => match self.entries[&id].members.get(name).cloned() {
+                        Some(Member::Node(child)) => Ok(Resolved::Node(child, bindings)),
+                        Some(member) => self.resolve_member(member),
+                        None => self.error(ctx.file, expression.position, &format!("'{}' has no member named '{}'.", self.entries[&id].display_name, name))
+                    },
+                    _ => self.error(ctx.file, expression.position, &format!("'{}' is not in a scope with members.", name))
+                }
+            },
+            ExpressionKind::Application(target, arguments) => {
+                let resolved = self.resolve(target, ctx)?;
+                let mut types = vec!();
+                for argument in arguments {
+                    if argument.name.is_some() {
+                        return self.error(ctx.file, argument.value.position, "Generic arguments can't be named.");
+                    }
+                    types.push(self.compile_type(&argument.value, ctx)?);
+                }
+                match resolved {
+                    Resolved::Builtin("List") if types.len() == 1 => Ok(Resolved::Type(ast::Type::List(Box::new(types.remove(0))))),
+                    Resolved::Node(id, mut bindings) if self.decl(id).parameters.len() == types.len() => {
+                        bindings.push((id, types.into_iter().map(ast::brand::Binding::Type).collect()));
+                        Ok(Resolved::Node(id, bindings))
+                    },
+                    _ => self.error(ctx.file, expression.position, "Wrong number of generic arguments.")
+                }
+            },
+            _ => self.error(ctx.file, expression.position, "Expected a name.")
+        }
+    }
+
+    ///
+    /// The brand of a reference to `id`: the given bindings, and the generic scopes the
+    /// reference is made from, which bind the same parameters as the enclosing context.
+    ///
+    fn brand(&self, id: Id, mut bindings: Vec<(Id, Vec<ast::brand::Binding>)>, ctx: Lexical) -> ast::Brand {
+        let enclosing = self.ancestors(ctx.scope);
+        let mut scopes = vec!();
+        for ancestor in self.ancestors(id) {
+            if self.decl(ancestor).parameters.is_empty() {
+                continue;
+            }
+            match bindings.iter().position(|(scope_id, _)| *scope_id == ancestor) {
+                Some(i) => scopes.push(ast::brand::Scope::new(ancestor, ast::brand::scope::Which::Bind(bindings.remove(i).1))),
+                None if enclosing.contains(&ancestor) => scopes.push(ast::brand::Scope::new(ancestor, ast::brand::scope::Which::Inherit)),
+                None => ()
+            }
+        }
+        ast::Brand::new(scopes)
+    }
+
+    fn compile_type(&mut self, expression: &Expression, ctx: Lexical) -> Result<ast::Type> {
+        match self.resolve(expression, ctx)? {
+            Resolved::Builtin(name) => match builtin_type(name) {
+                Some(type_) => Ok(type_),
+                None => self.error(ctx.file, expression.position, "'List' needs an element type.")
+            },
+            Resolved::Type(type_) => Ok(type_),
+            Resolved::Parameter(scope_id, index) => {
+                Ok(ast::Type::AnyPointer(ast::type_::AnyPointer::Parameter { scope_id: scope_id, parameter_index: index }))
+            },
+            Resolved::ImplicitParameter(index) => {
+                Ok(ast::Type::AnyPointer(ast::type_::AnyPointer::ImplicitMethodParameter { parameter_index: index }))
+            },
+            Resolved::Node(id, bindings) => {
+                let brand = self.brand(id, bindings, ctx);
+                match self.decl(id).kind {
+                    DeclarationKind::Struct => Ok(ast::Type::Struct { type_id: id, brand: brand }),
+                    DeclarationKind::Enum => Ok(ast::Type::Enum { type_id: id, brand: brand }),
+                    DeclarationKind::Interface { .. } => Ok(ast::Type::Interface { type_id: id, brand: brand }),
+                    _ => self.error(ctx.file, expression.position, &format!("'{}' is not a type.", self.entries[&id].display_name))
+                }
+            }
+        }
+    }
+
+    fn literal_name(&self, name: &str, type_: &ast::Type) -> Option<ast::Value> {
+        Some(
+            match (name, type_) {
+                ("void", ast::Type::Void) => ast::Value::Void,
+                ("true", ast::Type::Bool) => ast::Value::Bool(true),
+                ("false", ast::Type::Bool) => ast::Value::Bool(false),
+                ("inf", ast::Type::Float32) => ast::Value::Float32(f32::INFINITY),
+                ("inf", ast::Type::Float64) => ast::Value::Float64(f64::INFINITY),
+                ("nan", ast::Type::Float32) => ast::Value::Float32(f32::NAN),
+                ("nan", ast::Type::Float64) => ast::Value::Float64(f64::NAN),
+                (_, ast::Type::Enum { type_id, .. }) => {
+                    let enumerant = self.decl(*type_id).nested.iter()
+                        .find(|member| member.kind == DeclarationKind::Enumerant && member.name == name)?;
+                    ast::Value::Enum(enumerant.ordinal?)
+                },
+                _ => return None
+            }
+        )
+    }
+
+    fn compile_value(&mut self, expression: &Expression, type_: &ast::Type, ctx: Lexical) -> Result<ast::Value> {
+        let value = match (&expression.kind, type_) {
+            (ExpressionKind::Integer(i), _) => integer_value(*i as i128, type_),
+            (ExpressionKind::NegativeInteger(i), _) => integer_value(-(*i as i128), type_),
+            (ExpressionKind::Float(f), ast::Type::Float32) => Some(ast::Value::Float32(*f as f32)),
+            (ExpressionKind::Float(f), ast::Type::Float64) => Some(ast::Value::Float64(*f)),
+            (ExpressionKind::String(s), ast::Type::Text) => Some(ast::Value::Text(s.clone())),
+            (ExpressionKind::String(s), ast::Type::Data) => Some(ast::Value::Data(s.as_bytes().to_vec())),
+            (ExpressionKind::Binary(b), ast::Type::Data) => Some(ast::Value::Data(b.clone())),
+            (ExpressionKind::List(items), ast::Type::List(element_type)) => {
+                let mut values = vec!();
+                for item in items {
+                    values.push(self.compile_value(item, element_type, ctx)?);
+                }
+                Some(ast::Value::List(values))
+            },
+            (ExpressionKind::Tuple(arguments), ast::Type::Struct { type_id, .. }) => {
+                Some(self.struct_value(*type_id, arguments, ctx, expression.position)?)
+            },
+            (ExpressionKind::Name(_), _) | (ExpressionKind::AbsoluteName(_), _) | (ExpressionKind::Member(..), _) => {
+                if let ExpressionKind::Name(name) = &expression.kind {
+                    if let Some(value) = self.literal_name(name, type_) {
+                        return Ok(value);
+                    }
+                }
+                match self.resolve(expression, ctx)? {
+                    Resolved::Node(id, _) if self.decl(id).is_const() => Some(self.const_value(id)?),
+                    _ => None
+                }
+            },
+            _ => None
+        };
+        match value {
+            Some(value) => Ok(value),
+            None => self.error(ctx.file, expression.position, "Value doesn't match the expected type.")
+        }
+    }
+
+    fn const_value(&mut self, id: Id) -> Result<ast::Value> {
+        if let Some(value) = self.const_values.get(&id) {
+            return Ok(value.clone());
+        }
+        let decl = self.decl(id);
+        let ctx = Lexical { file: self.entries[&id].file, scope: id, implicit_parameters: &[] };
+        let (type_, value) = match &decl.kind {
+            DeclarationKind::Const { type_, value } => (type_, value),
+            _ => unreachable!()
+        };
+        if !self.in_progress.insert(id) {
+            return self.error(ctx.file, decl.position, "The constant's value depends on itself.");
+        }
+        let type_ = self.compile_type(type_, ctx)?;
+        let value = self.compile_value(value, &type_, ctx)?;
+        self.in_progress.remove(&id);
+        self.const_values.insert(id, value.clone());
+        Ok(value)
+    }
+
+    ///
+    /// The fields of a struct or group, or `None` while the struct is still being built.
+    ///
+    fn struct_fields(&mut self, type_id: Id) -> Result<Option<Vec<ast::Field>>> {
+        if !self.translated.contains_key(&type_id) && self.entries.contains_key(&type_id) && !self.in_progress.contains(&type_id) {
+            self.translate(type_id)?;
+        }
+        match self.translated.get(&type_id).map(|node| node.which()) {
+            Some(ast::node::Which::Struct { fields, .. }) => Ok(Some(fields.clone())),
+            _ => Ok(None)
+        }
+    }
+
+    ///
+    /// A struct value in the form the request reader produces: every field that isn't in a
+    /// union, and the union member that is set, with defaults for the fields not given.
+    ///
+    fn struct_value(&mut self, type_id: Id, arguments: &[Argument], ctx: Lexical, position: Position) -> Result<ast::Value> {
+        let fields = match self.struct_fields(type_id)? {
+            Some(fields) => fields,
+            None => return self.error(ctx.file, position, "A struct can't have a value of its own type before it is declared.")
+        };
+
+        let mut given = HashMap::new();
+        let mut active = None;
+        for argument in arguments {
+            let name = match &argument.name {
+                Some(name) => name,
+                None => return self.error(ctx.file, argument.value.position, "Struct fields must be assigned by name.")
+            };
+            let field = match fields.iter().find(|field| field.name() == name) {
+                Some(field) => field,
+                None => return self.error(ctx.file, argument.value.position, &format!("There is no field named '{}'.", name))
+            };
+            if field.discriminant_value() != ast::field::NO_DISCRIMINANT {
+                if active.is_some() {
+                    return self.error(ctx.file, argument.value.position, "Only one union member may be set.");
+                }
+                active = Some(field.discriminant_value());
+            }
+            given.insert(name.clone(), &argument.value);
+        }
+        let active = active.unwrap_or(0);
+
+        let mut values = vec!();
+        for field in &fields {
+            if field.discriminant_value() != ast::field::NO_DISCRIMINANT && field.discriminant_value() != active {
+                continue;
+            }
+            let value = match (field.which(), given.get(field.name())) {
+                (ast::field::Which::Slot { type_, .. }, Some(expression)) => self.compile_value(expression, type_, ctx)?,
+                (ast::field::Which::Slot { default_value, .. }, None) => default_value.clone(),
+                (ast::field::Which::Group(group_id), Some(expression)) => match &expression.kind {
+                    ExpressionKind::Tuple(arguments) => self.struct_value(*group_id, arguments, ctx, expression.position)?,
+                    _ => return self.error(ctx.file, expression.position, "A group's value is a list of its fields in parentheses.")
+                },
+                (ast::field::Which::Group(group_id), None) => self.struct_value(*group_id, &[], ctx, position)?
+            };
+            values.push(ast::value::StructField::new(field.name().clone(), value));
+        }
+        Ok(ast::Value::Struct(values))
+    }
+
+    ///
+    /// The default of a field without an explicit one, the same as a reader sees for an unset field.
+    ///
+    fn zero_value(&mut self, type_: &ast::Type, ctx: Lexical, position: Position) -> Result<ast::Value> {
+        Ok(
+            match type_ {
+                ast::Type::Void => ast::Value::Void,
+                ast::Type::Bool => ast::Value::Bool(false),
+                ast::Type::Int8 => ast::Value::Int8(0),
+                ast::Type::Int16 => ast::Value::Int16(0),
+                ast::Type::Int32 => ast::Value::Int32(0),
+                ast::Type::Int64 => ast::Value::Int64(0),
+                ast::Type::Uint8 => ast::Value::Uint8(0),
+                ast::Type::Uint16 => ast::Value::Uint16(0),
+                ast::Type::Uint32 => ast::Value::Uint32(0),
+                ast::Type::Uint64 => ast::Value::Uint64(0),
+                ast::Type::Float32 => ast::Value::Float32(0.0),
+                ast::Type::Float64 => ast::Value::Float64(0.0),
+                ast::Type::Text => ast::Value::Text(String::new()),
+                ast::Type::Data => ast::Value::Data(vec!()),
+                ast::Type::List(_) => ast::Value::List(vec!()),
+                ast::Type::Enum { .. } => ast::Value::Enum(0),
+                ast::Type::Struct { type_id, .. } => {
+                    // A struct containing itself has no finite default.
+                    if self.in_progress.contains(type_id) {
+                        ast::Value::Struct(vec!())
+                    } else {
+                        self.struct_value(*type_id, &[], ctx, position)?
+                    }
+                },
+                ast::Type::Interface { .. } => ast::Value::Interface,
+                ast::Type::AnyPointer(_) => ast::Value::AnyPointer
+            }
+        )
+    }
+
+    fn default_value(&mut self, default: &Option<Expression>, type_: &ast::Type, ctx: Lexical, position: Position) -> Result<(ast::Value, bool)> {
+        match default {
+            Some(expression) => Ok((self.compile_value(expression, type_, ctx)?, true)),
+            None => Ok((self.zero_value(type_, ctx, position)?, false))
+        }
+    }
+
+    fn annotation_type(&mut self, id: Id) -> Result<ast::Type> {
+        let ctx = Lexical { file: self.entries[&id].file, scope: id, implicit_parameters: &[] };
+        match &self.decl(id).kind {
+            DeclarationKind::Annotation { type_, .. } => self.compile_type(type_, ctx),
+            _ => unreachable!()
+        }
+    }
+
+    fn annotations(&mut self, applications: &[AnnotationApplication], ctx: Lexical) -> Result<Vec<ast::Annotation>> {
+        let mut annotations = vec!();
+        for application in applications {
+            let id = match self.resolve(&application.name, ctx)? {
+                Resolved::Node(id, _) if self.decl(id).is_annotation() => id,
+                _ => return self.error(ctx.file, application.position, "Not an annotation.")
+            };
+            let type_ = self.annotation_type(id)?;
+            let value = match &application.value {
+                Some(expression) => self.compile_value(expression, &type_, ctx)?,
+                None if type_ == ast::Type::Void => ast::Value::Void,
+                None => return self.error(ctx.file, application.position, "The annotation needs a value.")
+            };
+            annotations.push(ast::Annotation::new(id, value));
+        }
+        Ok(annotations)
+    }
+
+    ///
+    /// Checks that members are numbered 0, 1, 2... and returns them in that order.
+    ///
+    fn by_ordinal<'d>(&self, file: usize, members: Vec<&'d Declaration>) -> Result<Vec<(u16, &'d Declaration)>> {
+        let mut numbered = vec!();
+        for member in members {
+            match member.ordinal {
+                Some(ordinal) => numbered.push((ordinal, member)),
+                None => return self.error(file, member.position, "Missing ordinal, e.g. `@0`.")
+            }
+        }
+        numbered.sort_by_key(|(ordinal, _)| *ordinal);
+        for (expected, (ordinal, member)) in numbered.iter().enumerate() {
+            if *ordinal as usize != expected {
+                let message = if (*ordinal as usize) < expected {
+                    format!("Duplicate ordinal @{}.", ordinal)
+                } else {
+                    format!("Skipped ordinal @{}. Ordinals must be sequential with no holes.", expected)
+                };
+                return self.error(file, member.position, &message);
+            }
+        }
+        Ok(numbered)
+    }
+
+    fn collect_members(&self, collected: &mut Collected<'a>, file: usize, c: usize, members: &'a [Declaration], in_union_block: bool) -> Result<()> {
+        for member in members {
+            let in_union = in_union_block || collected.containers[c].is_union;
+            let code_order = collected.containers[c].code_order;
+            match &member.kind {
+                DeclarationKind::Field { .. } => {
+                    collected.containers[c].code_order += 1;
+                    let scope = if in_union { collected.layout.new_group(collected.containers[c].union.unwrap()) } else { collected.containers[c].scope };
+                    collected.leaves.push(Leaf { decl: member, container: c, scope: scope, in_union: in_union, code_order: code_order });
+                },
+                DeclarationKind::Union if member.name.is_empty() => {
+                    if in_union || collected.containers[c].union.is_some() {
+                        return self.error(file, member.position, "Only one unnamed union is allowed per scope.");
+                    }
+                    collected.containers[c].union = Some(collected.layout.new_union(collected.containers[c].scope));
+                    self.collect_members(collected, file, c, &member.nested, true)?;
+                },
+                DeclarationKind::Group | DeclarationKind::Union => {
+                    collected.containers[c].code_order += 1;
+                    let scope = if in_union { collected.layout.new_group(collected.containers[c].union.unwrap()) } else { collected.containers[c].scope };
+                    let is_union = member.kind == DeclarationKind::Union;
+                    collected.containers.push(Container {
+                        decl: member,
+                        parent: Some(c),
+                        code_order_in_parent: code_order,
+                        in_union: in_union,
+                        scope: scope,
+                        union: if is_union { Some(collected.layout.new_union(scope)) } else { None },
+                        is_union: is_union,
+                        id: None,
+                        display_name: String::new(),
+                        fields: vec!(),
+                        docs: vec!(),
+                        discriminant_count: 0,
+                        code_order: 0
+                    });
+                    let group = collected.containers.len() - 1;
+                    self.collect_members(collected, file, group, &member.nested, false)?;
+                },
+                DeclarationKind::Enumerant | DeclarationKind::Method { .. } | DeclarationKind::File => {
+                    return self.error(file, member.position, "Only fields, groups and unions may appear in a struct.");
+                },
+                _ if c != 0 => return self.error(file, member.position, "Declarations may not appear in groups or unions."),
+                _ => ()
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Gives a group its id and its place in the parent's fields, once its first field is laid out.
+    ///
+    fn initialize(&mut self, containers: &mut [Container<'a>], c: usize, ctx: Lexical) -> Result<()> {
+        if containers[c].id.is_some() {
+            return Ok(());
+        }
+        let parent = containers[c].parent.expect("the struct itself is initialized");
+        self.initialize(containers, parent, ctx)?;
+
+        let id = ids::group_id(containers[parent].id.unwrap(), containers[parent].fields.len() as u16);
+        let discriminant_value = if containers[c].in_union {
+            containers[parent].discriminant_count += 1;
+            containers[parent].discriminant_count - 1
+        } else {
+            ast::field::NO_DISCRIMINANT
+        };
+        let decl = containers[c].decl;
+        let annotations = self.annotations(&decl.annotations, ctx)?;
+
+        containers[c].id = Some(id);
+        containers[c].display_name = format!("{}.{}", containers[parent].display_name, decl.name);
+        containers[parent].fields.push(ast::Field::new(
+            decl.name.clone(),
+            containers[c].code_order_in_parent,
+            discriminant_value,
+            ast::field::Ordinal::Implicit,
+            annotations,
+            ast::field::Which::Group(id)
+        ));
+        containers[parent].docs.push(self.member_info(ctx.file, decl));
+        Ok(())
+    }
+
+    fn translate_struct(&mut self, id: Id, ctx: Lexical) -> Result<(ast::node::Which, Vec<ast::Node>, Vec<ast::node::source_info::Member>)> {
+        let decl = self.decl(id);
+        let top = Container {
+            decl: decl,
+            parent: None,
+            code_order_in_parent: 0,
+            in_union: false,
+            scope: Scope::Top,
+            union: None,
+            is_union: false,
+            id: Some(id),
+            display_name: self.entries[&id].display_name.clone(),
+            fields: vec!(),
+            docs: vec!(),
+            discriminant_count: 0,
+            code_order: 0
+        };
+        let mut collected = Collected { layout: StructLayout::new(), containers: vec!(top), leaves: vec!() };
+        self.collect_members(&mut collected, ctx.file, 0, &decl.nested, false)?;
+        let Collected { mut layout, mut containers, leaves } = collected;
+
+        self.in_progress.insert(id);
+        let leaf_decls = leaves.iter().map(|leaf| leaf.decl).collect();
+        let ordered = self.by_ordinal(ctx.file, leaf_decls)?;
+        for (ordinal, leaf_decl) in ordered {
+            let leaf = leaves.iter().find(|leaf| std::ptr::eq(leaf.decl, leaf_decl)).unwrap();
+            self.initialize(&mut containers, leaf.container, ctx)?;
+
+            let (type_expression, default) = match &leaf.decl.kind {
+                DeclarationKind::Field { type_, default } => (type_, default),
+                _ => unreachable!()
+            };
+            let type_ = self.compile_type(type_expression, ctx)?;
+            let offset = allocate(&mut layout, leaf.scope, &type_);
+            let container = &mut containers[leaf.container];
+            let discriminant_value = if leaf.in_union {
+                container.discriminant_count += 1;
+                container.discriminant_count - 1
+            } else {
+                ast::field::NO_DISCRIMINANT
+            };
+            let (default_value, had_explicit_default) = self.default_value(default, &type_, ctx, leaf.decl.position)?;
+            let annotations = self.annotations(&leaf.decl.annotations, ctx)?;
+            let member_info = self.member_info(ctx.file, leaf.decl);
+
+            let container = &mut containers[leaf.container];
+            container.fields.push(ast::Field::new(
+                leaf.decl.name.clone(),
+                leaf.code_order,
+                discriminant_value,
+                ast::field::Ordinal::Explicit(ordinal),
+                annotations,
+                ast::field::Which::Slot { offset: offset, type_: type_, default_value: default_value, had_explicit_default: had_explicit_default }
+            ));
+            container.docs.push(member_info);
+        }
+
+        for container in &containers {
+            if container.id.is_none() {
+                return self.error(ctx.file, container.decl.position, "Groups must contain at least one field.");
+            }
+            if container.union.is_some() && container.discriminant_count < 2 {
+                return self.error(ctx.file, container.decl.position, "Unions must have at least two members.");
+            }
+        }
+
+        let mut groups = vec!();
+        for c in 1..containers.len() {
+            let parent = &containers[containers[c].parent.unwrap()];
+            let (parent_id, prefix_length) = (parent.id.unwrap(), parent.display_name.len() + 1);
+            let container = &mut containers[c];
+            let group_id = container.id.unwrap();
+            let fields = std::mem::take(&mut container.fields);
+            groups.push(ast::Node::new(
+                group_id,
+                container.display_name.clone(),
+                prefix_length,
+                parent_id,
+                vec!(),
+                vec!(),
+                vec!(),
+                self.is_generic(id),
+                struct_which(&mut layout, true, container.union, container.discriminant_count, fields)
+            ));
+            let (start_byte, end_byte) = self.span(ctx.file, container.decl);
+            self.source_info.insert(group_id, ast::node::SourceInfo::new(group_id, None, std::mem::take(&mut container.docs), start_byte, end_byte));
+        }
+        self.in_progress.remove(&id);
+
+        let top = &mut containers[0];
+        let fields = std::mem::take(&mut top.fields);
+        let docs = std::mem::take(&mut top.docs);
+        let (union, discriminant_count) = (top.union, top.discriminant_count);
+        Ok((struct_which(&mut layout, false, union, discriminant_count, fields), groups, docs))
+    }
+
+    fn method_struct(
+        &mut self,
+        interface_id: Id,
+        method: &Declaration,
+        ordinal: u16,
+        params: Option<&MethodParams>,
+        is_results: bool,
+        ctx: Lexical
+    ) -> Result<(Id, ast::Brand, Option<ast::Node>)> {
+        let fields = match params {
+            Some(MethodParams::Type(expression)) => {
+                return match self.compile_type(expression, ctx)? {
+                    ast::Type::Struct { type_id, brand } => Ok((type_id, brand, None)),
+                    _ => self.error(ctx.file, expression.position, "Method parameters must be a struct.")
+                };
+            },
+            Some(MethodParams::Fields(fields)) => fields.as_slice(),
+            None => &[]
+        };
+
+        let struct_id = ids::method_params_id(interface_id, ordinal, is_results);
+        let mut layout = StructLayout::new();
+        let mut ast_fields = vec!();
+        let mut docs = vec!();
+        for (i, param) in fields.iter().enumerate() {
+            let (type_expression, default) = match &param.kind {
+                DeclarationKind::Field { type_, default } => (type_, default),
+                _ => unreachable!()
+            };
+            let type_ = self.compile_type(type_expression, ctx)?;
+            let offset = allocate(&mut layout, Scope::Top, &type_);
+            let (default_value, had_explicit_default) = self.default_value(default, &type_, ctx, param.position)?;
+            let annotations = self.annotations(&param.annotations, ctx)?;
+            ast_fields.push(ast::Field::new(
+                param.name.clone(),
+                i as u16,
+                ast::field::NO_DISCRIMINANT,
+                ast::field::Ordinal::Explicit(i as u16),
+                annotations,
+                ast::field::Which::Slot { offset: offset, type_: type_, default_value: default_value, had_explicit_default: had_explicit_default }
+            ));
+            let (start_byte, end_byte) = self.span(ctx.file, param);
+            docs.push(ast::node::source_info::Member::new(None, start_byte, end_byte));
+        }
+
+        let interface_name = self.entries[&interface_id].display_name.clone();
+        let node = ast::Node::new(
+            struct_id,
+            format!("{}.{}${}", interface_name, method.name, if is_results { "Results" } else { "Params" }),
+            interface_name.len() + 1,
+            0,
+            vec!(),
+            vec!(),
+            vec!(),
+            self.is_generic(interface_id),
+            struct_which(&mut layout, false, None, 0, ast_fields)
+        );
+        let (start_byte, end_byte) = self.span(ctx.file, method);
+        self.source_info.insert(struct_id, ast::node::SourceInfo::new(struct_id, None, docs, start_byte, end_byte));
+        Ok((struct_id, self.brand(interface_id, vec!(), ctx), Some(node)))
+    }
+
+    fn translate_interface(&mut self, id: Id, superclass_expressions: &[Expression], ctx: Lexical) -> Result<(ast::node::Which, Vec<ast::Node>, Vec<ast::node::source_info::Member>)> {
+        let mut superclasses = vec!();
+        for expression in superclass_expressions {
+            match self.compile_type(expression, ctx)? {
+                ast::Type::Interface { type_id, brand } => superclasses.push(ast::Superclass::new(type_id, brand)),
+                _ => return self.error(ctx.file, expression.position, "Superclasses must be interfaces.")
+            }
+        }
+
+        let decl = self.decl(id);
+        let methods: Vec<&Declaration> = decl.nested.iter().filter(|member| member.is_method()).collect();
+        let code_orders: HashMap<*const Declaration, u16> = methods.iter().enumerate().map(|(i, m)| (*m as *const Declaration, i as u16)).collect();
+
+        let mut ast_methods = vec!();
+        let mut param_structs = vec!();
+        let mut docs = vec!();
+        for (ordinal, method) in self.by_ordinal(ctx.file, methods)? {
+            let (implicit_parameters, params, results) = match &method.kind {
+                DeclarationKind::Method { implicit_parameters, params, results } => (implicit_parameters, params, results),
+                _ => unreachable!()
+            };
+            let method_ctx = Lexical { file: ctx.file, scope: id, implicit_parameters: implicit_parameters };
+            let (param_id, param_brand, param_node) = self.method_struct(id, method, ordinal, Some(params), false, method_ctx)?;
+            let (result_id, result_brand, result_node) = self.method_struct(id, method, ordinal, results.as_ref(), true, method_ctx)?;
+            param_structs.extend(param_node);
+            param_structs.extend(result_node);
+
+            ast_methods.push(ast::Method::new(
+                method.name.clone(),
+                code_orders[&(method as *const Declaration)],
+                param_id,
+                result_id,
+                param_brand,
+                result_brand,
+                implicit_parameters.iter().map(|name| ast::node::Parameter::new(name.clone())).collect(),
+                self.annotations(&method.annotations, method_ctx)?
+            ));
+            docs.push(self.member_info(ctx.file, method));
+        }
+        Ok((ast::node::Which::Interface { methods: ast_methods, superclasses: superclasses }, param_structs, docs))
+    }
+
+    fn translate_enum(&mut self, id: Id, ctx: Lexical) -> Result<(ast::node::Which, Vec<ast::Node>, Vec<ast::node::source_info::Member>)> {
+        let decl = self.decl(id);
+        let enumerants: Vec<&Declaration> = decl.nested.iter().filter(|member| member.kind == DeclarationKind::Enumerant).collect();
+        let code_orders: HashMap<*const Declaration, u16> = enumerants.iter().enumerate().map(|(i, e)| (*e as *const Declaration, i as u16)).collect();
+
+        let mut ast_enumerants = vec!();
+        let mut docs = vec!();
+        for (_, enumerant) in self.by_ordinal(ctx.file, enumerants)? {
+            ast_enumerants.push(ast::Enumerant::new(
+                enumerant.name.clone(),
+                code_orders[&(enumerant as *const Declaration)],
+                self.annotations(&enumerant.annotations, ctx)?
+            ));
+            docs.push(self.member_info(ctx.file, enumerant));
+        }
+        Ok((ast::node::Which::Enum(ast_enumerants), vec!(), docs))
+    }
+
+    fn translate(&mut self, id: Id) -> Result<()> {
+        if self.produced.contains_key(&id) {
+            return Ok(());
+        }
+        let decl = self.decl(id);
+        let ctx = Lexical { file: self.entries[&id].file, scope: id, implicit_parameters: &[] };
+
+        let (which, extra_nodes, docs) = match &decl.kind {
+            DeclarationKind::File => (ast::node::Which::File, vec!(), vec!()),
+            DeclarationKind::Struct => self.translate_struct(id, ctx)?,
+            DeclarationKind::Enum => self.translate_enum(id, ctx)?,
+            DeclarationKind::Interface { superclasses } => self.translate_interface(id, superclasses, ctx)?,
+            DeclarationKind::Const { type_, .. } => {
+                let type_ = self.compile_type(type_, ctx)?;
+                let value = self.const_value(id)?;
+                (ast::node::Which::Const { type_: type_, value: value }, vec!(), vec!())
+            },
+            DeclarationKind::Annotation { type_, targets } => {
+                let mut ast_targets = vec!();
+                for target in targets {
+                    match (target.as_str(), annotation_target(target)) {
+                        ("*", _) => ast_targets.extend(ANNOTATION_TARGETS.iter().filter_map(|name| annotation_target(name))),
+                        (_, Some(target)) => ast_targets.push(target),
+                        (_, None) => return self.error(ctx.file, decl.position, &format!("'{}' is not an annotation target.", target))
+                    }
+                }
+                (ast::node::Which::Annotation { type_: self.compile_type(type_, ctx)?, targets: ast_targets }, vec!(), vec!())
+            },
+            _ => unreachable!()
+        };
+        let annotations = self.annotations(&decl.annotations, ctx)?;
+
+        let entry = &self.entries[&id];
+        let node = ast::Node::new(
+            id,
+            entry.display_name.clone(),
+            entry.prefix_length,
+            entry.parent.unwrap_or(0),
+            entry.nested.clone(),
+            annotations,
+            decl.parameters.iter().map(|name| ast::node::Parameter::new(name.clone())).collect(),
+            self.is_generic(id),
+            which
+        );
+        let (start_byte, end_byte) = self.span(ctx.file, decl);
+        self.source_info.insert(id, ast::node::SourceInfo::new(id, decl.doc_comment.clone(), docs, start_byte, end_byte));
+
+        let mut produced = vec!(id);
+        self.translated.insert(id, node);
+        for extra in extra_nodes {
+            produced.push(extra.id());
+            self.translated.insert(extra.id(), extra);
+        }
+        self.produced.insert(id, produced);
+        Ok(())
+    }
+}
+
+impl Declaration {
+    fn is_const(&self) -> bool {
+        matches!(self.kind, DeclarationKind::Const { .. })
+    }
+
+    fn is_annotation(&self) -> bool {
+        matches!(self.kind, DeclarationKind::Annotation { .. })
+    }
+
+    fn is_method(&self) -> bool {
+        matches!(self.kind, DeclarationKind::Method { .. })
+    }
+}
+
+///
+/// Builds the request for the parsed files, the way `capnp compile` would for `requested`.
+/// Every loaded file is translated, so imported declarations are in the request too.
+///
+pub fn translate(files: &[SourceFile], requested: &[usize]) -> Result<ast::CodeGeneratorRequest> {
+    let mut translator = Translator {
+        files: files,
+        file_ids: vec!(),
+        entries: HashMap::new(),
+        order: vec!(),
+        translated: HashMap::new(),
+        produced: HashMap::new(),
+        in_progress: HashSet::new(),
+        aliases_in_progress: vec!(),
+        const_values: HashMap::new(),
+        source_info: HashMap::new()
+    };
+
+    for file in files {
+        match file.root.id {
+            Some(id) => translator.file_ids.push(id),
+            None => {
+                let message = "The file has no id. Add one at the top, e.g. `@0x...;` with an id from `capnp id`.";
+                return Err(SourceError::new(Position { line: 1, column: 1 }, message).in_file(&file.display_name));
+            }
+        }
+    }
+    for (index, file) in files.iter().enumerate() {
+        translator.check_id(index, &file.root)?;
+        let prefix_length = file.display_name.rfind('/').map_or(0, |i| i + 1);
+        translator.declare(&file.root, index, None, translator.file_ids[index], file.display_name.clone(), prefix_length)?;
+    }
+    for id in translator.order.clone() {
+        translator.translate(id)?;
+    }
+
+    let mut nodes = vec!();
+    for id in &translator.order {
+        for produced in &translator.produced[id] {
+            nodes.push(translator.translated[produced].clone());
+        }
+    }
+    let requested_files = requested.iter()
+        .map(|index| ast::code_generator_request::RequestedFile::new(
+            translator.file_ids[*index],
+            files[*index].display_name.clone(),
+            files[*index].imports.iter()
+                .map(|(name, file)| ast::code_generator_request::requested_file::Import::new(translator.file_ids[*file], name.clone()))
+                .collect()
+        ))
+        .collect();
+    Ok(ast::CodeGeneratorRequest::new(nodes, requested_files, translator.source_info))
+}
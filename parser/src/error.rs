@@ -0,0 +1,72 @@
+use std::fmt;
+
+///
+/// Why a code generator request could not be read or compiled.
+///
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be read from its source.
+    Io(std::io::Error),
+
+    /// The request, or a dump of it, could not be written to its destination.
+    Write(std::io::Error),
+
+    /// The input is not a valid Cap'n Proto message, or not a valid `CodeGeneratorRequest`.
+    Malformed(capnp::Error),
+
+    /// The request uses an enum value or union member that this version of schema.capnp doesn't know.
+    Unsupported(capnp::NotInSchema),
+
+    /// A schema file could not be compiled. The message starts with the file, line and column.
+    Schema(String),
+
+    /// A plugin was given arguments it doesn't understand.
+    Usage(String),
+
+    /// The request being written or printed has a value that doesn't match its type, or refers to a missing node.
+    Invalid(String)
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Unable to read the code generator request: {}", e),
+            Error::Write(e) => write!(f, "Unable to write the code generator request: {}", e),
+            Error::Malformed(e) => write!(f, "Malformed code generator request: {}", e),
+            Error::Unsupported(e) => write!(f, "Unsupported schema feature: discriminant {} is not in schema.capnp", e.0),
+            Error::Schema(message) => write!(f, "Invalid schema: {}", message),
+            Error::Usage(message) => write!(f, "Invalid arguments: {}", message),
+            Error::Invalid(message) => write!(f, "Invalid code generator request: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Write(e) => Some(e),
+            Error::Malformed(e) => Some(e),
+            Error::Unsupported(_) | Error::Schema(_) | Error::Usage(_) | Error::Invalid(_) => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<capnp::Error> for Error {
+    fn from(e: capnp::Error) -> Error {
+        Error::Malformed(e)
+    }
+}
+
+impl From<capnp::NotInSchema> for Error {
+    fn from(e: capnp::NotInSchema) -> Error {
+        Error::Unsupported(e)
+    }
+}
@@ -0,0 +1,62 @@
+//!
+//! Nodes built by hand, for tests that need requests the schema compiler wouldn't produce:
+//! invalid ones, or ones with an exact layout.
+//!
+
+use crate::ast;
+use crate::ast::Id;
+
+///
+/// A node with the display name prefix the `capnp` tool gives it: up to the last `:` or `.`
+/// after the file name, e.g. `test.capnp:Shape.` for `test.capnp:Shape.extra`.
+///
+pub fn node(id: Id, display_name: &str, scope_id: Id, nested: Vec<(Id, &str)>, which: ast::node::Which) -> ast::Node {
+    let prefix_length = match display_name.find(':') {
+        Some(_) => display_name.rfind(|c| c == ':' || c == '.').unwrap() + 1,
+        None => 0
+    };
+    ast::Node::new(
+        id,
+        String::from(display_name),
+        prefix_length,
+        scope_id,
+        nested.into_iter().map(|(id, name)| ast::node::NestedNode::new(id, String::from(name))).collect(),
+        vec!(),
+        vec!(),
+        false,
+        which
+    )
+}
+
+///
+/// A slot declared with an explicit default, whose ordinal is also its position in the code.
+///
+pub fn slot(name: &str, ordinal: u16, discriminant_value: u16, offset: u32, type_: ast::Type, default_value: ast::Value) -> ast::Field {
+    ast::Field::new(
+        String::from(name),
+        ordinal,
+        discriminant_value,
+        ast::field::Ordinal::Explicit(ordinal),
+        vec!(),
+        ast::field::Which::Slot { offset: offset, type_: type_, default_value: default_value, had_explicit_default: true }
+    )
+}
+
+pub fn struct_which(
+    data_word_count: u16,
+    pointer_count: u16,
+    is_group: bool,
+    discriminant_count: u16,
+    discriminant_offset: u32,
+    fields: Vec<ast::Field>
+) -> ast::node::Which {
+    ast::node::Which::Struct {
+        data_word_count: data_word_count,
+        pointer_count: pointer_count,
+        preferred_list_encoding: ast::node::ElementSize::InlineComposite,
+        is_group: is_group,
+        discriminant_count: discriminant_count,
+        discriminant_offset: discriminant_offset,
+        fields: fields
+    }
+}
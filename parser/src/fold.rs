@@ -0,0 +1,265 @@
+//!
+//! Rewriting a request. A `Fold` builds a new tree from an old one; each hook returns the
+//! replacement for its part, and the matching `fold_*` function rebuilds it unchanged apart
+//! from what the other hooks return.
+//!
+
+use crate::ast;
+use crate::ast::{CodeGeneratorRequest, Node};
+
+pub trait Fold {
+    fn fold_request(&mut self, cgr: &CodeGeneratorRequest) -> CodeGeneratorRequest {
+        fold_request(self, cgr)
+    }
+
+    fn fold_node(&mut self, node: &Node) -> Node {
+        fold_node(self, node)
+    }
+
+    fn fold_field(&mut self, field: &ast::Field) -> ast::Field {
+        fold_field(self, field)
+    }
+
+    fn fold_enumerant(&mut self, enumerant: &ast::Enumerant) -> ast::Enumerant {
+        fold_enumerant(self, enumerant)
+    }
+
+    fn fold_method(&mut self, method: &ast::Method) -> ast::Method {
+        fold_method(self, method)
+    }
+
+    fn fold_superclass(&mut self, superclass: &ast::Superclass) -> ast::Superclass {
+        fold_superclass(self, superclass)
+    }
+
+    fn fold_annotation(&mut self, annotation: &ast::Annotation) -> ast::Annotation {
+        fold_annotation(self, annotation)
+    }
+
+    fn fold_type(&mut self, type_: &ast::Type) -> ast::Type {
+        fold_type(self, type_)
+    }
+
+    fn fold_brand(&mut self, brand: &ast::Brand) -> ast::Brand {
+        fold_brand(self, brand)
+    }
+
+    fn fold_value(&mut self, value: &ast::Value) -> ast::Value {
+        fold_value(self, value)
+    }
+}
+
+///
+/// Folds every node. The requested files and source info are kept as they are.
+///
+pub fn fold_request<F: Fold + ?Sized>(folder: &mut F, cgr: &CodeGeneratorRequest) -> CodeGeneratorRequest {
+    CodeGeneratorRequest::new(
+        cgr.nodes().iter().map(|node| folder.fold_node(node)).collect(),
+        cgr.requested_files().clone(),
+        cgr.source_info().clone()
+    )
+}
+
+fn fold_annotations<F: Fold + ?Sized>(folder: &mut F, annotations: &[ast::Annotation]) -> Vec<ast::Annotation> {
+    annotations.iter().map(|annotation| folder.fold_annotation(annotation)).collect()
+}
+
+pub fn fold_node<F: Fold + ?Sized>(folder: &mut F, node: &Node) -> Node {
+    let which = match node.which() {
+        ast::node::Which::File => ast::node::Which::File,
+        ast::node::Which::Struct { data_word_count, pointer_count, preferred_list_encoding, is_group, discriminant_count, discriminant_offset, fields } => {
+            ast::node::Which::Struct {
+                data_word_count: *data_word_count,
+                pointer_count: *pointer_count,
+                preferred_list_encoding: *preferred_list_encoding,
+                is_group: *is_group,
+                discriminant_count: *discriminant_count,
+                discriminant_offset: *discriminant_offset,
+                fields: fields.iter().map(|field| folder.fold_field(field)).collect()
+            }
+        },
+        ast::node::Which::Enum(enumerants) => ast::node::Which::Enum(enumerants.iter().map(|enumerant| folder.fold_enumerant(enumerant)).collect()),
+        ast::node::Which::Interface { methods, superclasses } => ast::node::Which::Interface {
+            methods: methods.iter().map(|method| folder.fold_method(method)).collect(),
+            superclasses: superclasses.iter().map(|superclass| folder.fold_superclass(superclass)).collect()
+        },
+        ast::node::Which::Const { type_, value } => ast::node::Which::Const { type_: folder.fold_type(type_), value: folder.fold_value(value) },
+        ast::node::Which::Annotation { type_, targets } => ast::node::Which::Annotation { type_: folder.fold_type(type_), targets: targets.clone() }
+    };
+    Node::new(
+        node.id(),
+        node.display_name().clone(),
+        node.display_name_prefix_length(),
+        node.scope_id(),
+        node.nested_nodes().clone(),
+        fold_annotations(folder, node.annotations()),
+        node.parameters().clone(),
+        node.is_generic(),
+        which
+    )
+}
+
+pub fn fold_field<F: Fold + ?Sized>(folder: &mut F, field: &ast::Field) -> ast::Field {
+    let which = match field.which() {
+        ast::field::Which::Slot { offset, type_, default_value, had_explicit_default } => ast::field::Which::Slot {
+            offset: *offset,
+            type_: folder.fold_type(type_),
+            default_value: folder.fold_value(default_value),
+            had_explicit_default: *had_explicit_default
+        },
+        ast::field::Which::Group(id) => ast::field::Which::Group(*id)
+    };
+    ast::Field::new(
+        field.name().clone(),
+        field.code_order(),
+        field.discriminant_value(),
+        field.ordinal(),
+        fold_annotations(folder, field.annotations()),
+        which
+    )
+}
+
+pub fn fold_enumerant<F: Fold + ?Sized>(folder: &mut F, enumerant: &ast::Enumerant) -> ast::Enumerant {
+    ast::Enumerant::new(enumerant.name().clone(), enumerant.code_order(), fold_annotations(folder, enumerant.annotations()))
+}
+
+pub fn fold_method<F: Fold + ?Sized>(folder: &mut F, method: &ast::Method) -> ast::Method {
+    ast::Method::new(
+        method.name().clone(),
+        method.code_order(),
+        method.param_struct_type(),
+        method.result_struct_type(),
+        folder.fold_brand(method.param_brand()),
+        folder.fold_brand(method.result_brand()),
+        method.implicit_parameters().clone(),
+        fold_annotations(folder, method.annotations())
+    )
+}
+
+pub fn fold_superclass<F: Fold + ?Sized>(folder: &mut F, superclass: &ast::Superclass) -> ast::Superclass {
+    ast::Superclass::new(superclass.id(), folder.fold_brand(superclass.brand()))
+}
+
+pub fn fold_annotation<F: Fold + ?Sized>(folder: &mut F, annotation: &ast::Annotation) -> ast::Annotation {
+    ast::Annotation::new(annotation.id(), folder.fold_value(annotation.value()))
+}
+
+pub fn fold_type<F: Fold + ?Sized>(folder: &mut F, type_: &ast::Type) -> ast::Type {
+    match type_ {
+        ast::Type::List(element_type) => ast::Type::List(Box::new(folder.fold_type(element_type))),
+        ast::Type::Enum { type_id, brand } => ast::Type::Enum { type_id: *type_id, brand: folder.fold_brand(brand) },
+        ast::Type::Struct { type_id, brand } => ast::Type::Struct { type_id: *type_id, brand: folder.fold_brand(brand) },
+        ast::Type::Interface { type_id, brand } => ast::Type::Interface { type_id: *type_id, brand: folder.fold_brand(brand) },
+        other => other.clone()
+    }
+}
+
+pub fn fold_brand<F: Fold + ?Sized>(folder: &mut F, brand: &ast::Brand) -> ast::Brand {
+    let scopes = brand.scopes().iter()
+        .map(|scope| {
+            let which = match scope.which() {
+                ast::brand::scope::Which::Bind(bindings) => ast::brand::scope::Which::Bind(
+                    bindings.iter()
+                        .map(|binding| match binding {
+                            ast::brand::Binding::Type(type_) => ast::brand::Binding::Type(folder.fold_type(type_)),
+                            ast::brand::Binding::Unbound => ast::brand::Binding::Unbound
+                        })
+                        .collect()
+                ),
+                ast::brand::scope::Which::Inherit => ast::brand::scope::Which::Inherit
+            };
+            ast::brand::Scope::new(scope.scope_id(), which)
+        })
+        .collect();
+    ast::Brand::new(scopes)
+}
+
+pub fn fold_value<F: Fold + ?Sized>(folder: &mut F, value: &ast::Value) -> ast::Value {
+    match value {
+        ast::Value::List(values) => ast::Value::List(values.iter().map(|value| folder.fold_value(value)).collect()),
+        ast::Value::Struct(fields) => ast::Value::Struct(
+            fields.iter()
+                .map(|field| ast::value::StructField::new(field.name().clone(), folder.fold_value(field.value())))
+                .collect()
+        ),
+        other => other.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request() -> CodeGeneratorRequest {
+        let field = ast::Field::new(
+            String::from("count"),
+            0,
+            ast::field::NO_DISCRIMINANT,
+            ast::field::Ordinal::Explicit(0),
+            vec!(),
+            ast::field::Which::Slot {
+                offset: 0,
+                type_: ast::Type::List(Box::new(ast::Type::Int32)),
+                default_value: ast::Value::List(vec!(ast::Value::Int32(1), ast::Value::Int32(2))),
+                had_explicit_default: true
+            }
+        );
+        let which = ast::node::Which::Struct {
+            data_word_count: 0,
+            pointer_count: 1,
+            preferred_list_encoding: ast::node::ElementSize::Pointer,
+            is_group: false,
+            discriminant_count: 0,
+            discriminant_offset: 0,
+            fields: vec!(field)
+        };
+        CodeGeneratorRequest::new(
+            vec!(Node::new(2, String::from("test.capnp:Counter"), 11, 1, vec!(), vec!(), vec!(), false, which)),
+            vec!(),
+            HashMap::new()
+        )
+    }
+
+    struct Widen;
+
+    impl Fold for Widen {
+        fn fold_type(&mut self, type_: &ast::Type) -> ast::Type {
+            match type_ {
+                ast::Type::Int32 => ast::Type::Int64,
+                other => fold_type(self, other)
+            }
+        }
+
+        fn fold_value(&mut self, value: &ast::Value) -> ast::Value {
+            match value {
+                ast::Value::Int32(i) => ast::Value::Int64(*i as i64),
+                other => fold_value(self, other)
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold() {
+        let folded = Widen.fold_request(&request());
+        match folded.nodes()[0].which() {
+            ast::node::Which::Struct { fields, .. } => match fields[0].which() {
+                ast::field::Which::Slot { type_, default_value, .. } => {
+                    assert_eq!(type_, &ast::Type::List(Box::new(ast::Type::Int64)));
+                    assert_eq!(default_value, &ast::Value::List(vec!(ast::Value::Int64(1), ast::Value::Int64(2))));
+                },
+                which => panic!("Unexpected {:?}", which)
+            },
+            which => panic!("Unexpected {:?}", which)
+        }
+    }
+
+    #[test]
+    fn test_identity_fold() {
+        struct Identity;
+        impl Fold for Identity {}
+
+        let cgr = request();
+        assert_eq!(Identity.fold_request(&cgr), cgr);
+    }
+}
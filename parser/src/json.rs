@@ -0,0 +1,73 @@
+use crate::ast;
+use crate::{Error, Result};
+
+///
+/// A pretty JSON dump of the request. The output only depends on the request, so dumps
+/// can be diffed, e.g. to review how a schema change affects what generators are given.
+///
+pub fn to_json(cgr: &ast::CodeGeneratorRequest) -> String {
+    serde_json::to_string_pretty(cgr).expect("The AST has no maps with non-string keys")
+}
+
+pub fn write_json(writer: &mut dyn std::io::Write, cgr: &ast::CodeGeneratorRequest) -> Result<()> {
+    writer.write_all(to_json(cgr).as_bytes()).map_err(Error::Write)?;
+    writer.write_all(b"\n").map_err(Error::Write)
+}
+
+pub fn write_json_to_file<P: AsRef<std::path::Path>>(path: P, cgr: &ast::CodeGeneratorRequest) -> Result<()> {
+    write_json(&mut std::fs::File::create(path).map_err(Error::Write)?, cgr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request() -> ast::CodeGeneratorRequest {
+        let mut source_info = HashMap::new();
+        for id in vec!(30, 10, 20) {
+            source_info.insert(id, ast::node::SourceInfo::new(id, Some(format!("Node {}.", id)), vec!(), 0, 0));
+        }
+        ast::CodeGeneratorRequest::new(
+            vec!(ast::Node::new(
+                10,
+                String::from("test.capnp"),
+                0,
+                0,
+                vec!(),
+                vec!(ast::Annotation::new(11, ast::Value::Text(String::from("ns")))),
+                vec!(),
+                false,
+                ast::node::Which::File
+            )),
+            vec!(ast::code_generator_request::RequestedFile::new(10, String::from("test.capnp"), vec!())),
+            source_info
+        )
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json: serde_json::Value = serde_json::from_str(&to_json(&request())).unwrap();
+        assert_eq!(json["nodes"][0]["display_name"], "test.capnp");
+        assert_eq!(json["nodes"][0]["which"], "File");
+        assert_eq!(json["nodes"][0]["annotations"][0]["value"]["Text"], "ns");
+        assert_eq!(json["requested_files"][0]["id"], 10);
+        assert_eq!(json["source_info"]["20"]["doc_comment"], "Node 20.");
+    }
+
+    #[test]
+    fn test_to_json_is_stable() {
+        let json = to_json(&request());
+        assert_eq!(json, to_json(&request()));
+
+        let positions: Vec<usize> = vec!("\"10\"", "\"20\"", "\"30\"").iter().map(|key| json.find(key).unwrap()).collect();
+        assert!(positions[0] < positions[1] && positions[1] < positions[2]);
+    }
+
+    #[test]
+    fn test_write_json() {
+        let mut bytes = vec!();
+        write_json(&mut bytes, &request()).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), format!("{}\n", to_json(&request())));
+    }
+}
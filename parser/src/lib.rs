@@ -1,32 +1,94 @@
 extern crate capnp;
 #[macro_use] extern crate derive_more;
 extern crate getset;
+extern crate md5;
+#[macro_use] extern crate serde;
+extern crate serde_json;
 
+mod annotations;
 pub mod ast;
+pub mod fold;
+pub mod visit;
+mod compatibility;
+mod compiler;
+mod error;
+#[cfg(test)]
+mod fixtures;
+mod json;
+mod lint;
+mod plugin;
+mod printer;
+mod read_options;
 #[allow(dead_code)]
 mod schema_capnp;
+mod schema_graph;
+mod toposort;
+mod writer;
+
+pub use annotations::{AnnotationDeclaration, AnnotationRegistry, Applied, FromValue, CPP_NAME_ANNOTATION_ID, CPP_NAMESPACE_ANNOTATION_ID};
+pub use compatibility::{check_compatibility, Change, CompatibilityReport, Severity};
+pub use compiler::{compile_files, compile_source, CompileOptions};
+pub use error::{Error, Result};
+pub use json::{to_json, write_json, write_json_to_file};
+pub use lint::{lint, Diagnostic, Level, LintConfig, Rule};
+pub use plugin::{json_dump_path, RequestArgs};
+pub use printer::{print_file, print_requested_files};
+pub use read_options::{Framing, ReadOptions};
+pub use schema_graph::{Fingerprint, SchemaGraph};
+pub use toposort::{sort_by_dependencies, Component};
+pub use writer::{build_message, write_message, write_message_to_bytes, write_message_with_framing};
+
+use std::collections::HashMap;
+use capnp::private::layout::{ElementSize, PointerReader, StructReader};
+use capnp::traits::FromPointerReader;
+
+///
+/// The raw schema nodes of the request being parsed. Values are encoded against
+/// their schema types, so decoding lists and structs needs to look up struct layouts.
+///
+struct Context<'a> {
+    nodes: HashMap<ast::Id, schema_capnp::node::Reader<'a>>
+}
+
+impl<'a> Context<'a> {
+    fn new(nodes: capnp::struct_list::Reader<'a, schema_capnp::node::Owned>) -> Context<'a> {
+        Context {
+            nodes: nodes.iter().map(|node| (node.get_id(), node)).collect()
+        }
+    }
+
+    fn get_annotation_type(&self, id: ast::Id) -> Result<Option<ast::Type>> {
+        match self.nodes.get(&id) {
+            Some(node) => match node.which()? {
+                schema_capnp::node::Which::Annotation(a) => Ok(Some(ast::Type::parse(self, a.get_type()?)?)),
+                _ => Ok(None)
+            },
+            None => Ok(None)
+        }
+    }
+}
 
 trait ParseFrom<R> : Sized {
-    fn parse(reader: R) -> capnp::Result<Self>;
+    fn parse(ctx: &Context, reader: R) -> Result<Self>;
 }
 
 impl ParseFrom<schema_capnp::type_::Reader<'_>> for ast::Type {
-    fn parse(reader: schema_capnp::type_::Reader<'_>) -> capnp::Result<ast::Type> {
+    fn parse(ctx: &Context, reader: schema_capnp::type_::Reader<'_>) -> Result<ast::Type> {
         Ok(
             match reader.which()? {
-                schema_capnp::type_::Which::AnyPointer(_) => ast::Type::AnyPointer,
+                schema_capnp::type_::Which::AnyPointer(p) => ast::Type::AnyPointer(ast::type_::AnyPointer::parse(ctx, p)?),
                 schema_capnp::type_::Which::Bool(_) => ast::Type::Bool,
                 schema_capnp::type_::Which::Data(_) => ast::Type::Data,
-                schema_capnp::type_::Which::Enum(e) => ast::Type::Enum { type_id: e.get_type_id() },
+                schema_capnp::type_::Which::Enum(e) => ast::Type::Enum { type_id: e.get_type_id(), brand: ast::Brand::parse(ctx, e.get_brand()?)? },
                 schema_capnp::type_::Which::Float32(_) => ast::Type::Float32,
                 schema_capnp::type_::Which::Float64(_) => ast::Type::Float64,
                 schema_capnp::type_::Which::Int16(_) => ast::Type::Int16,
                 schema_capnp::type_::Which::Int32(_) => ast::Type::Int32,
                 schema_capnp::type_::Which::Int64(_) => ast::Type::Int64,
                 schema_capnp::type_::Which::Int8(_) => ast::Type::Int8,
-                schema_capnp::type_::Which::Interface(i) => ast::Type::Interface { type_id: i.get_type_id() },
-                schema_capnp::type_::Which::List(t) => ast::Type::List(Box::new(ast::Type::parse(t.get_element_type()?)?)),
-                schema_capnp::type_::Which::Struct(s) => ast::Type::Struct { type_id: s.get_type_id() },
+                schema_capnp::type_::Which::Interface(i) => ast::Type::Interface { type_id: i.get_type_id(), brand: ast::Brand::parse(ctx, i.get_brand()?)? },
+                schema_capnp::type_::Which::List(t) => ast::Type::List(Box::new(ast::Type::parse(ctx, t.get_element_type()?)?)),
+                schema_capnp::type_::Which::Struct(s) => ast::Type::Struct { type_id: s.get_type_id(), brand: ast::Brand::parse(ctx, s.get_brand()?)? },
                 schema_capnp::type_::Which::Text(_) => ast::Type::Text,
                 schema_capnp::type_::Which::Uint16(_) => ast::Type::Uint16,
                 schema_capnp::type_::Which::Uint32(_) => ast::Type::Uint32,
@@ -38,52 +100,206 @@ impl ParseFrom<schema_capnp::type_::Reader<'_>> for ast::Type {
     }
 }
 
+impl ParseFrom<schema_capnp::type_::any_pointer::Reader<'_>> for ast::type_::AnyPointer {
+    fn parse(_ctx: &Context, reader: schema_capnp::type_::any_pointer::Reader<'_>) -> Result<ast::type_::AnyPointer> {
+        use schema_capnp::type_::any_pointer::unconstrained;
+
+        Ok(
+            match reader.which()? {
+                schema_capnp::type_::any_pointer::Which::Unconstrained(u) => ast::type_::AnyPointer::Unconstrained(
+                    match u.which()? {
+                        unconstrained::Which::AnyKind(_) => ast::type_::Unconstrained::AnyKind,
+                        unconstrained::Which::Struct(_) => ast::type_::Unconstrained::Struct,
+                        unconstrained::Which::List(_) => ast::type_::Unconstrained::List,
+                        unconstrained::Which::Capability(_) => ast::type_::Unconstrained::Capability
+                    }
+                ),
+                schema_capnp::type_::any_pointer::Which::Parameter(p) => ast::type_::AnyPointer::Parameter {
+                    scope_id: p.get_scope_id(),
+                    parameter_index: p.get_parameter_index()
+                },
+                schema_capnp::type_::any_pointer::Which::ImplicitMethodParameter(p) => ast::type_::AnyPointer::ImplicitMethodParameter {
+                    parameter_index: p.get_parameter_index()
+                }
+            }
+        )
+    }
+}
+
+impl ParseFrom<schema_capnp::brand::binding::Reader<'_>> for ast::brand::Binding {
+    fn parse(ctx: &Context, reader: schema_capnp::brand::binding::Reader<'_>) -> Result<ast::brand::Binding> {
+        Ok(
+            match reader.which()? {
+                schema_capnp::brand::binding::Which::Unbound(_) => ast::brand::Binding::Unbound,
+                schema_capnp::brand::binding::Which::Type(t) => ast::brand::Binding::Type(ast::Type::parse(ctx, t?)?)
+            }
+        )
+    }
+}
+
+impl ParseFrom<schema_capnp::brand::scope::Reader<'_>> for ast::brand::Scope {
+    fn parse(ctx: &Context, reader: schema_capnp::brand::scope::Reader<'_>) -> Result<ast::brand::Scope> {
+        let which = match reader.which()? {
+            schema_capnp::brand::scope::Which::Bind(bindings) => {
+                let mut parsed_bindings = vec!();
+                for binding in bindings?.iter() {
+                    parsed_bindings.push(ast::brand::Binding::parse(ctx, binding)?);
+                }
+                ast::brand::scope::Which::Bind(parsed_bindings)
+            },
+            schema_capnp::brand::scope::Which::Inherit(_) => ast::brand::scope::Which::Inherit
+        };
+        Ok(
+            ast::brand::Scope::new(reader.get_scope_id(), which)
+        )
+    }
+}
+
+impl ParseFrom<schema_capnp::brand::Reader<'_>> for ast::Brand {
+    fn parse(ctx: &Context, reader: schema_capnp::brand::Reader<'_>) -> Result<ast::Brand> {
+        let mut scopes = vec!();
+        for scope in reader.get_scopes()?.iter() {
+            scopes.push(ast::brand::Scope::parse(ctx, scope)?);
+        }
+        Ok(
+            ast::Brand::new(scopes)
+        )
+    }
+}
+
 impl ParseFrom<schema_capnp::field::WhichReader<'_>> for ast::field::Which {
-    fn parse(reader: schema_capnp::field::WhichReader<'_>) -> capnp::Result<ast::field::Which> {
+    fn parse(ctx: &Context, reader: schema_capnp::field::WhichReader<'_>) -> Result<ast::field::Which> {
         Ok(
             match reader {
                 schema_capnp::field::Which::Group(g) => ast::field::Which::Group(g.get_type_id()),
-                schema_capnp::field::Which::Slot(s) => ast::field::Which::Slot(
-                    ast::Type::parse(s.get_type()?)?
-                )
+                schema_capnp::field::Which::Slot(s) => {
+                    let type_ = ast::Type::parse(ctx, s.get_type()?)?;
+                    ast::field::Which::Slot {
+                        offset: s.get_offset(),
+                        default_value: ast::Value::parse(ctx, (s.get_default_value()?, Some(&type_)))?,
+                        had_explicit_default: s.get_had_explicit_default(),
+                        type_: type_
+                    }
+                }
             }
         )
     }
 }
 
 impl ParseFrom<schema_capnp::field::Reader<'_>> for ast::Field {
-    fn parse(reader: schema_capnp::field::Reader<'_>) -> capnp::Result<ast::Field> {
+    fn parse(ctx: &Context, reader: schema_capnp::field::Reader<'_>) -> Result<ast::Field> {
+        let mut annotations = vec!();
+        for annotation in reader.get_annotations()?.iter() {
+            annotations.push(ast::Annotation::parse(ctx, annotation)?);
+        }
+
         Ok(
             ast::Field::new(
                 String::from(reader.get_name()?),
+                reader.get_code_order(),
                 reader.get_discriminant_value(),
-                ast::field::Which::parse(reader.which()?)?
+                match reader.get_ordinal().which()? {
+                    schema_capnp::field::ordinal::Which::Implicit(_) => ast::field::Ordinal::Implicit,
+                    schema_capnp::field::ordinal::Which::Explicit(n) => ast::field::Ordinal::Explicit(n)
+                },
+                annotations,
+                ast::field::Which::parse(ctx, reader.which()?)?
             )
         )
     }
 }
 
 impl ParseFrom<schema_capnp::enumerant::Reader<'_>> for ast::Enumerant {
-    fn parse(reader: schema_capnp::enumerant::Reader<'_>) -> capnp::Result<ast::Enumerant> {
+    fn parse(ctx: &Context, reader: schema_capnp::enumerant::Reader<'_>) -> Result<ast::Enumerant> {
+        let mut annotations = vec!();
+        for annotation in reader.get_annotations()?.iter() {
+            annotations.push(ast::Annotation::parse(ctx, annotation)?);
+        }
+
         Ok(
             ast::Enumerant::new(
-                String::from(reader.get_name()?)
+                String::from(reader.get_name()?),
+                reader.get_code_order(),
+                annotations
             )
         )
     }
 }
 
+impl ParseFrom<schema_capnp::ElementSize> for ast::node::ElementSize {
+    fn parse(_ctx: &Context, element_size: schema_capnp::ElementSize) -> Result<ast::node::ElementSize> {
+        Ok(
+            match element_size {
+                schema_capnp::ElementSize::Empty => ast::node::ElementSize::Empty,
+                schema_capnp::ElementSize::Bit => ast::node::ElementSize::Bit,
+                schema_capnp::ElementSize::Byte => ast::node::ElementSize::Byte,
+                schema_capnp::ElementSize::TwoBytes => ast::node::ElementSize::TwoBytes,
+                schema_capnp::ElementSize::FourBytes => ast::node::ElementSize::FourBytes,
+                schema_capnp::ElementSize::EightBytes => ast::node::ElementSize::EightBytes,
+                schema_capnp::ElementSize::Pointer => ast::node::ElementSize::Pointer,
+                schema_capnp::ElementSize::InlineComposite => ast::node::ElementSize::InlineComposite
+            }
+        )
+    }
+}
+
+impl ParseFrom<schema_capnp::node::parameter::Reader<'_>> for ast::node::Parameter {
+    fn parse(_ctx: &Context, reader: schema_capnp::node::parameter::Reader<'_>) -> Result<ast::node::Parameter> {
+        Ok(
+            ast::node::Parameter::new(String::from(reader.get_name()?))
+        )
+    }
+}
+
+impl ParseFrom<schema_capnp::method::Reader<'_>> for ast::Method {
+    fn parse(ctx: &Context, reader: schema_capnp::method::Reader<'_>) -> Result<ast::Method> {
+        let mut implicit_parameters = vec!();
+        for parameter in reader.get_implicit_parameters()?.iter() {
+            implicit_parameters.push(ast::node::Parameter::parse(ctx, parameter)?);
+        }
+
+        let mut annotations = vec!();
+        for annotation in reader.get_annotations()?.iter() {
+            annotations.push(ast::Annotation::parse(ctx, annotation)?);
+        }
+
+        Ok(
+            ast::Method::new(
+                String::from(reader.get_name()?),
+                reader.get_code_order(),
+                reader.get_param_struct_type(),
+                reader.get_result_struct_type(),
+                ast::Brand::parse(ctx, reader.get_param_brand()?)?,
+                ast::Brand::parse(ctx, reader.get_result_brand()?)?,
+                implicit_parameters,
+                annotations
+            )
+        )
+    }
+}
+
+impl ParseFrom<schema_capnp::superclass::Reader<'_>> for ast::Superclass {
+    fn parse(ctx: &Context, reader: schema_capnp::superclass::Reader<'_>) -> Result<ast::Superclass> {
+        Ok(
+            ast::Superclass::new(reader.get_id(), ast::Brand::parse(ctx, reader.get_brand()?)?)
+        )
+    }
+}
+
 impl ParseFrom<schema_capnp::node::WhichReader<'_>> for ast::node::Which {
-    fn parse(reader: schema_capnp::node::WhichReader) -> capnp::Result<ast::node::Which> {
+    fn parse(ctx: &Context, reader: schema_capnp::node::WhichReader) -> Result<ast::node::Which> {
         Ok(
             match reader {
                 schema_capnp::node::Which::File(_) => ast::node::Which::File,
                 schema_capnp::node::Which::Struct(s) => {
                     let mut fields = vec!();
                     for field in s.get_fields()?.iter() {
-                        fields.push(ast::Field::parse(field)?);
+                        fields.push(ast::Field::parse(ctx, field)?);
                     }
                     ast::node::Which::Struct {
+                        data_word_count: s.get_data_word_count(),
+                        pointer_count: s.get_pointer_count(),
+                        preferred_list_encoding: ast::node::ElementSize::parse(ctx, s.get_preferred_list_encoding()?)?,
                         is_group: s.get_is_group(),
                         discriminant_count: s.get_discriminant_count(),
                         discriminant_offset: s.get_discriminant_offset(),
@@ -93,35 +309,279 @@ impl ParseFrom<schema_capnp::node::WhichReader<'_>> for ast::node::Which {
                 schema_capnp::node::Which::Enum(e) => {
                     let mut enums = vec!();
                     for enumerant in e.get_enumerants()?.iter() {
-                        enums.push(ast::Enumerant::parse(enumerant)?);
+                        enums.push(ast::Enumerant::parse(ctx, enumerant)?);
                     }
                     ast::node::Which::Enum(enums)
                 },
-                schema_capnp::node::Which::Interface(_) => ast::node::Which::Interface,
-                schema_capnp::node::Which::Const(_) => ast::node::Which::Const,
-                schema_capnp::node::Which::Annotation(_) => ast::node::Which::Annotation,
+                schema_capnp::node::Which::Interface(i) => {
+                    let mut methods = vec!();
+                    for method in i.get_methods()?.iter() {
+                        methods.push(ast::Method::parse(ctx, method)?);
+                    }
+                    let mut superclasses = vec!();
+                    for superclass in i.get_superclasses()?.iter() {
+                        superclasses.push(ast::Superclass::parse(ctx, superclass)?);
+                    }
+                    ast::node::Which::Interface {
+                        methods: methods,
+                        superclasses: superclasses
+                    }
+                },
+                schema_capnp::node::Which::Const(c) => {
+                    let type_ = ast::Type::parse(ctx, c.get_type()?)?;
+                    ast::node::Which::Const {
+                        value: ast::Value::parse(ctx, (c.get_value()?, Some(&type_)))?,
+                        type_: type_
+                    }
+                },
+                schema_capnp::node::Which::Annotation(a) => {
+                    use ast::node::AnnotationTarget;
+
+                    let targets = [
+                        (a.get_targets_file(), AnnotationTarget::File),
+                        (a.get_targets_const(), AnnotationTarget::Const),
+                        (a.get_targets_enum(), AnnotationTarget::Enum),
+                        (a.get_targets_enumerant(), AnnotationTarget::Enumerant),
+                        (a.get_targets_struct(), AnnotationTarget::Struct),
+                        (a.get_targets_field(), AnnotationTarget::Field),
+                        (a.get_targets_union(), AnnotationTarget::Union),
+                        (a.get_targets_group(), AnnotationTarget::Group),
+                        (a.get_targets_interface(), AnnotationTarget::Interface),
+                        (a.get_targets_method(), AnnotationTarget::Method),
+                        (a.get_targets_param(), AnnotationTarget::Param),
+                        (a.get_targets_annotation(), AnnotationTarget::Annotation)
+                    ];
+                    ast::node::Which::Annotation {
+                        type_: ast::Type::parse(ctx, a.get_type()?)?,
+                        targets: targets.iter().filter(|(on, _)| *on).map(|(_, target)| *target).collect()
+                    }
+                }
             }
         )
     }
 }
 
 impl ParseFrom<schema_capnp::node::nested_node::Reader<'_>> for ast::node::NestedNode {
-    fn parse(reader: schema_capnp::node::nested_node::Reader<'_>) -> capnp::Result<ast::node::NestedNode> {
+    fn parse(_ctx: &Context, reader: schema_capnp::node::nested_node::Reader<'_>) -> Result<ast::node::NestedNode> {
         Ok(
             ast::node::NestedNode::new(reader.get_id(), String::from(reader.get_name()?))
         )
     }
 }
 
-impl ParseFrom<schema_capnp::value::Reader<'_>> for ast::Value {
-    fn parse(reader: schema_capnp::value::Reader) -> capnp::Result<ast::Value> {
+///
+/// Gives access to the raw pointer behind an `any_pointer::Reader`, so list and struct
+/// values can be decoded against a schema type that is only known at runtime.
+///
+struct RawPointer<'a> {
+    reader: PointerReader<'a>
+}
+
+impl<'a> FromPointerReader<'a> for RawPointer<'a> {
+    fn get_from_pointer(reader: &PointerReader<'a>, _default: Option<&'a [capnp::Word]>) -> capnp::Result<RawPointer<'a>> {
+        Ok(RawPointer { reader: *reader })
+    }
+}
+
+fn decode_primitive_list<'a, T, F>(pointer: &PointerReader<'a>, to_value: F) -> Result<ast::Value>
+    where T: capnp::private::layout::PrimitiveElement, F: Fn(T) -> ast::Value
+{
+    let list: capnp::primitive_list::Reader<'a, T> = FromPointerReader::get_from_pointer(pointer, None)?;
+    Ok(ast::Value::List((0..list.len()).map(|i| to_value(list.get(i))).collect()))
+}
+
+fn decode_list(ctx: &Context, pointer: &PointerReader, element_type: &ast::Type) -> Result<ast::Value> {
+    Ok(
+        match element_type {
+            ast::Type::Void => {
+                let list = pointer.get_list(ElementSize::Void, None)?;
+                ast::Value::List((0..list.len()).map(|_| ast::Value::Void).collect())
+            },
+            ast::Type::Bool => decode_primitive_list(pointer, ast::Value::Bool)?,
+            ast::Type::Int8 => decode_primitive_list(pointer, ast::Value::Int8)?,
+            ast::Type::Int16 => decode_primitive_list(pointer, ast::Value::Int16)?,
+            ast::Type::Int32 => decode_primitive_list(pointer, ast::Value::Int32)?,
+            ast::Type::Int64 => decode_primitive_list(pointer, ast::Value::Int64)?,
+            ast::Type::Uint8 => decode_primitive_list(pointer, ast::Value::Uint8)?,
+            ast::Type::Uint16 => decode_primitive_list(pointer, ast::Value::Uint16)?,
+            ast::Type::Uint32 => decode_primitive_list(pointer, ast::Value::Uint32)?,
+            ast::Type::Uint64 => decode_primitive_list(pointer, ast::Value::Uint64)?,
+            ast::Type::Float32 => decode_primitive_list(pointer, ast::Value::Float32)?,
+            ast::Type::Float64 => decode_primitive_list(pointer, ast::Value::Float64)?,
+            ast::Type::Enum { .. } => decode_primitive_list(pointer, ast::Value::Enum)?,
+            ast::Type::Text => {
+                let list: capnp::text_list::Reader = FromPointerReader::get_from_pointer(pointer, None)?;
+                let mut values = vec!();
+                for i in 0..list.len() {
+                    values.push(ast::Value::Text(String::from(list.get(i)?)));
+                }
+                ast::Value::List(values)
+            },
+            ast::Type::Data => {
+                let list: capnp::data_list::Reader = FromPointerReader::get_from_pointer(pointer, None)?;
+                let mut values = vec!();
+                for i in 0..list.len() {
+                    values.push(ast::Value::Data(list.get(i)?.to_vec()));
+                }
+                ast::Value::List(values)
+            },
+            ast::Type::Struct { type_id, .. } => {
+                let list = pointer.get_list(ElementSize::InlineComposite, None)?;
+                let mut values = vec!();
+                for i in 0..list.len() {
+                    values.push(decode_struct(ctx, &list.get_struct_element(i), *type_id)?);
+                }
+                ast::Value::List(values)
+            },
+            ast::Type::List(_) | ast::Type::Interface { .. } | ast::Type::AnyPointer(_) => {
+                let list = pointer.get_list(ElementSize::Pointer, None)?;
+                let mut values = vec!();
+                for i in 0..list.len() {
+                    values.push(decode_pointer(ctx, &list.get_pointer_element(i), element_type)?);
+                }
+                ast::Value::List(values)
+            }
+        }
+    )
+}
+
+fn decode_pointer(ctx: &Context, pointer: &PointerReader, value_type: &ast::Type) -> Result<ast::Value> {
+    Ok(
+        match value_type {
+            ast::Type::Text => {
+                let text: capnp::text::Reader = FromPointerReader::get_from_pointer(pointer, None)?;
+                ast::Value::Text(String::from(text))
+            },
+            ast::Type::Data => {
+                let data: capnp::data::Reader = FromPointerReader::get_from_pointer(pointer, None)?;
+                ast::Value::Data(data.to_vec())
+            },
+            ast::Type::List(element_type) => decode_list(ctx, pointer, element_type)?,
+            ast::Type::Struct { type_id, .. } => decode_struct(ctx, &pointer.get_struct(None)?, *type_id)?,
+            ast::Type::Interface { .. } => ast::Value::Interface,
+            ast::Type::AnyPointer(_) => ast::Value::AnyPointer,
+            _ => ast::Value::Unknown
+        }
+    )
+}
+
+///
+/// The raw bits of a primitive default value. Data fields are stored XORed with these.
+///
+fn default_bits(default: &ast::Value) -> u64 {
+    match default {
+        ast::Value::Bool(b) => *b as u64,
+        ast::Value::Int8(i) => *i as u64,
+        ast::Value::Int16(i) => *i as u64,
+        ast::Value::Int32(i) => *i as u64,
+        ast::Value::Int64(i) => *i as u64,
+        ast::Value::Uint8(i) => *i as u64,
+        ast::Value::Uint16(i) => *i as u64,
+        ast::Value::Uint32(i) => *i as u64,
+        ast::Value::Uint64(i) => *i,
+        ast::Value::Float32(f) => f.to_bits() as u64,
+        ast::Value::Float64(f) => f.to_bits(),
+        ast::Value::Enum(e) => *e as u64,
+        _ => 0
+    }
+}
+
+fn decode_slot(ctx: &Context, reader: &StructReader, slot: schema_capnp::field::slot::Reader) -> Result<ast::Value> {
+    let value_type = ast::Type::parse(ctx, slot.get_type()?)?;
+    let default = ast::Value::parse(ctx, (slot.get_default_value()?, Some(&value_type)))?;
+    let bits = default_bits(&default);
+    let offset = slot.get_offset() as usize;
+
+    Ok(
+        match &value_type {
+            ast::Type::Void => ast::Value::Void,
+            ast::Type::Bool => ast::Value::Bool(reader.get_bool_field(offset) ^ (bits != 0)),
+            ast::Type::Int8 => ast::Value::Int8((reader.get_data_field::<u8>(offset) ^ bits as u8) as i8),
+            ast::Type::Int16 => ast::Value::Int16((reader.get_data_field::<u16>(offset) ^ bits as u16) as i16),
+            ast::Type::Int32 => ast::Value::Int32((reader.get_data_field::<u32>(offset) ^ bits as u32) as i32),
+            ast::Type::Int64 => ast::Value::Int64((reader.get_data_field::<u64>(offset) ^ bits) as i64),
+            ast::Type::Uint8 => ast::Value::Uint8(reader.get_data_field::<u8>(offset) ^ bits as u8),
+            ast::Type::Uint16 => ast::Value::Uint16(reader.get_data_field::<u16>(offset) ^ bits as u16),
+            ast::Type::Uint32 => ast::Value::Uint32(reader.get_data_field::<u32>(offset) ^ bits as u32),
+            ast::Type::Uint64 => ast::Value::Uint64(reader.get_data_field::<u64>(offset) ^ bits),
+            ast::Type::Float32 => ast::Value::Float32(f32::from_bits(reader.get_data_field::<u32>(offset) ^ bits as u32)),
+            ast::Type::Float64 => ast::Value::Float64(f64::from_bits(reader.get_data_field::<u64>(offset) ^ bits)),
+            ast::Type::Enum { .. } => ast::Value::Enum(reader.get_data_field::<u16>(offset) ^ bits as u16),
+            _ => {
+                // Null pointers take the default value.
+                let pointer = reader.get_pointer_field(offset);
+                if pointer.is_null() {
+                    default
+                } else {
+                    decode_pointer(ctx, &pointer, &value_type)?
+                }
+            }
+        }
+    )
+}
+
+fn decode_struct_fields(ctx: &Context, reader: &StructReader, node: schema_capnp::node::struct_::Reader) -> Result<Vec<ast::value::StructField>> {
+    let discriminant =
+        if node.get_discriminant_count() > 0 {
+            reader.get_data_field::<u16>(node.get_discriminant_offset() as usize)
+        } else {
+            ast::field::NO_DISCRIMINANT
+        };
+
+    let mut fields = vec!();
+    for field in node.get_fields()?.iter() {
+        let discriminant_value = field.get_discriminant_value();
+        if discriminant_value != ast::field::NO_DISCRIMINANT && discriminant_value != discriminant {
+            continue;
+        }
+
+        let value = match field.which()? {
+            schema_capnp::field::Which::Slot(slot) => decode_slot(ctx, reader, slot)?,
+            schema_capnp::field::Which::Group(group) => decode_struct(ctx, reader, group.get_type_id())?
+        };
+        fields.push(ast::value::StructField::new(String::from(field.get_name()?), value));
+    }
+    Ok(fields)
+}
+
+fn decode_struct(ctx: &Context, reader: &StructReader, type_id: ast::Id) -> Result<ast::Value> {
+    match ctx.nodes.get(&type_id) {
+        Some(node) => match node.which()? {
+            schema_capnp::node::Which::Struct(s) => Ok(ast::Value::Struct(decode_struct_fields(ctx, reader, s)?)),
+            _ => Ok(ast::Value::Unknown)
+        },
+        None => Ok(ast::Value::Unknown)
+    }
+}
+
+impl ParseFrom<(schema_capnp::value::Reader<'_>, Option<&ast::Type>)> for ast::Value {
+    fn parse(ctx: &Context, (reader, value_type): (schema_capnp::value::Reader, Option<&ast::Type>)) -> Result<ast::Value> {
         Ok(
             match reader.which()? {
-                schema_capnp::value::Which::Text(t) => {
-                    ast::Value::Text(String::from(t?))
+                schema_capnp::value::Which::Void(_) => ast::Value::Void,
+                schema_capnp::value::Which::Bool(b) => ast::Value::Bool(b),
+                schema_capnp::value::Which::Int8(i) => ast::Value::Int8(i),
+                schema_capnp::value::Which::Int16(i) => ast::Value::Int16(i),
+                schema_capnp::value::Which::Int32(i) => ast::Value::Int32(i),
+                schema_capnp::value::Which::Int64(i) => ast::Value::Int64(i),
+                schema_capnp::value::Which::Uint8(i) => ast::Value::Uint8(i),
+                schema_capnp::value::Which::Uint16(i) => ast::Value::Uint16(i),
+                schema_capnp::value::Which::Uint32(i) => ast::Value::Uint32(i),
+                schema_capnp::value::Which::Uint64(i) => ast::Value::Uint64(i),
+                schema_capnp::value::Which::Float32(f) => ast::Value::Float32(f),
+                schema_capnp::value::Which::Float64(f) => ast::Value::Float64(f),
+                schema_capnp::value::Which::Text(t) => ast::Value::Text(String::from(t?)),
+                schema_capnp::value::Which::Data(d) => ast::Value::Data(d?.to_vec()),
+                schema_capnp::value::Which::Enum(e) => ast::Value::Enum(e),
+                schema_capnp::value::Which::Interface(_) => ast::Value::Interface,
+                schema_capnp::value::Which::AnyPointer(_) => ast::Value::AnyPointer,
+                schema_capnp::value::Which::List(l) => match value_type {
+                    Some(t @ ast::Type::List(_)) => decode_pointer(ctx, &l.get_as::<RawPointer>()?.reader, t)?,
+                    _ => ast::Value::Unknown
                 },
-                _ => {
-                    ast::Value::Unknown
+                schema_capnp::value::Which::Struct(s) => match value_type {
+                    Some(t @ ast::Type::Struct { .. }) => decode_pointer(ctx, &s.get_as::<RawPointer>()?.reader, t)?,
+                    _ => ast::Value::Unknown
                 }
             }
         )
@@ -129,26 +589,32 @@ impl ParseFrom<schema_capnp::value::Reader<'_>> for ast::Value {
 }
 
 impl ParseFrom<schema_capnp::annotation::Reader<'_>> for ast::Annotation {
-    fn parse(reader: schema_capnp::annotation::Reader) -> capnp::Result<ast::Annotation> {
+    fn parse(ctx: &Context, reader: schema_capnp::annotation::Reader) -> Result<ast::Annotation> {
+        let value_type = ctx.get_annotation_type(reader.get_id())?;
         Ok(
             ast::Annotation::new(
                 reader.get_id(),
-                ast::Value::parse(reader.get_value()?)?
+                ast::Value::parse(ctx, (reader.get_value()?, value_type.as_ref()))?
             )
         )
     }
 }
 
 impl ParseFrom<schema_capnp::node::Reader<'_>> for ast::Node {
-    fn parse(reader: schema_capnp::node::Reader<'_>) -> capnp::Result<ast::Node> {
+    fn parse(ctx: &Context, reader: schema_capnp::node::Reader<'_>) -> Result<ast::Node> {
         let mut nested_nodes = vec!();
         for nested_node in reader.get_nested_nodes()?.iter() {
-            nested_nodes.push(ast::node::NestedNode::parse(nested_node)?)
+            nested_nodes.push(ast::node::NestedNode::parse(ctx, nested_node)?)
         }
 
         let mut annotations = vec!();
         for annotation in reader.get_annotations()?.iter() {
-            annotations.push(ast::Annotation::parse(annotation)?)
+            annotations.push(ast::Annotation::parse(ctx, annotation)?)
+        }
+
+        let mut parameters = vec!();
+        for parameter in reader.get_parameters()?.iter() {
+            parameters.push(ast::node::Parameter::parse(ctx, parameter)?)
         }
 
         return Ok(
@@ -159,14 +625,16 @@ impl ParseFrom<schema_capnp::node::Reader<'_>> for ast::Node {
                 reader.get_scope_id(),
                 nested_nodes,
                 annotations,
-                ast::node::Which::parse(reader.which()?)?
+                parameters,
+                reader.get_is_generic(),
+                ast::node::Which::parse(ctx, reader.which()?)?
             )
         )
     }
 }
 
 impl ParseFrom<schema_capnp::code_generator_request::requested_file::import::Reader<'_>> for ast::code_generator_request::requested_file::Import {
-    fn parse(reader: schema_capnp::code_generator_request::requested_file::import::Reader<'_>) -> capnp::Result<ast::code_generator_request::requested_file::Import> {
+    fn parse(_ctx: &Context, reader: schema_capnp::code_generator_request::requested_file::import::Reader<'_>) -> Result<ast::code_generator_request::requested_file::Import> {
         return Ok(
             ast::code_generator_request::requested_file::Import::new(
                 reader.get_id(),
@@ -177,10 +645,10 @@ impl ParseFrom<schema_capnp::code_generator_request::requested_file::import::Rea
 }
 
 impl ParseFrom<schema_capnp::code_generator_request::requested_file::Reader<'_>> for ast::code_generator_request::RequestedFile {
-    fn parse(reader: schema_capnp::code_generator_request::requested_file::Reader<'_>) -> capnp::Result<ast::code_generator_request::RequestedFile> {
+    fn parse(ctx: &Context, reader: schema_capnp::code_generator_request::requested_file::Reader<'_>) -> Result<ast::code_generator_request::RequestedFile> {
         let mut imports = vec!();
         for import in reader.get_imports()?.iter() {
-            imports.push(ast::code_generator_request::requested_file::Import::parse(import)?);
+            imports.push(ast::code_generator_request::requested_file::Import::parse(ctx, import)?);
         }
         return Ok(
             ast::code_generator_request::RequestedFile::new(
@@ -192,26 +660,587 @@ impl ParseFrom<schema_capnp::code_generator_request::requested_file::Reader<'_>>
     }
 }
 
+fn parse_doc_comment(text: capnp::text::Reader<'_>) -> Option<String> {
+    if text.is_empty() {
+        None
+    } else {
+        Some(String::from(text))
+    }
+}
+
+impl ParseFrom<schema_capnp::node::source_info::member::Reader<'_>> for ast::node::source_info::Member {
+    fn parse(_ctx: &Context, reader: schema_capnp::node::source_info::member::Reader<'_>) -> Result<ast::node::source_info::Member> {
+        Ok(
+            ast::node::source_info::Member::new(parse_doc_comment(reader.get_doc_comment()?), reader.get_start_byte(), reader.get_end_byte())
+        )
+    }
+}
+
+impl ParseFrom<schema_capnp::node::source_info::Reader<'_>> for ast::node::SourceInfo {
+    fn parse(ctx: &Context, reader: schema_capnp::node::source_info::Reader<'_>) -> Result<ast::node::SourceInfo> {
+        let mut members = vec!();
+        for member in reader.get_members()?.iter() {
+            members.push(ast::node::source_info::Member::parse(ctx, member)?);
+        }
+        Ok(
+            ast::node::SourceInfo::new(reader.get_id(), parse_doc_comment(reader.get_doc_comment()?), members, reader.get_start_byte(), reader.get_end_byte())
+        )
+    }
+}
+
 impl ParseFrom<schema_capnp::code_generator_request::Reader<'_>> for ast::CodeGeneratorRequest {
-    fn parse(reader: schema_capnp::code_generator_request::Reader) -> capnp::Result<ast::CodeGeneratorRequest> {
+    fn parse(ctx: &Context, reader: schema_capnp::code_generator_request::Reader) -> Result<ast::CodeGeneratorRequest> {
         let mut nodes = vec!();
         for node in reader.get_nodes()?.iter() {
-            nodes.push(ast::Node::parse(node)?);
+            nodes.push(ast::Node::parse(ctx, node)?);
         }
         let mut requested_files = vec!();
         for requested_file in reader.get_requested_files()?.iter() {
-            requested_files.push(ast::code_generator_request::RequestedFile::parse(requested_file)?);
+            requested_files.push(ast::code_generator_request::RequestedFile::parse(ctx, requested_file)?);
         }
-        return Ok(ast::CodeGeneratorRequest::new(nodes, requested_files));
+        let mut source_info = HashMap::new();
+        for info in reader.get_source_info()?.iter() {
+            source_info.insert(info.get_id(), ast::node::SourceInfo::parse(ctx, info)?);
+        }
+        return Ok(ast::CodeGeneratorRequest::new(nodes, requested_files, source_info));
     }
 }
 
-pub fn parse(request: schema_capnp::code_generator_request::Reader) -> capnp::Result<ast::CodeGeneratorRequest> {
-    return ast::CodeGeneratorRequest::parse(request);
+pub fn parse(request: schema_capnp::code_generator_request::Reader) -> Result<ast::CodeGeneratorRequest> {
+    let ctx = Context::new(request.get_nodes()?);
+    return ast::CodeGeneratorRequest::parse(&ctx, request);
+}
+
+///
+/// Reads a serialized `CodeGeneratorRequest`, as the schema compiler writes it to a plugin's stdin.
+///
+pub fn try_read_message(reader: &mut dyn std::io::Read) -> Result<ast::CodeGeneratorRequest> {
+    try_read_message_with_options(reader, &ReadOptions::new())
+}
+
+pub fn try_read_message_with_options(reader: &mut dyn std::io::Read, options: &ReadOptions) -> Result<ast::CodeGeneratorRequest> {
+    // Read everything up front so I/O failures aren't mistaken for a malformed message.
+    let mut bytes = vec!();
+    reader.read_to_end(&mut bytes)?;
+    read_message_from_bytes(&bytes, options)
+}
+
+pub fn read_message_from_bytes(mut bytes: &[u8], options: &ReadOptions) -> Result<ast::CodeGeneratorRequest> {
+    let msg_raw = match options.get_framing() {
+        Framing::Unpacked => capnp::serialize::read_message(&mut bytes, options.to_reader_options())?,
+        Framing::Packed => capnp::serialize_packed::read_message(&mut bytes, options.to_reader_options())?
+    };
+    let msg_capnp = msg_raw.get_root::<schema_capnp::code_generator_request::Reader>()?;
+    return parse(msg_capnp);
+}
+
+///
+/// Reads a request previously saved to a file, e.g. with `capnp compile -o- schema.capnp > request.bin`.
+///
+pub fn read_message_from_file<P: AsRef<std::path::Path>>(path: P, options: &ReadOptions) -> Result<ast::CodeGeneratorRequest> {
+    read_message_from_bytes(&std::fs::read(path)?, options)
 }
 
-pub fn read_message(mut reader: &mut dyn std::io::Read) -> ast::CodeGeneratorRequest {
-    let msg_raw = capnp::serialize::read_message(&mut reader, capnp::message::ReaderOptions::new()).unwrap();
-    let msg_capnp = msg_raw.get_root::<schema_capnp::code_generator_request::Reader>().unwrap();
-    return parse(msg_capnp).unwrap();
-}
\ No newline at end of file
+pub fn read_message(reader: &mut dyn std::io::Read) -> ast::CodeGeneratorRequest {
+    match try_read_message(reader) {
+        Ok(cgr) => cgr,
+        Err(e) => panic!("{}", e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_value(ctx: &Context, message: &capnp::message::Builder<capnp::message::HeapAllocator>, value_type: &ast::Type) -> ast::Value {
+        let reader = message.get_root_as_reader::<schema_capnp::value::Reader>().unwrap();
+        ast::Value::parse(ctx, (reader, Some(value_type))).unwrap()
+    }
+
+    fn empty_context<'a>() -> Context<'a> {
+        Context { nodes: HashMap::new() }
+    }
+
+    fn init_slot<'a>(mut field: schema_capnp::field::Builder<'a>, name: &str, offset: u32) -> schema_capnp::field::slot::Builder<'a> {
+        field.set_name(name);
+        let mut slot = field.init_slot();
+        slot.set_offset(offset);
+        slot
+    }
+
+    fn serialized_request() -> Vec<u8> {
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let request = message.init_root::<schema_capnp::code_generator_request::Builder>();
+            let mut file = request.init_requested_files(1).get(0);
+            file.set_id(1);
+            file.set_filename("test.capnp");
+        }
+        let mut bytes = vec!();
+        capnp::serialize::write_message(&mut bytes, &message).unwrap();
+        bytes
+    }
+
+    struct FailingReader;
+
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "pipe closed"))
+        }
+    }
+
+    #[test]
+    fn test_read_message() {
+        let cgr = try_read_message(&mut serialized_request().as_slice()).unwrap();
+        assert_eq!(cgr.requested_files()[0].filename(), "test.capnp");
+    }
+
+    #[test]
+    fn test_read_packed_message() {
+        let mut message = capnp::message::Builder::new_default();
+        message.init_root::<schema_capnp::code_generator_request::Builder>()
+            .init_requested_files(1).get(0)
+            .set_filename("packed.capnp");
+        let mut bytes = vec!();
+        capnp::serialize_packed::write_message(&mut bytes, &message).unwrap();
+
+        let mut options = ReadOptions::new();
+        options.packed(true);
+        let cgr = read_message_from_bytes(&bytes, &options).unwrap();
+        assert_eq!(cgr.requested_files()[0].filename(), "packed.capnp");
+
+        // The same bytes aren't a valid unpacked message.
+        assert!(read_message_from_bytes(&bytes, &ReadOptions::new()).is_err());
+    }
+
+    #[test]
+    fn test_read_message_traversal_limit() {
+        let bytes = serialized_request();
+
+        let mut options = ReadOptions::new();
+        options.traversal_limit_in_words(1);
+        match read_message_from_bytes(&bytes, &options) {
+            Err(Error::Malformed(_)) => {},
+            other => panic!("expected the traversal limit to be hit, got {:?}", other)
+        }
+
+        options.traversal_limit_in_words(1024);
+        assert!(read_message_from_bytes(&bytes, &options).is_ok());
+    }
+
+    #[test]
+    fn test_read_message_from_file() {
+        let path = std::env::temp_dir().join(format!("parser-test-request-{}.bin", std::process::id()));
+        std::fs::write(&path, serialized_request()).unwrap();
+        let cgr = read_message_from_file(&path, &ReadOptions::new());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cgr.unwrap().requested_files()[0].filename(), "test.capnp");
+
+        match read_message_from_file(&path, &ReadOptions::new()) {
+            Err(Error::Io(_)) => {},
+            other => panic!("expected an I/O error for a missing file, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_read_message_truncated() {
+        let bytes = serialized_request();
+        for len in &[0, 4, bytes.len() / 2, bytes.len() - 1] {
+            match try_read_message(&mut &bytes[..*len]) {
+                Err(Error::Malformed(_)) => {},
+                other => panic!("expected a malformed message error for {} bytes, got {:?}", len, other)
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_message_garbage() {
+        let garbage = [0xAB; 64];
+        match try_read_message(&mut &garbage[..]) {
+            Err(Error::Malformed(_)) => {},
+            other => panic!("expected a malformed message error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_read_message_io_error() {
+        match try_read_message(&mut FailingReader) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::BrokenPipe),
+            other => panic!("expected an I/O error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_read_message_panics_on_garbage() {
+        read_message(&mut &[0xAB; 64][..]);
+    }
+
+    #[test]
+    fn test_parse_primitive_values() {
+        let ctx = empty_context();
+        let mut message = capnp::message::Builder::new_default();
+
+        message.init_root::<schema_capnp::value::Builder>().set_bool(true);
+        assert_eq!(parse_value(&ctx, &message, &ast::Type::Bool), ast::Value::Bool(true));
+
+        message.init_root::<schema_capnp::value::Builder>().set_int32(-42);
+        assert_eq!(parse_value(&ctx, &message, &ast::Type::Int32), ast::Value::Int32(-42));
+
+        message.init_root::<schema_capnp::value::Builder>().set_uint64(0xFFFF_FFFF_FFFF);
+        assert_eq!(parse_value(&ctx, &message, &ast::Type::Uint64), ast::Value::Uint64(0xFFFF_FFFF_FFFF));
+
+        message.init_root::<schema_capnp::value::Builder>().set_float64(1.5);
+        assert_eq!(parse_value(&ctx, &message, &ast::Type::Float64), ast::Value::Float64(1.5));
+
+        message.init_root::<schema_capnp::value::Builder>().set_enum(3);
+        assert_eq!(parse_value(&ctx, &message, &ast::Type::Enum { type_id: 1, brand: ast::Brand::default() }), ast::Value::Enum(3));
+
+        message.init_root::<schema_capnp::value::Builder>().set_text("hello");
+        assert_eq!(parse_value(&ctx, &message, &ast::Type::Text), ast::Value::Text(String::from("hello")));
+
+        message.init_root::<schema_capnp::value::Builder>().set_data(&[1, 2, 3]);
+        assert_eq!(parse_value(&ctx, &message, &ast::Type::Data), ast::Value::Data(vec!(1, 2, 3)));
+    }
+
+    #[test]
+    fn test_parse_list_values() {
+        let ctx = empty_context();
+        let mut message = capnp::message::Builder::new_default();
+
+        {
+            let mut list = message.init_root::<schema_capnp::value::Builder>()
+                .init_list()
+                .initn_as::<capnp::primitive_list::Builder<i16>>(3);
+            list.set(0, 1);
+            list.set(1, -2);
+            list.set(2, 3);
+        }
+        assert_eq!(
+            parse_value(&ctx, &message, &ast::Type::List(Box::new(ast::Type::Int16))),
+            ast::Value::List(vec!(ast::Value::Int16(1), ast::Value::Int16(-2), ast::Value::Int16(3)))
+        );
+
+        {
+            let mut list = message.init_root::<schema_capnp::value::Builder>()
+                .init_list()
+                .initn_as::<capnp::text_list::Builder>(2);
+            list.set(0, "a");
+            list.set(1, "b");
+        }
+        assert_eq!(
+            parse_value(&ctx, &message, &ast::Type::List(Box::new(ast::Type::Text))),
+            ast::Value::List(vec!(ast::Value::Text(String::from("a")), ast::Value::Text(String::from("b"))))
+        );
+
+        // Without a type, the contents of a list can't be decoded.
+        let reader = message.get_root_as_reader::<schema_capnp::value::Reader>().unwrap();
+        assert_eq!(ast::Value::parse(&ctx, (reader, None)).unwrap(), ast::Value::Unknown);
+    }
+
+    #[test]
+    fn test_parse_struct_value() {
+        // Describes a struct with the same layout as schema.capnp's CapnpVersion.
+        let mut schema_message = capnp::message::Builder::new_default();
+        {
+            let mut node = schema_message.init_root::<schema_capnp::code_generator_request::Builder>()
+                .init_nodes(1)
+                .get(0);
+            node.set_id(100);
+            node.set_display_name("test.capnp:Version");
+            let mut fields = node.init_struct().init_fields(3);
+            init_slot(fields.reborrow().get(0), "major", 0).init_type().set_uint16(());
+            init_slot(fields.reborrow().get(1), "minor", 2).init_type().set_uint8(());
+
+            let mut micro = init_slot(fields.reborrow().get(2), "micro", 3);
+            micro.reborrow().init_type().set_uint8(());
+            micro.init_default_value().set_uint8(7);
+        }
+        let schema = schema_message.get_root_as_reader::<schema_capnp::code_generator_request::Reader>().unwrap();
+        let ctx = Context::new(schema.get_nodes().unwrap());
+
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let mut version = message.init_root::<schema_capnp::value::Builder>()
+                .init_struct()
+                .init_as::<schema_capnp::capnp_version::Builder>();
+            version.set_major(1);
+            version.set_minor(8);
+        }
+
+        assert_eq!(
+            parse_value(&ctx, &message, &ast::Type::Struct { type_id: 100, brand: ast::Brand::default() }),
+            ast::Value::Struct(vec!(
+                ast::value::StructField::new(String::from("major"), ast::Value::Uint16(1)),
+                ast::value::StructField::new(String::from("minor"), ast::Value::Uint8(8)),
+                ast::value::StructField::new(String::from("micro"), ast::Value::Uint8(7))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_field_default() {
+        let ctx = empty_context();
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let mut slot = init_slot(message.init_root::<schema_capnp::field::Builder>(), "count", 2);
+            slot.set_had_explicit_default(true);
+            slot.reborrow().init_type().set_int32(());
+            slot.init_default_value().set_int32(-1);
+        }
+        let reader = message.get_root_as_reader::<schema_capnp::field::Reader>().unwrap();
+
+        assert_eq!(
+            ast::Field::parse(&ctx, reader).unwrap().which(),
+            &ast::field::Which::Slot {
+                offset: 2,
+                type_: ast::Type::Int32,
+                default_value: ast::Value::Int32(-1),
+                had_explicit_default: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_struct_layout_and_field_metadata() {
+        let ctx = empty_context();
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let mut node = message.init_root::<schema_capnp::node::Builder>();
+            node.set_id(150);
+            node.set_display_name("test.capnp:Point");
+            let mut struct_ = node.init_struct();
+            struct_.set_data_word_count(1);
+            struct_.set_pointer_count(0);
+            struct_.set_preferred_list_encoding(schema_capnp::ElementSize::EightBytes);
+            let mut fields = struct_.init_fields(2);
+            {
+                let mut field = fields.reborrow().get(0);
+                field.set_code_order(1);
+                field.reborrow().get_ordinal().set_explicit(0);
+                let mut annotation = field.reborrow().init_annotations(1).get(0);
+                annotation.set_id(151);
+                annotation.init_value().set_bool(true);
+                init_slot(field, "x", 0).init_type().set_int32(());
+            }
+            {
+                let mut field = fields.get(1);
+                field.set_code_order(0);
+                field.reborrow().get_ordinal().set_implicit(());
+                init_slot(field, "y", 1).init_type().set_int32(());
+            }
+        }
+        let reader = message.get_root_as_reader::<schema_capnp::node::Reader>().unwrap();
+
+        match ast::Node::parse(&ctx, reader).unwrap().which() {
+            ast::node::Which::Struct { data_word_count, pointer_count, preferred_list_encoding, fields, .. } => {
+                assert_eq!(*data_word_count, 1);
+                assert_eq!(*pointer_count, 0);
+                assert_eq!(*preferred_list_encoding, ast::node::ElementSize::EightBytes);
+
+                assert_eq!(fields[0].code_order(), 1);
+                assert_eq!(fields[0].ordinal(), ast::field::Ordinal::Explicit(0));
+                assert_eq!(fields[0].annotations(), &vec!(ast::Annotation::new(151, ast::Value::Bool(true))));
+
+                assert_eq!(fields[1].code_order(), 0);
+                assert_eq!(fields[1].ordinal(), ast::field::Ordinal::Implicit);
+                assert!(fields[1].annotations().is_empty());
+            },
+            _ => panic!("expected struct")
+        }
+    }
+
+    #[test]
+    fn test_parse_enumerant_metadata() {
+        let ctx = empty_context();
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let mut enumerant = message.init_root::<schema_capnp::enumerant::Builder>();
+            enumerant.set_name("red");
+            enumerant.set_code_order(2);
+            let mut annotation = enumerant.init_annotations(1).get(0);
+            annotation.set_id(152);
+            annotation.init_value().set_text("rot");
+        }
+        let reader = message.get_root_as_reader::<schema_capnp::enumerant::Reader>().unwrap();
+
+        assert_eq!(
+            ast::Enumerant::parse(&ctx, reader).unwrap(),
+            ast::Enumerant::new(
+                String::from("red"),
+                2,
+                vec!(ast::Annotation::new(152, ast::Value::Text(String::from("rot"))))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_const_node() {
+        let ctx = empty_context();
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let mut node = message.init_root::<schema_capnp::node::Builder>();
+            node.set_id(200);
+            node.set_display_name("test.capnp:version");
+            let mut c = node.init_const();
+            c.reborrow().init_type().set_text(());
+            c.init_value().set_text("1.2.3");
+        }
+        let reader = message.get_root_as_reader::<schema_capnp::node::Reader>().unwrap();
+
+        assert_eq!(
+            ast::Node::parse(&ctx, reader).unwrap().which(),
+            &ast::node::Which::Const {
+                type_: ast::Type::Text,
+                value: ast::Value::Text(String::from("1.2.3"))
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_interface_node() {
+        let ctx = empty_context();
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let mut node = message.init_root::<schema_capnp::node::Builder>();
+            node.set_id(300);
+            node.set_display_name("test.capnp:Service");
+            let mut interface = node.init_interface();
+            interface.reborrow().init_superclasses(1).get(0).set_id(301);
+
+            let mut method = interface.init_methods(1).get(0);
+            method.set_name("call");
+            method.set_code_order(0);
+            method.set_param_struct_type(302);
+            method.set_result_struct_type(303);
+            method.reborrow().init_implicit_parameters(1).get(0).set_name("T");
+            let mut annotation = method.init_annotations(1).get(0);
+            annotation.set_id(304);
+            annotation.init_value().set_bool(true);
+        }
+        let reader = message.get_root_as_reader::<schema_capnp::node::Reader>().unwrap();
+
+        assert_eq!(
+            ast::Node::parse(&ctx, reader).unwrap().which(),
+            &ast::node::Which::Interface {
+                methods: vec!(
+                    ast::Method::new(
+                        String::from("call"),
+                        0,
+                        302,
+                        303,
+                        ast::Brand::default(),
+                        ast::Brand::default(),
+                        vec!(ast::node::Parameter::new(String::from("T"))),
+                        vec!(ast::Annotation::new(304, ast::Value::Bool(true)))
+                    )
+                ),
+                superclasses: vec!(ast::Superclass::new(301, ast::Brand::default()))
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_generic_node() {
+        let ctx = empty_context();
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let mut node = message.init_root::<schema_capnp::node::Builder>();
+            node.set_id(400);
+            node.set_display_name("test.capnp:Map");
+            node.set_is_generic(true);
+            {
+                let mut parameters = node.reborrow().init_parameters(2);
+                parameters.reborrow().get(0).set_name("Key");
+                parameters.get(1).set_name("Value");
+            }
+            let mut fields = node.init_struct().init_fields(2);
+            let mut param = init_slot(fields.reborrow().get(0), "key", 0).init_type().init_any_pointer().init_parameter();
+            param.set_scope_id(400);
+            param.set_parameter_index(0);
+
+            let mut entry = init_slot(fields.get(1), "entry", 1).init_type().init_struct();
+            entry.set_type_id(401);
+            let mut scope = entry.init_brand().init_scopes(1).get(0);
+            scope.set_scope_id(400);
+            let mut bindings = scope.init_bind(2);
+            bindings.reborrow().get(0).init_type().set_text(());
+            bindings.get(1).set_unbound(());
+        }
+        let reader = message.get_root_as_reader::<schema_capnp::node::Reader>().unwrap();
+        let node = ast::Node::parse(&ctx, reader).unwrap();
+
+        assert!(node.is_generic());
+        assert_eq!(
+            node.parameters(),
+            &vec!(
+                ast::node::Parameter::new(String::from("Key")),
+                ast::node::Parameter::new(String::from("Value"))
+            )
+        );
+        match node.which() {
+            ast::node::Which::Struct { fields, .. } => {
+                match fields[0].which() {
+                    ast::field::Which::Slot { type_, .. } => assert_eq!(
+                        type_,
+                        &ast::Type::AnyPointer(ast::type_::AnyPointer::Parameter { scope_id: 400, parameter_index: 0 })
+                    ),
+                    _ => panic!("expected slot")
+                }
+                match fields[1].which() {
+                    ast::field::Which::Slot { type_, .. } => assert_eq!(
+                        type_,
+                        &ast::Type::Struct {
+                            type_id: 401,
+                            brand: ast::Brand::new(vec!(
+                                ast::brand::Scope::new(
+                                    400,
+                                    ast::brand::scope::Which::Bind(vec!(
+                                        ast::brand::Binding::Type(ast::Type::Text),
+                                        ast::brand::Binding::Unbound
+                                    ))
+                                )
+                            ))
+                        }
+                    ),
+                    _ => panic!("expected slot")
+                }
+            },
+            _ => panic!("expected struct")
+        }
+    }
+
+    #[test]
+    fn test_parse_source_info() {
+        let mut message = capnp::message::Builder::new_default();
+        {
+            let request = message.init_root::<schema_capnp::code_generator_request::Builder>();
+            let mut info = request.init_source_info(2);
+            {
+                let mut documented = info.reborrow().get(0);
+                documented.set_id(500);
+                documented.set_doc_comment("A documented struct.\n");
+                documented.set_start_byte(21);
+                documented.set_end_byte(120);
+                let mut members = documented.init_members(2);
+                members.reborrow().get(0).set_doc_comment("The first field.\n");
+                members.reborrow().get(0).set_start_byte(40);
+                members.get(1).set_doc_comment("");
+            }
+            info.get(1).set_id(501);
+        }
+        let reader = message.get_root_as_reader::<schema_capnp::code_generator_request::Reader>().unwrap();
+        let cgr = parse(reader).unwrap();
+
+        assert_eq!(cgr.doc_comment(500), Some(&String::from("A documented struct.\n")));
+        assert_eq!(cgr.member_doc_comment(500, 0), Some(&String::from("The first field.\n")));
+        assert_eq!(cgr.member_doc_comment(500, 1), None);
+        assert_eq!(cgr.member_doc_comment(500, 2), None);
+        assert_eq!(cgr.doc_comment(501), None);
+        assert_eq!(cgr.doc_comment(502), None);
+        assert_eq!(cgr.source_info()[&500].start_byte(), 21);
+        assert_eq!(cgr.source_info()[&500].end_byte(), 120);
+        assert_eq!(cgr.source_info()[&500].members()[0].start_byte(), 40);
+        assert_eq!(cgr.source_info()[&501].start_byte(), 0);
+    }
+}
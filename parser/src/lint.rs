@@ -0,0 +1,442 @@
+use getset::{Getters, CopyGetters};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ast;
+use crate::ast::{CodeGeneratorRequest, Id, Node};
+use crate::visit::{self, Visitor};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// Field and parameter names should be camelCase, which is what the generators expect.
+    FieldNameCase,
+
+    /// The first enumerant should be `unknown`, so readers have something to fall back to.
+    EnumUnknownZero,
+
+    /// Structs other than groups and method parameters should be documented.
+    StructDocComment,
+
+    /// Lists of lists are hard to build and to evolve; a list of structs is usually better.
+    NestedList,
+
+    /// Fields named after C++ or Rust keywords have to be renamed in generated code.
+    KeywordFieldName
+}
+
+impl Rule {
+    pub const ALL: [Rule; 5] = [
+        Rule::FieldNameCase,
+        Rule::EnumUnknownZero,
+        Rule::StructDocComment,
+        Rule::NestedList,
+        Rule::KeywordFieldName
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::FieldNameCase => "field-name-case",
+            Rule::EnumUnknownZero => "enum-unknown-zero",
+            Rule::StructDocComment => "struct-doc-comment",
+            Rule::NestedList => "nested-list",
+            Rule::KeywordFieldName => "keyword-field-name"
+        }
+    }
+
+    pub fn default_level(self) -> Level {
+        match self {
+            Rule::KeywordFieldName => Level::Error,
+            _ => Level::Warning
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Off,
+    Warning,
+    Error
+}
+
+///
+/// Which rules to check and how seriously to take them. Rules that aren't mentioned keep
+/// their default level. Usually read from a JSON file such as
+///
+/// ```json
+/// { "rules": { "struct-doc-comment": "off", "nested-list": "error" } }
+/// ```
+///
+#[derive(Clone, Default, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintConfig {
+    #[serde(default)]
+    rules: HashMap<Rule, Level>
+}
+
+impl LintConfig {
+    pub fn new() -> LintConfig {
+        LintConfig::default()
+    }
+
+    pub fn rule(&mut self, rule: Rule, level: Level) -> &mut LintConfig {
+        self.rules.insert(rule, level);
+        self
+    }
+
+    pub fn level(&self, rule: Rule) -> Level {
+        self.rules.get(&rule).cloned().unwrap_or_else(|| rule.default_level())
+    }
+}
+
+#[derive(Clone, Getters, CopyGetters, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    #[get_copy = "pub"]
+    rule: Rule,
+
+    #[get_copy = "pub"]
+    level: Level,
+
+    #[get_copy = "pub"]
+    node_id: Id,
+
+    /// The display name of the schema file, as the request gives it.
+    #[get = "pub"]
+    file: String,
+
+    /// The node's name within its file, e.g. `Shape.extra`.
+    #[get = "pub"]
+    node_name: String,
+
+    /// The field or enumerant the diagnostic is about, if any.
+    #[get = "pub"]
+    member: Option<String>,
+
+    /// From the request's source info, if the compiler gave positions.
+    #[get_copy = "pub"]
+    start_byte: Option<u32>,
+
+    /// Filled in by `locate`, given the file.
+    #[get_copy = "pub"]
+    line: Option<usize>,
+
+    #[get = "pub"]
+    message: String
+}
+
+impl Diagnostic {
+    ///
+    /// Works out the line from the start byte, given the text of the file.
+    ///
+    pub fn locate(&mut self, source: &str) {
+        self.line = self.start_byte
+            .filter(|byte| *byte as usize <= source.len())
+            .map(|byte| source.as_bytes()[..byte as usize].iter().filter(|b| **b == b'\n').count() + 1);
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: ", self.file, line)?,
+            None => write!(f, "{}: ", self.file)?
+        }
+        let level = match self.level {
+            Level::Error => "error",
+            _ => "warning"
+        };
+        match &self.member {
+            Some(member) => write!(f, "{}: {}.{}: {} [{}]", level, self.node_name, member, self.message, self.rule.name()),
+            None => write!(f, "{}: {}: {} [{}]", level, self.node_name, self.message, self.rule.name())
+        }
+    }
+}
+
+///
+/// Checks the requested files against the style rules in the config. Imported files are
+/// left alone. Diagnostics are ordered by file and position when the request has positions,
+/// and by node otherwise.
+///
+pub fn lint(cgr: &CodeGeneratorRequest, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        cgr: cgr,
+        config: config,
+        requested: cgr.requested_files().iter().map(|file| file.filename().as_str()).collect(),
+        node: None,
+        field: None,
+        next_field: 0,
+        diagnostics: vec!()
+    };
+    linter.visit_request(cgr);
+
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by(|a, b| a.file.cmp(&b.file).then(a.start_byte.cmp(&b.start_byte)));
+    diagnostics
+}
+
+const CPP_KEYWORDS: &[&str] = &[
+    "alignas", "alignof", "and", "and_eq", "asm", "auto", "bitand", "bitor", "bool", "break", "case", "catch", "char",
+    "char8_t", "char16_t", "char32_t", "class", "compl", "concept", "const", "consteval", "constexpr", "constinit",
+    "const_cast", "continue", "co_await", "co_return", "co_yield", "decltype", "default", "delete", "do", "double",
+    "dynamic_cast", "else", "enum", "explicit", "export", "extern", "false", "float", "for", "friend", "goto", "if",
+    "inline", "int", "long", "mutable", "namespace", "new", "noexcept", "not", "not_eq", "nullptr", "operator", "or",
+    "or_eq", "private", "protected", "public", "register", "reinterpret_cast", "requires", "return", "short", "signed",
+    "sizeof", "static", "static_assert", "static_cast", "struct", "switch", "template", "this", "thread_local", "throw",
+    "true", "try", "typedef", "typeid", "typename", "union", "unsigned", "using", "virtual", "void", "volatile",
+    "wchar_t", "while", "xor", "xor_eq"
+];
+
+const RUST_KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate", "do", "dyn", "else",
+    "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move",
+    "mut", "override", "priv", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true",
+    "try", "type", "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield"
+];
+
+fn is_camel_case(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_lowercase()) && name.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn to_camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    let mut upper = false;
+    for c in name.chars() {
+        if c == '_' || c == '-' {
+            upper = !camel.is_empty();
+        } else if upper {
+            camel.extend(c.to_uppercase());
+            upper = false;
+        } else if camel.is_empty() {
+            camel.extend(c.to_lowercase());
+        } else {
+            camel.push(c);
+        }
+    }
+    camel
+}
+
+struct Linter<'a> {
+    cgr: &'a CodeGeneratorRequest,
+    config: &'a LintConfig,
+    requested: HashSet<&'a str>,
+
+    /// The node being visited, if it's in a requested file.
+    node: Option<&'a Node>,
+
+    /// The field being visited and its index in the node.
+    field: Option<(usize, &'a ast::Field)>,
+    next_field: usize,
+    diagnostics: Vec<Diagnostic>
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, rule: Rule, member: Option<(usize, &str)>, message: String) {
+        let level = self.config.level(rule);
+        let node = match (level, self.node) {
+            (Level::Off, _) | (_, None) => return,
+            (_, Some(node)) => node
+        };
+
+        let info = self.cgr.source_info().get(&node.id());
+        let member_byte = member
+            .and_then(|(index, _)| info.and_then(|info| info.members().get(index)))
+            .map(|member| member.start_byte());
+        let start_byte = match member_byte {
+            Some(byte) if byte != 0 => byte,
+            _ => info.map_or(0, |info| info.start_byte())
+        };
+
+        let display_name = node.display_name();
+        let colon = display_name.find(':');
+        self.diagnostics.push(Diagnostic {
+            rule: rule,
+            level: level,
+            node_id: node.id(),
+            file: String::from(&display_name[..colon.unwrap_or(display_name.len())]),
+            node_name: String::from(colon.map_or("", |i| &display_name[i + 1..])),
+            member: member.map(|(_, name)| String::from(name)),
+            start_byte: if start_byte == 0 { None } else { Some(start_byte) },
+            line: None,
+            message: message
+        });
+    }
+
+    fn check_struct(&mut self, node: &'a Node, is_group: bool) {
+        if !is_group && node.scope_id() != 0 && self.cgr.doc_comment(node.id()).is_none() {
+            self.report(Rule::StructDocComment, None, String::from("Struct has no doc comment."));
+        }
+    }
+
+    fn check_enum(&mut self, enumerants: &'a [ast::Enumerant]) {
+        match enumerants.first() {
+            Some(first) if first.name() == "unknown" => (),
+            Some(first) => self.report(
+                Rule::EnumUnknownZero,
+                Some((0, first.name())),
+                format!("The zero value should be `unknown`, not `{}`.", first.name())
+            ),
+            None => self.report(Rule::EnumUnknownZero, None, String::from("Enum has no `unknown` zero value."))
+        }
+    }
+
+    fn check_field_name(&mut self, index: usize, name: &'a str) {
+        if !is_camel_case(name) {
+            self.report(Rule::FieldNameCase, Some((index, name)), format!("Field name is not camelCase; consider `{}`.", to_camel_case(name)));
+        }
+
+        let languages = match (CPP_KEYWORDS.contains(&name), RUST_KEYWORDS.contains(&name)) {
+            (true, true) => "C++ and Rust",
+            (true, false) => "C++",
+            (false, true) => "Rust",
+            (false, false) => return
+        };
+        self.report(Rule::KeywordFieldName, Some((index, name)), format!("Field name is a {} keyword.", languages));
+    }
+}
+
+impl<'a> Visitor<'a> for Linter<'a> {
+    fn visit_node(&mut self, node: &'a Node) {
+        let file = node.display_name().split(':').next().unwrap_or("");
+        if !self.requested.contains(file) {
+            return;
+        }
+        self.node = Some(node);
+        self.next_field = 0;
+
+        match node.which() {
+            ast::node::Which::Struct { is_group, .. } => self.check_struct(node, *is_group),
+            ast::node::Which::Enum(enumerants) => self.check_enum(enumerants),
+            _ => ()
+        }
+        visit::walk_node(self, node);
+        self.node = None;
+    }
+
+    fn visit_field(&mut self, field: &'a ast::Field) {
+        let index = self.next_field;
+        self.next_field += 1;
+        self.check_field_name(index, field.name());
+
+        self.field = Some((index, field));
+        visit::walk_field(self, field);
+        self.field = None;
+    }
+
+    fn visit_type(&mut self, type_: &'a ast::Type) {
+        match type_ {
+            ast::Type::List(element_type) if matches!(**element_type, ast::Type::List(_)) => {
+                // Reported once, however deep the nesting goes.
+                let member = self.field.map(|(index, field)| (index, field.name().as_str()));
+                self.report(Rule::NestedList, member, String::from("List of lists; consider a list of structs instead."));
+            },
+            _ => visit::walk_type(self, type_)
+        }
+    }
+
+    // Default values can't contain anything to check.
+    fn visit_value(&mut self, _: &'a ast::Value) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compile_source, CompileOptions};
+
+    const SCHEMA: &str = r#"@0xe87e0317861d3aab;
+
+struct Shape {
+  # A shape.
+  color @0 :Color;
+  corner_radius @1 :Float64;
+  points @2 :List(List(Float64));
+  type @3 :Text;
+}
+
+struct Undocumented {
+  name @0 :Text;
+}
+
+enum Color {
+  red @0;
+  green @1;
+}
+
+enum Kind {
+  unknown @0;
+  circle @1;
+}
+
+interface Drawer {
+  # Draws.
+  draw @0 (return :Shape) -> ();
+}
+"#;
+
+    fn lint_schema(config: &LintConfig) -> Vec<Diagnostic> {
+        let cgr = compile_source("test.capnp", SCHEMA, &CompileOptions::new()).unwrap();
+        let mut diagnostics = lint(&cgr, config);
+        for diagnostic in &mut diagnostics {
+            diagnostic.locate(SCHEMA);
+        }
+        diagnostics
+    }
+
+    fn summary(diagnostics: &[Diagnostic]) -> Vec<(Rule, Option<usize>, Option<&str>)> {
+        diagnostics.iter()
+            .map(|diagnostic| (diagnostic.rule(), diagnostic.line(), diagnostic.member().as_ref().map(String::as_str)))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let diagnostics = lint_schema(&LintConfig::new());
+        assert_eq!(summary(&diagnostics), vec!(
+            (Rule::FieldNameCase, Some(6), Some("corner_radius")),
+            (Rule::NestedList, Some(7), Some("points")),
+            (Rule::KeywordFieldName, Some(8), Some("type")),
+            (Rule::StructDocComment, Some(11), None),
+            (Rule::EnumUnknownZero, Some(16), Some("red")),
+            (Rule::KeywordFieldName, Some(27), Some("return"))
+        ));
+
+        assert_eq!(diagnostics[0].level(), Level::Warning);
+        assert_eq!(diagnostics[0].node_name(), "Shape");
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "test.capnp:6: warning: Shape.corner_radius: Field name is not camelCase; consider `cornerRadius`. [field-name-case]"
+        );
+        assert_eq!(diagnostics[2].message(), "Field name is a Rust keyword.");
+        assert_eq!(diagnostics[5].node_name(), "Drawer.draw$Params");
+        assert_eq!(diagnostics[5].message(), "Field name is a C++ and Rust keyword.");
+    }
+
+    #[test]
+    fn test_config() {
+        let config: LintConfig = serde_json::from_str(r#"{ "rules": { "struct-doc-comment": "off", "nested-list": "error" } }"#).unwrap();
+        assert_eq!(config.level(Rule::StructDocComment), Level::Off);
+        assert_eq!(config.level(Rule::FieldNameCase), Level::Warning);
+
+        let diagnostics = lint_schema(&config);
+        assert!(diagnostics.iter().all(|diagnostic| diagnostic.rule() != Rule::StructDocComment));
+        let nested = diagnostics.iter().find(|diagnostic| diagnostic.rule() == Rule::NestedList).unwrap();
+        assert_eq!(nested.level(), Level::Error);
+
+        let mut config = LintConfig::new();
+        for rule in Rule::ALL.iter() {
+            config.rule(*rule, Level::Off);
+        }
+        assert!(lint_schema(&config).is_empty());
+
+        assert!(serde_json::from_str::<LintConfig>(r#"{ "rules": { "no-such-rule": "off" } }"#).is_err());
+    }
+
+    #[test]
+    fn test_names() {
+        assert!(is_camel_case("cornerRadius"));
+        assert!(!is_camel_case("corner_radius"));
+        assert!(!is_camel_case("CornerRadius"));
+        assert_eq!(to_camel_case("corner_radius"), "cornerRadius");
+        assert_eq!(to_camel_case("Corner_radius_2"), "cornerRadius2");
+    }
+}
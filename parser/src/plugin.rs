@@ -0,0 +1,169 @@
+use std::env;
+use std::path::PathBuf;
+
+use getset::Getters;
+
+use crate::ast;
+use crate::{compile_files, try_read_message_with_options, CompileOptions, Error, ReadOptions, Result};
+
+///
+/// When CAPNPC_IDIOMATIC_DUMP_JSON is set, plugins also write the request to that file as JSON,
+/// which is handy for bug reports and for seeing what the schema compiler sent.
+///
+pub fn json_dump_path() -> Option<PathBuf> {
+    env::var_os("CAPNPC_IDIOMATIC_DUMP_JSON").map(PathBuf::from)
+}
+
+///
+/// The traversal limit for reading the request from stdin, for plugins run by `capnp compile`,
+/// which passes them no arguments.
+///
+const TRAVERSAL_LIMIT_VAR: &str = "CAPNPC_IDIOMATIC_TRAVERSAL_LIMIT";
+
+fn parse_traversal_limit(value: &str, source: &str) -> Result<u64> {
+    value.parse().map_err(|_| Error::Usage(format!("{} needs a number of words, not '{}'", source, value)))
+}
+
+///
+/// The arguments every plugin takes. Schema files given as arguments are compiled without
+/// the `capnp` tool, e.g. `-I /usr/local/include --src-prefix=schemas schemas/foo.capnp`.
+/// Otherwise the request is read from stdin, as `capnp compile` sends it, within the
+/// traversal limit given with `--traversal-limit=<words>` or CAPNPC_IDIOMATIC_TRAVERSAL_LIMIT.
+///
+#[derive(Clone, Debug, Default, Getters, PartialEq)]
+pub struct RequestArgs {
+    #[get = "pub"]
+    options: CompileOptions,
+
+    #[get = "pub"]
+    read_options: ReadOptions,
+
+    #[get = "pub"]
+    files: Vec<String>
+}
+
+impl RequestArgs {
+    pub fn new() -> RequestArgs {
+        RequestArgs::default()
+    }
+
+    ///
+    /// No arguments yet, but the traversal limit from the environment, if it's set.
+    ///
+    pub fn from_env() -> Result<RequestArgs> {
+        let mut args = RequestArgs::new();
+        if let Some(limit) = env::var_os(TRAVERSAL_LIMIT_VAR) {
+            let limit = parse_traversal_limit(&limit.to_string_lossy(), TRAVERSAL_LIMIT_VAR)?;
+            args.read_options.traversal_limit_in_words(limit);
+        }
+        Ok(args)
+    }
+
+    ///
+    /// Parses the arguments of a plugin that takes no options of its own.
+    ///
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<RequestArgs> {
+        let mut parsed = RequestArgs::from_env()?;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !parsed.parse_arg(&arg, &mut args)? {
+                return Err(Error::Usage(format!("Unknown option {}", arg)));
+            }
+        }
+        Ok(parsed)
+    }
+
+    ///
+    /// Takes `-I <dir>`, `-I<dir>`, `--src-prefix=<dir>`, `--traversal-limit=<words>` or a schema
+    /// file, pulling the directory of a bare `-I` from `rest`. Returns false for any other option,
+    /// so plugins can add their own.
+    ///
+    pub fn parse_arg<I: Iterator<Item = String>>(&mut self, arg: &str, rest: &mut I) -> Result<bool> {
+        if arg == "-I" {
+            match rest.next() {
+                Some(dir) => self.options.import_path(dir),
+                None => return Err(Error::Usage(String::from("-I needs a directory")))
+            };
+        } else if let Some(dir) = arg.strip_prefix("-I") {
+            self.options.import_path(dir);
+        } else if let Some(prefix) = arg.strip_prefix("--src-prefix=") {
+            self.options.src_prefix(prefix);
+        } else if let Some(limit) = arg.strip_prefix("--traversal-limit=") {
+            self.read_options.traversal_limit_in_words(parse_traversal_limit(limit, "--traversal-limit")?);
+        } else if arg.starts_with("--") {
+            return Ok(false);
+        } else {
+            self.files.push(String::from(arg));
+        }
+        Ok(true)
+    }
+
+    ///
+    /// Compiles the schema files, or reads the request from stdin if none were given.
+    ///
+    pub fn read_request(&self) -> Result<ast::CodeGeneratorRequest> {
+        if self.files.is_empty() {
+            try_read_message_with_options(&mut std::io::stdin(), &self.read_options)
+        } else {
+            compile_files(&self.files, &self.options)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| String::from(*arg)).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = RequestArgs::parse(args(&["-I", "/usr/include", "-Iinclude", "--src-prefix=schemas", "schemas/foo.capnp"])).unwrap();
+
+        let mut options = CompileOptions::new();
+        options.import_path("/usr/include").import_path("include").src_prefix("schemas");
+        assert_eq!(parsed.options(), &options);
+        assert_eq!(parsed.options().src_prefixes(), &[PathBuf::from("schemas")]);
+        assert_eq!(parsed.files(), &args(&["schemas/foo.capnp"]));
+    }
+
+    #[test]
+    fn test_traversal_limit() {
+        let mut parsed = RequestArgs::new();
+        assert!(parsed.parse_arg("--traversal-limit=67108864", &mut std::iter::empty::<String>()).unwrap());
+        let mut read_options = ReadOptions::new();
+        read_options.traversal_limit_in_words(67108864);
+        assert_eq!(parsed.read_options(), &read_options);
+
+        match parsed.parse_arg("--traversal-limit=lots", &mut std::iter::empty::<String>()) {
+            Err(Error::Usage(message)) => assert_eq!(message, "--traversal-limit needs a number of words, not 'lots'"),
+            other => panic!("Expected a usage error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_bare_import_path() {
+        match RequestArgs::parse(args(&["foo.capnp", "-I"])) {
+            Err(Error::Usage(_)) => (),
+            other => panic!("Expected a usage error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_unknown_option() {
+        match RequestArgs::parse(args(&["--verbose", "foo.capnp"])) {
+            Err(Error::Usage(message)) => assert_eq!(message, "Unknown option --verbose"),
+            other => panic!("Expected a usage error, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_plugin_options_are_left_to_the_plugin() {
+        let mut parsed = RequestArgs::new();
+        let mut rest = args(&["x"]).into_iter();
+        assert!(!parsed.parse_arg("--json", &mut rest).unwrap());
+        assert_eq!(rest.next(), Some(String::from("x")));
+    }
+}
@@ -0,0 +1,691 @@
+use std::cell::RefCell;
+
+use crate::ast;
+use crate::ast::{CodeGeneratorRequest, Id, Node};
+use crate::{Error, Result};
+use crate::schema_graph::SchemaGraph;
+
+const INDENT: &str = "  ";
+
+///
+/// Prints the declarations of one file of a request as `.capnp` source. Names from other
+/// files are printed through `using` aliases of their imports, which are collected as the
+/// declarations are printed.
+///
+struct Printer<'a> {
+    cgr: &'a CodeGeneratorRequest,
+    graph: SchemaGraph,
+    file_id: Id,
+    imports: RefCell<Vec<(Id, String)>>,
+    method_parameters: RefCell<Vec<String>>
+}
+
+fn indented(indent: usize, line: String) -> String {
+    format!("{}{}", INDENT.repeat(indent), line)
+}
+
+fn doc_comment_lines(doc_comment: Option<&String>, indent: usize) -> Vec<String> {
+    match doc_comment {
+        Some(doc_comment) => ast::doc_comment_lines(doc_comment, "#").into_iter().map(|line| indented(indent, line)).collect(),
+        None => vec!()
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn float_literal<F: std::fmt::Display>(f: F) -> String {
+    match f.to_string().as_str() {
+        "NaN" => String::from("nan"),
+        literal => String::from(literal)
+    }
+}
+
+fn annotation_targets(targets: &[ast::node::AnnotationTarget]) -> String {
+    use ast::node::AnnotationTarget;
+
+    if targets.len() == 12 {
+        return String::from("*");
+    }
+    targets.iter()
+        .map(|target| match target {
+            AnnotationTarget::File => "file",
+            AnnotationTarget::Const => "const",
+            AnnotationTarget::Enum => "enum",
+            AnnotationTarget::Enumerant => "enumerant",
+            AnnotationTarget::Struct => "struct",
+            AnnotationTarget::Field => "field",
+            AnnotationTarget::Union => "union",
+            AnnotationTarget::Group => "group",
+            AnnotationTarget::Interface => "interface",
+            AnnotationTarget::Method => "method",
+            AnnotationTarget::Param => "param",
+            AnnotationTarget::Annotation => "annotation"
+        })
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+///
+/// An identifier for an imported file, from its name: `/capnp/c++.capnp` becomes `Cxx`.
+///
+fn import_alias(file_name: &str) -> String {
+    let stem = file_name.rsplit('/').next().unwrap_or("").split('.').next().unwrap_or("");
+    let alias: String = stem.chars()
+        .filter_map(|c| if c == '+' { Some('x') } else if c.is_ascii_alphanumeric() { Some(c) } else { None })
+        .collect();
+    match alias.chars().next() {
+        Some(first) if first.is_ascii_alphabetic() => format!("{}{}", first.to_ascii_uppercase(), &alias[1..]),
+        _ => format!("Import{}", alias)
+    }
+}
+
+fn not_in_request(id: Id) -> Error {
+    Error::Invalid(format!("Node @0x{:016x} is not in the request", id))
+}
+
+///
+/// Whether a node is the group of a named union, which has only union members. A struct made of
+/// an unnamed union only has the same fields, but isn't a group.
+///
+fn is_named_union(node: &Node) -> bool {
+    match node.which() {
+        ast::node::Which::Struct { is_group: true, fields, .. } => {
+            !fields.is_empty() && fields.iter().all(|field| field.discriminant_value() != ast::field::NO_DISCRIMINANT)
+        },
+        _ => false
+    }
+}
+
+impl<'a> Printer<'a> {
+    fn import_name(&self, file_id: Id) -> String {
+        let requested_import = self.cgr.requested_files()
+            .iter()
+            .filter(|file| file.id() == self.file_id)
+            .flat_map(|file| file.imports().iter())
+            .find(|import| import.id() == file_id);
+        match requested_import {
+            Some(import) => import.name().clone(),
+            None => format!("/{}", self.graph.node(file_id).map(|file| file.display_name().clone()).unwrap_or_default())
+        }
+    }
+
+    fn alias_for_file(&self, file_id: Id) -> String {
+        if let Some((_, alias)) = self.imports.borrow().iter().find(|(id, _)| *id == file_id) {
+            return alias.clone();
+        }
+
+        let base = import_alias(&self.import_name(file_id));
+        let mut alias = base.clone();
+        let mut n = 2;
+        while self.imports.borrow().iter().any(|(_, existing)| *existing == alias) {
+            alias = format!("{}{}", base, n);
+            n += 1;
+        }
+        self.imports.borrow_mut().push((file_id, alias.clone()));
+        alias
+    }
+
+    ///
+    /// The name of a declaration as seen from the printed file, with generic parameters
+    /// bound by `brand`.
+    ///
+    fn name_of(&self, id: Id, brand: &ast::Brand) -> Result<String> {
+        let file_id = self.graph.file_of(id).ok_or_else(|| not_in_request(id))?.id();
+        let scopes: Vec<Id> = self.graph.ancestry(id).iter().skip(1).map(|node| node.id()).collect();
+        let names = self.graph.qualified_name(id).ok_or_else(|| not_in_request(id))?;
+
+        let mut segments = vec!();
+        if file_id != self.file_id {
+            segments.push(self.alias_for_file(file_id));
+        }
+        for (scope_id, name) in scopes.into_iter().zip(names) {
+            let bindings = brand.scopes().iter().find(|scope| scope.scope_id() == scope_id).and_then(|scope| match scope.which() {
+                ast::brand::scope::Which::Bind(bindings) => Some(bindings),
+                ast::brand::scope::Which::Inherit => None
+            });
+            match bindings {
+                Some(bindings) => {
+                    let mut arguments = vec!();
+                    for binding in bindings {
+                        arguments.push(match binding {
+                            ast::brand::Binding::Type(t) => self.type_name(t)?,
+                            ast::brand::Binding::Unbound => String::from("AnyPointer")
+                        });
+                    }
+                    segments.push(format!("{}({})", name, arguments.join(", ")));
+                },
+                None => segments.push(name)
+            }
+        }
+        Ok(segments.join("."))
+    }
+
+    fn type_name(&self, type_: &ast::Type) -> Result<String> {
+        Ok(
+            match type_ {
+                ast::Type::List(element_type) => format!("List({})", self.type_name(element_type)?),
+                ast::Type::Enum { type_id, brand } | ast::Type::Struct { type_id, brand } | ast::Type::Interface { type_id, brand } => {
+                    self.name_of(*type_id, brand)?
+                },
+                ast::Type::AnyPointer(p) => match p {
+                    ast::type_::AnyPointer::Unconstrained(ast::type_::Unconstrained::AnyKind) => String::from("AnyPointer"),
+                    ast::type_::AnyPointer::Unconstrained(ast::type_::Unconstrained::Struct) => String::from("AnyStruct"),
+                    ast::type_::AnyPointer::Unconstrained(ast::type_::Unconstrained::List) => String::from("AnyList"),
+                    ast::type_::AnyPointer::Unconstrained(ast::type_::Unconstrained::Capability) => String::from("Capability"),
+                    ast::type_::AnyPointer::Parameter { scope_id, parameter_index } => {
+                        self.graph.node(*scope_id)
+                            .and_then(|node| node.parameters().get(*parameter_index as usize))
+                            .map(|parameter| parameter.name().clone())
+                            .ok_or_else(|| Error::Invalid(format!("Node @0x{:016x} has no generic parameter {}", scope_id, parameter_index)))?
+                    },
+                    ast::type_::AnyPointer::ImplicitMethodParameter { parameter_index } => {
+                        self.method_parameters.borrow().get(*parameter_index as usize)
+                            .cloned()
+                            .ok_or_else(|| Error::Invalid(format!("The method has no implicit parameter {}", parameter_index)))?
+                    }
+                },
+                // schema.capnp spells the unsigned types `UInt`, where the ast has `Uint`.
+                primitive => format!("{:?}", primitive).replace("Uint", "UInt")
+            }
+        )
+    }
+
+    fn struct_fields(&self, type_id: Id) -> Option<&Vec<ast::Field>> {
+        match self.graph.node(type_id).map(|node| node.which()) {
+            Some(ast::node::Which::Struct { fields, .. }) => Some(fields),
+            _ => None
+        }
+    }
+
+    fn struct_value(&self, values: &[ast::value::StructField], type_id: Id) -> Option<String> {
+        let fields = self.struct_fields(type_id)?;
+        let mut printed = vec!();
+        for value in values {
+            let field = fields.iter().find(|field| field.name() == value.name())?;
+            let printed_value = match field.which() {
+                // Pointers without a literal can only be null here, which is also their default.
+                ast::field::Which::Slot { type_, .. } => match self.value(value.value(), type_) {
+                    Some(printed_value) => printed_value,
+                    None => continue
+                },
+                ast::field::Which::Group(group_id) => match value.value() {
+                    ast::Value::Struct(group_values) => self.struct_value(group_values, *group_id)?,
+                    _ => return None
+                }
+            };
+            printed.push(format!("{} = {}", value.name(), printed_value));
+        }
+        Some(format!("({})", printed.join(", ")))
+    }
+
+    ///
+    /// A value as a `.capnp` literal. Capabilities, untyped pointers, values of unknown types
+    /// and values that don't match their type have no literal.
+    ///
+    fn value(&self, value: &ast::Value, type_: &ast::Type) -> Option<String> {
+        Some(
+            match (value, type_) {
+                (ast::Value::Void, ast::Type::Void) => String::from("void"),
+                (ast::Value::Bool(b), ast::Type::Bool) => b.to_string(),
+                (ast::Value::Int8(i), ast::Type::Int8) => i.to_string(),
+                (ast::Value::Int16(i), ast::Type::Int16) => i.to_string(),
+                (ast::Value::Int32(i), ast::Type::Int32) => i.to_string(),
+                (ast::Value::Int64(i), ast::Type::Int64) => i.to_string(),
+                (ast::Value::Uint8(i), ast::Type::Uint8) => i.to_string(),
+                (ast::Value::Uint16(i), ast::Type::Uint16) => i.to_string(),
+                (ast::Value::Uint32(i), ast::Type::Uint32) => i.to_string(),
+                (ast::Value::Uint64(i), ast::Type::Uint64) => i.to_string(),
+                (ast::Value::Float32(f), ast::Type::Float32) => float_literal(f),
+                (ast::Value::Float64(f), ast::Type::Float64) => float_literal(f),
+                (ast::Value::Text(t), ast::Type::Text) => quote(t),
+                (ast::Value::Data(d), ast::Type::Data) => format!("0x\"{}\"", d.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(" ")),
+                (ast::Value::List(values), ast::Type::List(element_type)) => {
+                    let elements: Option<Vec<String>> = values.iter().map(|v| self.value(v, element_type)).collect();
+                    format!("[{}]", elements?.join(", "))
+                },
+                (ast::Value::Enum(e), ast::Type::Enum { type_id, .. }) => match self.graph.node(*type_id).map(|node| node.which()) {
+                    Some(ast::node::Which::Enum(enumerants)) => enumerants.get(*e as usize)?.name().clone(),
+                    _ => return None
+                },
+                (ast::Value::Struct(values), ast::Type::Struct { type_id, .. }) => self.struct_value(values, *type_id)?,
+                _ => return None
+            }
+        )
+    }
+
+    fn literal(&self, value: &ast::Value, type_: &ast::Type) -> Result<String> {
+        self.value(value, type_).ok_or_else(|| Error::Invalid(format!("Value {:?} has no literal of type {:?}", value, type_)))
+    }
+
+    fn annotations(&self, annotations: &[ast::Annotation]) -> Result<String> {
+        let mut printed = String::new();
+        for annotation in annotations {
+            let type_ = match self.graph.node(annotation.id()).map(|node| node.which()) {
+                Some(ast::node::Which::Annotation { type_, .. }) => type_,
+                Some(_) => return Err(Error::Invalid(format!("Node @0x{:016x} is not an annotation", annotation.id()))),
+                None => return Err(not_in_request(annotation.id()))
+            };
+            let name = self.name_of(annotation.id(), &ast::Brand::default())?;
+            match annotation.value() {
+                ast::Value::Void => printed.push_str(&format!(" ${}", name)),
+                // Struct values are already parenthesized.
+                value @ ast::Value::Struct(_) => printed.push_str(&format!(" ${}{}", name, self.literal(value, type_)?)),
+                value => printed.push_str(&format!(" ${}({})", name, self.literal(value, type_)?))
+            }
+        }
+        Ok(printed)
+    }
+
+    fn ordinal(field: &ast::Field) -> String {
+        match field.ordinal() {
+            ast::field::Ordinal::Explicit(n) => format!(" @{}", n),
+            ast::field::Ordinal::Implicit => String::new()
+        }
+    }
+
+    fn slot(&self, field: &ast::Field, with_ordinal: bool) -> Result<String> {
+        match field.which() {
+            ast::field::Which::Slot { type_, default_value, had_explicit_default, .. } => {
+                let default = if *had_explicit_default { format!(" = {}", self.literal(default_value, type_)?) } else { String::new() };
+                Ok(format!(
+                    "{}{} :{}{}{}",
+                    field.name(),
+                    if with_ordinal { Printer::ordinal(field) } else { String::new() },
+                    self.type_name(type_)?,
+                    default,
+                    self.annotations(field.annotations())?
+                ))
+            },
+            ast::field::Which::Group(_) => Ok(field.name().clone())
+        }
+    }
+
+    fn field(&self, scope_id: Id, index: usize, field: &ast::Field, indent: usize) -> Result<Vec<String>> {
+        let doc_comment = self.cgr.member_doc_comment(scope_id, index);
+        match field.which() {
+            ast::field::Which::Slot { .. } => {
+                let mut lines = vec!(indented(indent, format!("{};", self.slot(field, true)?)));
+                lines.extend(doc_comment_lines(doc_comment, indent));
+                Ok(lines)
+            },
+            ast::field::Which::Group(type_id) => {
+                let group = self.graph.node(*type_id).ok_or_else(|| not_in_request(*type_id))?;
+                let keyword = if is_named_union(group) { "union" } else { "group" };
+                let mut lines = vec!(indented(indent, format!("{} :{}{} {{", field.name(), keyword, self.annotations(field.annotations())?)));
+                lines.extend(doc_comment_lines(doc_comment, indent + 1));
+                lines.extend(self.struct_body(group, indent + 1)?);
+                lines.push(indented(indent, String::from("}")));
+                Ok(lines)
+            }
+        }
+    }
+
+    ///
+    /// Fields in declaration order, with the unnamed union where its first member was declared,
+    /// followed by the nested declarations.
+    ///
+    fn struct_body(&self, node: &Node, indent: usize) -> Result<Vec<String>> {
+        let fields = match node.which() {
+            ast::node::Which::Struct { fields, .. } => fields,
+            _ => return Ok(vec!())
+        };
+        let mut ordered: Vec<(usize, &ast::Field)> = fields.iter().enumerate().collect();
+        ordered.sort_by_key(|(_, field)| field.code_order());
+
+        let mut lines = vec!();
+        if is_named_union(node) {
+            for (index, field) in ordered {
+                lines.extend(self.field(node.id(), index, field, indent)?);
+            }
+        } else {
+            let mut printed_union = false;
+            for (index, field) in ordered.iter() {
+                if field.discriminant_value() == ast::field::NO_DISCRIMINANT {
+                    lines.extend(self.field(node.id(), *index, field, indent)?);
+                } else if !printed_union {
+                    printed_union = true;
+                    lines.push(indented(indent, String::from("union {")));
+                    for (index, member) in ordered.iter().filter(|(_, f)| f.discriminant_value() != ast::field::NO_DISCRIMINANT) {
+                        lines.extend(self.field(node.id(), *index, member, indent + 1)?);
+                    }
+                    lines.push(indented(indent, String::from("}")));
+                }
+            }
+        }
+
+        lines.extend(self.nested_declarations(node.id(), indent)?);
+        Ok(lines)
+    }
+
+    fn nested_declarations(&self, id: Id, indent: usize) -> Result<Vec<String>> {
+        let mut lines = vec!();
+        for nested in self.graph.nested_nodes(id) {
+            let declaration = self.declaration(nested, indent)?;
+            if !declaration.is_empty() {
+                lines.push(String::new());
+                lines.extend(declaration);
+            }
+        }
+        Ok(lines)
+    }
+
+    fn parameters(node: &Node) -> String {
+        if node.parameters().is_empty() {
+            String::new()
+        } else {
+            format!("({})", node.parameters().iter().map(|p| p.name().clone()).collect::<Vec<String>>().join(", "))
+        }
+    }
+
+    ///
+    /// The parameter or result list of a method: inline for the structs the compiler generated,
+    /// otherwise the name of the struct.
+    ///
+    fn method_struct(&self, type_id: Id, brand: &ast::Brand) -> Result<String> {
+        match (self.graph.node(type_id), self.struct_fields(type_id)) {
+            (Some(node), Some(fields)) if node.scope_id() == 0 => {
+                let mut ordered: Vec<&ast::Field> = fields.iter().collect();
+                ordered.sort_by_key(|field| field.code_order());
+                let slots = ordered.iter().map(|field| self.slot(field, false)).collect::<Result<Vec<String>>>()?;
+                Ok(format!("({})", slots.join(", ")))
+            },
+            _ => self.name_of(type_id, brand)
+        }
+    }
+
+    fn method(&self, number: usize, method: &ast::Method) -> Result<String> {
+        let implicit_parameters: Vec<String> = method.implicit_parameters().iter().map(|p| p.name().clone()).collect();
+        *self.method_parameters.borrow_mut() = implicit_parameters.clone();
+        let line = format!(
+            "{} @{} {}{} -> {}{};",
+            method.name(),
+            number,
+            if implicit_parameters.is_empty() { String::new() } else { format!("[{}] ", implicit_parameters.join(", ")) },
+            self.method_struct(method.param_struct_type(), method.param_brand())?,
+            self.method_struct(method.result_struct_type(), method.result_brand())?,
+            self.annotations(method.annotations())?
+        );
+        self.method_parameters.borrow_mut().clear();
+        Ok(line)
+    }
+
+    fn declaration(&self, node: &Node, indent: usize) -> Result<Vec<String>> {
+        let name = self.graph.name(node.id()).ok_or_else(|| not_in_request(node.id()))?;
+        let id = format!("@0x{:016x}", node.id());
+        let annotations = self.annotations(node.annotations())?;
+        let doc_comment = self.cgr.doc_comment(node.id());
+
+        let mut lines = vec!();
+        match node.which() {
+            ast::node::Which::File => (),
+            ast::node::Which::Struct { .. } => {
+                lines.push(indented(indent, format!("struct {}{} {}{} {{", name, Printer::parameters(node), id, annotations)));
+                lines.extend(doc_comment_lines(doc_comment, indent + 1));
+                lines.extend(self.struct_body(node, indent + 1)?);
+                lines.push(indented(indent, String::from("}")));
+            },
+            ast::node::Which::Enum(enumerants) => {
+                lines.push(indented(indent, format!("enum {} {}{} {{", name, id, annotations)));
+                lines.extend(doc_comment_lines(doc_comment, indent + 1));
+                let mut ordered: Vec<(usize, &ast::Enumerant)> = enumerants.iter().enumerate().collect();
+                ordered.sort_by_key(|(_, enumerant)| enumerant.code_order());
+                for (number, enumerant) in ordered {
+                    lines.push(indented(indent + 1, format!("{} @{}{};", enumerant.name(), number, self.annotations(enumerant.annotations())?)));
+                    lines.extend(doc_comment_lines(self.cgr.member_doc_comment(node.id(), number), indent + 1));
+                }
+                lines.extend(self.nested_declarations(node.id(), indent + 1)?);
+                lines.push(indented(indent, String::from("}")));
+            },
+            ast::node::Which::Interface { methods, superclasses } => {
+                let extends = if superclasses.is_empty() {
+                    String::new()
+                } else {
+                    let names = superclasses.iter()
+                        .map(|superclass| self.name_of(superclass.id(), superclass.brand()))
+                        .collect::<Result<Vec<String>>>()?;
+                    format!(" extends({})", names.join(", "))
+                };
+                lines.push(indented(indent, format!("interface {}{} {}{}{} {{", name, Printer::parameters(node), id, extends, annotations)));
+                lines.extend(doc_comment_lines(doc_comment, indent + 1));
+                let mut ordered: Vec<(usize, &ast::Method)> = methods.iter().enumerate().collect();
+                ordered.sort_by_key(|(_, method)| method.code_order());
+                for (number, method) in ordered {
+                    lines.push(indented(indent + 1, self.method(number, method)?));
+                    lines.extend(doc_comment_lines(self.cgr.member_doc_comment(node.id(), number), indent + 1));
+                }
+                lines.extend(self.nested_declarations(node.id(), indent + 1)?);
+                lines.push(indented(indent, String::from("}")));
+            },
+            ast::node::Which::Const { type_, value } => {
+                lines.push(indented(indent, format!("const {} {} :{} = {}{};", name, id, self.type_name(type_)?, self.literal(value, type_)?, annotations)));
+                lines.extend(doc_comment_lines(doc_comment, indent));
+            },
+            ast::node::Which::Annotation { type_, targets } => {
+                lines.push(indented(indent, format!("annotation {} {} ({}) :{}{};", name, id, annotation_targets(targets), self.type_name(type_)?, annotations)));
+                lines.extend(doc_comment_lines(doc_comment, indent));
+            }
+        }
+        Ok(lines)
+    }
+
+    fn file(&self) -> Result<String> {
+        let file = self.graph.node(self.file_id)
+            .filter(|node| node.which() == &ast::node::Which::File)
+            .ok_or_else(|| Error::Invalid(format!("Node @0x{:016x} is not a file in the request", self.file_id)))?;
+
+        // Printed first, so that the imports they use are known.
+        let annotations = file.annotations()
+            .iter()
+            .map(|annotation| self.annotations(std::slice::from_ref(annotation)).map(|printed| format!("{};", printed.trim_start())))
+            .collect::<Result<Vec<String>>>()?;
+        let declarations = self.nested_declarations(self.file_id, 0)?;
+
+        let mut lines = doc_comment_lines(self.cgr.doc_comment(self.file_id), 0);
+        lines.push(format!("@0x{:016x};", self.file_id));
+
+        let imports = self.imports.borrow();
+        if !imports.is_empty() {
+            lines.push(String::new());
+            for (file_id, alias) in imports.iter() {
+                lines.push(format!("using {} = import {};", alias, quote(&self.import_name(*file_id))));
+            }
+        }
+        if !annotations.is_empty() {
+            lines.push(String::new());
+            lines.extend(annotations);
+        }
+        lines.extend(declarations);
+
+        Ok(lines.join("\n") + "\n")
+    }
+}
+
+///
+/// Prints the declarations of a file in the request as `.capnp` source. The output is
+/// canonical: members are in declaration order, with explicit ids and ordinals. Fails if the
+/// file refers to nodes missing from the request, or has values with no `.capnp` literal.
+///
+pub fn print_file(cgr: &CodeGeneratorRequest, file_id: Id) -> Result<String> {
+    let printer = Printer {
+        cgr: cgr,
+        graph: SchemaGraph::new(cgr),
+        file_id: file_id,
+        imports: RefCell::new(vec!()),
+        method_parameters: RefCell::new(vec!())
+    };
+    printer.file()
+}
+
+///
+/// The source of each requested file, by file name.
+///
+pub fn print_requested_files(cgr: &CodeGeneratorRequest) -> Result<Vec<(String, String)>> {
+    cgr.requested_files()
+        .iter()
+        .map(|file| Ok((file.filename().clone(), print_file(cgr, file.id())?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::compiler::{compile_source, CompileOptions};
+    use crate::fixtures::{node, slot, struct_which};
+
+    const FILE_ID: Id = 0xe87e0317861d3aab;
+
+    ///
+    /// Written the way the printer prints it, so printing the compiled schema gives it back.
+    ///
+    const SCHEMA: &str = r#"@0xe87e0317861d3aab;
+
+struct Shape @0x8000000000000002 $tag("round") {
+  # A shape.
+  color @0 :Color = green;
+  # Its color.
+  union {
+    circle @1 :Float64;
+    square @2 :Float64 = 1.5;
+  }
+  extra :group {
+    visible @3 :Bool;
+    label @4 :Text = "say \"hi\"\n";
+  }
+  kind :union {
+    a @5 :Void;
+    b @6 :Data = 0x"0a ff";
+  }
+}
+
+enum Color @0x8000000000000003 {
+  red @0;
+  green @1 $tag("g");
+}
+
+annotation tag @0x8000000000000004 (struct, enumerant) :Text;
+
+const answer @0x8000000000000005 :List(Int32) = [4, -2];
+
+interface Drawer @0x8000000000000006 {
+  draw @0 (shape :Shape, scale :Float32 = 2) -> ();
+}
+"#;
+
+    fn compile(source: &str) -> ast::CodeGeneratorRequest {
+        compile_source("test.capnp", source, &CompileOptions::new()).unwrap()
+    }
+
+    #[test]
+    fn test_print_file() {
+        let cgr = compile(SCHEMA);
+        assert_eq!(print_file(&cgr, FILE_ID).unwrap(), SCHEMA);
+        assert!(print_file(&cgr, 0x8000000000000002).is_err());
+        assert!(print_file(&cgr, 0x8000000000000007).is_err());
+    }
+
+    #[test]
+    fn test_print_requested_files() {
+        let cgr = compile(SCHEMA);
+        let printed = print_requested_files(&cgr).unwrap();
+        assert_eq!(printed.len(), 1);
+        assert_eq!(printed[0].0, "test.capnp");
+        assert_eq!(printed[0].1, SCHEMA);
+    }
+
+    #[test]
+    fn test_print_imports_and_generics() {
+        let dir = std::env::temp_dir().join(format!("capnp-printer-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("include/capnp")).unwrap();
+        std::fs::write(dir.join("include/capnp/c++.capnp"), "@0xbdf87d7bb8304e81;\nannotation namespace @0xb9c6f99ebf805f2c (file) :Text;\n").unwrap();
+        std::fs::write(dir.join("shapes.capnp"), "@0xb2c3d4e5f6071829;\nstruct Stack(T) {}\nstruct Point {}\n").unwrap();
+
+        let source = r#"@0xe87e0317861d3aab;
+
+using Cxx = import "/capnp/c++.capnp";
+using Shapes = import "shapes.capnp";
+
+$Cxx.namespace("ns");
+
+struct Holder(T) @0x8000000000000002 {
+  items @0 :Shapes.Stack(T);
+  any @1 :Shapes.Stack(AnyPointer);
+  point @2 :Shapes.Point;
+}
+"#;
+        let mut options = CompileOptions::new();
+        options.import_path(dir.join("include"));
+        let cgr = compile_source(dir.join("main.capnp").to_str().unwrap(), source, &options);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(print_file(&cgr.unwrap(), FILE_ID).unwrap(), source);
+    }
+
+    #[test]
+    fn test_print_unnamed_union_struct() {
+        let source = r#"@0xe87e0317861d3aab;
+
+struct Value @0x8000000000000002 {
+  union {
+    void @0 :Void;
+    text @1 :Text;
+  }
+}
+"#;
+        assert_eq!(print_file(&compile(source), FILE_ID).unwrap(), source);
+    }
+
+    #[test]
+    fn test_print_errors() {
+        let with_field = |type_: ast::Type, default_value: ast::Value| ast::CodeGeneratorRequest::new(
+            vec!(
+                node(1, "test.capnp", 0, vec!((2, "Holder")), ast::node::Which::File),
+                node(2, "test.capnp:Holder", 1, vec!(), struct_which(0, 1, false, 0, 0, vec!(slot("field", 0, ast::field::NO_DISCRIMINANT, 0, type_, default_value))))
+            ),
+            vec!(),
+            HashMap::new()
+        );
+
+        // The annotation is applied, but its declaration left out of the request.
+        let annotated = compile("@0xe87e0317861d3aab;\nannotation tag @0x8000000000000004 (file) :Text;\n$tag(\"x\");\n");
+        let missing_annotation = ast::CodeGeneratorRequest::new(
+            annotated.nodes().iter().filter(|node| node.id() != 0x8000000000000004).cloned().collect(),
+            annotated.requested_files().clone(),
+            annotated.source_info().clone()
+        );
+
+        for (cgr, file_id) in vec!(
+            (with_field(ast::Type::Struct { type_id: 22, brand: ast::Brand::default() }, ast::Value::Struct(vec!())), 1),
+            (with_field(ast::Type::AnyPointer(ast::type_::AnyPointer::Parameter { scope_id: 2, parameter_index: 0 }), ast::Value::AnyPointer), 1),
+            (with_field(ast::Type::Int32, ast::Value::Text(String::from("42"))), 1),
+            (missing_annotation, FILE_ID)
+        ) {
+            match print_file(&cgr, file_id) {
+                Err(Error::Invalid(_)) => (),
+                other => panic!("Expected an invalid request, got {:?}", other)
+            }
+        }
+    }
+
+    #[test]
+    fn test_import_alias() {
+        assert_eq!(import_alias("/capnp/c++.capnp"), "Cxx");
+        assert_eq!(import_alias("foo-bar.capnp"), "Foobar");
+        assert_eq!(import_alias("/3d.capnp"), "Import3d");
+    }
+}
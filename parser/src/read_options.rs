@@ -0,0 +1,73 @@
+///
+/// How a serialized code generator request is laid out on the wire.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Framing {
+    /// The standard stream framing, as written by `capnp compile`.
+    Unpacked,
+
+    /// The packed encoding, as written by `capnp::serialize_packed`.
+    Packed
+}
+
+///
+/// Options for reading a code generator request. Starts from the capnp defaults with unpacked framing.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReadOptions {
+    traversal_limit_in_words: u64,
+    nesting_limit: i32,
+    framing: Framing
+}
+
+impl ReadOptions {
+    pub fn new() -> ReadOptions {
+        let defaults = capnp::message::ReaderOptions::new();
+        ReadOptions {
+            traversal_limit_in_words: defaults.traversal_limit_in_words,
+            nesting_limit: defaults.nesting_limit,
+            framing: Framing::Unpacked
+        }
+    }
+
+    ///
+    /// The number of words the reader may visit before giving up. Large schema bundles
+    /// need more than the default of 8M words.
+    ///
+    pub fn traversal_limit_in_words(&mut self, limit: u64) -> &mut ReadOptions {
+        self.traversal_limit_in_words = limit;
+        self
+    }
+
+    pub fn nesting_limit(&mut self, limit: i32) -> &mut ReadOptions {
+        self.nesting_limit = limit;
+        self
+    }
+
+    pub fn framing(&mut self, framing: Framing) -> &mut ReadOptions {
+        self.framing = framing;
+        self
+    }
+
+    pub fn packed(&mut self, packed: bool) -> &mut ReadOptions {
+        self.framing(if packed { Framing::Packed } else { Framing::Unpacked })
+    }
+
+    pub fn get_framing(&self) -> Framing {
+        self.framing
+    }
+
+    pub fn to_reader_options(&self) -> capnp::message::ReaderOptions {
+        let mut options = capnp::message::ReaderOptions::new();
+        options
+            .traversal_limit_in_words(self.traversal_limit_in_words)
+            .nesting_limit(self.nesting_limit);
+        options
+    }
+}
+
+impl Default for ReadOptions {
+    fn default() -> ReadOptions {
+        ReadOptions::new()
+    }
+}
@@ -735,6 +735,14 @@ pub mod node {
       pub fn has_members(&self) -> bool {
         !self.reader.get_pointer_field(1).is_null()
       }
+      #[inline]
+      pub fn get_start_byte(self) -> u32 {
+        self.reader.get_data_field::<u32>(2)
+      }
+      #[inline]
+      pub fn get_end_byte(self) -> u32 {
+        self.reader.get_data_field::<u32>(3)
+      }
     }
 
     pub struct Builder<'a> { builder: ::capnp::private::layout::StructBuilder<'a> }
@@ -823,6 +831,22 @@ pub mod node {
       pub fn has_members(&self) -> bool {
         !self.builder.get_pointer_field(1).is_null()
       }
+      #[inline]
+      pub fn get_start_byte(self) -> u32 {
+        self.builder.get_data_field::<u32>(2)
+      }
+      #[inline]
+      pub fn set_start_byte(&mut self, value: u32)  {
+        self.builder.set_data_field::<u32>(2, value);
+      }
+      #[inline]
+      pub fn get_end_byte(self) -> u32 {
+        self.builder.get_data_field::<u32>(3)
+      }
+      #[inline]
+      pub fn set_end_byte(&mut self, value: u32)  {
+        self.builder.set_data_field::<u32>(3, value);
+      }
     }
 
     pub struct Pipeline { _typeless: ::capnp::any_pointer::Pipeline }
@@ -835,7 +859,7 @@ pub mod node {
     }
     mod _private {
       use capnp::private::layout;
-      pub const STRUCT_SIZE: layout::StructSize = layout::StructSize { data: 1, pointers: 2 };
+      pub const STRUCT_SIZE: layout::StructSize = layout::StructSize { data: 2, pointers: 2 };
       pub const TYPE_ID: u64 = 0xf38e_1de3_0413_57ae;
     }
 
@@ -892,6 +916,14 @@ pub mod node {
         pub fn has_doc_comment(&self) -> bool {
           !self.reader.get_pointer_field(0).is_null()
         }
+        #[inline]
+        pub fn get_start_byte(self) -> u32 {
+          self.reader.get_data_field::<u32>(0)
+        }
+        #[inline]
+        pub fn get_end_byte(self) -> u32 {
+          self.reader.get_data_field::<u32>(1)
+        }
       }
 
       pub struct Builder<'a> { builder: ::capnp::private::layout::StructBuilder<'a> }
@@ -957,6 +989,22 @@ pub mod node {
         pub fn has_doc_comment(&self) -> bool {
           !self.builder.get_pointer_field(0).is_null()
         }
+        #[inline]
+        pub fn get_start_byte(self) -> u32 {
+          self.builder.get_data_field::<u32>(0)
+        }
+        #[inline]
+        pub fn set_start_byte(&mut self, value: u32)  {
+          self.builder.set_data_field::<u32>(0, value);
+        }
+        #[inline]
+        pub fn get_end_byte(self) -> u32 {
+          self.builder.get_data_field::<u32>(1)
+        }
+        #[inline]
+        pub fn set_end_byte(&mut self, value: u32)  {
+          self.builder.set_data_field::<u32>(1, value);
+        }
       }
 
       pub struct Pipeline { _typeless: ::capnp::any_pointer::Pipeline }
@@ -969,7 +1017,7 @@ pub mod node {
       }
       mod _private {
         use capnp::private::layout;
-        pub const STRUCT_SIZE: layout::StructSize = layout::StructSize { data: 0, pointers: 1 };
+        pub const STRUCT_SIZE: layout::StructSize = layout::StructSize { data: 1, pointers: 1 };
         pub const TYPE_ID: u64 = 0xc2ba_9038_898e_1fa2;
       }
     }
@@ -0,0 +1,534 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::ast;
+use crate::ast::{CodeGeneratorRequest, Id, Node};
+use crate::toposort::{sort_by_dependencies, Component};
+use crate::visit::{self, Visitor};
+
+///
+/// A digest of a node and of everything it depends on. See `SchemaGraph::fingerprint`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Fingerprint([u8; 16]);
+
+impl Fingerprint {
+    pub fn bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Collects the ids a node refers to.
+///
+#[derive(Default)]
+struct References(BTreeSet<Id>);
+
+impl<'ast> Visitor<'ast> for References {
+    fn visit_field(&mut self, field: &'ast ast::Field) {
+        if let ast::field::Which::Group(id) = field.which() {
+            self.0.insert(*id);
+        }
+        visit::walk_field(self, field);
+    }
+
+    fn visit_method(&mut self, method: &'ast ast::Method) {
+        self.0.insert(method.param_struct_type());
+        self.0.insert(method.result_struct_type());
+        visit::walk_method(self, method);
+    }
+
+    fn visit_superclass(&mut self, superclass: &'ast ast::Superclass) {
+        self.0.insert(superclass.id());
+        visit::walk_superclass(self, superclass);
+    }
+
+    fn visit_annotation(&mut self, annotation: &'ast ast::Annotation) {
+        self.0.insert(annotation.id());
+        visit::walk_annotation(self, annotation);
+    }
+
+    fn visit_type(&mut self, type_: &'ast ast::Type) {
+        match type_ {
+            ast::Type::Enum { type_id, .. } | ast::Type::Struct { type_id, .. } | ast::Type::Interface { type_id, .. } => {
+                self.0.insert(*type_id);
+            },
+            _ => ()
+        }
+        visit::walk_type(self, type_);
+    }
+
+    fn visit_brand(&mut self, brand: &'ast ast::Brand) {
+        for scope in brand.scopes() {
+            self.0.insert(scope.scope_id());
+        }
+        visit::walk_brand(self, brand);
+    }
+}
+
+///
+/// An index over the nodes of a `CodeGeneratorRequest`.
+///
+/// Nodes are linked to their parent through `scope_id`. Note that a node's children include
+/// groups and implicit method parameter structs, which are not listed in its `nested_nodes`.
+///
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct SchemaGraph {
+    nodes: Vec<Node>,
+    index: HashMap<Id, usize>,
+    names: HashMap<Id, String>,
+    children: HashMap<Id, Vec<Id>>
+}
+
+impl SchemaGraph {
+    pub fn new(cgr: &CodeGeneratorRequest) -> SchemaGraph {
+        let mut graph = SchemaGraph {
+            nodes: cgr.nodes().clone(),
+            index: HashMap::new(),
+            names: HashMap::new(),
+            children: HashMap::new()
+        };
+
+        for (i, node) in graph.nodes.iter().enumerate() {
+            graph.index.insert(node.id(), i);
+            graph.children.entry(node.scope_id()).or_insert_with(Vec::new).push(node.id());
+
+            // Groups and parameter structs aren't nested nodes; their display name ends in their name.
+            graph.names.entry(node.id())
+                .or_insert_with(|| String::from(node.display_name().get(node.display_name_prefix_length()..).unwrap_or("")));
+
+            for nested_node in node.nested_nodes() {
+                graph.names.insert(nested_node.id(), nested_node.name().clone());
+            }
+        }
+
+        graph
+    }
+
+    pub fn node(&self, id: Id) -> Option<&Node> {
+        self.index.get(&id).map(|i| &self.nodes[*i])
+    }
+
+    pub fn contains(&self, id: Id) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    ///
+    /// All nodes, in the order of the request.
+    ///
+    pub fn nodes(&self) -> &Vec<Node> {
+        &self.nodes
+    }
+
+    pub fn files(&self) -> Vec<&Node> {
+        self.nodes.iter().filter(|node| node.which() == &ast::node::Which::File).collect()
+    }
+
+    ///
+    /// The unqualified name of a node. For files this is the part of the path after the last '/'.
+    ///
+    pub fn name(&self, id: Id) -> Option<&String> {
+        self.names.get(&id)
+    }
+
+    pub fn parent(&self, id: Id) -> Option<&Node> {
+        self.node(id).and_then(|node| self.node(node.scope_id()))
+    }
+
+    ///
+    /// Every node whose scope is `id`, in the order of the request.
+    ///
+    pub fn children(&self, id: Id) -> Vec<&Node> {
+        match self.children.get(&id) {
+            Some(ids) => ids.iter().filter_map(|child_id| self.node(*child_id)).collect(),
+            None => vec!()
+        }
+    }
+
+    ///
+    /// The nodes declared inside `id`, in declaration order. Nested nodes that are missing from
+    /// the request (e.g. declarations in files that weren't imported) are skipped.
+    ///
+    pub fn nested_nodes(&self, id: Id) -> Vec<&Node> {
+        match self.node(id) {
+            Some(node) => node.nested_nodes().iter().filter_map(|nested| self.node(nested.id())).collect(),
+            None => vec!()
+        }
+    }
+
+    ///
+    /// The nodes on the path from the file down to `id`, both included.
+    ///
+    pub fn ancestry(&self, id: Id) -> Vec<&Node> {
+        let mut path = vec!();
+        let mut current = self.node(id);
+        while let Some(node) = current {
+            path.push(node);
+            if node.which() == &ast::node::Which::File {
+                break;
+            }
+            current = self.node(node.scope_id());
+        }
+        path.reverse();
+        path
+    }
+
+    pub fn file_of(&self, id: Id) -> Option<&Node> {
+        self.ancestry(id).into_iter().next().filter(|node| node.which() == &ast::node::Which::File)
+    }
+
+    ///
+    /// The names of the nodes from the file (excluded) down to `id`, e.g. `["Outer", "Inner"]`.
+    ///
+    pub fn qualified_name(&self, id: Id) -> Option<Vec<String>> {
+        if !self.contains(id) {
+            return None;
+        }
+        self.ancestry(id)
+            .iter()
+            .filter(|node| node.which() != &ast::node::Which::File)
+            .map(|node| self.name(node.id()).cloned())
+            .collect()
+    }
+
+    ///
+    /// The capnp name of a node, in the same form as its display name: `dir/file.capnp:Outer.Inner`.
+    ///
+    pub fn fully_qualified_name(&self, id: Id) -> Option<String> {
+        let file = self.file_of(id)?;
+        let qualified_name = self.qualified_name(id)?;
+        if qualified_name.is_empty() {
+            Some(file.display_name().clone())
+        } else {
+            Some(format!("{}:{}", file.display_name(), qualified_name.join(".")))
+        }
+    }
+
+    ///
+    /// The nodes that `id` refers to: its nested nodes and groups, the types of its fields,
+    /// constants and annotations, its methods' parameter structs, its superclasses, the
+    /// annotations applied to it and the scopes of any brands. Sorted by id, without nodes
+    /// that are missing from the request. Includes `id` only if the node refers to itself.
+    ///
+    pub fn dependencies(&self, id: Id) -> Vec<Id> {
+        let node = match self.node(id) {
+            Some(node) => node,
+            None => return vec!()
+        };
+        let mut references = References::default();
+        references.0.extend(node.nested_nodes().iter().map(|nested| nested.id()));
+        references.visit_node(node);
+        references.0.into_iter().filter(|dependency| self.contains(*dependency)).collect()
+    }
+
+    ///
+    /// Every node reachable from `id` through `dependencies`, sorted by id. Includes `id` only
+    /// if it's part of a cycle, e.g. a struct with a list of itself.
+    ///
+    pub fn transitive_dependencies(&self, id: Id) -> Vec<Id> {
+        let mut reached = BTreeSet::new();
+        let mut pending = self.dependencies(id);
+        while let Some(next) = pending.pop() {
+            if reached.insert(next) {
+                pending.extend(self.dependencies(next));
+            }
+        }
+        reached.into_iter().collect()
+    }
+
+    ///
+    /// The structs and enums that the fields of a struct refer to, including its groups and
+    /// the element types of lists. Sorted by id; empty for anything but a struct.
+    ///
+    pub fn type_dependencies(&self, id: Id) -> Vec<Id> {
+        let mut ids = BTreeSet::new();
+        if let Some(ast::node::Which::Struct { fields, .. }) = self.node(id).map(Node::which) {
+            for field in fields {
+                match field.which() {
+                    ast::field::Which::Group(group_id) => {
+                        ids.insert(*group_id);
+                    },
+                    ast::field::Which::Slot { type_, .. } => struct_and_enum_ids(type_, &mut ids)
+                }
+            }
+        }
+        ids.into_iter().filter(|id| self.contains(*id)).collect()
+    }
+
+    ///
+    /// The structs and enums of the request, each after the ones its fields refer to.
+    /// Mutually recursive structs, and structs that contain themselves, come out as cyclic
+    /// components. Only the dependencies are guaranteed to come first; see `sort_by_dependencies`.
+    ///
+    pub fn dependency_order(&self) -> Vec<Component<Id>> {
+        let ids: Vec<Id> = self.nodes.iter()
+            .filter(|node| matches!(node.which(), ast::node::Which::Struct { .. } | ast::node::Which::Enum(_)))
+            .map(Node::id)
+            .collect();
+        sort_by_dependencies(&ids, |id| self.type_dependencies(*id))
+    }
+
+    ///
+    /// A digest of the node and of every node it transitively depends on. It stays the same
+    /// as long as none of them change, whatever the order of the request's nodes, so it can
+    /// be used to tell which outputs need regenerating. Doc comments aren't part of the graph
+    /// and so aren't covered.
+    ///
+    pub fn fingerprint(&self, id: Id) -> Option<Fingerprint> {
+        let node = self.node(id)?;
+        let mut context = md5::Context::new();
+        context.consume(content(node));
+        for dependency in self.transitive_dependencies(id) {
+            context.consume(dependency.to_le_bytes());
+            context.consume(content(self.node(dependency)?));
+        }
+        Some(Fingerprint(context.compute().0))
+    }
+}
+
+fn struct_and_enum_ids(type_: &ast::Type, ids: &mut BTreeSet<Id>) {
+    match type_ {
+        ast::Type::List(element_type) => struct_and_enum_ids(element_type, ids),
+        ast::Type::Struct { type_id, brand } | ast::Type::Enum { type_id, brand } => {
+            ids.insert(*type_id);
+            struct_and_enum_brand_ids(brand, ids);
+        },
+        ast::Type::Interface { brand, .. } => struct_and_enum_brand_ids(brand, ids),
+        _ => ()
+    }
+}
+
+fn struct_and_enum_brand_ids(brand: &ast::Brand, ids: &mut BTreeSet<Id>) {
+    for scope in brand.scopes() {
+        if let ast::brand::scope::Which::Bind(bindings) = scope.which() {
+            for binding in bindings {
+                if let ast::brand::Binding::Type(type_) = binding {
+                    struct_and_enum_ids(type_, ids);
+                }
+            }
+        }
+    }
+}
+
+///
+/// The bit patterns of the floats in a node's values, in the order they are visited.
+///
+struct FloatBits(Vec<u8>);
+
+impl<'ast> Visitor<'ast> for FloatBits {
+    fn visit_value(&mut self, value: &'ast ast::Value) {
+        match value {
+            ast::Value::Float32(f) => self.0.extend_from_slice(&f.to_bits().to_le_bytes()),
+            ast::Value::Float64(f) => self.0.extend_from_slice(&f.to_bits().to_le_bytes()),
+            _ => visit::walk_value(self, value)
+        }
+    }
+}
+
+///
+/// Everything about a node, serialized. The AST has no maps, so this is deterministic. JSON
+/// writes NaN and the infinities as `null`, so the bits of every float are added as well.
+///
+fn content(node: &Node) -> Vec<u8> {
+    let mut content = serde_json::to_vec(node).expect("The AST has no maps with non-string keys");
+    let mut floats = FloatBits(vec!());
+    floats.visit_node(node);
+    content.extend(floats.0);
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: Id, display_name: &str, prefix_length: usize, scope_id: Id, nested: Vec<(Id, &str)>, which: ast::node::Which) -> Node {
+        Node::new(
+            id,
+            String::from(display_name),
+            prefix_length,
+            scope_id,
+            nested.into_iter().map(|(id, name)| ast::node::NestedNode::new(id, String::from(name))).collect(),
+            vec!(),
+            vec!(),
+            false,
+            which
+        )
+    }
+
+    fn struct_which() -> ast::node::Which {
+        ast::node::Which::Struct {
+            data_word_count: 0,
+            pointer_count: 0,
+            preferred_list_encoding: ast::node::ElementSize::InlineComposite,
+            is_group: false,
+            discriminant_count: 0,
+            discriminant_offset: 0,
+            fields: vec!()
+        }
+    }
+
+    ///
+    /// dir/test.capnp (1)
+    ///   Outer (2)
+    ///     Inner (3)
+    ///     group (4, not a nested node)
+    ///   Color (5)
+    ///
+    fn graph() -> SchemaGraph {
+        SchemaGraph::new(&CodeGeneratorRequest::new(
+            vec!(
+                node(3, "dir/test.capnp:Outer.Inner", 21, 2, vec!(), struct_which()),
+                node(1, "dir/test.capnp", 4, 0, vec!((2, "Outer"), (5, "Color"), (6, "Missing")), ast::node::Which::File),
+                node(2, "dir/test.capnp:Outer", 15, 1, vec!((3, "Inner")), struct_which()),
+                node(4, "dir/test.capnp:Outer.group", 21, 2, vec!(), struct_which()),
+                node(5, "dir/test.capnp:Color", 15, 1, vec!(), ast::node::Which::Enum(vec!()))
+            ),
+            vec!(),
+            HashMap::new()
+        ))
+    }
+
+    #[test]
+    fn test_lookup() {
+        let graph = graph();
+        assert_eq!(graph.node(3).unwrap().display_name(), "dir/test.capnp:Outer.Inner");
+        assert!(graph.node(6).is_none());
+        assert_eq!(graph.files().iter().map(|n| n.id()).collect::<Vec<Id>>(), vec!(1));
+    }
+
+    #[test]
+    fn test_names() {
+        let graph = graph();
+        assert_eq!(graph.name(1), Some(&String::from("test.capnp")));
+        assert_eq!(graph.name(3), Some(&String::from("Inner")));
+        assert_eq!(graph.name(4), Some(&String::from("group")));
+        assert_eq!(graph.name(6), Some(&String::from("Missing")));
+        assert_eq!(graph.name(7), None);
+    }
+
+    #[test]
+    fn test_traversal() {
+        let graph = graph();
+        assert_eq!(graph.parent(3).unwrap().id(), 2);
+        assert!(graph.parent(1).is_none());
+        assert_eq!(graph.children(2).iter().map(|n| n.id()).collect::<Vec<Id>>(), vec!(3, 4));
+        assert_eq!(graph.nested_nodes(2).iter().map(|n| n.id()).collect::<Vec<Id>>(), vec!(3));
+        assert_eq!(graph.nested_nodes(1).iter().map(|n| n.id()).collect::<Vec<Id>>(), vec!(2, 5));
+        assert_eq!(graph.file_of(4).unwrap().id(), 1);
+        assert_eq!(graph.file_of(1).unwrap().id(), 1);
+        assert!(graph.file_of(6).is_none());
+    }
+
+    fn field(name: &str, type_: ast::Type) -> ast::Field {
+        let which = ast::field::Which::Slot { offset: 0, type_: type_, default_value: ast::Value::Void, had_explicit_default: false };
+        ast::Field::new(String::from(name), 0, ast::field::NO_DISCRIMINANT, ast::field::Ordinal::Explicit(0), vec!(), which)
+    }
+
+    ///
+    /// test.capnp (1)
+    ///   Tree (2) with children: List(Tree) and color: Color
+    ///   Color (3)
+    ///   Other (4)
+    ///
+    fn dependent_nodes(colors: Vec<&str>) -> Vec<Node> {
+        let struct_type = |type_id| ast::Type::Struct { type_id: type_id, brand: ast::Brand::default() };
+        let tree = ast::node::Which::Struct {
+            data_word_count: 1,
+            pointer_count: 1,
+            preferred_list_encoding: ast::node::ElementSize::InlineComposite,
+            is_group: false,
+            discriminant_count: 0,
+            discriminant_offset: 0,
+            fields: vec!(
+                field("children", ast::Type::List(Box::new(struct_type(2)))),
+                field("color", ast::Type::Enum { type_id: 3, brand: ast::Brand::default() })
+            )
+        };
+        let enumerants = colors.iter().enumerate().map(|(i, name)| ast::Enumerant::new(String::from(*name), i as u16, vec!())).collect();
+        vec!(
+            node(1, "test.capnp", 0, 0, vec!((2, "Tree"), (3, "Color"), (4, "Other")), ast::node::Which::File),
+            node(2, "test.capnp:Tree", 11, 1, vec!(), tree),
+            node(3, "test.capnp:Color", 11, 1, vec!(), ast::node::Which::Enum(enumerants)),
+            node(4, "test.capnp:Other", 11, 1, vec!(), struct_which())
+        )
+    }
+
+    fn dependent_graph(nodes: Vec<Node>) -> SchemaGraph {
+        SchemaGraph::new(&CodeGeneratorRequest::new(nodes, vec!(), HashMap::new()))
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let graph = dependent_graph(dependent_nodes(vec!("red")));
+        assert_eq!(graph.dependencies(1), vec!(2, 3, 4));
+        assert_eq!(graph.dependencies(2), vec!(2, 3));
+        assert_eq!(graph.transitive_dependencies(2), vec!(2, 3));
+        assert!(graph.transitive_dependencies(3).is_empty());
+        assert!(graph.dependencies(9).is_empty());
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let graph = dependent_graph(dependent_nodes(vec!("red")));
+        assert_eq!(graph.type_dependencies(2), vec!(2, 3));
+        assert!(graph.type_dependencies(1).is_empty());
+
+        let order: Vec<(Vec<Id>, bool)> = graph.dependency_order()
+            .into_iter()
+            .map(|component| (component.members().clone(), component.is_cyclic()))
+            .collect();
+        assert_eq!(order, vec!((vec!(3), false), (vec!(2), true), (vec!(4), false)));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let graph = dependent_graph(dependent_nodes(vec!("red")));
+        let mut reversed = dependent_nodes(vec!("red"));
+        reversed.reverse();
+        let reversed = dependent_graph(reversed);
+        for id in 1..5 {
+            assert_eq!(graph.fingerprint(id), reversed.fingerprint(id));
+        }
+        assert_ne!(graph.fingerprint(2), graph.fingerprint(3));
+        assert_eq!(graph.fingerprint(9), None);
+        assert_eq!(graph.fingerprint(2).unwrap().to_string().len(), 32);
+
+        // A new enumerant changes the enum and everything that uses it, and nothing else.
+        let changed = dependent_graph(dependent_nodes(vec!("red", "green")));
+        assert_ne!(graph.fingerprint(3), changed.fingerprint(3));
+        assert_ne!(graph.fingerprint(2), changed.fingerprint(2));
+        assert_ne!(graph.fingerprint(1), changed.fingerprint(1));
+        assert_eq!(graph.fingerprint(4), changed.fingerprint(4));
+    }
+
+    #[test]
+    fn test_fingerprint_floats() {
+        let fingerprint = |value: f64| {
+            let constant = ast::node::Which::Const { type_: ast::Type::Float64, value: ast::Value::Float64(value) };
+            dependent_graph(vec!(node(1, "test.capnp:limit", 10, 0, vec!(), constant))).fingerprint(1).unwrap()
+        };
+        assert_ne!(fingerprint(f64::NAN), fingerprint(f64::INFINITY));
+        assert_ne!(fingerprint(f64::INFINITY), fingerprint(f64::NEG_INFINITY));
+        assert_ne!(fingerprint(0.0), fingerprint(-0.0));
+        assert_eq!(fingerprint(1.5), fingerprint(1.5));
+    }
+
+    #[test]
+    fn test_qualified_names() {
+        let graph = graph();
+        assert_eq!(graph.qualified_name(3), Some(vec!(String::from("Outer"), String::from("Inner"))));
+        assert_eq!(graph.qualified_name(1), Some(vec!()));
+        assert_eq!(graph.qualified_name(6), None);
+        assert_eq!(graph.fully_qualified_name(4), Some(String::from("dir/test.capnp:Outer.group")));
+        assert_eq!(graph.fully_qualified_name(1), Some(String::from("dir/test.capnp")));
+    }
+}
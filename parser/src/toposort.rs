@@ -0,0 +1,144 @@
+use getset::{Getters, CopyGetters};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+///
+/// Keys that have to be handled together: either a single key, or keys that depend on each
+/// other in a cycle, e.g. mutually recursive structs. Backends need some indirection (a
+/// pointer, a box, a forward declaration) somewhere within a cyclic component.
+///
+#[derive(Clone, Getters, CopyGetters, Debug, PartialEq)]
+pub struct Component<K> {
+    /// In the order the keys were given.
+    #[get = "pub"]
+    members: Vec<K>,
+
+    /// Whether the members depend on each other, or the single member on itself.
+    #[get_copy = "pub"]
+    is_cyclic: bool
+}
+
+///
+/// Orders keys so that each comes after the keys it depends on, grouping cycles into
+/// components. Only that is guaranteed: a dependency can be moved ahead of keys that were
+/// given before it and don't depend on it. The order is the same every time for the same
+/// keys. Dependencies that aren't among the keys are ignored.
+///
+pub fn sort_by_dependencies<K, F, I>(keys: &[K], mut dependencies: F) -> Vec<Component<K>>
+where
+    K: Clone + Eq + Hash,
+    F: FnMut(&K) -> I,
+    I: IntoIterator<Item = K>
+{
+    let positions: HashMap<&K, usize> = keys.iter().enumerate().map(|(i, key)| (key, i)).collect();
+    let edges: Vec<Vec<usize>> = keys.iter()
+        .map(|key| dependencies(key).into_iter().filter_map(|dependency| positions.get(&dependency).cloned()).collect())
+        .collect();
+
+    let mut tarjan = Tarjan {
+        edges: &edges,
+        index: vec!(None; keys.len()),
+        low_link: vec!(0; keys.len()),
+        on_stack: vec!(false; keys.len()),
+        stack: vec!(),
+        next_index: 0,
+        components: vec!()
+    };
+    for key in 0..keys.len() {
+        if tarjan.index[key].is_none() {
+            tarjan.visit(key);
+        }
+    }
+
+    tarjan.components.into_iter()
+        .map(|mut members| {
+            members.sort_unstable();
+            let is_cyclic = members.len() > 1 || edges[members[0]].contains(&members[0]);
+            Component { members: members.into_iter().map(|i| keys[i].clone()).collect(), is_cyclic: is_cyclic }
+        })
+        .collect()
+}
+
+///
+/// Tarjan's strongly connected components algorithm, which finds components in an order
+/// where dependencies come first.
+///
+struct Tarjan<'e> {
+    edges: &'e [Vec<usize>],
+    index: Vec<Option<usize>>,
+    low_link: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    components: Vec<Vec<usize>>
+}
+
+impl<'e> Tarjan<'e> {
+    fn visit(&mut self, key: usize) {
+        self.index[key] = Some(self.next_index);
+        self.low_link[key] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(key);
+        self.on_stack[key] = true;
+
+        for &dependency in &self.edges[key] {
+            match self.index[dependency] {
+                None => {
+                    self.visit(dependency);
+                    self.low_link[key] = self.low_link[key].min(self.low_link[dependency]);
+                },
+                Some(index) if self.on_stack[dependency] => self.low_link[key] = self.low_link[key].min(index),
+                Some(_) => ()
+            }
+        }
+
+        if Some(self.low_link[key]) == self.index[key] {
+            let mut component = vec!();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == key {
+                    break;
+                }
+            }
+            self.components.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sort(keys: &[&'static str], edges: &[(&'static str, &'static str)]) -> Vec<(Vec<&'static str>, bool)> {
+        sort_by_dependencies(keys, |key| edges.iter().filter(|(from, _)| from == key).map(|(_, to)| *to).collect::<Vec<_>>())
+            .into_iter()
+            .map(|component| (component.members().clone(), component.is_cyclic()))
+            .collect()
+    }
+
+    #[test]
+    fn test_order() {
+        assert_eq!(
+            sort(&["a", "b", "c", "d"], &[("a", "c"), ("c", "d"), ("b", "missing")]),
+            vec!((vec!("d"), false), (vec!("c"), false), (vec!("a"), false), (vec!("b"), false))
+        );
+    }
+
+    #[test]
+    fn test_dependency_moves_ahead_of_independent_keys() {
+        assert_eq!(
+            sort(&["p", "q", "r"], &[("p", "r")]),
+            vec!((vec!("r"), false), (vec!("p"), false), (vec!("q"), false))
+        );
+    }
+
+    #[test]
+    fn test_cycles() {
+        assert_eq!(
+            sort(&["tree", "a", "b", "c", "user"], &[("tree", "tree"), ("b", "a"), ("a", "c"), ("c", "b"), ("user", "a")]),
+            vec!((vec!("tree"), true), (vec!("a", "b", "c"), true), (vec!("user"), false))
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//!
+//! Read-only traversal of a request. Implement `Visitor`, override the hooks for the parts
+//! of the tree you care about, and call the matching `walk_*` function from an override to
+//! keep descending.
+//!
+
+use crate::ast;
+use crate::ast::{CodeGeneratorRequest, Node};
+
+pub trait Visitor<'ast> {
+    fn visit_request(&mut self, cgr: &'ast CodeGeneratorRequest) {
+        walk_request(self, cgr)
+    }
+
+    fn visit_node(&mut self, node: &'ast Node) {
+        walk_node(self, node)
+    }
+
+    fn visit_field(&mut self, field: &'ast ast::Field) {
+        walk_field(self, field)
+    }
+
+    fn visit_enumerant(&mut self, enumerant: &'ast ast::Enumerant) {
+        walk_enumerant(self, enumerant)
+    }
+
+    fn visit_method(&mut self, method: &'ast ast::Method) {
+        walk_method(self, method)
+    }
+
+    fn visit_superclass(&mut self, superclass: &'ast ast::Superclass) {
+        walk_superclass(self, superclass)
+    }
+
+    fn visit_annotation(&mut self, annotation: &'ast ast::Annotation) {
+        walk_annotation(self, annotation)
+    }
+
+    fn visit_type(&mut self, type_: &'ast ast::Type) {
+        walk_type(self, type_)
+    }
+
+    fn visit_brand(&mut self, brand: &'ast ast::Brand) {
+        walk_brand(self, brand)
+    }
+
+    fn visit_value(&mut self, value: &'ast ast::Value) {
+        walk_value(self, value)
+    }
+}
+
+///
+/// Visits every node. Groups and method parameter structs are nodes of their own, so
+/// they're reached here rather than through the fields and methods that refer to them.
+///
+pub fn walk_request<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, cgr: &'ast CodeGeneratorRequest) {
+    for node in cgr.nodes() {
+        visitor.visit_node(node);
+    }
+}
+
+pub fn walk_node<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, node: &'ast Node) {
+    for annotation in node.annotations() {
+        visitor.visit_annotation(annotation);
+    }
+    match node.which() {
+        ast::node::Which::File => (),
+        ast::node::Which::Struct { fields, .. } => {
+            for field in fields {
+                visitor.visit_field(field);
+            }
+        },
+        ast::node::Which::Enum(enumerants) => {
+            for enumerant in enumerants {
+                visitor.visit_enumerant(enumerant);
+            }
+        },
+        ast::node::Which::Interface { methods, superclasses } => {
+            for superclass in superclasses {
+                visitor.visit_superclass(superclass);
+            }
+            for method in methods {
+                visitor.visit_method(method);
+            }
+        },
+        ast::node::Which::Const { type_, value } => {
+            visitor.visit_type(type_);
+            visitor.visit_value(value);
+        },
+        ast::node::Which::Annotation { type_, .. } => visitor.visit_type(type_)
+    }
+}
+
+pub fn walk_field<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, field: &'ast ast::Field) {
+    for annotation in field.annotations() {
+        visitor.visit_annotation(annotation);
+    }
+    if let ast::field::Which::Slot { type_, default_value, .. } = field.which() {
+        visitor.visit_type(type_);
+        visitor.visit_value(default_value);
+    }
+}
+
+pub fn walk_enumerant<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, enumerant: &'ast ast::Enumerant) {
+    for annotation in enumerant.annotations() {
+        visitor.visit_annotation(annotation);
+    }
+}
+
+pub fn walk_method<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, method: &'ast ast::Method) {
+    visitor.visit_brand(method.param_brand());
+    visitor.visit_brand(method.result_brand());
+    for annotation in method.annotations() {
+        visitor.visit_annotation(annotation);
+    }
+}
+
+pub fn walk_superclass<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, superclass: &'ast ast::Superclass) {
+    visitor.visit_brand(superclass.brand());
+}
+
+pub fn walk_annotation<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, annotation: &'ast ast::Annotation) {
+    visitor.visit_value(annotation.value());
+}
+
+pub fn walk_type<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, type_: &'ast ast::Type) {
+    match type_ {
+        ast::Type::List(element_type) => visitor.visit_type(element_type),
+        ast::Type::Enum { brand, .. } | ast::Type::Struct { brand, .. } | ast::Type::Interface { brand, .. } => visitor.visit_brand(brand),
+        _ => ()
+    }
+}
+
+pub fn walk_brand<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, brand: &'ast ast::Brand) {
+    for scope in brand.scopes() {
+        if let ast::brand::scope::Which::Bind(bindings) = scope.which() {
+            for binding in bindings {
+                if let ast::brand::Binding::Type(type_) = binding {
+                    visitor.visit_type(type_);
+                }
+            }
+        }
+    }
+}
+
+pub fn walk_value<'ast, V: Visitor<'ast> + ?Sized>(visitor: &mut V, value: &'ast ast::Value) {
+    match value {
+        ast::Value::List(values) => {
+            for value in values {
+                visitor.visit_value(value);
+            }
+        },
+        ast::Value::Struct(fields) => {
+            for field in fields {
+                visitor.visit_value(field.value());
+            }
+        },
+        _ => ()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::ast::Id;
+
+    fn request() -> CodeGeneratorRequest {
+        let list_of_shapes = ast::Type::List(Box::new(ast::Type::Struct { type_id: 2, brand: ast::Brand::default() }));
+        let fields = vec!(
+            ast::Field::new(
+                String::from("shapes"),
+                0,
+                ast::field::NO_DISCRIMINANT,
+                ast::field::Ordinal::Explicit(0),
+                vec!(ast::Annotation::new(3, ast::Value::Text(String::from("note")))),
+                ast::field::Which::Slot {
+                    offset: 0,
+                    type_: list_of_shapes,
+                    default_value: ast::Value::List(vec!(ast::Value::Struct(vec!()))),
+                    had_explicit_default: true
+                }
+            ),
+            ast::Field::new(String::from("extra"), 1, ast::field::NO_DISCRIMINANT, ast::field::Ordinal::Implicit, vec!(), ast::field::Which::Group(4))
+        );
+        let node = |id: Id, which: ast::node::Which| Node::new(id, format!("test.capnp:N{}", id), 10, 1, vec!(), vec!(), vec!(), false, which);
+        CodeGeneratorRequest::new(
+            vec!(
+                node(1, ast::node::Which::File),
+                node(2, ast::node::Which::Struct {
+                    data_word_count: 0,
+                    pointer_count: 1,
+                    preferred_list_encoding: ast::node::ElementSize::Pointer,
+                    is_group: false,
+                    discriminant_count: 0,
+                    discriminant_offset: 0,
+                    fields: fields
+                }),
+                node(3, ast::node::Which::Annotation { type_: ast::Type::Text, targets: vec!(ast::node::AnnotationTarget::Field) })
+            ),
+            vec!(),
+            HashMap::new()
+        )
+    }
+
+    #[derive(Default)]
+    struct Counter<'ast> {
+        fields: Vec<&'ast String>,
+        struct_types: Vec<Id>,
+        values: usize
+    }
+
+    impl<'ast> Visitor<'ast> for Counter<'ast> {
+        fn visit_field(&mut self, field: &'ast ast::Field) {
+            self.fields.push(field.name());
+            walk_field(self, field);
+        }
+
+        fn visit_type(&mut self, type_: &'ast ast::Type) {
+            if let ast::Type::Struct { type_id, .. } = type_ {
+                self.struct_types.push(*type_id);
+            }
+            walk_type(self, type_);
+        }
+
+        fn visit_value(&mut self, value: &'ast ast::Value) {
+            self.values += 1;
+            walk_value(self, value);
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let cgr = request();
+        let mut counter = Counter::default();
+        counter.visit_request(&cgr);
+        assert_eq!(counter.fields, vec!("shapes", "extra"));
+        assert_eq!(counter.struct_types, vec!(2));
+        // The annotation's text, the default list and the struct in it.
+        assert_eq!(counter.values, 3);
+    }
+
+    #[test]
+    fn test_overrides_stop_descent() {
+        struct Fields(usize);
+
+        impl<'ast> Visitor<'ast> for Fields {
+            fn visit_node(&mut self, node: &'ast Node) {
+                if node.id() != 2 {
+                    walk_node(self, node);
+                }
+            }
+
+            fn visit_field(&mut self, _: &'ast ast::Field) {
+                self.0 += 1;
+            }
+        }
+
+        let mut fields = Fields(0);
+        fields.visit_request(&request());
+        assert_eq!(fields.0, 0);
+    }
+}
@@ -0,0 +1,813 @@
+use std::collections::HashMap;
+
+use capnp::private::layout::{ElementSize, PointerBuilder, StructBuilder, StructSize};
+use capnp::traits::FromPointerBuilder;
+
+use crate::ast;
+use crate::schema_capnp;
+use crate::{default_bits, Error, Framing, Result};
+
+///
+/// The nodes of the request being written. Like when parsing, list and struct values
+/// can only be encoded once the layout of their struct types is known.
+///
+struct Context<'a> {
+    nodes: HashMap<ast::Id, &'a ast::Node>
+}
+
+impl<'a> Context<'a> {
+    fn new(cgr: &'a ast::CodeGeneratorRequest) -> Context<'a> {
+        Context {
+            nodes: cgr.nodes().iter().map(|node| (node.id(), node)).collect()
+        }
+    }
+
+    fn get_annotation_type(&self, id: ast::Id) -> Option<&ast::Type> {
+        match self.nodes.get(&id).map(|node| node.which()) {
+            Some(ast::node::Which::Annotation { type_, .. }) => Some(type_),
+            _ => None
+        }
+    }
+
+    fn get_struct_size(&self, type_id: ast::Id) -> StructSize {
+        match self.nodes.get(&type_id).map(|node| node.which()) {
+            Some(ast::node::Which::Struct { data_word_count, pointer_count, .. }) => StructSize { data: *data_word_count, pointers: *pointer_count },
+            _ => StructSize { data: 0, pointers: 0 }
+        }
+    }
+}
+
+trait WriteTo<B> {
+    fn write_to(&self, ctx: &Context, builder: B) -> Result<()>;
+}
+
+fn mismatch(value: &ast::Value, value_type: Option<&ast::Type>) -> Error {
+    Error::Invalid(format!("Value {:?} does not match its type {:?}", value, value_type))
+}
+
+impl WriteTo<schema_capnp::type_::Builder<'_>> for ast::Type {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::type_::Builder<'_>) -> Result<()> {
+        match self {
+            ast::Type::Void => builder.set_void(()),
+            ast::Type::Bool => builder.set_bool(()),
+            ast::Type::Int8 => builder.set_int8(()),
+            ast::Type::Int16 => builder.set_int16(()),
+            ast::Type::Int32 => builder.set_int32(()),
+            ast::Type::Int64 => builder.set_int64(()),
+            ast::Type::Uint8 => builder.set_uint8(()),
+            ast::Type::Uint16 => builder.set_uint16(()),
+            ast::Type::Uint32 => builder.set_uint32(()),
+            ast::Type::Uint64 => builder.set_uint64(()),
+            ast::Type::Float32 => builder.set_float32(()),
+            ast::Type::Float64 => builder.set_float64(()),
+            ast::Type::Text => builder.set_text(()),
+            ast::Type::Data => builder.set_data(()),
+            ast::Type::List(element_type) => element_type.write_to(ctx, builder.init_list().init_element_type())?,
+            ast::Type::Enum { type_id, brand } => {
+                let mut e = builder.init_enum();
+                e.set_type_id(*type_id);
+                brand.write_to(ctx, e.init_brand())?
+            },
+            ast::Type::Struct { type_id, brand } => {
+                let mut s = builder.init_struct();
+                s.set_type_id(*type_id);
+                brand.write_to(ctx, s.init_brand())?
+            },
+            ast::Type::Interface { type_id, brand } => {
+                let mut i = builder.init_interface();
+                i.set_type_id(*type_id);
+                brand.write_to(ctx, i.init_brand())?
+            },
+            ast::Type::AnyPointer(p) => p.write_to(ctx, builder.init_any_pointer())?
+        }
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::type_::any_pointer::Builder<'_>> for ast::type_::AnyPointer {
+    fn write_to(&self, _ctx: &Context, builder: schema_capnp::type_::any_pointer::Builder<'_>) -> Result<()> {
+        match self {
+            ast::type_::AnyPointer::Unconstrained(u) => {
+                let mut unconstrained = builder.init_unconstrained();
+                match u {
+                    ast::type_::Unconstrained::AnyKind => unconstrained.set_any_kind(()),
+                    ast::type_::Unconstrained::Struct => unconstrained.set_struct(()),
+                    ast::type_::Unconstrained::List => unconstrained.set_list(()),
+                    ast::type_::Unconstrained::Capability => unconstrained.set_capability(())
+                }
+            },
+            ast::type_::AnyPointer::Parameter { scope_id, parameter_index } => {
+                let mut parameter = builder.init_parameter();
+                parameter.set_scope_id(*scope_id);
+                parameter.set_parameter_index(*parameter_index);
+            },
+            ast::type_::AnyPointer::ImplicitMethodParameter { parameter_index } => {
+                builder.init_implicit_method_parameter().set_parameter_index(*parameter_index);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::brand::binding::Builder<'_>> for ast::brand::Binding {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::brand::binding::Builder<'_>) -> Result<()> {
+        match self {
+            ast::brand::Binding::Unbound => builder.set_unbound(()),
+            ast::brand::Binding::Type(t) => t.write_to(ctx, builder.init_type())?
+        }
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::brand::scope::Builder<'_>> for ast::brand::Scope {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::brand::scope::Builder<'_>) -> Result<()> {
+        builder.set_scope_id(self.scope_id());
+        match self.which() {
+            ast::brand::scope::Which::Bind(bindings) => {
+                let mut list = builder.init_bind(bindings.len() as u32);
+                for (i, binding) in bindings.iter().enumerate() {
+                    binding.write_to(ctx, list.reborrow().get(i as u32))?;
+                }
+            },
+            ast::brand::scope::Which::Inherit => builder.set_inherit(())
+        }
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::brand::Builder<'_>> for ast::Brand {
+    fn write_to(&self, ctx: &Context, builder: schema_capnp::brand::Builder<'_>) -> Result<()> {
+        let mut scopes = builder.init_scopes(self.scopes().len() as u32);
+        for (i, scope) in self.scopes().iter().enumerate() {
+            scope.write_to(ctx, scopes.reborrow().get(i as u32))?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Gives access to the raw pointer behind an `any_pointer::Builder`; the counterpart of `RawPointer`.
+///
+struct RawPointerBuilder<'a> {
+    builder: PointerBuilder<'a>
+}
+
+impl<'a> FromPointerBuilder<'a> for RawPointerBuilder<'a> {
+    fn init_pointer(builder: PointerBuilder<'a>, _size: u32) -> RawPointerBuilder<'a> {
+        RawPointerBuilder { builder: builder }
+    }
+
+    fn get_from_pointer(builder: PointerBuilder<'a>, _default: Option<&'a [capnp::Word]>) -> capnp::Result<RawPointerBuilder<'a>> {
+        Ok(RawPointerBuilder { builder: builder })
+    }
+}
+
+fn encode_primitive_list<T, F>(pointer: PointerBuilder, values: &[ast::Value], element_type: &ast::Type, from_value: F) -> Result<()>
+    where T: capnp::private::layout::PrimitiveElement, F: Fn(&ast::Value) -> Option<T>
+{
+    let mut list: capnp::primitive_list::Builder<T> = FromPointerBuilder::init_pointer(pointer, values.len() as u32);
+    for (i, value) in values.iter().enumerate() {
+        match from_value(value) {
+            Some(v) => list.set(i as u32, v),
+            None => return Err(mismatch(value, Some(element_type)))
+        }
+    }
+    Ok(())
+}
+
+fn encode_list(ctx: &Context, pointer: PointerBuilder, values: &[ast::Value], element_type: &ast::Type) -> Result<()> {
+    let len = values.len() as u32;
+    match element_type {
+        ast::Type::Void => { pointer.init_list(ElementSize::Void, len); },
+        ast::Type::Bool => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Bool(b) = v { Some(*b) } else { None })?,
+        ast::Type::Int8 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Int8(i) = v { Some(*i) } else { None })?,
+        ast::Type::Int16 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Int16(i) = v { Some(*i) } else { None })?,
+        ast::Type::Int32 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Int32(i) = v { Some(*i) } else { None })?,
+        ast::Type::Int64 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Int64(i) = v { Some(*i) } else { None })?,
+        ast::Type::Uint8 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Uint8(i) = v { Some(*i) } else { None })?,
+        ast::Type::Uint16 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Uint16(i) = v { Some(*i) } else { None })?,
+        ast::Type::Uint32 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Uint32(i) = v { Some(*i) } else { None })?,
+        ast::Type::Uint64 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Uint64(i) = v { Some(*i) } else { None })?,
+        ast::Type::Float32 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Float32(f) = v { Some(*f) } else { None })?,
+        ast::Type::Float64 => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Float64(f) = v { Some(*f) } else { None })?,
+        ast::Type::Enum { .. } => encode_primitive_list(pointer, values, element_type, |v| if let ast::Value::Enum(e) = v { Some(*e) } else { None })?,
+        ast::Type::Text => {
+            let mut list: capnp::text_list::Builder = FromPointerBuilder::init_pointer(pointer, len);
+            for (i, value) in values.iter().enumerate() {
+                match value {
+                    ast::Value::Text(t) => list.set(i as u32, t),
+                    _ => return Err(mismatch(value, Some(element_type)))
+                }
+            }
+        },
+        ast::Type::Data => {
+            let mut list: capnp::data_list::Builder = FromPointerBuilder::init_pointer(pointer, len);
+            for (i, value) in values.iter().enumerate() {
+                match value {
+                    ast::Value::Data(d) => list.set(i as u32, d),
+                    _ => return Err(mismatch(value, Some(element_type)))
+                }
+            }
+        },
+        ast::Type::Struct { type_id, .. } => {
+            let mut list = pointer.init_struct_list(len, ctx.get_struct_size(*type_id));
+            for (i, value) in values.iter().enumerate() {
+                encode_struct(ctx, &list.reborrow().get_struct_element(i as u32), value, *type_id)?;
+            }
+        },
+        ast::Type::List(_) | ast::Type::Interface { .. } | ast::Type::AnyPointer(_) => {
+            let mut list = pointer.init_list(ElementSize::Pointer, len);
+            for (i, value) in values.iter().enumerate() {
+                encode_pointer(ctx, list.reborrow().get_pointer_element(i as u32), value, element_type)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn encode_pointer(ctx: &Context, pointer: PointerBuilder, value: &ast::Value, value_type: &ast::Type) -> Result<()> {
+    match (value, value_type) {
+        (ast::Value::Text(t), ast::Type::Text) => pointer.set_text(t),
+        (ast::Value::Data(d), ast::Type::Data) => pointer.set_data(d),
+        (ast::Value::List(values), ast::Type::List(element_type)) => encode_list(ctx, pointer, values, element_type)?,
+        (ast::Value::Struct(_), ast::Type::Struct { type_id, .. }) => {
+            encode_struct(ctx, &pointer.init_struct(ctx.get_struct_size(*type_id)), value, *type_id)?
+        },
+        // Capabilities and untyped pointers aren't kept in the AST, and are left null.
+        (ast::Value::Interface, ast::Type::Interface { .. }) | (ast::Value::AnyPointer, ast::Type::AnyPointer(_)) | (ast::Value::Unknown, _) => (),
+        _ => return Err(mismatch(value, Some(value_type)))
+    }
+    Ok(())
+}
+
+fn encode_slot(ctx: &Context, builder: &StructBuilder, offset: usize, slot_type: &ast::Type, default: &ast::Value, value: &ast::Value) -> Result<()> {
+    let bits = default_bits(default);
+
+    match (slot_type, value) {
+        (ast::Type::Void, ast::Value::Void) => (),
+        (ast::Type::Bool, ast::Value::Bool(b)) => builder.set_bool_field(offset, *b ^ (bits != 0)),
+        (ast::Type::Int8, ast::Value::Int8(i)) => builder.set_data_field::<u8>(offset, *i as u8 ^ bits as u8),
+        (ast::Type::Int16, ast::Value::Int16(i)) => builder.set_data_field::<u16>(offset, *i as u16 ^ bits as u16),
+        (ast::Type::Int32, ast::Value::Int32(i)) => builder.set_data_field::<u32>(offset, *i as u32 ^ bits as u32),
+        (ast::Type::Int64, ast::Value::Int64(i)) => builder.set_data_field::<u64>(offset, *i as u64 ^ bits),
+        (ast::Type::Uint8, ast::Value::Uint8(i)) => builder.set_data_field::<u8>(offset, *i ^ bits as u8),
+        (ast::Type::Uint16, ast::Value::Uint16(i)) => builder.set_data_field::<u16>(offset, *i ^ bits as u16),
+        (ast::Type::Uint32, ast::Value::Uint32(i)) => builder.set_data_field::<u32>(offset, *i ^ bits as u32),
+        (ast::Type::Uint64, ast::Value::Uint64(i)) => builder.set_data_field::<u64>(offset, *i ^ bits),
+        (ast::Type::Float32, ast::Value::Float32(f)) => builder.set_data_field::<u32>(offset, f.to_bits() ^ bits as u32),
+        (ast::Type::Float64, ast::Value::Float64(f)) => builder.set_data_field::<u64>(offset, f.to_bits() ^ bits),
+        (ast::Type::Enum { .. }, ast::Value::Enum(e)) => builder.set_data_field::<u16>(offset, *e ^ bits as u16),
+        (ast::Type::Text, _) | (ast::Type::Data, _) | (ast::Type::List(_), _) | (ast::Type::Struct { .. }, _)
+            | (ast::Type::Interface { .. }, _) | (ast::Type::AnyPointer(_), _) => {
+            encode_pointer(ctx, builder.get_pointer_field(offset), value, slot_type)?
+        },
+        _ => return Err(mismatch(value, Some(slot_type)))
+    }
+    Ok(())
+}
+
+fn encode_struct_fields(ctx: &Context, builder: &StructBuilder, values: &[ast::value::StructField], discriminant_offset: u32, fields: &[ast::Field]) -> Result<()> {
+    for value in values {
+        let field = match fields.iter().find(|field| field.name() == value.name()) {
+            Some(field) => field,
+            None => return Err(Error::Invalid(format!("Struct value has no field named {}", value.name())))
+        };
+
+        if field.discriminant_value() != ast::field::NO_DISCRIMINANT {
+            builder.set_data_field::<u16>(discriminant_offset as usize, field.discriminant_value());
+        }
+
+        match field.which() {
+            ast::field::Which::Slot { offset, type_, default_value, .. } => encode_slot(ctx, builder, *offset as usize, type_, default_value, value.value())?,
+            ast::field::Which::Group(type_id) => encode_struct(ctx, builder, value.value(), *type_id)?
+        }
+    }
+    Ok(())
+}
+
+fn encode_struct(ctx: &Context, builder: &StructBuilder, value: &ast::Value, type_id: ast::Id) -> Result<()> {
+    match (value, ctx.nodes.get(&type_id).map(|node| node.which())) {
+        (ast::Value::Struct(values), Some(ast::node::Which::Struct { discriminant_offset, fields, .. })) => {
+            encode_struct_fields(ctx, builder, values, *discriminant_offset, fields)
+        },
+        (ast::Value::Unknown, _) => Ok(()),
+        _ => Err(Error::Invalid(format!("Value {:?} does not match struct type {}", value, type_id)))
+    }
+}
+
+///
+/// Values are written against their schema type, the inverse of `ast::Value::parse`.
+/// `Value::Unknown` is written as a null pointer. That only parses back to `Value::Unknown`
+/// when the type isn't in the request: a null struct pointer of a known type parses as a
+/// struct of default field values, and a null list pointer as an empty list.
+///
+fn encode_value(ctx: &Context, value: &ast::Value, value_type: Option<&ast::Type>, mut builder: schema_capnp::value::Builder<'_>) -> Result<()> {
+    match value {
+        ast::Value::Void => builder.set_void(()),
+        ast::Value::Bool(b) => builder.set_bool(*b),
+        ast::Value::Int8(i) => builder.set_int8(*i),
+        ast::Value::Int16(i) => builder.set_int16(*i),
+        ast::Value::Int32(i) => builder.set_int32(*i),
+        ast::Value::Int64(i) => builder.set_int64(*i),
+        ast::Value::Uint8(i) => builder.set_uint8(*i),
+        ast::Value::Uint16(i) => builder.set_uint16(*i),
+        ast::Value::Uint32(i) => builder.set_uint32(*i),
+        ast::Value::Uint64(i) => builder.set_uint64(*i),
+        ast::Value::Float32(f) => builder.set_float32(*f),
+        ast::Value::Float64(f) => builder.set_float64(*f),
+        ast::Value::Text(t) => builder.set_text(t),
+        ast::Value::Data(d) => builder.set_data(d),
+        ast::Value::Enum(e) => builder.set_enum(*e),
+        ast::Value::Interface => builder.set_interface(()),
+        ast::Value::AnyPointer => { builder.init_any_pointer(); },
+        ast::Value::List(values) => match value_type {
+            Some(ast::Type::List(element_type)) => {
+                let pointer = builder.init_list().init_as::<RawPointerBuilder>().builder;
+                encode_list(ctx, pointer, values, element_type)?
+            },
+            _ => return Err(mismatch(value, value_type))
+        },
+        ast::Value::Struct(_) => match value_type {
+            Some(ast::Type::Struct { type_id, .. }) => {
+                let pointer = builder.init_struct().init_as::<RawPointerBuilder>().builder;
+                encode_struct(ctx, &pointer.init_struct(ctx.get_struct_size(*type_id)), value, *type_id)?
+            },
+            _ => return Err(mismatch(value, value_type))
+        },
+        ast::Value::Unknown => match value_type {
+            Some(ast::Type::Struct { .. }) => { builder.init_struct(); },
+            _ => { builder.init_list(); }
+        }
+    }
+    Ok(())
+}
+
+impl WriteTo<schema_capnp::annotation::Builder<'_>> for ast::Annotation {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::annotation::Builder<'_>) -> Result<()> {
+        builder.set_id(self.id());
+        encode_value(ctx, self.value(), ctx.get_annotation_type(self.id()), builder.init_value())
+    }
+}
+
+fn write_annotations(ctx: &Context, annotations: &[ast::Annotation], mut builder: capnp::struct_list::Builder<'_, schema_capnp::annotation::Owned>) -> Result<()> {
+    for (i, annotation) in annotations.iter().enumerate() {
+        annotation.write_to(ctx, builder.reborrow().get(i as u32))?;
+    }
+    Ok(())
+}
+
+impl WriteTo<schema_capnp::node::parameter::Builder<'_>> for ast::node::Parameter {
+    fn write_to(&self, _ctx: &Context, mut builder: schema_capnp::node::parameter::Builder<'_>) -> Result<()> {
+        builder.set_name(self.name());
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::field::Builder<'_>> for ast::Field {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::field::Builder<'_>) -> Result<()> {
+        builder.set_name(self.name());
+        builder.set_code_order(self.code_order());
+        builder.set_discriminant_value(self.discriminant_value());
+        match self.ordinal() {
+            ast::field::Ordinal::Implicit => builder.reborrow().init_ordinal().set_implicit(()),
+            ast::field::Ordinal::Explicit(n) => builder.reborrow().init_ordinal().set_explicit(n)
+        }
+        write_annotations(ctx, self.annotations(), builder.reborrow().init_annotations(self.annotations().len() as u32))?;
+
+        match self.which() {
+            ast::field::Which::Slot { offset, type_, default_value, had_explicit_default } => {
+                let mut slot = builder.init_slot();
+                slot.set_offset(*offset);
+                slot.set_had_explicit_default(*had_explicit_default);
+                type_.write_to(ctx, slot.reborrow().init_type())?;
+                encode_value(ctx, default_value, Some(type_), slot.init_default_value())?
+            },
+            ast::field::Which::Group(type_id) => builder.init_group().set_type_id(*type_id)
+        }
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::enumerant::Builder<'_>> for ast::Enumerant {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::enumerant::Builder<'_>) -> Result<()> {
+        builder.set_name(self.name());
+        builder.set_code_order(self.code_order());
+        write_annotations(ctx, self.annotations(), builder.init_annotations(self.annotations().len() as u32))
+    }
+}
+
+fn element_size(size: ast::node::ElementSize) -> schema_capnp::ElementSize {
+    match size {
+        ast::node::ElementSize::Empty => schema_capnp::ElementSize::Empty,
+        ast::node::ElementSize::Bit => schema_capnp::ElementSize::Bit,
+        ast::node::ElementSize::Byte => schema_capnp::ElementSize::Byte,
+        ast::node::ElementSize::TwoBytes => schema_capnp::ElementSize::TwoBytes,
+        ast::node::ElementSize::FourBytes => schema_capnp::ElementSize::FourBytes,
+        ast::node::ElementSize::EightBytes => schema_capnp::ElementSize::EightBytes,
+        ast::node::ElementSize::Pointer => schema_capnp::ElementSize::Pointer,
+        ast::node::ElementSize::InlineComposite => schema_capnp::ElementSize::InlineComposite
+    }
+}
+
+impl WriteTo<schema_capnp::method::Builder<'_>> for ast::Method {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::method::Builder<'_>) -> Result<()> {
+        builder.set_name(self.name());
+        builder.set_code_order(self.code_order());
+        builder.set_param_struct_type(self.param_struct_type());
+        builder.set_result_struct_type(self.result_struct_type());
+        self.param_brand().write_to(ctx, builder.reborrow().init_param_brand())?;
+        self.result_brand().write_to(ctx, builder.reborrow().init_result_brand())?;
+
+        let mut implicit_parameters = builder.reborrow().init_implicit_parameters(self.implicit_parameters().len() as u32);
+        for (i, parameter) in self.implicit_parameters().iter().enumerate() {
+            parameter.write_to(ctx, implicit_parameters.reborrow().get(i as u32))?;
+        }
+
+        write_annotations(ctx, self.annotations(), builder.init_annotations(self.annotations().len() as u32))
+    }
+}
+
+impl WriteTo<schema_capnp::superclass::Builder<'_>> for ast::Superclass {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::superclass::Builder<'_>) -> Result<()> {
+        builder.set_id(self.id());
+        self.brand().write_to(ctx, builder.init_brand())
+    }
+}
+
+impl WriteTo<schema_capnp::node::Builder<'_>> for ast::node::Which {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::node::Builder<'_>) -> Result<()> {
+        match self {
+            ast::node::Which::File => builder.set_file(()),
+            ast::node::Which::Struct {
+                data_word_count, pointer_count, preferred_list_encoding, is_group, discriminant_count, discriminant_offset, fields
+            } => {
+                let mut s = builder.init_struct();
+                s.set_data_word_count(*data_word_count);
+                s.set_pointer_count(*pointer_count);
+                s.set_preferred_list_encoding(element_size(*preferred_list_encoding));
+                s.set_is_group(*is_group);
+                s.set_discriminant_count(*discriminant_count);
+                s.set_discriminant_offset(*discriminant_offset);
+                let mut list = s.init_fields(fields.len() as u32);
+                for (i, field) in fields.iter().enumerate() {
+                    field.write_to(ctx, list.reborrow().get(i as u32))?;
+                }
+            },
+            ast::node::Which::Enum(enumerants) => {
+                let mut list = builder.init_enum().init_enumerants(enumerants.len() as u32);
+                for (i, enumerant) in enumerants.iter().enumerate() {
+                    enumerant.write_to(ctx, list.reborrow().get(i as u32))?;
+                }
+            },
+            ast::node::Which::Interface { methods, superclasses } => {
+                let mut i = builder.init_interface();
+                let mut method_list = i.reborrow().init_methods(methods.len() as u32);
+                for (index, method) in methods.iter().enumerate() {
+                    method.write_to(ctx, method_list.reborrow().get(index as u32))?;
+                }
+                let mut superclass_list = i.init_superclasses(superclasses.len() as u32);
+                for (index, superclass) in superclasses.iter().enumerate() {
+                    superclass.write_to(ctx, superclass_list.reborrow().get(index as u32))?;
+                }
+            },
+            ast::node::Which::Const { type_, value } => {
+                let mut c = builder.init_const();
+                type_.write_to(ctx, c.reborrow().init_type())?;
+                encode_value(ctx, value, Some(type_), c.init_value())?
+            },
+            ast::node::Which::Annotation { type_, targets } => {
+                use ast::node::AnnotationTarget;
+
+                let mut a = builder.init_annotation();
+                for target in targets {
+                    match target {
+                        AnnotationTarget::File => a.set_targets_file(true),
+                        AnnotationTarget::Const => a.set_targets_const(true),
+                        AnnotationTarget::Enum => a.set_targets_enum(true),
+                        AnnotationTarget::Enumerant => a.set_targets_enumerant(true),
+                        AnnotationTarget::Struct => a.set_targets_struct(true),
+                        AnnotationTarget::Field => a.set_targets_field(true),
+                        AnnotationTarget::Union => a.set_targets_union(true),
+                        AnnotationTarget::Group => a.set_targets_group(true),
+                        AnnotationTarget::Interface => a.set_targets_interface(true),
+                        AnnotationTarget::Method => a.set_targets_method(true),
+                        AnnotationTarget::Param => a.set_targets_param(true),
+                        AnnotationTarget::Annotation => a.set_targets_annotation(true)
+                    }
+                }
+                type_.write_to(ctx, a.init_type())?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::node::Builder<'_>> for ast::Node {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::node::Builder<'_>) -> Result<()> {
+        builder.set_id(self.id());
+        builder.set_display_name(self.display_name());
+        builder.set_display_name_prefix_length(self.display_name_prefix_length() as u32);
+        builder.set_scope_id(self.scope_id());
+        builder.set_is_generic(self.is_generic());
+
+        let mut nested_nodes = builder.reborrow().init_nested_nodes(self.nested_nodes().len() as u32);
+        for (i, nested_node) in self.nested_nodes().iter().enumerate() {
+            let mut nested_node_builder = nested_nodes.reborrow().get(i as u32);
+            nested_node_builder.set_id(nested_node.id());
+            nested_node_builder.set_name(nested_node.name());
+        }
+
+        write_annotations(ctx, self.annotations(), builder.reborrow().init_annotations(self.annotations().len() as u32))?;
+
+        let mut parameters = builder.reborrow().init_parameters(self.parameters().len() as u32);
+        for (i, parameter) in self.parameters().iter().enumerate() {
+            parameter.write_to(ctx, parameters.reborrow().get(i as u32))?;
+        }
+
+        self.which().write_to(ctx, builder)
+    }
+}
+
+impl WriteTo<schema_capnp::code_generator_request::requested_file::Builder<'_>> for ast::code_generator_request::RequestedFile {
+    fn write_to(&self, _ctx: &Context, mut builder: schema_capnp::code_generator_request::requested_file::Builder<'_>) -> Result<()> {
+        builder.set_id(self.id());
+        builder.set_filename(self.filename());
+
+        let mut imports = builder.init_imports(self.imports().len() as u32);
+        for (i, import) in self.imports().iter().enumerate() {
+            let mut import_builder = imports.reborrow().get(i as u32);
+            import_builder.set_id(import.id());
+            import_builder.set_name(import.name());
+        }
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::node::source_info::Builder<'_>> for ast::node::SourceInfo {
+    fn write_to(&self, _ctx: &Context, mut builder: schema_capnp::node::source_info::Builder<'_>) -> Result<()> {
+        builder.set_id(self.id());
+        if let Some(doc_comment) = self.doc_comment() {
+            builder.set_doc_comment(doc_comment);
+        }
+        builder.set_start_byte(self.start_byte());
+        builder.set_end_byte(self.end_byte());
+
+        let mut members = builder.init_members(self.members().len() as u32);
+        for (i, member) in self.members().iter().enumerate() {
+            let mut member_builder = members.reborrow().get(i as u32);
+            if let Some(doc_comment) = member.doc_comment() {
+                member_builder.set_doc_comment(doc_comment);
+            }
+            member_builder.set_start_byte(member.start_byte());
+            member_builder.set_end_byte(member.end_byte());
+        }
+        Ok(())
+    }
+}
+
+impl WriteTo<schema_capnp::code_generator_request::Builder<'_>> for ast::CodeGeneratorRequest {
+    fn write_to(&self, ctx: &Context, mut builder: schema_capnp::code_generator_request::Builder<'_>) -> Result<()> {
+        let mut nodes = builder.reborrow().init_nodes(self.nodes().len() as u32);
+        for (i, node) in self.nodes().iter().enumerate() {
+            node.write_to(ctx, nodes.reborrow().get(i as u32))?;
+        }
+
+        let mut requested_files = builder.reborrow().init_requested_files(self.requested_files().len() as u32);
+        for (i, requested_file) in self.requested_files().iter().enumerate() {
+            requested_file.write_to(ctx, requested_files.reborrow().get(i as u32))?;
+        }
+
+        // Sorted, so that the same request is always written the same way.
+        let mut ids: Vec<&ast::Id> = self.source_info().keys().collect();
+        ids.sort();
+        let mut source_info = builder.init_source_info(ids.len() as u32);
+        for (i, id) in ids.into_iter().enumerate() {
+            self.source_info()[id].write_to(ctx, source_info.reborrow().get(i as u32))?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Builds a `CodeGeneratorRequest` message from the AST, e.g. to hand a modified request on to another plugin.
+///
+pub fn build_message(cgr: &ast::CodeGeneratorRequest) -> Result<capnp::message::Builder<capnp::message::HeapAllocator>> {
+    let ctx = Context::new(cgr);
+    let mut message = capnp::message::Builder::new_default();
+    cgr.write_to(&ctx, message.init_root::<schema_capnp::code_generator_request::Builder>())?;
+    Ok(message)
+}
+
+///
+/// Writes a request the way the schema compiler writes it to a plugin's stdin.
+///
+pub fn write_message(writer: &mut dyn std::io::Write, cgr: &ast::CodeGeneratorRequest) -> Result<()> {
+    write_message_with_framing(writer, cgr, Framing::Unpacked)
+}
+
+pub fn write_message_with_framing(mut writer: &mut dyn std::io::Write, cgr: &ast::CodeGeneratorRequest, framing: Framing) -> Result<()> {
+    let message = build_message(cgr)?;
+    let written = match framing {
+        Framing::Unpacked => capnp::serialize::write_message(&mut writer, &message),
+        Framing::Packed => capnp::serialize_packed::write_message(&mut writer, &message)
+    };
+    written.map_err(Error::Write)
+}
+
+pub fn write_message_to_bytes(cgr: &ast::CodeGeneratorRequest, framing: Framing) -> Result<Vec<u8>> {
+    let mut bytes = vec!();
+    write_message_with_framing(&mut bytes, cgr, framing)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::value::StructField;
+    use crate::fixtures::{node, slot, struct_which};
+    use crate::{read_message_from_bytes, ReadOptions};
+
+    fn shape_type() -> ast::Type {
+        ast::Type::Struct { type_id: 2, brand: ast::Brand::default() }
+    }
+
+    fn shape_value(color: u16, label: &str, shape: StructField, visible: bool) -> ast::Value {
+        ast::Value::Struct(vec!(
+            StructField::new(String::from("color"), ast::Value::Enum(color)),
+            StructField::new(String::from("label"), ast::Value::Text(String::from(label))),
+            shape,
+            StructField::new(String::from("extra"), ast::Value::Struct(vec!(StructField::new(String::from("visible"), ast::Value::Bool(visible)))))
+        ))
+    }
+
+    ///
+    /// test.capnp (1)
+    ///   Shape (2) { color, label, union { circle, square }, extra (7) { visible } }
+    ///   Color (3)
+    ///   tag (4), an annotation of type Shape
+    ///   shapes (5), a List(Shape) constant
+    ///   Drawer(T) (6) with draw (8 -> 9)
+    ///
+    fn request() -> ast::CodeGeneratorRequest {
+        let circle = shape_value(0, "a", StructField::new(String::from("circle"), ast::Value::Float64(1.5)), false);
+        let square = shape_value(1, "none", StructField::new(String::from("square"), ast::Value::Uint32(4)), true);
+
+        let shape = ast::Node::new(
+            2,
+            String::from("test.capnp:Shape"),
+            11,
+            1,
+            vec!(),
+            vec!(ast::Annotation::new(4, square.clone())),
+            vec!(),
+            false,
+            struct_which(3, 1, false, 2, 1, vec!(
+                slot("color", 0, ast::field::NO_DISCRIMINANT, 0, ast::Type::Enum { type_id: 3, brand: ast::Brand::default() }, ast::Value::Enum(1)),
+                slot("label", 1, ast::field::NO_DISCRIMINANT, 0, ast::Type::Text, ast::Value::Text(String::from("none"))),
+                slot("circle", 2, 0, 1, ast::Type::Float64, ast::Value::Float64(0.0)),
+                slot("square", 3, 1, 1, ast::Type::Uint32, ast::Value::Uint32(0)),
+                ast::Field::new(String::from("extra"), 4, ast::field::NO_DISCRIMINANT, ast::field::Ordinal::Implicit, vec!(), ast::field::Which::Group(7))
+            ))
+        );
+
+        let drawer = ast::Node::new(
+            6,
+            String::from("test.capnp:Drawer"),
+            11,
+            1,
+            vec!(),
+            vec!(),
+            vec!(ast::node::Parameter::new(String::from("T"))),
+            true,
+            ast::node::Which::Interface {
+                methods: vec!(ast::Method::new(
+                    String::from("draw"),
+                    0,
+                    8,
+                    9,
+                    ast::Brand::new(vec!(ast::brand::Scope::new(6, ast::brand::scope::Which::Inherit))),
+                    ast::Brand::default(),
+                    vec!(ast::node::Parameter::new(String::from("U"))),
+                    vec!()
+                )),
+                superclasses: vec!(ast::Superclass::new(10, ast::Brand::new(vec!(ast::brand::Scope::new(
+                    10,
+                    ast::brand::scope::Which::Bind(vec!(
+                        ast::brand::Binding::Type(ast::Type::AnyPointer(ast::type_::AnyPointer::Parameter { scope_id: 6, parameter_index: 0 })),
+                        ast::brand::Binding::Unbound
+                    ))
+                )))))
+            }
+        );
+
+        let mut source_info = HashMap::new();
+        source_info.insert(2, ast::node::SourceInfo::new(
+            2,
+            Some(String::from("A shape.")),
+            vec!(
+                ast::node::source_info::Member::new(Some(String::from("Its color.")), 40, 56),
+                ast::node::source_info::Member::default()
+            ),
+            21,
+            120
+        ));
+        source_info.insert(6, ast::node::SourceInfo::new(6, None, vec!(ast::node::source_info::Member::new(Some(String::from("Draws.")), 0, 0)), 0, 0));
+
+        ast::CodeGeneratorRequest::new(
+            vec!(
+                node(1, "test.capnp", 0, vec!((2, "Shape"), (3, "Color"), (4, "tag"), (5, "shapes"), (6, "Drawer")), ast::node::Which::File),
+                shape,
+                node(7, "test.capnp:Shape.extra", 2, vec!(), struct_which(3, 1, true, 0, 1, vec!(
+                    slot("visible", 0, ast::field::NO_DISCRIMINANT, 128, ast::Type::Bool, ast::Value::Bool(false))
+                ))),
+                node(3, "test.capnp:Color", 1, vec!(), ast::node::Which::Enum(vec!(
+                    ast::Enumerant::new(String::from("red"), 0, vec!()),
+                    ast::Enumerant::new(String::from("green"), 1, vec!(ast::Annotation::new(4, circle.clone())))
+                ))),
+                node(4, "test.capnp:tag", 1, vec!(), ast::node::Which::Annotation {
+                    type_: shape_type(),
+                    targets: vec!(ast::node::AnnotationTarget::Struct, ast::node::AnnotationTarget::Enumerant)
+                }),
+                node(5, "test.capnp:shapes", 1, vec!(), ast::node::Which::Const {
+                    type_: ast::Type::List(Box::new(shape_type())),
+                    value: ast::Value::List(vec!(circle, square))
+                }),
+                drawer,
+                node(8, "test.capnp:Drawer.draw$Params", 0, vec!(), struct_which(0, 2, false, 0, 1, vec!(
+                    slot("shape", 0, ast::field::NO_DISCRIMINANT, 0, ast::Type::AnyPointer(ast::type_::AnyPointer::ImplicitMethodParameter { parameter_index: 0 }), ast::Value::AnyPointer),
+                    slot("names", 1, ast::field::NO_DISCRIMINANT, 1, ast::Type::List(Box::new(ast::Type::Text)), ast::Value::List(vec!()))
+                ))),
+                node(9, "test.capnp:Drawer.draw$Results", 0, vec!(), struct_which(0, 0, false, 0, 1, vec!()))
+            ),
+            vec!(ast::code_generator_request::RequestedFile::new(
+                1,
+                String::from("test.capnp"),
+                vec!(ast::code_generator_request::requested_file::Import::new(11, String::from("other.capnp")))
+            )),
+            source_info
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cgr = request();
+        let bytes = write_message_to_bytes(&cgr, Framing::Unpacked).unwrap();
+        assert_eq!(read_message_from_bytes(&bytes, &ReadOptions::new()).unwrap(), cgr);
+    }
+
+    #[test]
+    fn test_round_trip_packed() {
+        let cgr = request();
+        let bytes = write_message_to_bytes(&cgr, Framing::Packed).unwrap();
+        assert_eq!(read_message_from_bytes(&bytes, ReadOptions::new().packed(true)).unwrap(), cgr);
+    }
+
+    #[test]
+    fn test_round_trip_unknown_struct() {
+        let cgr = request();
+        let mut nodes = cgr.nodes().clone();
+        nodes.push(node(12, "test.capnp:origin", 1, vec!(), ast::node::Which::Const { type_: shape_type(), value: ast::Value::Unknown }));
+        let cgr = ast::CodeGeneratorRequest::new(nodes, cgr.requested_files().clone(), cgr.source_info().clone());
+
+        let bytes = write_message_to_bytes(&cgr, Framing::Unpacked).unwrap();
+        let parsed = read_message_from_bytes(&bytes, &ReadOptions::new()).unwrap();
+        let defaults = shape_value(1, "none", StructField::new(String::from("circle"), ast::Value::Float64(0.0)), false);
+        match parsed.nodes().last().unwrap().which() {
+            ast::node::Which::Const { value, .. } => assert_eq!(value, &defaults),
+            which => panic!("Unexpected {:?}", which)
+        }
+    }
+
+    #[test]
+    fn test_write_mismatched_value() {
+        let cgr = ast::CodeGeneratorRequest::new(
+            vec!(node(1, "test.capnp:answer", 0, vec!(), ast::node::Which::Const { type_: ast::Type::List(Box::new(ast::Type::Int32)), value: ast::Value::List(vec!(ast::Value::Text(String::from("42")))) })),
+            vec!(),
+            HashMap::new()
+        );
+        match build_message(&cgr) {
+            Err(Error::Invalid(_)) => (),
+            other => panic!("Expected an invalid request, got {:?}", other.map(|_| ()))
+        }
+    }
+
+    struct FailingWriter;
+
+    impl std::io::Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "pipe closed"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_io_error() {
+        match write_message(&mut FailingWriter, &request()) {
+            Err(Error::Write(e)) => assert_eq!(e.kind(), std::io::ErrorKind::BrokenPipe),
+            other => panic!("Expected a write error, got {:?}", other)
+        }
+    }
+}